@@ -0,0 +1,274 @@
+mod table;
+use table::{Lifetime, Table, TABLE_PATH};
+
+extern crate anyhow;
+extern crate clap;
+extern crate netutils;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Arg, ArgAction, Command};
+use netutils::link::{Link, NETWORK_DEVICE};
+use netutils::packet::{parse_ethernet, Packet, ETHERTYPE_ARP};
+use netutils::{n16, Arp, EthernetII, EthernetIIHeader, Ipv4Addr, Ipv4Cidr, MacAddr, NetCfg, ARP_REPLY};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/*
+static ARP_MAN: &'static str = /* @MANSTART{arp} */
+    r#"
+NAME
+    arp - resolve IPv4 addresses to Ethernet addresses
+
+SYNOPSIS
+    arp [-h | --help] -a
+    arp [-i interface] [-w timeout] host
+    arp -s address mac
+
+DESCRIPTION
+    arp broadcasts an ARP request for host and prints the Ethernet address
+    from the reply. The network stack does not share its own ARP cache, so
+    arp keeps a table of its own in /var/lib/arp/table, with the addresses it
+    learned in the last twenty minutes and the static entries added with -s.
+
+OPTIONS
+    -h
+    --help
+        Print this manual page.
+
+    -a
+    --all
+        Print the table.
+
+    -s address mac
+    --set address mac
+        Add a static entry to the table, which never expires.
+
+    -i interface
+        Send the request from interface. The default is eth0.
+
+    -w timeout
+        Seconds to wait for a reply, repeating the request every second. The
+        default is 3.
+"#; /* @MANEND */ */
+
+const DEFAULT_INTERFACE: &str = "eth0";
+const DEFAULT_TIMEOUT_S: &str = "3";
+const RESEND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One line of `arp -a`, e.g. `10.0.2.2 at 52:55:0a:00:02:02 expires in 1190 seconds`
+fn format_entry(ip: Ipv4Addr, mac: MacAddr, lifetime: Lifetime) -> String {
+    match lifetime {
+        Lifetime::Permanent => format!("{} at {} permanent", ip.to_string(), mac),
+        Lifetime::Expires(left) => format!("{} at {} expires in {} seconds", ip.to_string(), mac, left.as_secs()),
+    }
+}
+
+fn resolve_host(host: &str) -> Result<Ipv4Addr> {
+    let addrs = (host, 0)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve host: {}", host))?;
+    for addr in addrs {
+        if let IpAddr::V4(addr) = addr.ip() {
+            return Ok(Ipv4Addr { bytes: addr.octets() });
+        }
+    }
+    bail!("{} has no IPv4 address", host)
+}
+
+/// MAC and first IPv4 address of the interface
+fn local_addresses(iface: &str) -> Result<(MacAddr, Ipv4Addr)> {
+    let cfg = NetCfg::new();
+    let mac_str = cfg.get_iface(iface, "mac")?;
+    let mac = mac_str
+        .parse::<MacAddr>()
+        .map_err(|e| anyhow!("Invalid MAC address for {}: {} ({})", iface, mac_str, e))?;
+
+    let addrs = cfg.get_iface(iface, "addr/list")?;
+    let ip = Ipv4Cidr::parse_list(&addrs)
+        .0
+        .first()
+        .map(|cidr| cidr.addr)
+        .ok_or_else(|| anyhow!("{} has no IPv4 address", iface))?;
+    Ok((mac, ip))
+}
+
+/// Broadcast requests for `target` until it replies or `timeout` runs out,
+/// learning every ARP packet seen meanwhile
+fn resolve(iface: &str, target: Ipv4Addr, timeout: Duration, table: &mut Table) -> Result<Option<MacAddr>> {
+    let (src_mac, src_ip) = local_addresses(iface)?;
+    let link = Link::open().with_context(|| format!("Can't open {}", NETWORK_DEVICE))?;
+
+    let request = EthernetII {
+        header: EthernetIIHeader {
+            dst: MacAddr::BROADCAST,
+            src: src_mac,
+            ethertype: n16::new(ETHERTYPE_ARP),
+        },
+        data: Arp::request(src_mac, src_ip, target).to_bytes(),
+    }.to_bytes();
+
+    let deadline = Instant::now() + timeout;
+    let mut next_request = Instant::now();
+    let mut buf = [0; 65536];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        if now >= next_request {
+            link.send(&request).context("Failed to send request")?;
+            next_request = now + RESEND_INTERVAL;
+        }
+
+        let wait = next_request.min(deadline);
+        if let Some(count) = link.recv_until(&mut buf, wait).context("Failed to receive reply")? {
+            if let Some(Packet::Arp(arp)) = parse_ethernet(&buf[..count]) {
+                table.learn(&arp);
+                let header = arp.header;
+                if header.oper.get() == ARP_REPLY && header.src_ip == target {
+                    return Ok(Some(header.src_mac));
+                }
+            }
+        }
+    }
+}
+
+enum Action {
+    List,
+    Set(Ipv4Addr, MacAddr),
+    Resolve { host: String, iface: String, timeout: Duration },
+}
+
+fn parse_args() -> Result<Action> {
+    let matches = Command::new("arp")
+        .about("resolve IPv4 addresses to Ethernet addresses")
+        //.after_help(ARP_MAN)
+        .arg(
+            Arg::new("host")
+                .help("The host to resolve (an IPv4 address or hostname)")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("all")
+                .short('a')
+                .long("all")
+                .help("Print the table.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("set")
+                .short('s')
+                .long("set")
+                .value_names(["ADDRESS", "MAC"])
+                .help("Add a static entry.")
+                .num_args(2)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("interface")
+                .short('i')
+                .value_name("INTERFACE")
+                .help("Send the request from INTERFACE.")
+                .default_value(DEFAULT_INTERFACE)
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("timeout")
+                .short('w')
+                .value_name("TIMEOUT")
+                .help("Seconds to wait for a reply.")
+                .default_value(DEFAULT_TIMEOUT_S)
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .get_matches();
+
+    let host = matches.get_one::<String>("host");
+    let set: Option<Vec<&String>> = matches.get_many::<String>("set").map(|values| values.collect());
+    let all = matches.get_flag("all");
+
+    match (host, set, all) {
+        (None, None, true) => Ok(Action::List),
+        (None, Some(set), false) => {
+            let ip = set[0]
+                .parse::<Ipv4Addr>()
+                .map_err(|e| anyhow!("Invalid address for -s: {} ({})", set[0], e))?;
+            let mac = set[1]
+                .parse::<MacAddr>()
+                .map_err(|e| anyhow!("Invalid MAC address for -s: {} ({})", set[1], e))?;
+            Ok(Action::Set(ip, mac))
+        }
+        (Some(host), None, false) => {
+            let timeout_str = matches
+                .get_one::<String>("timeout")
+                .expect("timeout should have a default");
+            let timeout: f64 = timeout_str
+                .parse()
+                .map_err(|e| anyhow!("Invalid timeout for -w: {} ({})", timeout_str, e))?;
+            if timeout.is_nan() || timeout <= 0.0 {
+                bail!("Timeout must be a positive number");
+            }
+            Ok(Action::Resolve {
+                host: host.to_string(),
+                iface: matches
+                    .get_one::<String>("interface")
+                    .expect("interface should have a default")
+                    .to_string(),
+                timeout: Duration::from_secs_f64(timeout),
+            })
+        }
+        (None, None, false) => bail!("Nothing to do, give a host, -a or -s"),
+        _ => bail!("Only one of a host, -a and -s can be given"),
+    }
+}
+
+fn main() -> Result<()> {
+    let action = parse_args()?;
+    let path = Path::new(TABLE_PATH);
+    let mut table = Table::load(path).map_err(|e| anyhow!(e))?;
+
+    match action {
+        Action::List => {
+            for (ip, mac, lifetime) in table.entries() {
+                println!("{}", format_entry(ip, mac, lifetime));
+            }
+        }
+        Action::Set(ip, mac) => {
+            table.set_permanent(ip, mac);
+            table.save(path).map_err(|e| anyhow!(e))?;
+        }
+        Action::Resolve { host, iface, timeout } => {
+            let target = resolve_host(&host)?;
+            let reply = resolve(&iface, target, timeout, &mut table)?;
+            // Whatever was learned is worth keeping, even without an answer
+            table.save(path).map_err(|e| anyhow!(e))?;
+            match reply {
+                Some(mac) => println!("{} at {}", target.to_string(), mac),
+                None => bail!("No reply from {} after {:.1} seconds", target.to_string(), timeout.as_secs_f64()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_entry;
+    use netutils::{Ipv4Addr, MacAddr};
+    use std::time::Duration;
+    use table::Lifetime;
+
+    #[test]
+    fn entry_lines() {
+        let ip = Ipv4Addr::from_str("10.0.2.2");
+        let mac = MacAddr::from_str("52-55-0A-00-02-02");
+        assert_eq!(format_entry(ip, mac, Lifetime::Permanent), "10.0.2.2 at 52:55:0a:00:02:02 permanent");
+        assert_eq!(
+            format_entry(ip, mac, Lifetime::Expires(Duration::from_millis(1_190_400))),
+            "10.0.2.2 at 52:55:0a:00:02:02 expires in 1190 seconds"
+        );
+    }
+}
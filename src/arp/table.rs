@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use netutils::{Arp, ArpCache, Ipv4Addr, MacAddr};
+
+/// Where the table is kept between runs
+pub const TABLE_PATH: &str = "/var/lib/arp/table";
+
+/// How long a learned entry is trusted, twenty minutes as on the BSDs
+pub const ENTRY_TTL: Duration = Duration::from_secs(20 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lifetime {
+    /// Added with -s, never expires
+    Permanent,
+    /// Learned from the network, trusted for this much longer
+    Expires(Duration),
+}
+
+/// The entries learned by earlier runs and the static ones added with -s
+///
+/// The file has one `address mac learned` line per entry, where `learned` is
+/// the Unix time it was learned at, or `permanent` for static entries.
+pub struct Table {
+    cache: ArpCache,
+    permanent: BTreeMap<Ipv4Addr, MacAddr>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Table {
+            cache: ArpCache::new(ENTRY_TTL),
+            permanent: BTreeMap::new(),
+        }
+    }
+
+    /// Parse the file contents, dropping the entries that expired since
+    pub fn parse(text: &str, now: SystemTime) -> Result<Table, String> {
+        let mut table = Table::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let entry = match fields[..] {
+                [ip, mac, learned] => ip.parse::<Ipv4Addr>().ok().and_then(|ip| {
+                    mac.parse::<MacAddr>().ok().map(|mac| (ip, mac, learned))
+                }),
+                _ => None,
+            };
+            let (ip, mac, learned) = entry.ok_or_else(|| format!("line {}: invalid entry '{}'", i + 1, line))?;
+
+            if learned == "permanent" {
+                table.permanent.insert(ip, mac);
+            } else {
+                let secs = learned
+                    .parse::<u64>()
+                    .map_err(|_| format!("line {}: invalid time '{}'", i + 1, learned))?;
+                let age = now.duration_since(UNIX_EPOCH + Duration::from_secs(secs)).unwrap_or_default();
+                table.cache.insert_aged(ip, mac, age);
+            }
+        }
+        Ok(table)
+    }
+
+    /// Load the table, which is empty before the first run saved it
+    pub fn load(path: &Path) -> Result<Table, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Table::parse(&text, SystemTime::now()).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Table::new()),
+            Err(err) => Err(format!("failed to read {}: {}", path.display(), err)),
+        }
+    }
+
+    pub fn to_text(&self, now: SystemTime) -> String {
+        let mut text = String::new();
+        for (ip, mac) in &self.permanent {
+            text.push_str(&format!("{} {} permanent\n", ip.to_string(), mac));
+        }
+        for (ip, mac, age) in self.cache.entries() {
+            if self.permanent.contains_key(&ip) {
+                continue;
+            }
+            let learned = now.checked_sub(age).unwrap_or(UNIX_EPOCH);
+            let secs = learned.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+            text.push_str(&format!("{} {} {}\n", ip.to_string(), mac, secs));
+        }
+        text
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| format!("failed to create {}: {}", parent.display(), err))?;
+        }
+        fs::write(path, self.to_text(SystemTime::now()))
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err))
+    }
+
+    /// Remember the sender of an ARP packet seen on the link
+    pub fn learn(&mut self, arp: &Arp) {
+        self.cache.learn(arp);
+    }
+
+    pub fn set_permanent(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        self.cache.remove(ip);
+        self.permanent.insert(ip, mac);
+    }
+
+    /// Every entry by address, a static one hiding a learned one
+    pub fn entries(&self) -> Vec<(Ipv4Addr, MacAddr, Lifetime)> {
+        let mut entries: BTreeMap<Ipv4Addr, (MacAddr, Lifetime)> = self.cache
+            .entries()
+            .into_iter()
+            .map(|(ip, mac, age)| (ip, (mac, Lifetime::Expires(ENTRY_TTL - age))))
+            .collect();
+        for (&ip, &mac) in &self.permanent {
+            entries.insert(ip, (mac, Lifetime::Permanent));
+        }
+        entries.into_iter().map(|(ip, (mac, lifetime))| (ip, mac, lifetime)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lifetime, Table};
+    use netutils::{Arp, Ipv4Addr, MacAddr};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parse_and_save() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let text = "# arp table\n\
+                    10.0.2.2 52:55:0a:00:02:02 1699999900\n\
+                    10.0.2.3 52:55:0a:00:02:03 1699990000\n\
+                    10.0.2.4 02:00:00:00:00:04 permanent\n";
+        let table = Table::parse(text, now).unwrap();
+
+        // 10.0.2.3 was learned 10000 seconds ago, well past the twenty minutes
+        let entries = table.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].0, entries[0].1), (Ipv4Addr::from_str("10.0.2.2"), MacAddr::from_str("52:55:0a:00:02:02")));
+        assert_eq!(entries[1], (Ipv4Addr::from_str("10.0.2.4"), MacAddr::from_str("02:00:00:00:00:04"), Lifetime::Permanent));
+        match entries[0].2 {
+            Lifetime::Expires(left) => assert!(left <= Duration::from_secs(1100) && left > Duration::from_secs(1000)),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let saved = table.to_text(now);
+        assert!(saved.starts_with("10.0.2.4 02:00:00:00:00:04 permanent\n10.0.2.2 52:55:0a:00:02:02 "));
+        assert_eq!(Table::parse(&saved, now).unwrap().entries().len(), 2);
+    }
+
+    #[test]
+    fn static_entries_win() {
+        let ip = Ipv4Addr::from_str("10.0.2.2");
+        let fixed = MacAddr::from_str("02:00:00:00:00:01");
+        let mut table = Table::new();
+        table.learn(&Arp::reply(MacAddr::from_str("02:00:00:00:00:02"), ip, fixed, Ipv4Addr::from_str("10.0.2.15")));
+        table.set_permanent(ip, fixed);
+        assert_eq!(table.entries(), vec![(ip, fixed, Lifetime::Permanent)]);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let now = UNIX_EPOCH;
+        assert!(Table::parse("10.0.2.2 52:55:0a:00:02:02", now).is_err());
+        assert!(Table::parse("10.0.2 52:55:0a:00:02:02 permanent", now).is_err());
+        assert!(Table::parse("10.0.2.2 52:55:0a:00:02 permanent", now).is_err());
+        assert_eq!(
+            Table::parse("\n10.0.2.2 52:55:0a:00:02:02 soon", now).err(),
+            Some("line 2: invalid time 'soon'".to_string())
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+
+/// Exit codes, so boot scripts can tell why addressing failed:
+///
+/// - 0: the address, and the route and name server when offered, were set
+/// - 1: any other failure, such as reading the interface or binding the socket
+/// - 2: no offer or acknowledgement arrived before the timeout
+/// - 3: the server answered the request with a NAK
+/// - 4: writing the configuration to the netcfg scheme failed
+/// - 5: with --arp-probe, every address offered was already in use
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_NO_REPLY: i32 = 2;
+pub const EXIT_NAK: i32 = 3;
+pub const EXIT_SCHEME: i32 = 4;
+pub const EXIT_CONFLICT: i32 = 5;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DhcpError {
+    /// The server did not answer the discover or the request in time
+    NoReply(String),
+    /// The server refused to lease the offered address
+    Nak,
+    /// The lease was granted but a netcfg entry could not be written
+    Scheme(String),
+    /// Another host answered the ARP probe for the leased address, which was declined
+    Conflict(String),
+    Other(String),
+}
+
+impl DhcpError {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            DhcpError::NoReply(_) => EXIT_NO_REPLY,
+            DhcpError::Nak => EXIT_NAK,
+            DhcpError::Scheme(_) => EXIT_SCHEME,
+            DhcpError::Conflict(_) => EXIT_CONFLICT,
+            DhcpError::Other(_) => EXIT_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for DhcpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DhcpError::NoReply(ref err)
+            | DhcpError::Scheme(ref err)
+            | DhcpError::Conflict(ref err)
+            | DhcpError::Other(ref err) => {
+                write!(f, "{}", err)
+            }
+            DhcpError::Nak => write!(f, "server declined the request"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(DhcpError::NoReply("failed to receive offer: timed out".to_string()).exit_code(), 2);
+        assert_eq!(DhcpError::Nak.exit_code(), 3);
+        assert_eq!(DhcpError::Scheme("failed to set ip: denied".to_string()).exit_code(), 4);
+        assert_eq!(DhcpError::Conflict("10.0.2.15 is in use by 52:54:00:ab:cd:ef".to_string()).exit_code(), 5);
+        assert_eq!(DhcpError::Other("failed to bind udp".to_string()).exit_code(), 1);
+        assert_eq!(DhcpError::Nak.to_string(), "server declined the request");
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+
+/// Directory holding the last address acquired on each interface
+pub const LEASE_DIR: &str = "/var/lib/dhcpd";
+
+/// File remembering the last address acquired on `iface`
+pub fn lease_path(iface: &str) -> PathBuf {
+    Path::new(LEASE_DIR).join(format!("{}.lease", iface))
+}
+
+/// Parse an address to put in option 50, which has to be one a client could
+/// be assigned: not unspecified, loopback, multicast or broadcast
+pub fn parse_requested_ip(addr: &str) -> Result<[u8; 4], String> {
+    let ip: Ipv4Addr = addr.trim().parse().map_err(|_| format!("invalid IPv4 address '{}'", addr.trim()))?;
+    if ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() || ip.is_broadcast() {
+        return Err(format!("{} can't be requested from a DHCP server", ip));
+    }
+    Ok(ip.octets())
+}
+
+/// The address saved by the last successful lease, if there is one
+pub fn read_lease(path: &Path) -> Result<Option<[u8; 4]>, String> {
+    match fs::read_to_string(path) {
+        Ok(data) => parse_requested_ip(&data)
+            .map(Some)
+            .map_err(|err| format!("{}: {}", path.display(), err)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("failed to read {}: {}", path.display(), err)),
+    }
+}
+
+pub fn write_lease(path: &Path, addr: [u8; 4]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, format!("{}\n", Ipv4Addr::from(addr)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_requested_ip, read_lease, write_lease};
+    use netutils::tempdir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn requested_ip() {
+        assert_eq!(parse_requested_ip("192.168.1.20"), Ok([192, 168, 1, 20]));
+        assert_eq!(parse_requested_ip(" 10.0.2.15\n"), Ok([10, 0, 2, 15]));
+        assert!(parse_requested_ip("192.168.1").is_err());
+        assert!(parse_requested_ip("192.168.1.300").is_err());
+        assert!(parse_requested_ip("0.0.0.0").is_err());
+        assert!(parse_requested_ip("127.0.0.1").is_err());
+        assert!(parse_requested_ip("224.0.0.1").is_err());
+        assert!(parse_requested_ip("255.255.255.255").is_err());
+    }
+
+    #[test]
+    fn lease_file() {
+        let dir = TempDir::new("dhcpd-test").unwrap();
+        let path = dir.path().join("lib/eth0.lease");
+
+        assert_eq!(read_lease(&path), Ok(None));
+        write_lease(&path, [10, 0, 2, 15]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "10.0.2.15\n");
+        assert_eq!(read_lease(&path), Ok(Some([10, 0, 2, 15])));
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(read_lease(&path).is_err());
+    }
+}
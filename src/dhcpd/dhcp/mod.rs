@@ -1,3 +1,30 @@
+pub use self::error::{DhcpError, EXIT_ERROR};
+pub use self::lease::{lease_path, parse_requested_ip, read_lease, write_lease};
+pub use self::probe::probe;
+pub use self::search::{parse_domain_name, parse_domain_search};
+
+mod error;
+mod lease;
+mod probe;
+mod search;
+
+/// Asks the server to broadcast its replies (RFC 2131 4.1)
+const BROADCAST_FLAG: u16 = 0x8000;
+
+/// The flags field of a DISCOVER or REQUEST, in network order.
+///
+/// A client without an address may not be able to receive the unicast a
+/// server sends otherwise. Broadcast replies always arrive, at the cost of
+/// waking every host on the link. Both messages must agree, as some servers
+/// answer the REQUEST differently from the DISCOVER when they don't.
+pub fn flags(broadcast: bool) -> u16 {
+    if broadcast {
+        BROADCAST_FLAG.to_be()
+    } else {
+        0
+    }
+}
+
 #[repr(packed)]
 pub struct Dhcp {
     pub op: u8,
@@ -17,3 +44,38 @@ pub struct Dhcp {
     pub magic: u32,
     pub options: [u8; 308]
 }
+
+impl Dhcp {
+    /// Data of the first instance of an option
+    pub fn option(&self, code: u8) -> Option<&[u8]> {
+        let options = &self.options;
+        let mut i = 0;
+        while i < options.len() {
+            match options[i] {
+                0 => i += 1,
+                255 => break,
+                option => {
+                    let len = *options.get(i + 1)? as usize;
+                    let data = options.get(i + 2..i + 2 + len)?;
+                    if option == code {
+                        return Some(data);
+                    }
+                    i += 2 + len;
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flags;
+
+    #[test]
+    fn broadcast_flag() {
+        // The flag is the most significant bit of the field as it goes on the wire
+        assert_eq!(flags(true).to_ne_bytes(), [0x80, 0x00]);
+        assert_eq!(flags(false).to_ne_bytes(), [0x00, 0x00]);
+    }
+}
@@ -0,0 +1,77 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use netutils::link::Link;
+use netutils::packet::{parse_ethernet, Packet, ETHERTYPE_ARP};
+use netutils::{n16, Arp, EthernetII, EthernetIIHeader, Ipv4Addr, MacAddr, ARP_REQUEST};
+
+/// Probes sent, one per interval, and how long to listen after the last one.
+/// RFC 5227 uses random gaps of one to two seconds, a fixed one does for a
+/// single client.
+const PROBE_NUM: u32 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether an ARP packet shows another host using `ip`, or probing for it
+/// at the same time (RFC 5227 2.1.1)
+pub fn is_conflict(arp: &Arp, ip: Ipv4Addr, mac: MacAddr) -> bool {
+    let header = arp.header;
+    if header.src_mac == mac {
+        return false;
+    }
+    header.src_ip == ip
+        || (header.oper.get() == ARP_REQUEST && header.src_ip == Ipv4Addr::NULL && header.dst_ip == ip)
+}
+
+/// Ask whether anyone already has `ip`, returning the MAC of the host that
+/// answered. The probes carry 0.0.0.0 as the sender address, so they do not
+/// update the ARP caches of other hosts.
+pub fn probe(mac: MacAddr, ip: Ipv4Addr) -> io::Result<Option<MacAddr>> {
+    let link = Link::open()?;
+    let request = EthernetII {
+        header: EthernetIIHeader {
+            dst: MacAddr::BROADCAST,
+            src: mac,
+            ethertype: n16::new(ETHERTYPE_ARP),
+        },
+        data: Arp::request(mac, Ipv4Addr::NULL, ip).to_bytes(),
+    }.to_bytes_padded();
+
+    let mut buf = [0; 65536];
+    for _ in 0..PROBE_NUM {
+        link.send(&request)?;
+        let next_probe = Instant::now() + PROBE_INTERVAL;
+        while let Some(count) = link.recv_until(&mut buf, next_probe)? {
+            if let Some(Packet::Arp(arp)) = parse_ethernet(&buf[..count]) {
+                if is_conflict(&arp, ip, mac) {
+                    return Ok(Some(arp.header.src_mac));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_conflict;
+    use netutils::{Arp, Ipv4Addr, MacAddr};
+
+    #[test]
+    fn conflicts() {
+        let ours = MacAddr::from_str("52:54:00:12:34:56");
+        let other = MacAddr::from_str("52:54:00:ab:cd:ef");
+        let ip = Ipv4Addr::from_str("10.0.2.15");
+        let gateway = Ipv4Addr::from_str("10.0.2.2");
+
+        // The address is in use: its owner answers, or asks about someone else
+        assert!(is_conflict(&Arp::reply(other, ip, ours, Ipv4Addr::NULL), ip, ours));
+        assert!(is_conflict(&Arp::request(other, ip, gateway), ip, ours));
+        // Another client probing for the same address
+        assert!(is_conflict(&Arp::request(other, Ipv4Addr::NULL, ip), ip, ours));
+
+        // Our own probe coming back, and traffic about other addresses
+        assert!(!is_conflict(&Arp::request(ours, Ipv4Addr::NULL, ip), ip, ours));
+        assert!(!is_conflict(&Arp::request(other, gateway, ip), ip, ours));
+        assert!(!is_conflict(&Arp::reply(other, gateway, ours, ip), ip, ours));
+    }
+}
@@ -0,0 +1,91 @@
+/// Most compression pointers followed while reading a single name
+const MAX_POINTERS: usize = 16;
+
+/// Parse option 15, a domain name optionally padded with NUL bytes
+pub fn parse_domain_name(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let name = String::from_utf8(data[..end].to_vec()).ok()?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Parse option 119, a list of names in RFC 1035 label encoding.
+///
+/// Compression pointers are offsets into `data`, which must hold every
+/// instance of the option concatenated in order (RFC 3397).
+pub fn parse_domain_search(data: &[u8]) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (name, next) = read_name(data, offset)?;
+        names.push(name);
+        offset = next;
+    }
+    Some(names)
+}
+
+/// Read the name at `offset`, returning it with the offset just past it
+fn read_name(data: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut pos = offset;
+    let mut end = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = *data.get(pos)? as usize;
+        if len == 0 {
+            pos += 1;
+            break;
+        } else if len & 0xC0 == 0xC0 {
+            let target = (len & 0x3F) << 8 | *data.get(pos + 1)? as usize;
+            // Pointers may only refer to earlier data, which also rules out loops
+            if target >= pos || pointers == MAX_POINTERS {
+                return None;
+            }
+            pointers += 1;
+            if end.is_none() {
+                end = Some(pos + 2);
+            }
+            pos = target;
+        } else if len & 0xC0 == 0 {
+            let label = data.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8(label.to_vec()).ok()?);
+            pos += 1 + len;
+        } else {
+            return None;
+        }
+    }
+
+    Some((labels.join("."), end.unwrap_or(pos)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_domain_name, parse_domain_search};
+
+    #[test]
+    fn compressed_search_list() {
+        // The example from RFC 3397: eng.apple.com and marketing.apple.com
+        let data = [
+            3, b'e', b'n', b'g', 5, b'a', b'p', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+            9, b'm', b'a', b'r', b'k', b'e', b't', b'i', b'n', b'g', 0xC0, 4,
+        ];
+        assert_eq!(
+            parse_domain_search(&data),
+            Some(vec!["eng.apple.com".to_string(), "marketing.apple.com".to_string()])
+        );
+
+        // Forward pointers and truncated labels are rejected
+        assert_eq!(parse_domain_search(&[0xC0, 0]), None);
+        assert_eq!(parse_domain_search(&[3, b'c', b'o']), None);
+    }
+
+    #[test]
+    fn domain_name() {
+        assert_eq!(parse_domain_name(b"example.com\0"), Some("example.com".to_string()));
+        assert_eq!(parse_domain_name(b""), None);
+    }
+}
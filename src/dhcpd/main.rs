@@ -1,72 +1,142 @@
+extern crate libredox;
 extern crate netutils;
 
-use netutils::MacAddr;
-use std::{env, process, time};
-use std::io::{self, Read, Write};
-use std::fs::{File, OpenOptions};
+use netutils::{validate_offer, Ipv4Addr, Ipv4Cidr, MacAddr, NetCfg};
+use std::{env, process, thread, time};
+use std::io::{self, Write};
 use std::net::{SocketAddr, UdpSocket};
 use std::time::Duration;
 
-use dhcp::Dhcp;
+use dhcp::{lease_path, parse_domain_name, parse_domain_search, parse_requested_ip, probe, read_lease, write_lease};
+use dhcp::{flags, Dhcp, DhcpError, EXIT_ERROR};
 
 mod dhcp;
 
 macro_rules! try_fmt {
-    ($e:expr, $m:expr) =>(
+    ($e:expr, $m:expr) => (try_fmt!($e, $m, DhcpError::Other));
+    ($e:expr, $m:expr, $kind:path) =>(
         match $e {
             Ok(ok) => ok,
-            Err(err) => return Err(format!("{}: {}", $m, err)),
+            Err(err) => return Err($kind(format!("{}: {}", $m, err))),
         }
     )
 }
 
-fn get_cfg_value(path: &str) -> Result<String, String> {
-    let path = format!("/scheme/netcfg/{}", path);
-    let mut file = File::open(&path).map_err(|_| format!("Can't open {}", &path))?;
-    let mut result = String::new();
-    file.read_to_string(&mut result)
-        .map_err(|_| format!("Can't read {}", path))?;
-    Ok(result)
-}
+/// Leases declined after a failed ARP probe before giving up
+const MAX_DECLINES: usize = 3;
+/// Pause after a DHCPDECLINE before starting over (RFC 2131 3.1.5)
+const DECLINE_WAIT: Duration = Duration::from_secs(10);
 
-fn get_iface_cfg_value(iface: &str, cfg: &str) -> Result<String, String> {
-    let path = format!("ifaces/{}/{}", iface, cfg);
-    get_cfg_value(&path)
+/// Client hardware address field, the MAC followed by zero padding
+fn chaddr(mac: &MacAddr) -> [u8; 16] {
+    let mut chaddr = [0; 16];
+    chaddr[..6].copy_from_slice(mac.as_bytes());
+    chaddr
 }
 
-fn set_cfg_value(path: &str, value: &str) -> Result<(), String> {
-    let path = format!("/scheme/netcfg/{}", path);
-    let mut file = OpenOptions::new().read(false).write(true).create(false).open(&path)
-        .map_err(|_| format!("Can't open {}", path))?;
-    file.write(value.as_bytes())
-        .map(|_| ())
-        .map_err(|_| format!("Can't write {} to {}", value, path))?;
-    file.sync_data()
-        .map_err(|_| format!("Can't commit {} to {}", value, path))
-}
+/// Tell the server that `addr` is already in use, so it is not leased again
+fn send_decline(socket: &UdpSocket, tid: u32, mac: &MacAddr, addr: [u8; 4], server: [u8; 4]) -> Result<(), DhcpError> {
+    let mut decline = Dhcp {
+        op: 1,
+        htype: 1,
+        hlen: 6,
+        hops: 0,
+        tid,
+        secs: 0,
+        flags: 0,
+        ciaddr: [0; 4],
+        yiaddr: [0; 4],
+        siaddr: [0; 4],
+        giaddr: [0; 4],
+        chaddr: chaddr(mac),
+        sname: [0; 64],
+        file: [0; 128],
+        magic: 0x63825363u32.to_be(),
+        options: [0; 308],
+    };
+
+    for (s, d) in [
+        // DHCP Message Type (Decline)
+        53,
+        1,
+        4,
+
+        // Requested IP Address, the one being declined
+        50,
+        4,
+        addr[0],
+        addr[1],
+        addr[2],
+        addr[3],
+
+        // Server Identifier
+        54,
+        4,
+        server[0],
+        server[1],
+        server[2],
+        server[3],
+
+        // End
+        255,
+    ].iter()
+        .zip(decline.options.iter_mut())
+    {
+        *d = *s;
+    }
 
-fn set_iface_cfg_value(iface: &str, cfg: &str, value: &str) -> Result<(), String> {
-    let path = format!("ifaces/{}/{}", iface, cfg);
-    set_cfg_value(&path, value)
+    let decline_data = unsafe {
+        std::slice::from_raw_parts(
+            (&decline as *const Dhcp) as *const u8,
+            std::mem::size_of::<Dhcp>(),
+        )
+    };
+    try_fmt!(socket.send(decline_data), "failed to send decline");
+    Ok(())
 }
 
-fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
-    let current_mac = MacAddr::from_str(get_iface_cfg_value(iface, "mac")?.trim());
-
-    let current_ip = get_iface_cfg_value(iface, "addr/list")?
-        .lines()
-        .next()
-        .map(|l| l.to_owned())
-        .unwrap_or("0.0.0.0".to_string());
+/// Run one exchange. Addresses in `declined` are not requested, and one the
+/// ARP probe finds in use is added to it.
+fn dhcp(
+    iface: &str,
+    quiet: bool,
+    keep_routes: bool,
+    request_ip: Option<[u8; 4]>,
+    arp_probe: bool,
+    broadcast: bool,
+    declined: &mut Vec<[u8; 4]>,
+) -> Result<(), DhcpError> {
+    let cfg = NetCfg::new();
+    let current_mac = MacAddr::from_str(&cfg.get_iface(iface, "mac").map_err(|err| DhcpError::Other(err.to_string()))?);
+
+    // Put back if the leased address turns out to be in use
+    let previous_ips = cfg.get_iface(iface, "addr/list").map_err(|err| DhcpError::Other(err.to_string()))?;
+    let current_ip = Ipv4Cidr::parse_list(&previous_ips)
+        .0
+        .first()
+        .map_or("0.0.0.0".to_string(), |cidr| cidr.to_string());
 
     if !quiet {
         println!(
             "DHCP: MAC: {} Current IP: {}",
-            current_mac.to_string(),
-            current_ip.trim()
+            current_mac.to_string_dashed(),
+            current_ip
         );
     }
 
+    let lease = lease_path(iface);
+    // An address given on the command line wins over the one from the last boot
+    let request_ip = match request_ip {
+        Some(ip) => Some(ip),
+        None => read_lease(&lease).unwrap_or_else(|err| {
+            if !quiet {
+                println!("DHCP: Ignoring last lease: {}", err);
+            }
+            None
+        }),
+    };
+    let request_ip = request_ip.filter(|ip| !declined.contains(ip));
+
     let tid = try_fmt!(
         time::SystemTime::now().duration_since(time::UNIX_EPOCH),
         "failed to get time"
@@ -94,44 +164,41 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
             hops: 0,
             tid,
             secs: 0,
-            flags: 0x8000u16.to_be(),
+            flags: flags(broadcast),
             ciaddr: [0, 0, 0, 0],
             yiaddr: [0, 0, 0, 0],
             siaddr: [0, 0, 0, 0],
             giaddr: [0, 0, 0, 0],
-            chaddr: [
-                current_mac.bytes[0],
-                current_mac.bytes[1],
-                current_mac.bytes[2],
-                current_mac.bytes[3],
-                current_mac.bytes[4],
-                current_mac.bytes[5],
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-            ],
+            chaddr: chaddr(&current_mac),
             sname: [0; 64],
             file: [0; 128],
             magic: 0x63825363u32.to_be(),
             options: [0; 308],
         };
 
-        for (s, d) in [
+        let mut options = vec![
             // DHCP Message Type (Discover)
             53,
             1,
             1,
 
-            // End
-            255
-        ].iter().zip(discover.options.iter_mut()) {
+            // Parameter Request List (subnet, router, DNS, domain name, domain search)
+            55,
+            5,
+            1,
+            3,
+            6,
+            15,
+            119,
+        ];
+        if let Some(ip) = request_ip {
+            // Requested IP Address, only a preference at this point
+            options.extend_from_slice(&[50, 4, ip[0], ip[1], ip[2], ip[3]]);
+        }
+        // End
+        options.push(255);
+
+        for (s, d) in options.iter().zip(discover.options.iter_mut()) {
             *d = *s;
         }
 
@@ -145,12 +212,15 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
         let _sent = try_fmt!(socket.send(discover_data), "failed to send discover");
 
         if !quiet {
-            println!("DHCP: Sent Discover");
+            match request_ip {
+                Some(ip) => println!("DHCP: Sent Discover, requesting {}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
+                None => println!("DHCP: Sent Discover"),
+            }
         }
     }
 
     let mut offer_data = [0; 65536];
-    try_fmt!(socket.recv(&mut offer_data), "failed to receive offer");
+    try_fmt!(socket.recv(&mut offer_data), "failed to receive offer", DhcpError::NoReply);
     let offer = unsafe { &*(offer_data.as_ptr() as *const Dhcp) };
     if !quiet {
         println!(
@@ -158,11 +228,21 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
             offer.yiaddr, offer.siaddr
         );
     }
+    // The server is free to ignore the requested address, take what it offers
+    if let Some(ip) = request_ip {
+        if ip != offer.yiaddr && !quiet {
+            println!("DHCP: Requested IP {}.{}.{}.{} not offered, accepting {:?}",
+                     ip[0], ip[1], ip[2], ip[3], offer.yiaddr);
+        }
+    }
 
     {
         let mut subnet_option = None;
         let mut router_option = None;
         let mut dns_option = None;
+        let mut domain_option = None;
+        // Option 119 may be split over several instances that are concatenated before decoding
+        let mut search_data: Option<Vec<u8>> = None;
 
         let mut options = offer.options.iter();
         while let Some(option) = options.next() {
@@ -200,6 +280,11 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
                                     dns_option = Some(Vec::from(data));
                                 }
                             }
+                            15 => {
+                                if domain_option.is_none() {
+                                    domain_option = parse_domain_name(data);
+                                }
+                            }
                             51 => {
                                 if !quiet {
                                     println!("DHCP: Lease Time: {:?}", data);
@@ -215,6 +300,9 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
                                     println!("DHCP: Server ID: {:?}", data);
                                 }
                             }
+                            119 => {
+                                search_data.get_or_insert_with(Vec::new).extend_from_slice(data);
+                            }
                             _ => {
                                 if !quiet {
                                     println!("DHCP: {}: {:?}", option, data);
@@ -226,38 +314,46 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
             }
         }
 
-        let mask_len = if let Some(subnet) = subnet_option {
-            let mut subnet: u32 = (subnet[0] as u32) << 24 | (subnet[1] as u32) << 16 |
-                                  (subnet[2] as u32) << 8 | subnet[3] as u32;
-            subnet = !subnet;
-            subnet.leading_zeros()
-        } else {
-            0
-        };
+        let addr = Ipv4Addr { bytes: offer.yiaddr };
+        let netmask = subnet_option.and_then(|subnet| Ipv4Addr::from_bytes(&subnet));
+        // Without both a mask and a router there is no subnet to check the offer against
+        let router = router_option.as_ref().and_then(|router| Ipv4Addr::from_bytes(router));
+        if let (Some(netmask), Some(router)) = (netmask, router) {
+            try_fmt!(validate_offer(addr, netmask, router), "rejecting malformed offer");
+        }
+        let cidr = netmask
+            .and_then(|netmask| Ipv4Cidr::from_netmask(addr, netmask))
+            .unwrap_or_else(|| Ipv4Cidr::new(addr, 0));
 
-        let new_ips = format!("{}.{}.{}.{}/{}\n",
-                              offer.yiaddr[0], offer.yiaddr[1], offer.yiaddr[2], offer.yiaddr[3], mask_len);
+        let new_ips = format!("{}\n", cidr);
         try_fmt!(
-            set_iface_cfg_value(iface, "addr/set", &new_ips),
-            "failed to set ip"
+            cfg.set_iface(iface, "addr/set", &new_ips),
+            "failed to set ip",
+            DhcpError::Scheme
         );
 
         if !quiet {
-            let new_ip = try_fmt!(get_iface_cfg_value(iface, "addr/list"), "failed to get ip");
+            let new_ip = try_fmt!(cfg.get_iface(iface, "addr/list"), "failed to get ip");
             println!("DHCP: New IP: {}", new_ip.trim());
         }
 
-        if let Some(router) = router_option {
+        if keep_routes {
+            if let (Some(router), false) = (router_option, quiet) {
+                println!("DHCP: Ignoring Router {}.{}.{}.{}, keeping existing routes",
+                         router[0], router[1], router[2], router[3]);
+            }
+        } else if let Some(router) = router_option {
             let default_route = format!("default via {}.{}.{}.{}",
                                         router[0], router[1], router[2], router[3]);
 
             try_fmt!(
-                set_cfg_value("route/add", &default_route),
-                "failed to set default route"
+                cfg.set("route/add", &default_route),
+                "failed to set default route",
+                DhcpError::Scheme
             );
 
             if !quiet {
-                let new_router = try_fmt!(get_cfg_value("route/list"), "failed to get ip router");
+                let new_router = try_fmt!(cfg.get("route/list"), "failed to get ip router");
                 println!("DHCP: New Router: {}", new_router.trim());
             }
         }
@@ -275,15 +371,47 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
             let nameserver = format!("{}.{}.{}.{}", dns[0], dns[1], dns[2], dns[3]);
 
             try_fmt!(
-                set_cfg_value("resolv/nameserver", &nameserver),
-                "failed to set name server"
+                cfg.set("resolv/nameserver", &nameserver),
+                "failed to set name server",
+                DhcpError::Scheme
             );
 
             if !quiet {
-                let new_dns = try_fmt!(get_cfg_value("resolv/nameserver"), "failed to get dns");
+                let new_dns = try_fmt!(cfg.get("resolv/nameserver"), "failed to get dns");
                 println!("DHCP: New DNS: {}", new_dns.trim());
             }
         }
+
+        if let Some(domain) = domain_option {
+            try_fmt!(
+                cfg.set("resolv/domain", &domain),
+                "failed to set domain",
+                DhcpError::Scheme
+            );
+
+            if !quiet {
+                println!("DHCP: Domain Name: {}", domain);
+            }
+        }
+
+        if let Some(data) = search_data {
+            match parse_domain_search(&data) {
+                Some(search) => {
+                    try_fmt!(
+                        cfg.set("resolv/search", &search.join(" ")),
+                        "failed to set domain search list",
+                        DhcpError::Scheme
+                    );
+
+                    if !quiet {
+                        println!("DHCP: Domain Search: {}", search.join(" "));
+                    }
+                }
+                None => if !quiet {
+                    println!("DHCP: Ignoring malformed domain search option: {:?}", data);
+                },
+            }
+        }
     }
 
     {
@@ -294,29 +422,12 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
             hops: 0,
             tid,
             secs: 0,
-            flags: 0,
+            flags: flags(broadcast),
             ciaddr: [0; 4],
             yiaddr: [0; 4],
             siaddr: [0; 4],
             giaddr: [0; 4],
-            chaddr: [
-                current_mac.bytes[0],
-                current_mac.bytes[1],
-                current_mac.bytes[2],
-                current_mac.bytes[3],
-                current_mac.bytes[4],
-                current_mac.bytes[5],
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-            ],
+            chaddr: chaddr(&current_mac),
             sname: [0; 64],
             file: [0; 128],
             magic: 0x63825363u32.to_be(),
@@ -329,6 +440,15 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
             1,
             3,
 
+            // Parameter Request List (subnet, router, DNS, domain name, domain search)
+            55,
+            5,
+            1,
+            3,
+            6,
+            15,
+            119,
+
             // Requested IP Address
             50,
             4,
@@ -369,7 +489,7 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
 
     {
         let mut ack_data = [0; 65536];
-        try_fmt!(socket.recv(&mut ack_data), "failed to receive ack");
+        try_fmt!(socket.recv(&mut ack_data), "failed to receive ack", DhcpError::NoReply);
         let ack = unsafe { &*(ack_data.as_ptr() as *const Dhcp) };
         if !quiet {
             println!(
@@ -377,21 +497,102 @@ fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
                 ack.yiaddr, ack.siaddr
             );
         }
+
+        match ack.option(53) {
+            // DHCPACK
+            Some(&[5]) => {
+                if arp_probe {
+                    let addr = Ipv4Addr { bytes: ack.yiaddr };
+                    let owner = try_fmt!(probe(current_mac, addr), "failed to probe for the leased address");
+                    if let Some(owner) = owner {
+                        let server = ack.option(54)
+                            .and_then(Ipv4Addr::from_bytes)
+                            .map_or(ack.siaddr, |server| server.bytes);
+                        send_decline(&socket, tid, &current_mac, ack.yiaddr, server)?;
+                        declined.push(ack.yiaddr);
+                        try_fmt!(
+                            cfg.set_iface(iface, "addr/set", &previous_ips),
+                            "failed to restore the previous ip",
+                            DhcpError::Scheme
+                        );
+                        return Err(DhcpError::Conflict(format!(
+                            "{} is in use by {}, declined it",
+                            addr.to_string(),
+                            owner
+                        )));
+                    }
+                    if !quiet {
+                        println!("DHCP: No reply to the ARP probe for {}", addr.to_string());
+                    }
+                }
+
+                // Remember the address for the next boot
+                if let Err(err) = write_lease(&lease, ack.yiaddr) {
+                    if !quiet {
+                        println!("DHCP: Failed to save lease to {}: {}", lease.display(), err);
+                    }
+                }
+            }
+            Some(&[6]) => return Err(DhcpError::Nak),
+            // Anything but an ACK leaves the lease unconfirmed
+            other => return Err(DhcpError::Other(format!("unexpected reply to the request, message type {:?}", other))),
+        }
     }
 
     Ok(())
 }
 
+/// Run the exchange, starting over with a new discover after declining an
+/// address that turned out to be in use
+fn run(iface: &str, quiet: bool, keep_routes: bool, request_ip: Option<[u8; 4]>, arp_probe: bool, broadcast: bool) -> Result<(), DhcpError> {
+    let mut declined = Vec::new();
+    loop {
+        match dhcp(iface, quiet, keep_routes, request_ip, arp_probe, broadcast, &mut declined) {
+            Err(DhcpError::Conflict(ref err)) if declined.len() < MAX_DECLINES => {
+                if !quiet {
+                    println!("DHCP: {}, starting over in {} seconds", err, DECLINE_WAIT.as_secs());
+                }
+                thread::sleep(DECLINE_WAIT);
+            }
+            result => return result,
+        }
+    }
+}
+
 fn main() {
     let mut background = false;
     let mut quiet = false;
+    let mut keep_routes = false;
+    let mut request_ip = None;
+    // Off by default: probing reads and writes raw frames on the network
+    // scheme, which needs more privileges than the UDP exchange
+    let mut arp_probe = false;
+    // On by default: until it has an address the client may not receive the
+    // unicast replies servers send otherwise. Stacks that accept them can use
+    // --no-broadcast to keep the exchange off the other hosts of the link.
+    let mut broadcast = true;
     let iface = "eth0";
 
     //TODO: parse iface from the args
-    for arg in env::args().skip(1) {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
         match arg.as_ref() {
             "-b" => background = true,
             "-q" => quiet = true,
+            "--keep-routes" => keep_routes = true,
+            "--arp-probe" => arp_probe = true,
+            "--broadcast" => broadcast = true,
+            "--no-broadcast" => broadcast = false,
+            "--request-ip" => {
+                let addr = args.next().unwrap_or_default();
+                match parse_requested_ip(&addr) {
+                    Ok(ip) => request_ip = Some(ip),
+                    Err(err) => {
+                        writeln!(io::stderr(), "dhcpd: --request-ip: {}", err).unwrap();
+                        process::exit(EXIT_ERROR);
+                    }
+                }
+            }
             _ => (),
         }
     }
@@ -400,17 +601,17 @@ fn main() {
         redox_daemon::Daemon::new(move |daemon| {
             daemon.ready().expect("failed to signal readiness");
 
-            if let Err(err) = dhcp(iface, quiet) {
+            if let Err(err) = run(iface, quiet, keep_routes, request_ip, arp_probe, broadcast) {
                 writeln!(io::stderr(), "dhcpd: {}", err).unwrap();
-                process::exit(1);
+                process::exit(err.exit_code());
             }
             process::exit(0);
         }).expect("dhcpd: failed to daemonize");
     } else {
-        if let Err(err) = dhcp(iface, quiet) {
+        if let Err(err) = run(iface, quiet, keep_routes, request_ip, arp_probe, broadcast) {
             println!("Error {}", err);
             writeln!(io::stderr(), "dhcpd: {}", err).unwrap();
-            process::exit(1);
+            process::exit(err.exit_code());
         }
     }
 }
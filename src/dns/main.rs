@@ -1,14 +1,269 @@
-use std::{env, process};
-use std::io::{stderr, Write};
-use std::net::ToSocketAddrs;
+use std::{env, process, thread};
+use std::fs::File;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-fn main(){
-    if let Some(name) = env::args().nth(1) {
-        for addr in (name.as_str(), 0).to_socket_addrs().unwrap() {
-            println!("{}", addr.ip());
+extern crate netutils;
+
+use netutils::dns::{self, DnsMessage, RecordType, RCODE_NOERROR, RCODE_NXDOMAIN, RCODE_SERVFAIL};
+
+/// Exit codes, so scripts can tell a missing name from a broken resolver
+const EXIT_ERROR: i32 = 1;
+const EXIT_NXDOMAIN: i32 = 2;
+const EXIT_SERVFAIL: i32 = 3;
+const EXIT_TIMEOUT: i32 = 4;
+
+/// How long to wait for each server when no shorter --timeout is given
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const USAGE: &str = "dns [-t A|AAAA|MX|TXT|CNAME|NS|PTR] [--server ip[:port][,ip[:port]...]] [--timeout secs] hostname";
+
+fn fail(message: &str, code: i32) -> ! {
+    eprintln!("dns: {}", message);
+    process::exit(code);
+}
+
+/// Nameserver from the network configuration, falling back to resolv.conf
+fn system_nameserver() -> Option<String> {
+    let mut value = String::new();
+    if let Ok(mut file) = File::open("/scheme/netcfg/resolv/nameserver") {
+        if file.read_to_string(&mut value).is_ok() {
+            if let Some(line) = value.lines().map(|line| line.trim()).find(|line| !line.is_empty()) {
+                return Some(line.to_string());
+            }
         }
+    }
+
+    value.clear();
+    let mut file = File::open("/etc/resolv.conf").ok()?;
+    file.read_to_string(&mut value).ok()?;
+    value.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("nameserver"), Some(server)) => Some(server.to_string()),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Address of --server, port 53 unless given as `ip:port` or `[ipv6]:port`
+fn server_addr(server: &str) -> Result<SocketAddr, String> {
+    // A bare IPv6 address has colons too, so try it before host:port
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    let addr = if server.contains(':') {
+        server.to_socket_addrs()
     } else {
-        write!(stderr(), "dns: no hostname provided\n").unwrap();
-        process::exit(1);
+        (server, 53).to_socket_addrs()
+    };
+    addr.map_err(|e| format!("invalid server '{}': {}", server, e))?
+        .next()
+        .ok_or_else(|| format!("invalid server '{}'", server))
+}
+
+/// Parse the value of --timeout, a positive number of seconds
+fn parse_timeout(value: &str) -> Option<Duration> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+        _ => None,
+    }
+}
+
+/// Run `resolve` on a worker thread and give up on it after `timeout`.
+///
+/// A resolver that never answers leaves the thread blocked, which is fine
+/// since the process exits right after.
+fn with_timeout<T, F>(timeout: Option<Duration>, resolve: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Some(resolve()),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(resolve());
+    });
+    receiver.recv_timeout(timeout).ok()
+}
+
+/// Addresses of `name` from the system resolver
+fn lookup_host(name: &str) -> Result<Vec<IpAddr>, String> {
+    (name, 0).to_socket_addrs()
+        .map(|addrs| addrs.map(|addr| addr.ip()).collect())
+        .map_err(|e| format!("{}: {}", name, e))
+}
+
+fn query(name: &str, rtype: RecordType, server: &str, read_timeout: Duration) -> Result<DnsMessage, String> {
+    let server = server_addr(server)?;
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() as u16)
+        .unwrap_or(0);
+    let request = dns::build_query(id, name, rtype).map_err(|e| format!("{}: {}", e, name))?;
+
+    let local: IpAddr = if server.is_ipv6() { Ipv6Addr::UNSPECIFIED.into() } else { Ipv4Addr::UNSPECIFIED.into() };
+    let socket = UdpSocket::bind((local, 0)).map_err(|e| format!("failed to bind udp: {}", e))?;
+    socket.set_read_timeout(Some(read_timeout))
+        .map_err(|e| format!("failed to set read timeout: {}", e))?;
+    socket.send_to(&request, server).map_err(|e| format!("failed to send query: {}", e))?;
+
+    let mut buf = [0; 4096];
+    loop {
+        let (count, from) = socket.recv_from(&mut buf)
+            .map_err(|e| format!("no response from {}: {}", server, e))?;
+        // Ignore stray datagrams from other hosts or queries
+        if from != server {
+            continue;
+        }
+        let response = dns::parse_response(&buf[..count])
+            .map_err(|e| format!("invalid response from {}: {}", server, e))?;
+        if response.id() == id {
+            return Ok(response);
+        }
+    }
+}
+
+/// Ask the servers in order, moving on to the next one when a server does
+/// not answer or reports a failure. The last server's result is returned.
+fn query_servers(name: &str, rtype: RecordType, servers: &[String], read_timeout: Duration)
+    -> Result<DnsMessage, String>
+{
+    for (i, server) in servers.iter().enumerate() {
+        let last = i + 1 == servers.len();
+        match query(name, rtype, server, read_timeout) {
+            Ok(ref response) if response.rcode() == RCODE_SERVFAIL && !last => {
+                eprintln!("dns: {}: server failure from {}, trying the next server", name, server);
+            }
+            Err(ref err) if !last => {
+                eprintln!("dns: {}, trying the next server", err);
+            }
+            result => return result,
+        }
+    }
+    Err("no nameserver to ask".to_string())
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut name = None;
+    let mut rtype = None;
+    let mut servers: Vec<String> = Vec::new();
+    let mut timeout = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "-t" => match args.next().as_ref().and_then(|value| RecordType::from_name(value)) {
+                Some(value) => rtype = Some(value),
+                None => fail(&format!("-t requires one of A, AAAA, MX, TXT, CNAME, NS or PTR\n{}", USAGE), EXIT_ERROR),
+            },
+            "--server" => match args.next() {
+                // Repeated or comma separated, tried in the order given
+                Some(value) => servers.extend(value.split(',').filter(|s| !s.is_empty()).map(|s| s.to_string())),
+                None => fail(&format!("--server requires an address\n{}", USAGE), EXIT_ERROR),
+            },
+            "--timeout" => match args.next().as_ref().and_then(|value| parse_timeout(value)) {
+                Some(value) => timeout = Some(value),
+                None => fail(&format!("--timeout requires a positive number of seconds\n{}", USAGE), EXIT_ERROR),
+            },
+            _ => name = Some(arg),
+        }
+    }
+
+    let name = match name {
+        Some(name) => name,
+        None => fail("no hostname provided", EXIT_ERROR),
+    };
+
+    let timed_out = |timeout: Duration| -> ! {
+        fail(&format!("{}: timed out after {:.1} seconds", name, timeout.as_secs_f64()), EXIT_TIMEOUT)
+    };
+
+    // Plain `dns <name>` asks the system resolver for addresses
+    if rtype.is_none() && servers.is_empty() {
+        let lookup_name = name.clone();
+        match with_timeout(timeout, move || lookup_host(&lookup_name)) {
+            Some(Ok(addrs)) => for addr in addrs {
+                println!("{}", addr);
+            },
+            Some(Err(err)) => fail(&err, EXIT_ERROR),
+            None => timed_out(timeout.unwrap()),
+        }
+        return;
+    }
+
+    if servers.is_empty() {
+        match system_nameserver() {
+            Some(server) => servers.push(server),
+            None => fail("no nameserver configured, use --server", EXIT_ERROR),
+        }
+    }
+
+    let read_timeout = timeout.map_or(READ_TIMEOUT, |timeout| timeout.min(READ_TIMEOUT));
+    let query_name = name.clone();
+    let rtype = rtype.unwrap_or(RecordType::A);
+    let response = match with_timeout(timeout, move || query_servers(&query_name, rtype, &servers, read_timeout)) {
+        Some(Ok(response)) => response,
+        Some(Err(err)) => fail(&err, EXIT_ERROR),
+        None => timed_out(timeout.unwrap()),
+    };
+
+    if response.truncated() {
+        eprintln!("dns: response was truncated");
+    }
+
+    match response.rcode() {
+        RCODE_NOERROR => for record in response.answers {
+            println!("{}", record);
+        },
+        RCODE_NXDOMAIN => fail(&format!("{}: no such domain", name), EXIT_NXDOMAIN),
+        RCODE_SERVFAIL => fail(&format!("{}: server failure", name), EXIT_SERVFAIL),
+        rcode => fail(&format!("{}: query failed with rcode {}", name, rcode), EXIT_ERROR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timeout, server_addr, with_timeout};
+    use std::net::SocketAddr;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn timeouts() {
+        assert_eq!(parse_timeout("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_timeout("0.5"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_timeout("0"), None);
+        assert_eq!(parse_timeout("-1"), None);
+        assert_eq!(parse_timeout("inf"), None);
+        assert_eq!(parse_timeout("soon"), None);
+
+        assert_eq!(with_timeout(None, || 1), Some(1));
+        assert_eq!(with_timeout(Some(Duration::from_secs(5)), || 2), Some(2));
+        let hung = with_timeout(Some(Duration::from_millis(50)), || thread::sleep(Duration::from_secs(5)));
+        assert_eq!(hung, None);
+    }
+
+    #[test]
+    fn server_addrs() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        assert_eq!(server_addr("10.0.2.3"), Ok(addr("10.0.2.3:53")));
+        assert_eq!(server_addr("10.0.2.3:5353"), Ok(addr("10.0.2.3:5353")));
+        assert_eq!(server_addr("::1"), Ok(addr("[::1]:53")));
+        assert_eq!(server_addr("2001:db8::1"), Ok(addr("[2001:db8::1]:53")));
+        assert_eq!(server_addr("[2001:db8::1]:5353"), Ok(addr("[2001:db8::1]:5353")));
+        assert!(server_addr("10.0.2.3:dns").is_err());
     }
 }
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result};
+use hyper::header::{Authorization, Basic, Headers};
+
+pub const REALM: &str = "httpd";
+
+/// Username and password accepted by HTTP Basic authentication
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credential {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credential {
+    /// Parse `user:password`, the password may itself contain colons
+    pub fn parse(string: &str) -> Option<Credential> {
+        let mut parts = string.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(username), Some(password)) if !username.is_empty() => Some(Credential {
+                username: username.to_string(),
+                password: password.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Read a file of `user:password` lines, skipping blank lines and `#` comments
+    pub fn read_file(path: &str) -> Result<Vec<Credential>> {
+        let mut credentials = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Credential::parse(line) {
+                Some(credential) => credentials.push(credential),
+                None => eprintln!("httpd: ignoring malformed line in {}", path),
+            }
+        }
+        Ok(credentials)
+    }
+}
+
+/// Compare without returning early, so the time taken doesn't reveal the matching prefix
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = (a.len() != b.len()) as u8;
+    for i in 0..a.len().max(b.len()) {
+        let x = a.get(i).cloned().unwrap_or(0);
+        let y = b.get(i).cloned().unwrap_or(0);
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+/// Whether the request may be served, always true when no credentials are configured
+pub fn is_authorized(credentials: &[Credential], headers: &Headers) -> bool {
+    if credentials.is_empty() {
+        return true;
+    }
+
+    let basic = match headers.get::<Authorization<Basic>>() {
+        Some(authorization) => &authorization.0,
+        None => return false,
+    };
+    let password = basic.password.as_ref().map_or("", |password| password.as_str());
+
+    // Check every entry so the position of a match isn't observable either
+    credentials.iter().fold(false, |found, credential| {
+        let username = constant_time_eq(credential.username.as_bytes(), basic.username.as_bytes());
+        let password = constant_time_eq(credential.password.as_bytes(), password.as_bytes());
+        found | (username & password)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, is_authorized, Credential};
+    use hyper::header::Headers;
+
+    fn headers(authorization: &str) -> Headers {
+        let mut headers = Headers::new();
+        headers.set_raw("Authorization", vec![authorization.as_bytes().to_vec()]);
+        headers
+    }
+
+    #[test]
+    fn credential_parse() {
+        let credential = Credential::parse("user:pa:ss").unwrap();
+        assert_eq!(credential.username, "user");
+        assert_eq!(credential.password, "pa:ss");
+        assert_eq!(Credential::parse("user"), None);
+        assert_eq!(Credential::parse(":password"), None);
+    }
+
+    #[test]
+    fn compare() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"x"));
+    }
+
+    #[test]
+    fn authorization() {
+        let credentials = vec![Credential::parse("user:pass").unwrap()];
+
+        assert!(is_authorized(&[], &Headers::new()));
+        assert!(!is_authorized(&credentials, &Headers::new()));
+        // "user:pass" and "user:wrong" in base64
+        assert!(is_authorized(&credentials, &headers("Basic dXNlcjpwYXNz")));
+        assert!(!is_authorized(&credentials, &headers("Basic dXNlcjp3cm9uZw==")));
+        assert!(!is_authorized(&credentials, &headers("Bearer dXNlcjpwYXNz")));
+        assert!(!is_authorized(&credentials, &headers("Basic !!!")));
+    }
+}
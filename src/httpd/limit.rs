@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+use hyper::status::StatusCode;
+
+/// Clients tracked before idle ones with a full bucket are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+struct Client {
+    /// Requests from this address being served right now
+    active: usize,
+    /// Requests the client may still make at once, refilled over time
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Per address accounting for --max-conn-per-ip and --rate-limit.
+///
+/// hyper only hands over requests, not connections, so a connection counts
+/// while one of its requests is being served. A keep-alive connection waiting
+/// for the next request holds a worker thread but is not counted.
+pub struct Limiter {
+    max_active: Option<usize>,
+    /// Requests per second, which is also the burst a client may send at once
+    rate: Option<f64>,
+    clients: Mutex<HashMap<IpAddr, Client>>,
+}
+
+/// An admitted request, given back to the limiter when dropped
+pub struct Permit<'a> {
+    limiter: &'a Limiter,
+    ip: IpAddr,
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        self.limiter.release(self.ip, Instant::now());
+    }
+}
+
+impl Limiter {
+    pub fn new(max_active: Option<usize>, rate: Option<f64>) -> Self {
+        Limiter {
+            max_active,
+            rate,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn burst(&self) -> f64 {
+        self.rate.map_or(0.0, |rate| rate.max(1.0))
+    }
+
+    /// Add the tokens earned since the last refill, up to the burst
+    fn refill(&self, client: &mut Client, now: Instant) {
+        if let Some(rate) = self.rate {
+            let elapsed = now.saturating_duration_since(client.refilled).as_secs_f64();
+            client.tokens = (client.tokens + elapsed * rate).min(self.burst());
+            client.refilled = now;
+        }
+    }
+
+    /// Whether the entry would look the same if it were created afresh
+    fn is_idle(&self, client: &Client) -> bool {
+        client.active == 0 && (self.rate.is_none() || client.tokens >= self.burst())
+    }
+
+    /// Admit a request from `ip`, or the status to reject it with: 503 when
+    /// the address has too many requests in flight, 429 when it is over its rate
+    pub fn admit<'a>(&'a self, ip: IpAddr) -> Result<Permit<'a>, StatusCode> {
+        self.admit_at(ip, Instant::now())?;
+        Ok(Permit { limiter: self, ip })
+    }
+
+    fn admit_at(&self, ip: IpAddr, now: Instant) -> Result<(), StatusCode> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= PRUNE_THRESHOLD {
+            clients.retain(|_, client| {
+                self.refill(client, now);
+                !self.is_idle(client)
+            });
+        }
+
+        let burst = self.burst();
+        let client = clients.entry(ip).or_insert_with(|| Client { active: 0, tokens: burst, refilled: now });
+        self.refill(client, now);
+        if self.max_active.is_some_and(|max| client.active >= max) {
+            return Err(StatusCode::ServiceUnavailable);
+        }
+        if self.rate.is_some() {
+            if client.tokens < 1.0 {
+                return Err(StatusCode::TooManyRequests);
+            }
+            client.tokens -= 1.0;
+        }
+        client.active += 1;
+        Ok(())
+    }
+
+    fn release(&self, ip: IpAddr, now: Instant) {
+        let mut clients = self.clients.lock().unwrap();
+        let idle = match clients.get_mut(&ip) {
+            Some(client) => {
+                client.active = client.active.saturating_sub(1);
+                self.refill(client, now);
+                self.is_idle(client)
+            }
+            None => false,
+        };
+        if idle {
+            clients.remove(&ip);
+        }
+    }
+
+    #[cfg(test)]
+    fn tracked(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Limiter;
+    use hyper::status::StatusCode;
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn connections_per_ip() {
+        let limiter = Limiter::new(Some(2), None);
+        let a: IpAddr = "10.0.2.15".parse().unwrap();
+        let b: IpAddr = "10.0.2.16".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(limiter.admit_at(a, now), Ok(()));
+        assert_eq!(limiter.admit_at(a, now), Ok(()));
+        assert_eq!(limiter.admit_at(a, now), Err(StatusCode::ServiceUnavailable));
+        // Other clients are not affected
+        assert_eq!(limiter.admit_at(b, now), Ok(()));
+
+        limiter.release(a, now);
+        assert_eq!(limiter.admit_at(a, now), Ok(()));
+
+        // Entries go away once their connections close
+        limiter.release(a, now);
+        limiter.release(a, now);
+        limiter.release(b, now);
+        assert_eq!(limiter.tracked(), 0);
+
+        {
+            let _permit = limiter.admit(a).unwrap();
+            assert_eq!(limiter.tracked(), 1);
+        }
+        assert_eq!(limiter.tracked(), 0);
+    }
+
+    #[test]
+    fn token_bucket() {
+        let limiter = Limiter::new(None, Some(2.0));
+        let ip: IpAddr = "10.0.2.15".parse().unwrap();
+        let now = Instant::now();
+
+        for _ in 0..2 {
+            assert_eq!(limiter.admit_at(ip, now), Ok(()));
+            limiter.release(ip, now);
+        }
+        assert_eq!(limiter.admit_at(ip, now), Err(StatusCode::TooManyRequests));
+        // The entry is kept while the bucket refills
+        assert_eq!(limiter.tracked(), 1);
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.admit_at(ip, later), Ok(()));
+        assert_eq!(limiter.admit_at(ip, later), Err(StatusCode::TooManyRequests));
+
+        // Idle long enough to refill the burst, and then forgotten
+        limiter.release(ip, later + Duration::from_secs(1));
+        assert_eq!(limiter.tracked(), 0);
+    }
+
+    #[test]
+    fn slow_rates_still_allow_one_request() {
+        let limiter = Limiter::new(None, Some(0.5));
+        let ip: IpAddr = "10.0.2.15".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(limiter.admit_at(ip, now), Ok(()));
+        limiter.release(ip, now);
+        assert_eq!(limiter.admit_at(ip, now + Duration::from_secs(1)), Err(StatusCode::TooManyRequests));
+        assert_eq!(limiter.admit_at(ip, now + Duration::from_secs(2)), Ok(()));
+    }
+}
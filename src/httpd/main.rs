@@ -1,15 +1,87 @@
-#![cfg_attr(not(target_os = "redox"), feature(libc))]
-
+extern crate flate2;
 extern crate hyper;
+#[cfg(test)]
+extern crate netutils;
+extern crate rustls;
+extern crate time;
 
-use std::{env, str};
-use std::fs::{self, File};
-use std::io::{Error, ErrorKind, Result, Read, Write};
+use std::{env, mem, process, str};
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata};
+use std::io::{Error, ErrorKind, Result, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use hyper::method::Method;
+use hyper::net::NetworkListener;
 use hyper::server::{Server, Request, Response};
 use hyper::status::StatusCode;
 use hyper::uri::RequestUri::AbsolutePath;
-use hyper::header::{Headers, ContentType, ContentLength};
+use hyper::header::{Headers, AcceptEncoding, ContentEncoding, ContentType, ContentLength, Encoding, Range,
+                    AcceptRanges, ByteRangeSpec, ContentRange, ContentRangeSpec, RangeUnit,
+                    ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, IfRange, LastModified,
+                    AccessControlAllowMethods, AccessControlAllowOrigin, AccessControlMaxAge};
+
+use auth::Credential;
+use limit::Limiter;
+use mime::MimeTypes;
+use tls::Tls;
+
+mod auth;
+mod limit;
+mod mime;
+mod tls;
+
+/// Where the server listens, on another port when serving HTTPS
+const HTTP_ADDR: &str = "0.0.0.0:8080";
+const HTTPS_ADDR: &str = "0.0.0.0:8443";
+
+/// Keep-alive timeout hyper uses unless told otherwise
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+/// Largest body accepted by --upload-dir unless --max-upload says otherwise
+const DEFAULT_MAX_UPLOAD: u64 = 16 * 1024 * 1024;
+
+/// Files larger than this are sent with chunked encoding as they are read,
+/// smaller ones are read whole and sent with a Content-Length
+const STREAM_THRESHOLD: u64 = 1024 * 1024;
+
+/// Bytes read from a streamed file for each chunk
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Worker threads hyper starts by default, 5/4 of the available CPUs
+fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |cpus| cpus.get()) * 5 / 4
+}
+
+struct Config {
+    root: PathBuf,
+    compression: bool,
+    /// Basic authentication is required when not empty
+    credentials: Vec<Credential>,
+    /// Pages set with --error-page, served instead of the templates in the root
+    error_pages: BTreeMap<u16, PathBuf>,
+    /// Number of worker threads, which bounds the connections served at once
+    threads: usize,
+    /// How long an idle connection is kept open, or `None` to close after each response
+    keep_alive: Option<Duration>,
+    /// Origin allowed to make cross-origin requests, `*` for any
+    cors: Option<String>,
+    /// Directory receiving PUT and POST uploads, writes are refused when `None`
+    upload_dir: Option<PathBuf>,
+    /// Largest upload accepted, in bytes
+    max_upload: u64,
+    /// Serve the root index.html for missing routes of a single page app, set with --spa
+    spa: bool,
+    /// Requests one client address may have in flight, more get a 503
+    max_conn_per_ip: Option<usize>,
+    /// Requests per second allowed from one client address, more get a 429
+    rate_limit: Option<f64>,
+    /// Types from --mime-types, over the built-in ones
+    mime_types: MimeTypes,
+}
 
 fn read_dir(root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
     let mut names = vec![];
@@ -62,50 +134,289 @@ fn read_dir(root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
     Ok((headers, response.into_bytes()))
 }
 
-fn read_file(_root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
-    let mut file = File::open(path)?;
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or(0)
+}
 
-    let mut response = Vec::new();
-    file.read_to_end(&mut response)?;
+/// Weak validator built from the file size and modification time
+fn file_etag(len: u64, modified: i64) -> EntityTag {
+    EntityTag::weak(format!("{:x}-{:x}", len, modified))
+}
 
-    let extension = path.extension().map_or("", |ext_os| ext_os.to_str().unwrap_or(""));
-    let mime_type = match extension {
-        "css" => "text/css",
-        "html" => "text/html",
-        "js" => "text/javascript",
-        "jpg" | "jpeg" => "text/jpeg",
-        "png" => "image/png",
-        "svg" => "image/svg+xml",
-        _ => "text/plain"
-    };
+/// Whether the request's validators show the client already has this response
+fn is_not_modified(request: &Headers, response: &Headers) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since (RFC 7232 section 6)
+    if let Some(if_none_match) = request.get::<IfNoneMatch>() {
+        return match (if_none_match, response.get::<ETag>()) {
+            (IfNoneMatch::Any, Some(_)) => true,
+            (IfNoneMatch::Items(tags), Some(etag)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            (_, None) => false,
+        };
+    }
+
+    match (request.get::<IfModifiedSince>(), response.get::<LastModified>()) {
+        (Some(since), Some(modified)) => (modified.0).0.to_timespec() <= (since.0).0.to_timespec(),
+        _ => false,
+    }
+}
+
+/// Response body, in memory or still in the file for large files
+enum Body {
+    Buffered(Vec<u8>),
+    /// The next `len` bytes of the file, from its current position
+    Stream(File, u64),
+    /// Ranges of the file from its start, each after the header of its part,
+    /// then the closing boundary
+    Multipart(File, Vec<(Vec<u8>, u64, u64)>, Vec<u8>),
+}
+
+impl Body {
+    fn len(&self) -> u64 {
+        match *self {
+            Body::Buffered(ref data) => data.len() as u64,
+            Body::Stream(_, len) => len,
+            Body::Multipart(_, ref parts, ref closing) => {
+                let parts: u64 = parts.iter().map(|&(ref header, first, last)| header.len() as u64 + last - first + 1).sum();
+                parts + closing.len() as u64
+            }
+        }
+    }
+}
+
+/// Type and validators of a file
+fn file_headers(path: &Path, metadata: &Metadata, mime_types: &MimeTypes) -> Headers {
+    let mime_type = mime_types.lookup(path);
 
     let mut headers = Headers::new();
     headers.set(ContentType(mime_type.parse().unwrap()));
-    headers.set(ContentLength(response.len() as u64));
+    if let Ok(modified) = metadata.modified() {
+        let modified = unix_secs(modified);
+        headers.set(ETag(file_etag(metadata.len(), modified)));
+        headers.set(LastModified(HttpDate(time::at_utc(time::Timespec::new(modified, 0)))));
+    }
+    headers
+}
+
+fn read_file(_root: &Path, mime_types: &MimeTypes, path: &Path) -> Result<(Headers, Vec<u8>)> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
 
+    let mut response = Vec::new();
+    file.read_to_end(&mut response)?;
+
+    let mut headers = file_headers(path, &metadata, mime_types);
+    headers.set(ContentLength(response.len() as u64));
     Ok((headers, response))
 }
 
-fn read_path(root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
+/// Body of a file, streamed when it is too large to hold in memory
+fn file_body(root: &Path, mime_types: &MimeTypes, path: &Path) -> Result<(Headers, Body)> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let (mut headers, body) = if metadata.len() > STREAM_THRESHOLD {
+        // Without a Content-Length hyper uses chunked encoding
+        (file_headers(path, &metadata, mime_types), Body::Stream(file, metadata.len()))
+    } else {
+        let (headers, response) = read_file(root, mime_types, path)?;
+        (headers, Body::Buffered(response))
+    };
+    headers.set(AcceptRanges(vec![RangeUnit::Bytes]));
+    Ok((headers, body))
+}
+
+fn read_path(root: &Path, mime_types: &MimeTypes, path: &Path) -> Result<(Headers, Body)> {
     if path.is_dir() {
         let mut index_path = path.to_path_buf();
         index_path.push("index.html");
         if index_path.is_file() {
-            read_file(root, &index_path)
+            file_body(root, mime_types, &index_path)
         } else {
-            read_dir(root, path)
+            read_dir(root, path).map(|(headers, response)| (headers, Body::Buffered(response)))
         }
     } else {
-        read_file(root, path)
+        file_body(root, mime_types, path)
+    }
+}
+
+/// First and last byte of one range, for a body of `len` bytes, or `None`
+/// when it starts past the end
+fn byte_range(spec: &ByteRangeSpec, len: u64) -> Option<(u64, u64)> {
+    let (first, last) = match *spec {
+        ByteRangeSpec::FromTo(first, last) => (first, last.min(len.saturating_sub(1))),
+        ByteRangeSpec::AllFrom(first) => (first, len.saturating_sub(1)),
+        ByteRangeSpec::Last(0) => return None,
+        ByteRangeSpec::Last(count) => (len.saturating_sub(count), len.saturating_sub(1)),
+    };
+    if first >= len || first > last {
+        return None;
+    }
+    Some((first, last))
+}
+
+/// Ranges asked for by a Range header, for a body of `len` bytes
+///
+/// Ranges past the end are dropped, and the rest sorted with the ones that
+/// overlap or touch merged, as RFC 7233 section 4.1 allows. Returns `Ok(None)`
+/// to ignore the header and send everything, for units other than bytes, and
+/// `Err` when no range is left.
+fn byte_ranges(range: &Range, len: u64) -> ::std::result::Result<Option<Vec<(u64, u64)>>, ()> {
+    let specs = match *range {
+        Range::Bytes(ref specs) => specs,
+        _ => return Ok(None),
+    };
+    let mut ranges: Vec<(u64, u64)> = specs.iter().filter_map(|spec| byte_range(spec, len)).collect();
+    ranges.sort();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some(previous) if first <= previous.1.saturating_add(1) => previous.1 = previous.1.max(last),
+            _ => merged.push((first, last)),
+        }
+    }
+    if merged.is_empty() {
+        return Err(());
+    }
+    Ok(Some(merged))
+}
+
+/// Whether If-Range lets the Range header apply. When the client's copy is
+/// out of date it gets the whole body instead of parts of a different one.
+fn if_range_matches(request: &Headers, response: &Headers) -> bool {
+    match request.get::<IfRange>() {
+        None => true,
+        // A strong comparison, which the weak file validators never pass
+        Some(IfRange::EntityTag(tag)) => response.get::<ETag>().is_some_and(|etag| tag.strong_eq(etag)),
+        Some(IfRange::Date(date)) => response.get::<LastModified>().is_some_and(|modified| {
+            (modified.0).0.to_timespec() == date.0.to_timespec()
+        }),
+    }
+}
+
+/// Separator between the parts of a multipart/byteranges body
+fn multipart_boundary() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos());
+    format!("httpd-byteranges-{:x}", nanos)
+}
+
+/// Boundary and headers that come before a part of a multipart/byteranges body
+fn part_header(boundary: &str, content_type: Option<&str>, (first, last): (u64, u64), len: u64) -> Vec<u8> {
+    let mut header = format!("\r\n--{}\r\n", boundary);
+    if let Some(content_type) = content_type {
+        header.push_str(&format!("Content-Type: {}\r\n", content_type));
     }
+    header.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n\r\n", first, last, len));
+    header.into_bytes()
 }
 
-fn read_req(root: &Path, request: &Request) -> Result<(Headers, Vec<u8>)> {
+/// Cut the body down to the requested ranges, returning the status to send
+///
+/// A single range is sent as is, several as a multipart/byteranges body with
+/// a part for each.
+fn apply_range(request: &Headers, headers: &mut Headers, body: &mut Body) -> Result<StatusCode> {
+    let range = match request.get::<Range>() {
+        Some(range) if headers.has::<AcceptRanges>() && if_range_matches(request, headers) => range,
+        _ => return Ok(StatusCode::Ok),
+    };
+
+    let len = body.len();
+    let ranges = match byte_ranges(range, len) {
+        Ok(Some(ranges)) => ranges,
+        Ok(None) => return Ok(StatusCode::Ok),
+        Err(()) => {
+            headers.set(ContentRange(ContentRangeSpec::Bytes { range: None, instance_length: Some(len) }));
+            headers.set(ContentLength(0));
+            *body = Body::Buffered(Vec::new());
+            return Ok(StatusCode::RangeNotSatisfiable);
+        }
+    };
+
+    if let [(first, last)] = ranges[..] {
+        let part = last - first + 1;
+        match *body {
+            Body::Buffered(ref mut data) => {
+                data.truncate(last as usize + 1);
+                data.drain(..first as usize);
+                headers.set(ContentLength(part));
+            }
+            Body::Stream(ref mut file, ref mut remaining) => {
+                file.seek(SeekFrom::Current(first as i64))?;
+                *remaining = part;
+            }
+            Body::Multipart(..) => unreachable!("ranges are applied once"),
+        }
+        headers.set(ContentRange(ContentRangeSpec::Bytes { range: Some((first, last)), instance_length: Some(len) }));
+        return Ok(StatusCode::PartialContent);
+    }
+
+    let boundary = multipart_boundary();
+    let content_type = headers.get::<ContentType>().map(|content_type| content_type.to_string());
+    let parts: Vec<(Vec<u8>, u64, u64)> = ranges.iter()
+        .map(|&range| (part_header(&boundary, content_type.as_deref(), range, len), range.0, range.1))
+        .collect();
+    let closing = format!("\r\n--{}--\r\n", boundary).into_bytes();
+
+    *body = match mem::replace(body, Body::Buffered(Vec::new())) {
+        Body::Buffered(data) => {
+            let mut multipart = Vec::new();
+            for (header, first, last) in parts {
+                multipart.extend_from_slice(&header);
+                multipart.extend_from_slice(&data[first as usize..=last as usize]);
+            }
+            multipart.extend_from_slice(&closing);
+            Body::Buffered(multipart)
+        }
+        Body::Stream(file, _) => Body::Multipart(file, parts, closing),
+        Body::Multipart(..) => unreachable!("ranges are applied once"),
+    };
+    headers.set(ContentType(format!("multipart/byteranges; boundary={}", boundary).parse().unwrap()));
+    headers.set(ContentLength(body.len()));
+    Ok(StatusCode::PartialContent)
+}
+
+/// Whether compressing a body of this type is worth it
+fn is_compressible(mime_type: &str) -> bool {
+    let mime_type = mime_type.split(';').next().unwrap_or("").trim();
+    mime_type.starts_with("text/")
+        || mime_type == "application/json"
+        || mime_type == "application/javascript"
+        || mime_type == "image/svg+xml"
+}
+
+fn accepts_gzip(headers: &Headers) -> bool {
+    headers.get::<AcceptEncoding>().is_some_and(|accept| {
+        accept.iter().any(|item| item.item == Encoding::Gzip)
+    })
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Gzip the response body in place if the client accepts it and the type benefits
+fn compress(request: &Request, headers: &mut Headers, response: &mut Vec<u8>) -> Result<()> {
+    let compressible = headers.get::<ContentType>().is_some_and(|content_type| {
+        is_compressible(&content_type.0.to_string())
+    });
+
+    if compressible && accepts_gzip(&request.headers) && !request.headers.has::<Range>() {
+        *response = gzip(response)?;
+        headers.set(ContentEncoding(vec![Encoding::Gzip]));
+        headers.set(ContentLength(response.len() as u64));
+        headers.set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+    }
+
+    Ok(())
+}
+
+fn read_req(root: &Path, mime_types: &MimeTypes, request: &Request) -> Result<(Headers, Body)> {
     if let AbsolutePath(ref path) = request.uri {
         let mut full_path = root.to_path_buf();
-        full_path.push(path.trim_left_matches('/'));
+        full_path.push(path.trim_start_matches('/'));
         if full_path.as_path().strip_prefix(root).is_ok() {
-            read_path(root, &full_path)
+            read_path(root, mime_types, &full_path)
         } else {
             Err(Error::new(ErrorKind::InvalidInput, "Path is invalid"))
         }
@@ -114,59 +425,810 @@ fn read_req(root: &Path, request: &Request) -> Result<(Headers, Vec<u8>)> {
     }
 }
 
-fn http(root: PathBuf) {
-    Server::http("0.0.0.0:8080").unwrap().handle(move |req: Request, mut res: Response| {
-        match req.method {
-            hyper::Get => {
-                match read_req(&root, &req) {
-                    Ok((headers, response)) => {
-                        *res.headers_mut() = headers;
-                        res.start().unwrap().write(&response).unwrap();
-                    },
-                    Err(err) => {
-                        *res.status_mut() = match err.kind() {
-                            ErrorKind::NotFound => StatusCode::NotFound,
-                            ErrorKind::InvalidInput => StatusCode::BadRequest,
-                            _ => StatusCode::InternalServerError
-                        };
-
-                        write!(res.start().unwrap(), "{}", err);
-                    }
+/// Whether a request for a missing path should get the app's index.html.
+///
+/// Paths with an extension are assets that really are missing, `/api` is left
+/// to report its own errors, and anything trying to leave the root is refused.
+fn is_spa_route(path: &str) -> bool {
+    let path = path.split('?').next().unwrap_or("");
+    let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty() && *component != ".").collect();
+    if components.iter().any(|component| *component == ".." || component.contains(['\\', '\0'])) {
+        return false;
+    }
+    if components.first() == Some(&"api") {
+        return false;
+    }
+    components.last().is_none_or(|last| !last.contains('.'))
+}
+
+fn respond(config: &Config, request: &Request) -> Result<(StatusCode, Headers, Body)> {
+    let fallback = config.spa && match request.uri {
+        AbsolutePath(ref path) => is_spa_route(path),
+        _ => false,
+    };
+    let (mut headers, mut body) = match read_req(&config.root, &config.mime_types, request) {
+        Err(ref err) if fallback && err.kind() == ErrorKind::NotFound => {
+            file_body(&config.root, &config.mime_types, &config.root.join("index.html"))?
+        }
+        result => result?,
+    };
+
+    if is_not_modified(&request.headers, &headers) {
+        headers.remove::<ContentLength>();
+        return Ok((StatusCode::NotModified, headers, Body::Buffered(Vec::new())));
+    }
+
+    let status = apply_range(&request.headers, &mut headers, &mut body)?;
+
+    // Streamed files are sent as they are, compressing them would need the whole file
+    if let Body::Buffered(ref mut response) = body {
+        if config.compression && status == StatusCode::Ok {
+            compress(request, &mut headers, response)?;
+        }
+    }
+
+    Ok((status, headers, body))
+}
+
+/// Send the response, leaving out the body for HEAD requests
+fn send(mut res: Response, body: Body, head: bool) -> Result<()> {
+    if head {
+        // Announce the length a GET would get, instead of starting an empty chunked body
+        if let Body::Stream(_, len) = body {
+            res.headers_mut().set(ContentLength(len));
+        }
+        return res.start()?.end();
+    }
+
+    let mut res = res.start()?;
+    write_body(body, &mut res)?;
+    res.end()
+}
+
+/// Copy everything `reader` has to `out`, a chunk at a time
+fn copy_chunks<R: Read, W: Write>(mut reader: R, out: &mut W) -> Result<()> {
+    let mut buffer = vec![0; STREAM_CHUNK_SIZE];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(());
+        }
+        out.write_all(&buffer[..count])?;
+    }
+}
+
+fn write_body<W: Write>(body: Body, out: &mut W) -> Result<()> {
+    match body {
+        Body::Buffered(response) => out.write_all(&response),
+        Body::Stream(file, len) => copy_chunks(file.take(len), out),
+        Body::Multipart(mut file, parts, closing) => {
+            for (header, first, last) in parts {
+                out.write_all(&header)?;
+                file.seek(SeekFrom::Start(first))?;
+                copy_chunks((&mut file).take(last - first + 1), out)?;
+            }
+            out.write_all(&closing)
+        }
+    }
+}
+
+/// Template in the document root used for an error status, if any
+fn error_template(status: StatusCode) -> Option<&'static str> {
+    if status == StatusCode::NotFound {
+        Some("404.html")
+    } else if status.is_server_error() {
+        Some("50x.html")
+    } else {
+        None
+    }
+}
+
+/// Body for an error response, from an error page when one exists or the plain message otherwise
+fn error_response(config: &Config, status: StatusCode, message: &str) -> (Headers, Vec<u8>) {
+    let page = config.error_pages.get(&status.to_u16()).cloned()
+        .or_else(|| error_template(status).map(|name| config.root.join(name)))
+        .filter(|path| path.is_file());
+
+    if let Some(path) = page {
+        if let Ok((mut headers, response)) = read_file(&config.root, &config.mime_types, &path) {
+            // The validators describe the template, not the missing resource
+            headers.remove::<ETag>();
+            headers.remove::<LastModified>();
+            return (headers, response);
+        }
+    }
+
+    let response = message.as_bytes().to_vec();
+    let mut headers = Headers::new();
+    headers.set(ContentType("text/plain".parse().unwrap()));
+    headers.set(ContentLength(response.len() as u64));
+    (headers, response)
+}
+
+/// File under `dir` that an upload to the request path is written to, or
+/// `None` when the path names no file or contains `..`
+fn upload_path(dir: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.split('?').next().unwrap_or("");
+    if path.ends_with('/') {
+        return None;
+    }
+
+    let mut target = dir.to_path_buf();
+    let mut depth = 0;
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => return None,
+            // Separators and scheme prefixes of other platforms could escape the directory too
+            _ if component.contains(['\\', ':', '\0']) => return None,
+            _ => {
+                target.push(component);
+                depth += 1;
+            }
+        }
+    }
+
+    if depth > 0 {
+        Some(target)
+    } else {
+        None
+    }
+}
+
+/// Read a request body of at most `max` bytes, `None` when it is longer
+fn read_limited<R: Read>(body: R, max: u64) -> Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    body.take(max.saturating_add(1)).read_to_end(&mut data)?;
+    if data.len() as u64 > max {
+        Ok(None)
+    } else {
+        Ok(Some(data))
+    }
+}
+
+/// Store the body of a PUT or POST under the upload directory
+fn upload(config: &Config, dir: &Path, request: &mut Request) -> (StatusCode, Headers, Vec<u8>) {
+    let fail = |status: StatusCode, message: &str| {
+        let (headers, response) = error_response(config, status, message);
+        (status, headers, response)
+    };
+
+    let target = match request.uri {
+        AbsolutePath(ref path) => upload_path(dir, path),
+        _ => None
+    };
+    let target = match target {
+        Some(target) => target,
+        None => return fail(StatusCode::BadRequest, "Invalid upload path")
+    };
+
+    // Refuse early when the client announces the size
+    if request.headers.get::<ContentLength>().is_some_and(|length| length.0 > config.max_upload) {
+        return fail(StatusCode::PayloadTooLarge, "Upload too large");
+    }
+    let body = match read_limited(request, config.max_upload) {
+        Ok(Some(body)) => body,
+        Ok(None) => return fail(StatusCode::PayloadTooLarge, "Upload too large"),
+        Err(err) => return fail(StatusCode::BadRequest, &format!("{}", err))
+    };
+
+    let written = target.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| File::create(&target)?.write_all(&body));
+    if let Err(err) = written {
+        return fail(StatusCode::InternalServerError, &format!("{}", err));
+    }
+
+    let stored = target.strip_prefix(dir).unwrap_or(&target);
+    let response = format!("/{}\n", stored.display()).into_bytes();
+    let mut headers = Headers::new();
+    headers.set(ContentType("text/plain".parse().unwrap()));
+    headers.set(ContentLength(response.len() as u64));
+    (StatusCode::Created, headers, response)
+}
+
+fn http(config: Config, tls: Option<Tls>) {
+    match tls {
+        Some(tls) => serve(Server::https(HTTPS_ADDR, tls).unwrap(), config),
+        None => serve(Server::http(HTTP_ADDR).unwrap(), config),
+    }
+}
+
+fn serve<L: NetworkListener + Send + 'static>(mut server: Server<L>, config: Config) {
+    server.keep_alive(config.keep_alive);
+    let threads = config.threads;
+    let limiter = if config.max_conn_per_ip.is_some() || config.rate_limit.is_some() {
+        Some(Limiter::new(config.max_conn_per_ip, config.rate_limit))
+    } else {
+        None
+    };
+    server.handle_threads(move |mut req: Request, mut res: Response| {
+        // Browsers send preflights without credentials, so they skip authentication
+        let preflight = req.method == Method::Options && config.cors.is_some();
+        // Held until the response is sent, so the request counts as active until then
+        let permit = limiter.as_ref().map(|limiter| limiter.admit(req.remote_addr.ip()));
+
+        let head = req.method == Method::Head;
+        let (status, mut headers, body) = if let Some(Err(status)) = permit {
+            let (mut headers, response) = error_response(&config, status, "Too many requests from this address");
+            headers.set_raw("Retry-After", vec![b"1".to_vec()]);
+            (status, headers, Body::Buffered(response))
+        } else if !preflight && !auth::is_authorized(&config.credentials, &req.headers) {
+            let response = b"Unauthorized".to_vec();
+            let mut headers = Headers::new();
+            let challenge = format!("Basic realm=\"{}\"", auth::REALM);
+            headers.set_raw("WWW-Authenticate", vec![challenge.into_bytes()]);
+            headers.set(ContentLength(response.len() as u64));
+            (StatusCode::Unauthorized, headers, Body::Buffered(response))
+        } else if preflight {
+            (StatusCode::NoContent, preflight_headers(&req.headers, config.upload_dir.is_some()), Body::Buffered(Vec::new()))
+        } else if req.method == hyper::Get || head {
+            match respond(&config, &req) {
+                Ok(response) => response,
+                Err(err) => {
+                    let status = match err.kind() {
+                        ErrorKind::NotFound => StatusCode::NotFound,
+                        ErrorKind::InvalidInput => StatusCode::BadRequest,
+                        _ => StatusCode::InternalServerError
+                    };
+
+                    let (headers, response) = error_response(&config, status, &format!("{}", err));
+                    (status, headers, Body::Buffered(response))
                 }
             }
-            _ => *res.status_mut() = StatusCode::MethodNotAllowed
+        } else if req.method == Method::Put || req.method == Method::Post {
+            let (status, headers, response) = match config.upload_dir {
+                Some(ref dir) => upload(&config, dir, &mut req),
+                None => {
+                    let status = StatusCode::MethodNotAllowed;
+                    let (headers, response) = error_response(&config, status, "Method not allowed");
+                    (status, headers, response)
+                }
+            };
+            (status, headers, Body::Buffered(response))
+        } else {
+            let status = StatusCode::MethodNotAllowed;
+            let (headers, response) = error_response(&config, status, "Method not allowed");
+            (status, headers, Body::Buffered(response))
+        };
+
+        match config.cors.as_deref() {
+            Some("*") => headers.set(AccessControlAllowOrigin::Any),
+            Some(origin) => headers.set(AccessControlAllowOrigin::Value(origin.to_string())),
+            None => ()
         }
-    }).unwrap();
+
+        *res.status_mut() = status;
+        *res.headers_mut() = headers;
+        // Clients hanging up halfway through a download is nothing to report
+        let _ = send(res, body, head);
+    }, threads).unwrap();
 }
 
-#[cfg(target_os = "redox")]
-fn fork()  -> usize {
-    extern crate syscall;
-    unsafe { syscall::clone(0).unwrap() }
+/// Checks a --cors value, which is either `*` or a scheme, host and optional port
+fn parse_cors_origin(value: &str) -> Option<String> {
+    if value == "*" {
+        return Some(value.to_string());
+    }
+
+    let origin = value.trim_end_matches('/');
+    let rest = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://"))?;
+    let (host, port) = match rest.rfind(':') {
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None)
+    };
+    if host.is_empty() || host.contains(|c: char| c == '/' || c == '?' || c == '#' || c == '@' || c.is_whitespace()) {
+        return None;
+    }
+    if let Some(port) = port {
+        port.parse::<u16>().ok()?;
+    }
+
+    Some(origin.to_string())
 }
 
-#[cfg(not(target_os = "redox"))]
-fn fork()  -> usize {
-    extern crate libc;
-    unsafe { libc::fork() as usize }
+/// Headers answering a CORS preflight, allowing whatever request headers were asked for
+fn preflight_headers(request: &Headers, uploads: bool) -> Headers {
+    let mut headers = Headers::new();
+    let mut methods = vec![Method::Get, Method::Head, Method::Options];
+    if uploads {
+        methods.extend_from_slice(&[Method::Put, Method::Post]);
+    }
+    headers.set(AccessControlAllowMethods(methods));
+    headers.set(AccessControlMaxAge(86400));
+    if let Some(requested) = request.get_raw("Access-Control-Request-Headers") {
+        headers.set_raw("Access-Control-Allow-Headers", requested.to_vec());
+    }
+    headers
 }
 
 fn main() {
     let mut background = false;
-    let mut root = env::current_dir().unwrap();
-    for arg in env::args().skip(1) {
+    let mut config = Config {
+        root: env::current_dir().unwrap(),
+        compression: true,
+        credentials: Vec::new(),
+        error_pages: BTreeMap::new(),
+        threads: default_threads(),
+        keep_alive: Some(DEFAULT_KEEP_ALIVE),
+        cors: None,
+        upload_dir: None,
+        max_upload: DEFAULT_MAX_UPLOAD,
+        spa: false,
+        max_conn_per_ip: None,
+        rate_limit: None,
+        mime_types: MimeTypes::new(),
+    };
+    let mut tls_cert = None;
+    let mut tls_key = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
         match arg.as_ref() {
             "-b" => background = true,
-            _ => root = fs::canonicalize(arg).unwrap()
+            "--no-compression" => config.compression = false,
+            "--spa" => config.spa = true,
+            "--auth" => {
+                let value = args.next().expect("httpd: --auth requires user:password");
+                let credential = Credential::parse(&value).expect("httpd: --auth requires user:password");
+                config.credentials.push(credential);
+            },
+            "--auth-file" => {
+                let path = args.next().expect("httpd: --auth-file requires a path");
+                let credentials = Credential::read_file(&path).expect("httpd: failed to read --auth-file");
+                config.credentials.extend(credentials);
+            },
+            "--threads" => {
+                config.threads = args.next().and_then(|threads| threads.parse::<usize>().ok())
+                    .filter(|threads| *threads > 0)
+                    .expect("httpd: --threads requires a positive number");
+            },
+            "--keep-alive" => {
+                let secs = args.next().and_then(|secs| secs.parse::<u64>().ok())
+                    .filter(|secs| *secs > 0)
+                    .expect("httpd: --keep-alive requires a positive number of seconds");
+                config.keep_alive = Some(Duration::from_secs(secs));
+            },
+            "--no-keep-alive" => config.keep_alive = None,
+            "--cors" => {
+                let origin = args.next().and_then(|origin| parse_cors_origin(&origin))
+                    .expect("httpd: --cors requires '*' or an origin like https://example.com");
+                config.cors = Some(origin);
+            },
+            "--error-page" => {
+                let code = args.next().and_then(|code| code.parse::<u16>().ok())
+                    .filter(|code| *code >= 400 && *code < 600)
+                    .expect("httpd: --error-page requires a status code between 400 and 599");
+                let path = args.next().expect("httpd: --error-page requires a path");
+                config.error_pages.insert(code, fs::canonicalize(path).expect("httpd: failed to find --error-page"));
+            },
+            "--upload-dir" => {
+                let dir = args.next().expect("httpd: --upload-dir requires a directory");
+                fs::create_dir_all(&dir).expect("httpd: failed to create --upload-dir");
+                config.upload_dir = Some(fs::canonicalize(dir).expect("httpd: failed to find --upload-dir"));
+            },
+            "--max-upload" => {
+                config.max_upload = args.next().and_then(|bytes| bytes.parse::<u64>().ok())
+                    .expect("httpd: --max-upload requires a number of bytes");
+            },
+            "--max-conn-per-ip" => {
+                let max = args.next().and_then(|max| max.parse::<usize>().ok())
+                    .filter(|max| *max > 0)
+                    .expect("httpd: --max-conn-per-ip requires a positive number");
+                config.max_conn_per_ip = Some(max);
+            },
+            "--rate-limit" => {
+                let rate = args.next().and_then(|rate| rate.parse::<f64>().ok())
+                    .filter(|rate| *rate > 0.0 && rate.is_finite())
+                    .expect("httpd: --rate-limit requires a positive number of requests per second");
+                config.rate_limit = Some(rate);
+            },
+            "--mime-types" => {
+                let path = args.next().expect("httpd: --mime-types requires a path");
+                config.mime_types = MimeTypes::read_file(&path).unwrap_or_else(|err| {
+                    eprintln!("httpd: --mime-types: {}", err);
+                    process::exit(1);
+                });
+            },
+            "--tls-cert" => tls_cert = Some(args.next().expect("httpd: --tls-cert requires a path")),
+            "--tls-key" => tls_key = Some(args.next().expect("httpd: --tls-key requires a path")),
+            _ => config.root = fs::canonicalize(arg).unwrap()
         }
     }
 
-    println!("HTTP: {}", root.display());
-    if background {
-        if fork() == 0 {
-            http(root);
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => match tls::load(&cert, &key) {
+            Ok(tls) => Some(tls),
+            Err(err) => {
+                eprintln!("httpd: {}", err);
+                process::exit(1);
+            }
+        },
+        (None, None) => None,
+        _ => {
+            eprintln!("httpd: --tls-cert and --tls-key must be given together");
+            process::exit(1);
         }
+    };
+
+    println!("HTTP: {}", config.root.display());
+    match tls {
+        Some(_) => println!("HTTP: serving HTTPS on {}", HTTPS_ADDR),
+        None => println!("HTTP: serving on {}", HTTP_ADDR),
+    }
+    match config.keep_alive {
+        Some(timeout) => println!("HTTP: {} threads, keep-alive {}s", config.threads, timeout.as_secs()),
+        None => println!("HTTP: {} threads, keep-alive off", config.threads),
+    }
+    if let Some(ref origin) = config.cors {
+        println!("HTTP: allowing cross-origin requests from {}", origin);
+    }
+    if let Some(ref dir) = config.upload_dir {
+        println!("HTTP: accepting uploads of up to {} bytes into {}", config.max_upload, dir.display());
+    }
+    if config.spa {
+        println!("HTTP: serving index.html for missing routes");
+    }
+    if let Some(max) = config.max_conn_per_ip {
+        println!("HTTP: at most {} connections per client address", max);
+    }
+    if let Some(rate) = config.rate_limit {
+        println!("HTTP: at most {} requests per second per client address", rate);
+    }
+    if !config.mime_types.is_empty() {
+        println!("HTTP: {} extensions from --mime-types", config.mime_types.len());
+    }
+    if background {
+        redox_daemon::Daemon::new(move |daemon| {
+            daemon.ready().expect("failed to signal readiness");
+            http(config, tls);
+            process::exit(0);
+        }).expect("httpd: failed to daemonize");
     } else {
-        http(root);
+        http(config, tls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_range, byte_ranges, error_response, file_body, file_etag, is_compressible, is_not_modified,
+                is_spa_route, parse_cors_origin, preflight_headers, read_limited, time, upload_path, write_body, Body,
+                Config, STREAM_THRESHOLD};
+    use mime::MimeTypes;
+    use std::path::Path;
+    use hyper::status::StatusCode;
+    use std::collections::BTreeMap;
+    use netutils::tempdir::TempDir;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use hyper::header::{ByteRangeSpec, ContentLength, ContentType, EntityTag, ETag, Headers, HttpDate, IfModifiedSince,
+                        IfNoneMatch, IfRange, LastModified, Range};
+
+    fn http_date(secs: i64) -> HttpDate {
+        HttpDate(time::at_utc(time::Timespec::new(secs, 0)))
+    }
+
+    #[test]
+    fn compressible_types() {
+        assert!(is_compressible("text/html"));
+        assert!(is_compressible("text/plain; charset=utf-8"));
+        assert!(is_compressible("application/json"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("image/jpeg"));
+        assert!(!is_compressible("application/pdf"));
+    }
+
+    #[test]
+    fn etag_generation() {
+        let etag = file_etag(1024, 1500000000);
+        assert!(etag.weak);
+        assert_eq!(etag.tag(), "400-59682f00");
+        assert!(!etag.weak_eq(&file_etag(1025, 1500000000)));
+        assert!(!etag.weak_eq(&file_etag(1024, 1500000001)));
+    }
+
+    #[test]
+    fn conditional_match() {
+        let mut response = Headers::new();
+        response.set(ETag(file_etag(1024, 1500000000)));
+        response.set(LastModified(http_date(1500000000)));
+
+        let mut request = Headers::new();
+        assert!(!is_not_modified(&request, &response));
+
+        request.set(IfNoneMatch::Items(vec![file_etag(1024, 1500000000)]));
+        assert!(is_not_modified(&request, &response));
+        request.set(IfNoneMatch::Items(vec![EntityTag::strong("400-59682f00".to_string())]));
+        assert!(is_not_modified(&request, &response));
+        request.set(IfNoneMatch::Items(vec![file_etag(2048, 1500000000)]));
+        assert!(!is_not_modified(&request, &response));
+        request.set(IfNoneMatch::Any);
+        assert!(is_not_modified(&request, &response));
+
+        // An ETag mismatch wins over a matching date
+        request.set(IfNoneMatch::Items(vec![file_etag(2048, 1500000000)]));
+        request.set(IfModifiedSince(http_date(1500000000)));
+        assert!(!is_not_modified(&request, &response));
+
+        let mut request = Headers::new();
+        request.set(IfModifiedSince(http_date(1500000000)));
+        assert!(is_not_modified(&request, &response));
+        request.set(IfModifiedSince(http_date(1500000100)));
+        assert!(is_not_modified(&request, &response));
+        request.set(IfModifiedSince(http_date(1499999999)));
+        assert!(!is_not_modified(&request, &response));
+    }
+
+    #[test]
+    fn error_pages() {
+        let dir = TempDir::new("httpd-error-pages").unwrap();
+        let root = dir.path();
+        let mut config = Config {
+            root: root.to_path_buf(),
+            compression: true,
+            credentials: Vec::new(),
+            error_pages: BTreeMap::new(),
+            threads: 1,
+            keep_alive: None,
+            cors: None,
+            upload_dir: None,
+            max_upload: 0,
+            spa: false,
+            max_conn_per_ip: None,
+            rate_limit: None,
+            mime_types: MimeTypes::new(),
+        };
+
+        // Without templates the message is sent as plain text
+        let (headers, body) = error_response(&config, StatusCode::NotFound, "not here");
+        assert_eq!(headers.get_raw("Content-Type").unwrap()[0], b"text/plain".to_vec());
+        assert_eq!(body, b"not here".to_vec());
+
+        File::create(root.join("404.html")).unwrap().write_all(b"<h1>missing</h1>").unwrap();
+        File::create(root.join("50x.html")).unwrap().write_all(b"<h1>broken</h1>").unwrap();
+        File::create(root.join("gone.txt")).unwrap().write_all(b"gone").unwrap();
+
+        let (headers, body) = error_response(&config, StatusCode::NotFound, "not here");
+        assert_eq!(headers.get_raw("Content-Type").unwrap()[0], b"text/html".to_vec());
+        assert!(headers.get_raw("ETag").is_none());
+        assert_eq!(body, b"<h1>missing</h1>".to_vec());
+
+        let (_, body) = error_response(&config, StatusCode::ServiceUnavailable, "down");
+        assert_eq!(body, b"<h1>broken</h1>".to_vec());
+        let (_, body) = error_response(&config, StatusCode::BadRequest, "bad");
+        assert_eq!(body, b"bad".to_vec());
+
+        config.error_pages.insert(404, root.join("gone.txt"));
+        let (headers, body) = error_response(&config, StatusCode::NotFound, "not here");
+        assert_eq!(headers.get_raw("Content-Type").unwrap()[0], b"text/plain".to_vec());
+        assert_eq!(body, b"gone".to_vec());
+    }
+
+    #[test]
+    fn cors_origins() {
+        assert_eq!(parse_cors_origin("*"), Some("*".to_string()));
+        assert_eq!(parse_cors_origin("https://example.com"), Some("https://example.com".to_string()));
+        assert_eq!(parse_cors_origin("http://localhost:3000/"), Some("http://localhost:3000".to_string()));
+        assert_eq!(parse_cors_origin("example.com"), None);
+        assert_eq!(parse_cors_origin("ftp://example.com"), None);
+        assert_eq!(parse_cors_origin("https://"), None);
+        assert_eq!(parse_cors_origin("https://example.com/app"), None);
+        assert_eq!(parse_cors_origin("https://example.com:http"), None);
+    }
+
+    #[test]
+    fn cors_preflight() {
+        let mut request = Headers::new();
+        let headers = preflight_headers(&request, false);
+        assert_eq!(headers.get_raw("Access-Control-Allow-Methods").unwrap()[0], b"GET, HEAD, OPTIONS".to_vec());
+        assert_eq!(headers.get_raw("Access-Control-Max-Age").unwrap()[0], b"86400".to_vec());
+        assert!(headers.get_raw("Access-Control-Allow-Headers").is_none());
+
+        request.set_raw("Access-Control-Request-Headers", vec![b"Authorization, X-Custom".to_vec()]);
+        let headers = preflight_headers(&request, true);
+        assert_eq!(headers.get_raw("Access-Control-Allow-Methods").unwrap()[0], b"GET, HEAD, OPTIONS, PUT, POST".to_vec());
+        assert_eq!(headers.get_raw("Access-Control-Allow-Headers").unwrap()[0], b"Authorization, X-Custom".to_vec());
+    }
+
+    #[test]
+    fn upload_paths() {
+        let dir = Path::new("/srv/uploads");
+        assert_eq!(upload_path(dir, "/a.txt"), Some(dir.join("a.txt")));
+        assert_eq!(upload_path(dir, "/logs/./today//b.log?overwrite=1"), Some(dir.join("logs/today/b.log")));
+        assert_eq!(upload_path(dir, "/"), None);
+        assert_eq!(upload_path(dir, "/logs/"), None);
+        assert_eq!(upload_path(dir, "/../etc/passwd"), None);
+        assert_eq!(upload_path(dir, "/a/../../b"), None);
+        assert_eq!(upload_path(dir, "/..\\b"), None);
+        assert_eq!(upload_path(dir, "/file:/etc/passwd"), None);
+    }
+
+    #[test]
+    fn spa_routes() {
+        assert!(is_spa_route("/"));
+        assert!(is_spa_route("/users/42"));
+        assert!(is_spa_route("/settings/profile/?tab=keys"));
+        assert!(is_spa_route("/docs/./intro"));
+        assert!(!is_spa_route("/logo.png"));
+        assert!(!is_spa_route("/static/js/app.3f2a.js?v=1"));
+        assert!(!is_spa_route("/api"));
+        assert!(!is_spa_route("/api/users/42"));
+        assert!(!is_spa_route("/../etc/passwd"));
+        assert!(!is_spa_route("/users/../../secret"));
+        assert!(!is_spa_route("/..\\windows"));
+        // Only the last component decides whether it is an asset
+        assert!(is_spa_route("/v1.2/changelog"));
+        assert!(is_spa_route("/apis"));
+    }
+
+    #[test]
+    fn upload_size_limit() {
+        assert_eq!(read_limited(&b"12345"[..], 5).unwrap(), Some(b"12345".to_vec()));
+        assert_eq!(read_limited(&b"123456"[..], 5).unwrap(), None);
+        assert_eq!(read_limited(&b""[..], 0).unwrap(), Some(Vec::new()));
+        assert_eq!(read_limited(&b"1"[..], 0).unwrap(), None);
+        assert_eq!(read_limited(&b"12"[..], u64::MAX).unwrap(), Some(b"12".to_vec()));
+    }
+
+    #[test]
+    fn range_lists() {
+        let bytes = |specs| Range::Bytes(specs);
+        let single = |spec| byte_ranges(&Range::Bytes(vec![spec]), 1000);
+        assert_eq!(single(ByteRangeSpec::FromTo(0, 99)), Ok(Some(vec![(0, 99)])));
+        assert_eq!(single(ByteRangeSpec::FromTo(900, 5000)), Ok(Some(vec![(900, 999)])));
+        assert_eq!(single(ByteRangeSpec::AllFrom(10)), Ok(Some(vec![(10, 999)])));
+        assert_eq!(single(ByteRangeSpec::Last(100)), Ok(Some(vec![(900, 999)])));
+        assert_eq!(single(ByteRangeSpec::Last(5000)), Ok(Some(vec![(0, 999)])));
+        assert_eq!(single(ByteRangeSpec::AllFrom(1000)), Err(()));
+        assert_eq!(single(ByteRangeSpec::Last(0)), Err(()));
+        assert_eq!(byte_ranges(&bytes(vec![ByteRangeSpec::Last(1)]), 0), Err(()));
+        assert_eq!(byte_ranges(&Range::Unregistered("lines".to_string(), "1-2".to_string()), 1000), Ok(None));
+
+        // Several ranges are sorted, with overlapping and adjacent ones merged
+        let several = |specs| byte_ranges(&bytes(specs), 1000);
+        assert_eq!(several(vec![ByteRangeSpec::FromTo(0, 1), ByteRangeSpec::FromTo(5, 6)]), Ok(Some(vec![(0, 1), (5, 6)])));
+        assert_eq!(
+            several(vec![ByteRangeSpec::FromTo(500, 599), ByteRangeSpec::FromTo(0, 99), ByteRangeSpec::FromTo(50, 149)]),
+            Ok(Some(vec![(0, 149), (500, 599)]))
+        );
+        assert_eq!(several(vec![ByteRangeSpec::FromTo(0, 99), ByteRangeSpec::FromTo(100, 199)]), Ok(Some(vec![(0, 199)])));
+        assert_eq!(several(vec![ByteRangeSpec::FromTo(10, 20), ByteRangeSpec::FromTo(12, 15)]), Ok(Some(vec![(10, 20)])));
+        assert_eq!(several(vec![ByteRangeSpec::Last(10), ByteRangeSpec::AllFrom(995)]), Ok(Some(vec![(990, 999)])));
+        // Unsatisfiable ranges are dropped, and only matter when nothing is left
+        assert_eq!(several(vec![ByteRangeSpec::AllFrom(2000), ByteRangeSpec::FromTo(0, 0)]), Ok(Some(vec![(0, 0)])));
+        assert_eq!(several(vec![ByteRangeSpec::AllFrom(2000), ByteRangeSpec::Last(0)]), Err(()));
+    }
+
+    /// Boundary from a multipart/byteranges Content-Type
+    fn boundary_of(headers: &Headers) -> String {
+        let content_type = headers.get::<ContentType>().unwrap().to_string();
+        let (kind, boundary) = content_type.split_at(content_type.find(';').unwrap());
+        assert_eq!(kind, "multipart/byteranges");
+        boundary.trim_start_matches("; boundary=").to_string()
+    }
+
+    #[test]
+    fn multipart_ranges() {
+        let dir = TempDir::new("httpd-multipart-ranges").unwrap();
+        let root = dir.path();
+        let data: Vec<u8> = (0..STREAM_THRESHOLD + 10).map(|i| i as u8).collect();
+        File::create(root.join("large.bin")).unwrap().write_all(&data).unwrap();
+        File::create(root.join("small.txt")).unwrap().write_all(b"0123456789").unwrap();
+
+        let mut request = Headers::new();
+        request.set(Range::Bytes(vec![ByteRangeSpec::FromTo(0, 1), ByteRangeSpec::FromTo(4, 5)]));
+        let (mut headers, mut body) = file_body(root, &MimeTypes::new(), &root.join("small.txt")).unwrap();
+        assert_eq!(apply_range(&request, &mut headers, &mut body).unwrap(), StatusCode::PartialContent);
+        assert!(headers.get_raw("Content-Range").is_none());
+        let boundary = boundary_of(&headers);
+        let expected = format!(
+            "\r\n--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 4-5/10\r\n\r\n45\
+             \r\n--{0}--\r\n",
+            boundary
+        );
+        assert_eq!(headers.get::<ContentLength>(), Some(&ContentLength(expected.len() as u64)));
+        let mut sent = Vec::new();
+        write_body(body, &mut sent).unwrap();
+        assert_eq!(String::from_utf8(sent).unwrap(), expected);
+
+        // Parts of a streamed file are read as they are sent
+        let far = STREAM_THRESHOLD + 8;
+        request.set(Range::Bytes(vec![ByteRangeSpec::FromTo(far, far + 5), ByteRangeSpec::FromTo(1, 2)]));
+        let (mut headers, mut body) = file_body(root, &MimeTypes::new(), &root.join("large.bin")).unwrap();
+        assert_eq!(apply_range(&request, &mut headers, &mut body).unwrap(), StatusCode::PartialContent);
+        assert!(matches!(body, Body::Multipart(..)));
+        let boundary = boundary_of(&headers);
+        let mut expected = format!(
+            "\r\n--{0}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 1-2/{1}\r\n\r\n",
+            boundary,
+            data.len()
+        ).into_bytes();
+        expected.extend_from_slice(&data[1..3]);
+        expected.extend_from_slice(format!(
+            "\r\n--{0}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {1}-{2}/{3}\r\n\r\n",
+            boundary,
+            far,
+            data.len() - 1,
+            data.len()
+        ).as_bytes());
+        expected.extend_from_slice(&data[far as usize..]);
+        expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        assert_eq!(body.len(), expected.len() as u64);
+        assert_eq!(headers.get::<ContentLength>(), Some(&ContentLength(expected.len() as u64)));
+        let mut sent = Vec::new();
+        write_body(body, &mut sent).unwrap();
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn if_range() {
+        let dir = TempDir::new("httpd-if-range").unwrap();
+        let root = dir.path();
+        File::create(root.join("small.txt")).unwrap().write_all(b"0123456789").unwrap();
+        let path = root.join("small.txt");
+        let (headers, _) = file_body(root, &MimeTypes::new(), &path).unwrap();
+        let modified = headers.get::<LastModified>().unwrap().0;
+
+        let status = |if_range: IfRange| {
+            let mut request = Headers::new();
+            request.set(Range::Bytes(vec![ByteRangeSpec::FromTo(0, 1)]));
+            request.set(if_range);
+            let (mut headers, mut body) = file_body(root, &MimeTypes::new(), &path).unwrap();
+            let status = apply_range(&request, &mut headers, &mut body).unwrap();
+            assert_eq!(body.len(), if status == StatusCode::Ok { 10 } else { 2 });
+            status
+        };
+        assert_eq!(status(IfRange::Date(modified)), StatusCode::PartialContent);
+        let earlier = http_date((modified.0).to_timespec().sec - 60);
+        assert_eq!(status(IfRange::Date(earlier)), StatusCode::Ok);
+        // The file validators are weak, and If-Range only accepts a strong match
+        let etag = headers.get::<ETag>().unwrap().0.clone();
+        assert_eq!(status(IfRange::EntityTag(etag.clone())), StatusCode::Ok);
+        assert_eq!(status(IfRange::EntityTag(EntityTag::strong(etag.tag().to_string()))), StatusCode::Ok);
+    }
+
+    #[test]
+    fn streamed_files() {
+        let dir = TempDir::new("httpd-streamed-files").unwrap();
+        let root = dir.path();
+        let data: Vec<u8> = (0..STREAM_THRESHOLD + 10).map(|i| i as u8).collect();
+        File::create(root.join("large.bin")).unwrap().write_all(&data).unwrap();
+        File::create(root.join("small.txt")).unwrap().write_all(b"0123456789").unwrap();
+
+        // Small files keep their Content-Length, large ones are left to chunked encoding
+        let (headers, body) = file_body(root, &MimeTypes::new(), &root.join("small.txt")).unwrap();
+        assert_eq!(headers.get::<ContentLength>(), Some(&ContentLength(10)));
+        assert!(match body { Body::Buffered(ref data) => data == b"0123456789", _ => false });
+        let (headers, body) = file_body(root, &MimeTypes::new(), &root.join("large.bin")).unwrap();
+        assert!(headers.get::<ContentLength>().is_none());
+        assert_eq!(headers.get_raw("Accept-Ranges").unwrap()[0], b"bytes".to_vec());
+        assert_eq!(body.len(), data.len() as u64);
+
+        let mut request = Headers::new();
+        request.set(Range::Bytes(vec![ByteRangeSpec::Last(4)]));
+        let (mut headers, mut body) = file_body(root, &MimeTypes::new(), &root.join("small.txt")).unwrap();
+        assert_eq!(apply_range(&request, &mut headers, &mut body).unwrap(), StatusCode::PartialContent);
+        assert_eq!(headers.get_raw("Content-Range").unwrap()[0], b"bytes 6-9/10".to_vec());
+        assert_eq!(headers.get::<ContentLength>(), Some(&ContentLength(4)));
+        assert!(match body { Body::Buffered(ref data) => data == b"6789", _ => false });
+
+        request.set(Range::Bytes(vec![ByteRangeSpec::FromTo(STREAM_THRESHOLD, STREAM_THRESHOLD + 99)]));
+        let (mut headers, mut body) = file_body(root, &MimeTypes::new(), &root.join("large.bin")).unwrap();
+        assert_eq!(apply_range(&request, &mut headers, &mut body).unwrap(), StatusCode::PartialContent);
+        match body {
+            Body::Stream(file, len) => {
+                assert_eq!(len, 10);
+                let mut rest = Vec::new();
+                file.take(len).read_to_end(&mut rest).unwrap();
+                assert_eq!(rest, &data[STREAM_THRESHOLD as usize..]);
+            }
+            _ => panic!("large file was not streamed"),
+        }
+
+        request.set(Range::Bytes(vec![ByteRangeSpec::AllFrom(10)]));
+        let (mut headers, mut body) = file_body(root, &MimeTypes::new(), &root.join("small.txt")).unwrap();
+        assert_eq!(apply_range(&request, &mut headers, &mut body).unwrap(), StatusCode::RangeNotSatisfiable);
+        assert_eq!(headers.get_raw("Content-Range").unwrap()[0], b"bytes */10".to_vec());
+        assert_eq!(body.len(), 0);
     }
 }
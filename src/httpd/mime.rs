@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use hyper::mime::Mime;
+
+/// Sent for extensions nobody knows, telling the client to treat the file as opaque bytes
+pub const DEFAULT_TYPE: &str = "application/octet-stream";
+
+/// Types httpd knows without a --mime-types file, by lowercase extension
+fn builtin(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "css" => "text/css",
+        "html" | "htm" => "text/html",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "txt" => "text/plain",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        _ => return None,
+    })
+}
+
+/// Content types by extension, from --mime-types, consulted before the built-in ones
+#[derive(Default)]
+pub struct MimeTypes {
+    types: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    pub fn new() -> MimeTypes {
+        MimeTypes::default()
+    }
+
+    /// Parse apache style `type ext ext...` lines. Blank lines, `#` comments
+    /// and types without extensions are skipped, and a later line wins when
+    /// an extension is listed twice. A type that is not a valid MIME type is
+    /// an error, naming its line.
+    pub fn parse<I: IntoIterator<Item = S>, S: AsRef<str>>(lines: I) -> Result<MimeTypes, String> {
+        let mut types = HashMap::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            let line = line.as_ref().split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            if let Some(mime_type) = words.next() {
+                if mime_type.parse::<Mime>().is_err() {
+                    return Err(format!("line {}: invalid MIME type '{}'", i + 1, mime_type));
+                }
+                for extension in words {
+                    types.insert(extension.to_lowercase(), mime_type.to_string());
+                }
+            }
+        }
+        Ok(MimeTypes { types })
+    }
+
+    /// Read a mime.types file
+    pub fn read_file(path: &str) -> Result<MimeTypes, String> {
+        let lines = File::open(path)
+            .and_then(|file| BufReader::new(file).lines().collect::<Result<Vec<String>, _>>())
+            .map_err(|err| format!("{}: {}", path, err))?;
+        MimeTypes::parse(lines).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Content type of `path`, ignoring the case of its extension
+    pub fn lookup(&self, path: &Path) -> &str {
+        let extension = match path.extension().and_then(|ext_os| ext_os.to_str()) {
+            Some(extension) => extension.to_lowercase(),
+            None => return DEFAULT_TYPE,
+        };
+        self.types.get(&extension).map(String::as_str)
+            .or_else(|| builtin(&extension))
+            .unwrap_or(DEFAULT_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MimeTypes, DEFAULT_TYPE};
+    use std::path::Path;
+
+    #[test]
+    fn parse_file() {
+        let types = MimeTypes::parse(vec![
+            "# MIME type            Extensions",
+            "",
+            "text/markdown          md markdown",
+            "application/x-empty",
+            "font/woff2\twoff2   # web fonts",
+            "text/x-old             MD",
+        ]).unwrap();
+        assert_eq!(types.len(), 3);
+        assert_eq!(types.lookup(Path::new("notes.markdown")), "text/markdown");
+        assert_eq!(types.lookup(Path::new("font.woff2")), "font/woff2");
+        // Extensions are stored lowercase, the later line wins
+        assert_eq!(types.lookup(Path::new("README.md")), "text/x-old");
+    }
+
+    #[test]
+    fn lookup_precedence() {
+        let types = MimeTypes::parse(vec!["text/x-c c h", "text/html-custom html"]).unwrap();
+        assert_eq!(types.lookup(Path::new("main.C")), "text/x-c");
+        // The file overrides the built-in table, which fills in the rest
+        assert_eq!(types.lookup(Path::new("index.html")), "text/html-custom");
+        assert_eq!(types.lookup(Path::new("style.CSS")), "text/css");
+        assert_eq!(MimeTypes::new().lookup(Path::new("index.HTML")), "text/html");
+        assert_eq!(types.lookup(Path::new("archive.xyz")), DEFAULT_TYPE);
+        assert_eq!(types.lookup(Path::new("Makefile")), DEFAULT_TYPE);
+    }
+
+    #[test]
+    fn invalid_types() {
+        let err = MimeTypes::parse(vec!["text/plain txt", "", "text md"]).err().unwrap();
+        assert_eq!(err, "line 3: invalid MIME type 'text'");
+        assert!(MimeTypes::parse(vec!["/markdown md"]).is_err());
+        // Types are checked even when they list no extension
+        assert!(MimeTypes::parse(vec!["bogus"]).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::net::{HttpStream, NetworkStream, SslServer};
+use rustls::crypto;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+/// How long a client gets to finish the handshake, so one that stalls does
+/// not hold a worker thread until the keep-alive timeout applies
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Problems with --tls-cert and --tls-key, reported before the server starts
+#[derive(Debug)]
+pub enum TlsError {
+    Cert(String, String),
+    Key(String, String),
+    Config(String),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TlsError::Cert(ref path, ref err) => write!(f, "failed to read certificate {}: {}", path, err),
+            TlsError::Key(ref path, ref err) => write!(f, "failed to read private key {}: {}", path, err),
+            TlsError::Config(ref err) => write!(f, "certificate and key do not make a TLS configuration: {}", err),
+        }
+    }
+}
+
+/// Read the certificate chain and private key, both PEM, into a server configuration
+pub fn load(cert_path: &str, key_path: &str) -> Result<Tls, TlsError> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| TlsError::Cert(cert_path.to_string(), err.to_string()))?;
+    if certs.is_empty() {
+        return Err(TlsError::Cert(cert_path.to_string(), "no certificates found".to_string()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|err| TlsError::Key(key_path.to_string(), err.to_string()))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|err| TlsError::Config(err.to_string()))?;
+    Ok(Tls { config: Arc::new(config) })
+}
+
+/// Wraps accepted connections in TLS, for `Server::https`
+#[derive(Clone)]
+pub struct Tls {
+    config: Arc<ServerConfig>,
+}
+
+impl SslServer for Tls {
+    type Stream = TlsStream;
+
+    fn wrap_server(&self, mut stream: HttpStream) -> hyper::Result<TlsStream> {
+        let mut session = ServerConnection::new(self.config.clone())
+            .map_err(|err| hyper::Error::Ssl(Box::new(err)))?;
+
+        // Handshake here so a failed one drops the connection before hyper sees it
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        while session.is_handshaking() {
+            session.complete_io(&mut stream.0)?;
+        }
+
+        Ok(TlsStream {
+            inner: Arc::new(Mutex::new(StreamOwned::new(session, stream))),
+        })
+    }
+}
+
+/// A TLS connection hyper can clone into its reader and writer halves.
+///
+/// hyper only reads and writes from the thread handling the connection, one
+/// after the other, so the lock is never contended.
+#[derive(Clone)]
+pub struct TlsStream {
+    inner: Arc<Mutex<StreamOwned<ServerConnection, HttpStream>>>,
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl NetworkStream for TlsStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.lock().unwrap().sock.peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.lock().unwrap().sock.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.lock().unwrap().sock.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        let mut stream = self.inner.lock().unwrap();
+        if how != Shutdown::Read {
+            stream.conn.send_close_notify();
+            // The client may already be gone, the socket is shut down either way
+            let _ = stream.flush();
+        }
+        stream.sock.close(how)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, TlsError};
+    use netutils::tempdir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn load_errors() {
+        let temp = TempDir::new("httpd-tls").unwrap();
+        let dir = temp.path();
+        let cert = dir.join("cert.pem");
+        let key = dir.join("key.pem");
+        fs::write(&cert, "not a certificate\n").unwrap();
+        fs::write(&key, "").unwrap();
+        let (cert, key) = (cert.to_str().unwrap(), key.to_str().unwrap());
+
+        match load(cert, key) {
+            Err(TlsError::Cert(path, err)) => {
+                assert_eq!(path, cert);
+                assert_eq!(err, "no certificates found");
+            }
+            _ => panic!("expected a certificate error"),
+        }
+        match load(dir.join("missing.pem").to_str().unwrap(), key) {
+            Err(err @ TlsError::Cert(..)) => assert!(err.to_string().starts_with("failed to read certificate")),
+            _ => panic!("expected a certificate error"),
+        }
+    }
+}
@@ -1,11 +1,12 @@
 /// interface.rs
 /// handle interface-related logic for the ifconfig utility on Redox OS.
+use netutils::{Ipv4Cidr, MacAddr, NetCfg, NetCfgError};
 use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
-use std::fs;
 use std::net::IpAddr;
-use std::path::Path;
 
 /// Custom error type for interface operations
 #[derive(Debug)]
@@ -32,12 +33,34 @@ impl fmt::Display for InterfaceError {
 /// Implement the Error trait for InterfaceError
 impl Error for InterfaceError {}
 
+/// Convert netcfg errors, keeping missing entries apart from failed reads
+impl From<NetCfgError> for InterfaceError {
+    fn from(err: NetCfgError) -> Self {
+        match err {
+            NetCfgError::NotFound(_) => InterfaceError::NotFound(err.to_string()),
+            _ => InterfaceError::ReadError(err.to_string()),
+        }
+    }
+}
+
 /// Structure to represent a network interface
+///
+/// Serialized for `--json`, where field names follow the output rather than the struct
+#[derive(Serialize)]
 pub struct NetworkInterface {
     pub name: String,
-    pub mac_address: String,
+    /// Colon form of the hardware address, `None` when the interface has none
+    /// or netcfg could not give it. Left out of the JSON rather than made up.
+    #[serde(rename = "mac", skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<String>,
+    #[serde(rename = "ip")]
     pub ip_address: String,
     pub netmask: String,
+    /// Every address in addr/list, in CIDR notation
+    pub addresses: Vec<String>,
+    /// Counters of the stats entry under the names netcfg gives them, `None`
+    /// when netcfg has no stats for the interface
+    pub stats: Option<BTreeMap<String, u64>>,
     // Additional fields can be added here
 }
 
@@ -52,11 +75,16 @@ impl NetworkInterface {
         }
 
         // Get IP address and netmask from addr/list
-        let addr_data = get_iface_cfg_value(iface, "addr/list")?;
+        let cfg = NetCfg::new();
+        let addr_data = cfg.get_iface(iface, "addr/list")?;
         let (ip_address, netmask) = parse_ip_and_netmask(&addr_data)?;
+        let addresses = parse_addresses(&addr_data);
 
-        // Placeholder for MAC address (not available in this structure)
-        let mac_address = "00:00:00:00:00:00".to_string();
+        // netcfg gives the dashed form, shown like every other MAC in colon form
+        let mac_address = cfg.get_iface(iface, "mac").ok()
+            .and_then(|mac| mac.parse::<MacAddr>().ok())
+            .map(|mac| mac.to_string());
+        let stats = cfg.get_iface(iface, "stats").ok().map(|data| parse_stats(&data));
 
         // Create the NetworkInterface instance
         Ok(NetworkInterface {
@@ -64,70 +92,94 @@ impl NetworkInterface {
             mac_address,
             ip_address,
             netmask,
+            addresses,
+            stats,
         })
     }
+
+    /// Flags shown by `-s`, derived from the configured address
+    ///
+    /// There is no UP flag: netcfg does not say whether the link is up.
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = Vec::new();
+        if self.ip_address.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()) {
+            flags.push("LOOPBACK");
+        }
+        flags
+    }
+
+    /// One row of the `-s` table
+    pub fn short_line(&self) -> String {
+        let line = format!(
+            "{:<10} {:<18} {}",
+            self.name,
+            format!("{}/{}", self.ip_address, self.netmask),
+            self.flags().join(",")
+        );
+        line.trim_end().to_string()
+    }
 }
 
 /// Implement Display trait for NetworkInterface to format output
 impl fmt::Display for NetworkInterface {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{}:", self.name)?;
-        writeln!(f, "    MAC Address: {}", self.mac_address)?;
+        if let Some(ref mac) = self.mac_address {
+            writeln!(f, "    MAC Address: {}", mac)?;
+        }
         writeln!(f, "    IP Address: {}", self.ip_address)?;
         writeln!(f, "    Netmask: {}", self.netmask)
     }
 }
 
-/// Parses IP address and netmask from a string
+/// Parses IP address and prefix length from the first line of addr/list (e.g., "10.0.2.15/24")
 fn parse_ip_and_netmask(addr_data: &str) -> Result<(String, String), InterfaceError> {
-    // Split the address and netmask (e.g., "10.0.2.15/24")
-    let parts: Vec<&str> = addr_data.split('/').collect();
-    if parts.len() != 2 {
-        return Err(InterfaceError::InvalidIpAddress(addr_data.to_string()));
-    }
-    let ip_address = parts[0].to_string();
-    let netmask = parts[1].to_string();
-    Ok((ip_address, netmask))
+    let line = addr_data.lines().next().unwrap_or("").trim();
+    let cidr = line
+        .parse::<Ipv4Cidr>()
+        .map_err(|_| InterfaceError::InvalidIpAddress(line.to_string()))?;
+    Ok((cidr.addr.to_string(), cidr.prefix.to_string()))
 }
 
-/// Reads the value of a configuration file for a given interface
-fn get_iface_cfg_value(iface: &str, cfg: &str) -> Result<String, InterfaceError> {
-    let base_path = Path::new("/scheme/netcfg/ifaces").join(iface).join(cfg);
-
-    if !base_path.exists() {
-        return Err(InterfaceError::NotFound(format!(
-            "Path does not exist: {}",
-            base_path.display()
-        )));
-    }
+/// All addresses of addr/list, skipping lines that are not valid CIDR blocks
+fn parse_addresses(addr_data: &str) -> Vec<String> {
+    addr_data
+        .lines()
+        .filter_map(|line| line.trim().parse::<Ipv4Cidr>().ok())
+        .map(|cidr| format!("{}/{}", cidr.addr.to_string(), cidr.prefix))
+        .collect()
+}
 
-    fs::read_to_string(&base_path)
-        .map(|s| s.trim().to_string())
-        .map_err(|e| {
-            InterfaceError::ReadError(format!("Failed to read {}: {}", base_path.display(), e))
+/// Counters of a stats entry, one `name value` pair per line, skipping
+/// malformed lines
+fn parse_stats(stats_data: &str) -> BTreeMap<String, u64> {
+    stats_data
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next().and_then(|value| value.parse().ok()), parts.next()) {
+                (Some(name), Some(value), None) => Some((name.to_string(), value)),
+                _ => None,
+            }
         })
+        .collect()
 }
 
 /// Lists all available network interfaces
 pub fn list_all_interfaces() -> Result<Vec<NetworkInterface>, InterfaceError> {
-    let path = Path::new("/scheme/netcfg/ifaces");
-    if !path.exists() {
-        return Ok(vec![]); // Return an empty list if no interfaces directory exists
-    }
-
-    let entries = fs::read_dir(path)
-        .map_err(|e| InterfaceError::ReadError(format!("Failed to read interfaces: {}", e)))?;
+    let names = match NetCfg::new().list_ifaces() {
+        Ok(names) => names,
+        // Return an empty list if no interfaces directory exists
+        Err(NetCfgError::NotFound(_)) => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
 
     let mut interfaces = Vec::new();
-    for entry in entries {
-        let entry =
-            entry.map_err(|e| InterfaceError::ReadError(format!("Failed to read entry: {}", e)))?;
-        if let Some(iface_name) = entry.file_name().to_str() {
-            // Try to create a NetworkInterface instance
-            match NetworkInterface::new(iface_name) {
-                Ok(interface) => interfaces.push(interface),
-                Err(e) => eprintln!("Skipping interface '{}': {}", iface_name, e),
-            }
+    for iface_name in names {
+        // Try to create a NetworkInterface instance
+        match NetworkInterface::new(&iface_name) {
+            Ok(interface) => interfaces.push(interface),
+            Err(e) => eprintln!("Skipping interface '{}': {}", iface_name, e),
         }
     }
     Ok(interfaces)
@@ -186,4 +238,60 @@ mod tests {
         assert!(validate_ip_address("999.999.999.999").is_err());
         assert!(validate_ip_address("::1").is_ok()); // IPv6 loopback
     }
+
+    #[test]
+    fn test_parse_ip_and_netmask() {
+        let (ip, netmask) = parse_ip_and_netmask("10.0.2.15/24\n192.168.1.2/16\n").unwrap();
+        assert_eq!((ip.as_str(), netmask.as_str()), ("10.0.2.15", "24"));
+        assert!(parse_ip_and_netmask("10.0.2.15").is_err());
+        assert!(parse_ip_and_netmask("10.0.2.15/40").is_err());
+        assert!(parse_ip_and_netmask("").is_err());
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let stats = parse_stats("rx_packets 12\ntx_bytes 3400\nbogus\nrx_errors -1\ntx_packets 1 2\n");
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats["rx_packets"], 12);
+        assert_eq!(stats["tx_bytes"], 3400);
+        assert!(parse_stats("").is_empty());
+    }
+
+    #[test]
+    fn test_json_output() {
+        let interface = NetworkInterface {
+            name: "eth0".to_string(),
+            mac_address: Some("52:54:00:12:34:56".to_string()),
+            ip_address: "10.0.2.15".to_string(),
+            netmask: "24".to_string(),
+            addresses: parse_addresses("10.0.2.15/24\nbogus\n192.168.1.2/16\n"),
+            stats: Some(parse_stats("tx_packets 7\nrx_packets 12\n")),
+        };
+        assert_eq!(
+            serde_json::to_string(&interface).unwrap(),
+            concat!(
+                r#"{"name":"eth0","mac":"52:54:00:12:34:56","ip":"10.0.2.15","netmask":"24","#,
+                r#""addresses":["10.0.2.15/24","192.168.1.2/16"],"stats":{"rx_packets":12,"tx_packets":7}}"#
+            )
+        );
+        assert!(interface.flags().is_empty());
+        assert_eq!(interface.short_line(), "eth0       10.0.2.15/24");
+
+        let list = serde_json::to_string(&[interface]).unwrap();
+        assert!(list.starts_with(r#"[{"name":"eth0","#) && list.ends_with("}}]"));
+
+        let loopback = NetworkInterface {
+            name: "lo \"x\"".to_string(),
+            mac_address: None,
+            ip_address: "127.0.0.1".to_string(),
+            netmask: "8".to_string(),
+            addresses: parse_addresses("127.0.0.1/8"),
+            stats: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&loopback).unwrap(),
+            r#"{"name":"lo \"x\"","ip":"127.0.0.1","netmask":"8","addresses":["127.0.0.1/8"],"stats":null}"#
+        );
+        assert_eq!(loopback.flags(), ["LOOPBACK"]);
+    }
 }
@@ -14,6 +14,8 @@ The program supports the following options:
 
 * `-h` or `--help`: Prints the help message and exits.
 * `-a`: Shows information about all available interfaces.
+* `-s`: Prints one line per interface with its name, address and flags.
+* `--json`: Prints the interfaces as JSON.
 
 **Limitations:**
 
@@ -24,8 +26,12 @@ The program supports the following options:
 
 
 
+extern crate netutils;
 extern crate regex;
+extern crate serde;
+extern crate serde_json;
 use std::env;
+use std::process;
 
 mod interface;
 use interface::*; // Module to handle interface-related logic
@@ -36,7 +42,7 @@ NAME
     ifconfig - Configure or display network interfaces
 
 SYNOPSIS
-    ifconfig [-h | --help] [-a] interface
+    ifconfig [-h | --help] [-a] [-s | --json] interface
 
 DESCRIPTION//! ## ifconfig Utility for Redox OS
 
@@ -71,6 +77,12 @@ OPTIONS
         Print this manual page.
     -a
         Display information about all available interfaces in the system.
+    -s
+        Print a terse table with one line per interface: name, address and flags.
+    --json
+        Print the interfaces as JSON: an object for a single interface, an
+        array otherwise. "stats" holds the counters netcfg keeps for the
+        interface, or null when it keeps none.
         interface
         This parameter is a string of the form "name unit", for example "eth0".
 
@@ -78,10 +90,47 @@ AUTHOR
     Written by G. Gielly.
 "#; /* @MANEND */
 
+/// How the interfaces are printed
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Verbose,
+    Short,
+    Json,
+}
+
+/// Print `interfaces` in `format`, `single` when a name was given on the command line
+fn print_interfaces(interfaces: &[NetworkInterface], format: Format, single: bool) {
+    match format {
+        Format::Verbose => {
+            if interfaces.is_empty() {
+                println!("No interfaces found.");
+            }
+            for interface in interfaces {
+                println!("{}", interface);
+                if !single {
+                    println!(); // Add an empty line between interfaces
+                }
+            }
+        }
+        Format::Short => {
+            for interface in interfaces {
+                println!("{}", interface.short_line());
+            }
+        }
+        Format::Json => {
+            let json = match interfaces {
+                [interface] if single => serde_json::to_string(interface),
+                _ => serde_json::to_string(interfaces),
+            };
+            println!("{}", json.expect("interfaces should serialize to JSON"));
+        }
+    }
+}
+
 fn main() {
     // Collect command-line arguments, skipping the program name
     let mut args = env::args().skip(1);
-    let mut show_all = false;
+    let mut format = Format::Verbose;
     let mut interface_name = None;
 
     // Parse command-line arguments
@@ -93,8 +142,15 @@ fn main() {
                 return;
             }
             "-a" => {
-                // Set flag to show all interfaces
-                show_all = true;
+                // All interfaces are shown unless one is named
+            }
+            "-s" | "--json" => {
+                let requested = if arg == "-s" { Format::Short } else { Format::Json };
+                if format != Format::Verbose && format != requested {
+                    eprintln!("-s and --json cannot be used together");
+                    process::exit(1);
+                }
+                format = requested;
             }
             _ => {
                 // Capture the interface name if provided
@@ -109,49 +165,16 @@ fn main() {
         }
     }
 
-    // Determine behavior based on parsed arguments
-    if show_all {
-        if let Some(name) = interface_name {
-            // Display details for the specific interface if it exists
-            match NetworkInterface::new(&name) {
-                Ok(interface) => println!("{}", interface),
-                Err(_) => eprintln!("Error: Interface '{}' not found.", name),
-            }
-        } else {
-            // Display all interfaces if no specific name is provided
-            match list_all_interfaces() {
-                Ok(interfaces) => {
-                    if interfaces.is_empty() {
-                        println!("No interfaces found.");
-                    } else {
-                        for interface in interfaces {
-                            println!("{}", interface);
-                            println!(); // Add an empty line between interfaces
-                        }
-                    }
-                }
-                Err(e) => eprintln!("Error listing interfaces: {}", e),
-            }
-        }
-    } else if let Some(name) = interface_name {
-        // Show details for a specific interface without `-a`
+    if let Some(name) = interface_name {
+        // Show details for a specific interface
         match NetworkInterface::new(&name) {
-            Ok(interface) => println!("{}", interface),
+            Ok(interface) => print_interfaces(&[interface], format, true),
             Err(_) => eprintln!("Error: Interface '{}' not found.", name),
         }
     } else {
-        // Default behavior: Show all interfaces if no arguments are provided
+        // Show all interfaces, with or without `-a`
         match list_all_interfaces() {
-            Ok(interfaces) => {
-                if interfaces.is_empty() {
-                    println!("No interfaces found.");
-                } else {
-                    for interface in interfaces {
-                        println!("{}", interface);
-                        println!(); // Add an empty line between interfaces
-                    }
-                }
-            }
+            Ok(interfaces) => print_interfaces(&interfaces, format, false),
             Err(e) => eprintln!("Error listing interfaces: {}", e),
         }
     }
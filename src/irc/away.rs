@@ -0,0 +1,95 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct State {
+    /// Set with /away, which auto-away leaves alone
+    manual: bool,
+    /// Set by the idle timer and cleared by the next line typed
+    auto: bool,
+    last_input: Instant,
+}
+
+/// Away status shared by the stdin thread and the --auto-away timer
+pub struct Away {
+    state: Mutex<State>,
+}
+
+impl Away {
+    pub fn new(now: Instant) -> Self {
+        Away {
+            state: Mutex::new(State {
+                manual: false,
+                auto: false,
+                last_input: now,
+            }),
+        }
+    }
+
+    /// Note a line from the user, true when an automatic away has to be cleared
+    pub fn on_input(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.last_input = now;
+        let was_auto = state.auto;
+        state.auto = false;
+        was_auto
+    }
+
+    /// Record an explicit /away, with a message or without one to come back
+    pub fn set_manual(&self, away: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.manual = away;
+        state.auto = false;
+    }
+
+    /// Whether the user has just been idle for `idle`, so AWAY should be sent
+    pub fn check_idle(&self, idle: Duration, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.manual || state.auto || now.saturating_duration_since(state.last_input) < idle {
+            return false;
+        }
+        state.auto = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Away;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn auto_away() {
+        let idle = Duration::from_secs(600);
+        let start = Instant::now();
+        let away = Away::new(start);
+
+        assert!(!away.check_idle(idle, start + Duration::from_secs(599)));
+        assert!(away.check_idle(idle, start + idle));
+        // Sent once, not at every tick
+        assert!(!away.check_idle(idle, start + idle * 2));
+
+        let typed = start + idle * 3;
+        assert!(away.on_input(typed));
+        assert!(!away.on_input(typed));
+        assert!(!away.check_idle(idle, typed + Duration::from_secs(1)));
+        assert!(away.check_idle(idle, typed + idle));
+    }
+
+    #[test]
+    fn manual_away_wins() {
+        let idle = Duration::from_secs(60);
+        let start = Instant::now();
+        let away = Away::new(start);
+
+        away.set_manual(true);
+        assert!(!away.check_idle(idle, start + idle * 10));
+        // Typing does not undo an explicit /away
+        assert!(!away.on_input(start + idle * 11));
+
+        away.set_manual(false);
+        assert!(away.check_idle(idle, start + idle * 12));
+        // An explicit /away replaces the automatic one
+        away.set_manual(true);
+        assert!(!away.on_input(start + idle * 13));
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use log::timestamp;
+
+/// Marks the start and end of a CTCP message inside a PRIVMSG or NOTICE
+const DELIM: char = '\x01';
+
+/// Answer to VERSION requests
+const VERSION: &str = concat!("Redox irc ", env!("CARGO_PKG_VERSION"));
+
+/// Split a CTCP message into its command, uppercased, and its arguments.
+/// Returns `None` for ordinary text. The closing delimiter is optional, as
+/// some clients leave it out.
+pub fn parse(text: &str) -> Option<(String, &str)> {
+    let body = text.strip_prefix(DELIM)?;
+    let body = body.strip_suffix(DELIM).unwrap_or(body);
+    let (command, args) = match body.find(' ') {
+        Some(i) => (&body[..i], &body[i + 1..]),
+        None => (body, ""),
+    };
+    if command.is_empty() {
+        return None;
+    }
+    Some((command.to_ascii_uppercase(), args))
+}
+
+/// Wrap `command` and its arguments in CTCP delimiters
+pub fn format(command: &str, args: &str) -> String {
+    if args.is_empty() {
+        format!("{}{}{}", DELIM, command, DELIM)
+    } else {
+        format!("{}{} {}{}", DELIM, command, args, DELIM)
+    }
+}
+
+/// The NOTICE body answering a request, `now` being the time since the Unix
+/// epoch. Requests we do not know, and ACTION, get no answer.
+pub fn reply(command: &str, args: &str, now: Duration) -> Option<String> {
+    match command {
+        "VERSION" => Some(format("VERSION", VERSION)),
+        // Echo the argument, which the sender uses to time the round trip
+        "PING" => Some(format("PING", args)),
+        "TIME" => Some(format("TIME", &format!("{} UTC", timestamp(now.as_secs())))),
+        _ => None,
+    }
+}
+
+/// Arguments to send with a request from /ctcp, a PING carrying the time it
+/// was sent in milliseconds
+pub fn request_args(command: &str, now: Duration) -> String {
+    if command == "PING" {
+        now.as_millis().to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Readable text for a reply from `source`, with the round trip time for a
+/// PING sent by /ctcp
+pub fn render_reply(source: &str, command: &str, args: &str, now: Duration) -> String {
+    if command == "PING" {
+        if let Some(sent) = args.trim().parse::<u64>().ok().map(Duration::from_millis) {
+            if let Some(rtt) = now.checked_sub(sent) {
+                return format!("CTCP PING reply from {}: {:.3} seconds", source, rtt.as_secs_f64());
+            }
+        }
+    }
+    format!("CTCP {} reply from {}: {}", command, source, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, parse, render_reply, reply, request_args};
+    use std::time::Duration;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(format("VERSION", ""), "\x01VERSION\x01");
+        assert_eq!(parse(&format("VERSION", "")), Some(("VERSION".to_string(), "")));
+        let action = format("ACTION", "waves at everyone");
+        assert_eq!(action, "\x01ACTION waves at everyone\x01");
+        assert_eq!(parse(&action), Some(("ACTION".to_string(), "waves at everyone")));
+
+        // Lowercase commands and a missing closing delimiter are accepted
+        assert_eq!(parse("\x01ping 123"), Some(("PING".to_string(), "123")));
+        assert_eq!(parse("just text"), None);
+        assert_eq!(parse("\x01\x01"), None);
+    }
+
+    #[test]
+    fn replies() {
+        // 2023-11-14 22:13:20 UTC
+        let now = Duration::from_secs(1_700_000_000);
+        assert_eq!(reply("PING", "12345", now), Some("\x01PING 12345\x01".to_string()));
+        assert_eq!(reply("TIME", "", now), Some("\x01TIME 2023-11-14 22:13:20 UTC\x01".to_string()));
+        assert!(reply("VERSION", "", now).unwrap().starts_with("\x01VERSION Redox irc "));
+        assert_eq!(reply("ACTION", "waves", now), None);
+        assert_eq!(reply("CLIENTINFO", "", now), None);
+
+        let sent = request_args("PING", now);
+        assert_eq!(sent, "1700000000000");
+        assert_eq!(request_args("VERSION", now), "");
+        assert_eq!(
+            render_reply("bob", "PING", &sent, now + Duration::from_millis(250)),
+            "CTCP PING reply from bob: 0.250 seconds"
+        );
+        assert_eq!(render_reply("bob", "VERSION", "irssi 1.4", now), "CTCP VERSION reply from bob: irssi 1.4");
+    }
+}
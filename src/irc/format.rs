@@ -0,0 +1,88 @@
+/// Bold
+const BOLD: char = '\x02';
+/// Color, followed by up to two digits and optionally a comma and up to two more
+const COLOR: char = '\x03';
+/// Reset all formatting
+const RESET: char = '\x0f';
+/// Reverse video
+const REVERSE: char = '\x16';
+/// Italics
+const ITALIC: char = '\x1d';
+/// Underline
+const UNDERLINE: char = '\x1f';
+
+/// Remove mIRC color codes and formatting control characters from a message
+pub fn strip_irc_formatting(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            BOLD | RESET | REVERSE | ITALIC | UNDERLINE => (),
+            COLOR => {
+                if skip_digits(&mut chars) > 0 && chars.peek() == Some(&',') {
+                    // Only a comma followed by a digit belongs to the color code
+                    let mut lookahead = chars.clone();
+                    lookahead.next();
+                    if lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        chars.next();
+                        skip_digits(&mut chars);
+                    }
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// Skip up to two ASCII digits, returning how many were skipped
+fn skip_digits<I: Iterator<Item = char> + Clone>(chars: &mut ::std::iter::Peekable<I>) -> usize {
+    let mut count = 0;
+    while count < 2 && chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+        chars.next();
+        count += 1;
+    }
+    count
+}
+
+/// Whether `c` can be part of a nickname, letters, digits and the specials of RFC 2812
+fn is_nick_char(c: char) -> bool {
+    c.is_alphanumeric() || "-_[]\\`^{}|".contains(c)
+}
+
+/// Whether `message` mentions `nick` as a whole word, ignoring case, so that
+/// "al" is mentioned by "al: hi" and "@al" but not by "also"
+pub fn mentions(message: &str, nick: &str) -> bool {
+    !nick.is_empty() && message.split(|c| !is_nick_char(c)).any(|word| word.eq_ignore_ascii_case(nick))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mentions, strip_irc_formatting};
+
+    #[test]
+    fn strip() {
+        assert_eq!(
+            strip_irc_formatting("\x0304,12red on blue\x03 \x02bold\x02 \x1ditalic\x1d \x1funder\x0f"),
+            "red on blue bold italic under"
+        );
+        assert_eq!(strip_irc_formatting("\x033,5 x"), " x");
+        assert_eq!(strip_irc_formatting("\x0312,wait"), ",wait");
+        assert_eq!(strip_irc_formatting("\x03123"), "3");
+        assert_eq!(strip_irc_formatting("plain text"), "plain text");
+    }
+
+    #[test]
+    fn mention() {
+        assert!(mentions("al: are you there?", "al"));
+        assert!(mentions("thanks @al", "al"));
+        assert!(mentions("ping AL, again", "al"));
+        assert!(mentions("al", "Al"));
+        assert!(mentions("hi al[m].", "al[m]"));
+        assert!(!mentions("also, almost", "al"));
+        assert!(!mentions("pal", "al"));
+        assert!(!mentions("al_", "al"));
+        assert!(!mentions("al[m] left", "al"));
+        assert!(!mentions("anything", ""));
+    }
+}
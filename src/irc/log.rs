@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Result, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use netutils::date::DateTime;
+
+/// Per channel history files, written with --log-dir
+///
+/// Files are opened on the first line for a channel and appended to, so a
+/// restarted client continues the same history.
+pub struct ChatLog {
+    dir: Option<PathBuf>,
+    /// Open files by channel, `None` once writing to one has failed
+    files: BTreeMap<String, Option<File>>,
+}
+
+impl ChatLog {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        ChatLog {
+            dir,
+            files: BTreeMap::new(),
+        }
+    }
+
+    fn open(&self, name: &str) -> Result<File> {
+        let dir = self.dir.as_ref().expect("log directory");
+        fs::create_dir_all(dir)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(file_name(name)))
+    }
+
+    /// Append a timestamped line to the channel's file
+    ///
+    /// Errors are reported once per channel, after which its lines are dropped
+    /// rather than interrupting the chat.
+    pub fn write(&mut self, channel: &str, line: &str) {
+        if self.dir.is_none() {
+            return;
+        }
+
+        let name = channel.to_lowercase();
+        if !self.files.contains_key(&name) {
+            let file = self.open(&name).map_err(|err| {
+                println!("irc: Can't open log for {}: {}", channel, err);
+            }).ok();
+            self.files.insert(name.clone(), file);
+        }
+
+        let entry = self.files.get_mut(&name).unwrap();
+        let failed = match *entry {
+            Some(ref mut file) => {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+                writeln!(file, "[{}] {}", timestamp(secs), line).err()
+            }
+            None => None,
+        };
+        if let Some(err) = failed {
+            println!("irc: Can't write log for {}: {}", channel, err);
+            *entry = None;
+        }
+    }
+}
+
+/// Name of the log file for a channel, with characters that would leave the
+/// log directory replaced
+pub fn file_name(channel: &str) -> String {
+    let name: String = channel
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect();
+    if name.starts_with('.') {
+        format!("_{}.log", name)
+    } else {
+        format!("{}.log", name)
+    }
+}
+
+/// UTC time as `YYYY-MM-DD HH:MM:SS`
+pub fn timestamp(secs: u64) -> String {
+    DateTime::from_unix(secs as i64).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_name, timestamp};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(timestamp(951782400), "2000-02-29 00:00:00");
+        assert_eq!(timestamp(1700000000), "2023-11-14 22:13:20");
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name("#redox"), "#redox.log");
+        assert_eq!(file_name("#a/../b"), "#a_.._b.log");
+        assert_eq!(file_name(".."), "_...log");
+    }
+}
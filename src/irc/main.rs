@@ -1,44 +1,113 @@
+extern crate netutils;
 extern crate termion;
 
 use termion::{color, style};
 
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
 use std::io::{stdin, Read, Result, Write};
 use std::net::{TcpStream, ToSocketAddrs};
-use std::str;
-use std::sync::{Arc, Mutex};
+use std::path::PathBuf;
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use away::Away;
+use format::{mentions, strip_irc_formatting};
+use log::ChatLog;
+use message::{parse_irc_line, privmsg_lines};
+use session::Session;
+
+mod away;
+mod ctcp;
+mod format;
+mod log;
+mod message;
+mod replies;
+mod session;
+
+/// Cleared by --no-color to print plain text without terminal styling
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Render a termion color or style, or nothing when colors are disabled
+fn paint<T: fmt::Display>(code: T) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        code.to_string()
+    } else {
+        String::new()
+    }
+}
 
-use std::cell::UnsafeCell;
+const SERVER: &str = "irc.mozilla.org:6667";
+/// First wait before reconnecting, doubled after every failed attempt
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(60);
+/// How often the --auto-away timer looks at the idle time
+const AWAY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 
-/// Redox domain socket
+/// Connection to the server, shared by the reader and the stdin thread.
+///
+/// The stream can be replaced on reconnect while both keep their reference.
 pub struct Socket {
-    file: UnsafeCell<TcpStream>,
+    file: RwLock<TcpStream>,
 }
 
-unsafe impl Send for Socket {}
-unsafe impl Sync for Socket {}
-
 impl Socket {
     pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Socket> {
         let file: TcpStream = TcpStream::connect(addr)?;
         Ok(Socket {
-            file: UnsafeCell::new(file),
+            file: RwLock::new(file),
         })
     }
 
+    pub fn reconnect<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        let file: TcpStream = TcpStream::connect(addr)?;
+        *self.file.write().unwrap() = file;
+        Ok(())
+    }
+
     pub fn receive(&self, buf: &mut [u8]) -> Result<usize> {
-        unsafe { (*self.file.get()).read(buf) }
+        (&*self.file.read().unwrap()).read(buf)
     }
 
     pub fn send(&self, buf: &[u8]) -> Result<usize> {
-        unsafe { (*self.file.get()).write(buf) }
+        (&*self.file.read().unwrap()).write(buf)
+    }
+
+    /// Send on behalf of the user, who is told instead of the client panicking
+    /// when the connection is down
+    pub fn send_user(&self, buf: &[u8]) {
+        if let Err(err) = self.send(buf) {
+            println!("irc: Not sent, the connection is down ({})", err);
+        }
+    }
+}
+
+/// Connect again, waiting longer after each failure, and register with the server
+fn reconnect(socket: &Socket, register: &str) {
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        println!("irc: Reconnecting in {} seconds", delay.as_secs());
+        thread::sleep(delay);
+        match socket.reconnect(SERVER) {
+            Ok(()) => match socket.send(register.as_bytes()) {
+                Ok(_) => return,
+                Err(err) => println!("irc: Can't register: {}", err),
+            },
+            Err(err) => println!("irc: Can't reconnect: {}", err),
+        }
+        delay = cmp::min(delay * 2, RECONNECT_DELAY_MAX);
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Chat { user: String, message: String },
+    /// A CTCP ACTION, as sent with /me
+    Action { user: String, message: String },
     Info { message: String },
     Joined { user: String, message: String },
     Parted { user: String, message: String },
@@ -56,6 +125,8 @@ pub struct Channel {
     pub users: Vec<String>,
     /// Has the nickname been mentioned since last look at the channel?
     pub mentioned: bool,
+    /// Private conversation with the user `name` rather than a joined channel
+    pub query: bool,
 }
 
 impl Channel {
@@ -66,13 +137,29 @@ impl Channel {
             unread: 0,
             users: vec![],
             mentioned: false,
+            query: false,
         }
     }
 
+    fn new_query(user: String) -> Self {
+        let mut channel = Channel::new(user);
+        channel.query = true;
+        channel
+    }
+
     fn get_name(&self) -> String {
         self.name.clone()
     }
 
+    /// Name as shown in the channel list, marking query windows
+    fn get_label(&self) -> String {
+        if self.query {
+            format!("{} (query)", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+
     /*fn push(&mut self, arg: &str) {
         self.buffer.push_str(arg);
     }*/
@@ -83,21 +170,28 @@ impl Channel {
             match message {
                 Message::Chat { user, message } => println!(
                     "{}{}{}: {}{}",
-                    style::Bold,
-                    color::Fg(color::Green),
+                    paint(style::Bold),
+                    paint(color::Fg(color::Green)),
                     user,
                     message,
-                    style::Reset
+                    paint(style::Reset)
+                ),
+                Message::Action { user, message } => println!(
+                    "{}* {} {}{}",
+                    paint(color::Fg(color::Green)),
+                    user,
+                    message,
+                    paint(style::Reset)
                 ),
                 Message::Info { message } => println!("info: {}", message),
                 Message::Joined { user, message } => {
                     //print!("\x1B[1m{} joined {}\x1B[21m", user, self.get_name());
                     print!(
                         "{}{} joined {}{}",
-                        color::Fg(color::Blue),
+                        paint(color::Fg(color::Blue)),
                         user,
                         self.get_name(),
-                        style::Reset
+                        paint(style::Reset)
                     );
                     if message == "".to_string() {
                         print!("\n");
@@ -108,10 +202,10 @@ impl Channel {
                 Message::Parted { user, message } => {
                     print!(
                         "{}{} parted {}{}",
-                        color::Fg(color::Blue),
+                        paint(color::Fg(color::Blue)),
                         user,
                         self.get_name(),
-                        style::Reset
+                        paint(style::Reset)
                     );
                     if message == "".to_string() {
                         print!("\n");
@@ -122,10 +216,10 @@ impl Channel {
                 Message::Quit { user, message } => {
                     print!(
                         "{}{} Quit ({}){}\n",
-                        color::Fg(color::Blue),
+                        paint(color::Fg(color::Blue)),
                         user,
                         message,
-                        style::Reset
+                        paint(style::Reset)
                     );
                 }
             }
@@ -178,16 +272,68 @@ impl Channel {
     }
 }
 
+/// Time since the Unix epoch, for CTCP PING and TIME
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Record the joined channels in the session file, if one is in use
+fn save_session(path: &Option<String>, nick: &str, channels: &[Channel]) {
+    if let Some(ref path) = *path {
+        let names: Vec<String> = channels
+            .iter()
+            .filter(|channel| !channel.query)
+            .map(|channel| channel.get_name())
+            .collect();
+        if let Err(err) = Session::save(path, nick, &names) {
+            println!("irc: Can't write session file {}: {}", path, err);
+        }
+    }
+}
+
 fn main() {
     use std::num::Wrapping;
 
     let mut args = env::args().skip(1);
 
-    let nick = args.next().expect("No nickname provided");
+    let mut nick = None;
+    let mut session_path = None;
+    let mut auto_reconnect = true;
+    let mut log_dir = None;
+    let mut auto_away = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--session" => session_path = Some(args.next().expect("No session file provided")),
+            "--no-color" => COLOR.store(false, Ordering::Relaxed),
+            "--no-reconnect" => auto_reconnect = false,
+            "--log-dir" => log_dir = Some(PathBuf::from(args.next().expect("No log directory provided"))),
+            "--auto-away" => {
+                let minutes = args
+                    .next()
+                    .and_then(|minutes| minutes.parse::<u64>().ok())
+                    .filter(|&minutes| minutes > 0)
+                    .expect("--auto-away requires a number of minutes");
+                auto_away = Some(Duration::from_secs(minutes * 60));
+            }
+            _ => nick = Some(arg),
+        }
+    }
 
-    let socket_write = Arc::new(
-        Socket::connect("irc.mozilla.org:6667").expect("Failed to connect to irc.mozilla.org"),
-    );
+    let session = match session_path {
+        Some(ref path) => Session::load(path).unwrap_or_else(|err| {
+            println!("irc: Can't read session file {}: {}", path, err);
+            None
+        }),
+        None => None,
+    };
+
+    let nick = nick
+        .or_else(|| session.as_ref().and_then(|session| session.nick.clone()))
+        .expect("No nickname provided");
+    // Channels to join again once the server has accepted the registration
+    let mut rejoin = session.map_or(vec![], |session| session.channels);
+
+    let socket_write = Arc::new(Socket::connect(SERVER).expect("Failed to connect to irc.mozilla.org"));
     let socket_read = socket_write.clone();
 
     let channels: Arc<Mutex<(Vec<Channel>, Wrapping<usize>)>> =
@@ -198,8 +344,32 @@ fn main() {
     print!("{}", register);
     socket_write.send(register.as_bytes()).unwrap();
 
+    // Set once the user quits so a closed connection isn't reestablished
+    let quitting = Arc::new(AtomicBool::new(false));
+
+    let away = Arc::new(Away::new(Instant::now()));
+    if let Some(idle) = auto_away {
+        let away = away.clone();
+        let socket = socket_write.clone();
+        thread::spawn(move || loop {
+            thread::sleep(AWAY_CHECK_INTERVAL);
+            if away.check_idle(idle, Instant::now()) {
+                let message = format!("AWAY :Idle for {} minutes\r\n", idle.as_secs() / 60);
+                let _ = socket.send(message.as_bytes());
+            }
+        });
+    }
+
+    let nick_thread = nick.clone();
+    let session_path_thread = session_path.clone();
+    let quitting_thread = quitting.clone();
+    let away_thread = away.clone();
     thread::spawn(move || {
         let channels = channels_thread;
+        let nick = nick_thread;
+        let session_path = session_path_thread;
+        let quitting = quitting_thread;
+        let away = away_thread;
         'stdin: loop {
             let mut line_original = String::new();
             if stdin().read_line(&mut line_original).unwrap() == 0 {
@@ -208,6 +378,9 @@ fn main() {
             }
 
             let line = line_original.trim();
+            if away.on_input(Instant::now()) {
+                socket_write.send_user(b"AWAY\r\n");
+            }
             if line.starts_with('/') {
                 let mut args = line.split(' ');
                 if let Some(cmd) = args.next() {
@@ -216,9 +389,9 @@ fn main() {
                             if let Some(target) = args.next() {
                                 let parts: Vec<&str> = args.collect();
                                 let message = parts.join(" ");
-                                socket_write
-                                    .send(format!("PRIVMSG {} :{}\r\n", target, message).as_bytes())
-                                    .unwrap();
+                                for line in privmsg_lines(target, &message) {
+                                    socket_write.send_user(line.as_bytes());
+                                }
                             } else {
                                 println!("irc: MSG: No message target given, use /msg target_user message.");
                             }
@@ -230,22 +403,98 @@ fn main() {
 
                                 channels_lock.0.push(channel);
                                 channels_lock.1 = Wrapping(channels_lock.0.len() - 1);
-                                socket_write
-                                    .send(format!("JOIN {}\r\n", chan).as_bytes())
-                                    .unwrap();
+                                socket_write.send_user(format!("JOIN {}\r\n", chan).as_bytes());
+                                save_session(&session_path, &nick, &channels_lock.0);
                             } else {
                                 println!("irc: JOIN: You must provide a channel to join, use /join #chan_name.");
                             }
                         }
+                        "/query" => {
+                            if let Some(user) = args.next() {
+                                let mut channels_lock = channels.lock().unwrap();
+
+                                let existing = channels_lock.0.iter().position(|chan| chan.get_name() == user);
+                                let channel_number = match existing {
+                                    Some(i) => i,
+                                    None => {
+                                        channels_lock.0.push(Channel::new_query(user.to_string()));
+                                        channels_lock.0.len() - 1
+                                    }
+                                };
+                                channels_lock.1 = Wrapping(channel_number);
+                                println!("irc: Talking with {}", user);
+                                channels_lock.0.get_mut(channel_number).unwrap().dump_buf();
+                            } else {
+                                println!("irc: QUERY: You must provide a user to talk with, use /query user.");
+                            }
+                        }
+                        "/ctcp" => {
+                            match (args.next(), args.next()) {
+                                (Some(target), Some(command)) => {
+                                    let command = command.to_ascii_uppercase();
+                                    let request = ctcp::format(&command, &ctcp::request_args(&command, unix_now()));
+                                    socket_write.send_user(format!("PRIVMSG {} :{}\r\n", target, request).as_bytes());
+                                }
+                                _ => println!("irc: CTCP: You must provide a nickname and a command, use /ctcp user VERSION."),
+                            }
+                        }
+                        "/me" => {
+                            let parts: Vec<&str> = args.collect();
+                            let channels_lock = channels.lock().unwrap();
+
+                            if let Some(chan) = channels_lock.0.get((channels_lock.1).0) {
+                                let action = ctcp::format("ACTION", &parts.join(" "));
+                                socket_write.send_user(format!("PRIVMSG {} :{}\r\n", chan.name, action).as_bytes());
+                            } else {
+                                println!("irc: You haven't joined a channel yet, use /join #chan_name");
+                            }
+                        }
+                        "/whois" => {
+                            if let Some(user) = args.next() {
+                                socket_write.send_user(format!("WHOIS {}\r\n", user).as_bytes());
+                            } else {
+                                println!("irc: WHOIS: You must provide a nickname, use /whois user.");
+                            }
+                        }
+                        "/away" => {
+                            let parts: Vec<&str> = args.collect();
+                            let message = parts.join(" ");
+                            // RFC 2812 3.6.5: AWAY without a message marks us as back
+                            if message.is_empty() {
+                                socket_write.send_user(b"AWAY\r\n");
+                            } else {
+                                socket_write.send_user(format!("AWAY :{}\r\n", message).as_bytes());
+                            }
+                            away.set_manual(!message.is_empty());
+                        }
+                        "/topic" | "/names" => {
+                            let channels_lock = channels.lock().unwrap();
+
+                            match channels_lock.0.get((channels_lock.1).0) {
+                                Some(chan) if !chan.query => {
+                                    let text: Vec<&str> = args.collect();
+                                    let command = if cmd == "/names" {
+                                        format!("NAMES {}\r\n", chan.name)
+                                    } else if text.is_empty() {
+                                        format!("TOPIC {}\r\n", chan.name)
+                                    } else {
+                                        format!("TOPIC {} :{}\r\n", chan.name, text.join(" "))
+                                    };
+                                    socket_write.send_user(command.as_bytes());
+                                }
+                                Some(_) => println!("irc: {}: This is a private conversation.", cmd[1..].to_uppercase()),
+                                None => println!("irc: {}: You aren't connected to any channels.", cmd[1..].to_uppercase()),
+                            }
+                        }
                         "/users" => {
                             let channels_lock = channels.lock().unwrap();
 
-                            if channels_lock.0.get((channels_lock.1).0).is_some() {
+                            if channels_lock.0.get((channels_lock.1).0).is_some_and(|chan| chan.query) {
+                                println!("irc: USERS: This is a private conversation.")
+                            } else if channels_lock.0.get((channels_lock.1).0).is_some() {
                                 let chan =
                                     channels_lock.0.get((channels_lock.1).0).unwrap().get_name();
-                                socket_write
-                                    .send(format!("JOIN {}\r\n", chan).as_bytes())
-                                    .unwrap();
+                                socket_write.send_user(format!("JOIN {}\r\n", chan).as_bytes());
                                 println!(
                                     "irc: Users in this channel: \n{}",
                                     channels_lock.0.get((channels_lock.1).0).unwrap().users()
@@ -317,35 +566,35 @@ fn main() {
                                 if i == (channels_lock.1).0 {
                                     println!(
                                         "{}{}. > {}{}",
-                                        color::Fg(color::Green),
+                                        paint(color::Fg(color::Green)),
                                         i + 1,
-                                        channel.get_name(),
-                                        style::Reset
+                                        channel.get_label(),
+                                        paint(style::Reset)
                                     );
                                 } else if channel.mentioned == true {
                                     println!(
                                         "{}{}.     {}, {} unread, you were mentioned{}",
-                                        color::Fg(color::Red),
+                                        paint(color::Fg(color::Red)),
                                         i + 1,
-                                        channel.get_name(),
+                                        channel.get_label(),
                                         channel.unread,
-                                        style::Reset
+                                        paint(style::Reset)
                                     );
                                 } else if channel.unread > 0 {
                                     println!(
                                         "{}.     {}, {}{}{} unread{}",
                                         i + 1,
-                                        channel.get_name(),
-                                        color::Fg(color::Yellow),
-                                        style::Bold,
+                                        channel.get_label(),
+                                        paint(color::Fg(color::Yellow)),
+                                        paint(style::Bold),
                                         channel.unread,
-                                        style::Reset
+                                        paint(style::Reset)
                                     );
                                 } else {
                                     println!(
                                         "{}.     {}, {} unread",
                                         i + 1,
-                                        channel.get_name(),
+                                        channel.get_label(),
                                         channel.unread
                                     );
                                 }
@@ -356,14 +605,12 @@ fn main() {
 
                             if channels_lock.0.get((channels_lock.1).0).is_some() {
                                 {
-                                    let chan = channels_lock
-                                        .0
-                                        .get((channels_lock.1).0)
-                                        .unwrap()
-                                        .get_name();
-                                    socket_write
-                                        .send(format!("PART {}\r\n", chan).as_bytes())
-                                        .unwrap();
+                                    let chan = channels_lock.0.get((channels_lock.1).0).unwrap();
+                                    // Closing a query window needs nothing from the server
+                                    if !chan.query {
+                                        socket_write
+                                            .send_user(format!("PART {}\r\n", chan.get_name()).as_bytes());
+                                    }
                                 }
                                 let channel_number = (channels_lock.1).0;
 
@@ -371,6 +618,7 @@ fn main() {
                                 if channel_number != 0 {
                                     (channels_lock.1).0 = channel_number - 1;
                                 }
+                                save_session(&session_path, &nick, &channels_lock.0);
                             } else {
                                 println!("irc: LEAVE: You aren't connected to any channels.")
                             }
@@ -383,6 +631,13 @@ fn main() {
                             println!("     /back - Goes to the earlier channel");
                             println!("     /goto <channel_number> - Goes to a specified channel");
                             println!("     /msg <user> <message> - Sends a private message");
+                            println!("     /query <user> - Opens a private conversation window");
+                            println!("     /whois <user> - Shows who a user is and where they are connected");
+                            println!("     /ctcp <user> <command> - Sends a CTCP request such as VERSION, PING or TIME");
+                            println!("     /me <action> - Describes what you are doing");
+                            println!("     /topic [<text>] - Shows or sets the topic of the channel");
+                            println!("     /names - Lists the users in the channel");
+                            println!("     /away [<message>] - Marks you as away, or back without a message");
                             println!("     /leave or /part - Leaves a channel");
                             println!("     /quit or /exit - Exits this program");
                             println!("     /help or /commands - Shows this help message");
@@ -424,67 +679,88 @@ fn main() {
                 let channels_lock = channels.lock().unwrap();
 
                 if let Some(ref chan) = channels_lock.0.get((channels_lock.1).0) {
-                    socket_write
-                        .send(format!("PRIVMSG {} :{}\r\n", chan.name, line).as_bytes())
-                        .unwrap();
+                    for line in privmsg_lines(&chan.name, line) {
+                        socket_write.send_user(line.as_bytes());
+                    }
                 } else {
                     println!("irc: You haven't joined a channel yet, use /join #chan_name");
                 }
             }
         }
 
-        socket_write.send(b"QUIT\r\n").unwrap();
+        quitting.store(true, Ordering::SeqCst);
+        socket_write.send_user(b"QUIT\r\n");
     });
 
+    // Written for every channel, whether it is focused or not
+    let mut chat_log = ChatLog::new(log_dir);
+    // Names by channel, collected from 353 replies until the closing 366
+    let mut pending_names: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
     'stdout: loop {
         let mut buffer = [0; 65536];
-        let count = socket_read.receive(&mut buffer).unwrap();
+        let count = socket_read.receive(&mut buffer).unwrap_or_else(|err| {
+            println!("irc: Connection lost: {}", err);
+            0
+        });
 
         if count == 0 {
             println!("CONNECTION CLOSED");
-            break 'stdout;
+            if !auto_reconnect || quitting.load(Ordering::SeqCst) {
+                break 'stdout;
+            }
+
+            reconnect(&socket_read, &register);
+
+            // Join the open channels again once registered
+            let channels_lock = channels.lock().unwrap();
+            for channel in channels_lock.0.iter().filter(|channel| !channel.query) {
+                if !rejoin.contains(&channel.name) {
+                    rejoin.push(channel.name.clone());
+                }
+            }
+            continue 'stdout;
         }
 
-        for line in unsafe { str::from_utf8_unchecked(&buffer[..count]) }.lines() {
-            let mut args = line.split(' ');
-
-            let prefix = if line.starts_with(':') {
-                args.next()
-            } else {
-                None
-            };
-
-            let source = prefix
-                .unwrap_or("")
-                .split(':')
-                .nth(1)
-                .unwrap_or("")
-                .split("!")
-                .next()
-                .unwrap_or("");
-
-            if let Some(cmd) = args.next() {
+        for line in String::from_utf8_lossy(&buffer[..count]).lines() {
+            let msg = parse_irc_line(line);
+            let source = msg.source();
+
+            if !msg.command.is_empty() {
+                let cmd = msg.command.as_str();
                 match cmd {
-                    "ERROR" => {
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
+                    "001" => {
+                        // Welcome, registration is complete
+                        println!("{}", line);
+
+                        let mut channels_lock = channels.lock().unwrap();
+                        for chan in rejoin.drain(..) {
+                            socket_read
+                                .send(format!("JOIN {}\r\n", chan).as_bytes())
+                                .unwrap();
+                            if !channels_lock.0.iter().any(|channel| channel.name == chan) {
+                                channels_lock.0.push(Channel::new(chan));
+                            }
                         }
-                        println!("\x1B[1mERROR: {}\x1B[21m", message);
+                        if !channels_lock.0.is_empty() {
+                            channels_lock.1 = Wrapping(channels_lock.0.len() - 1);
+                        }
+                        save_session(&session_path, &nick, &channels_lock.0);
+                    }
+                    "ERROR" => {
+                        let message = msg.param(0);
+                        println!("{}ERROR: {}{}", paint("\x1B[1m"), message, paint("\x1B[21m"));
                     }
                     "JOIN" => {
                         let mut channels_lock = channels.lock().unwrap();
 
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        let message_split: Vec<&str> = message.split(":").collect();
-                        let _target = message_split[0].to_string();
-                        let _target = _target.trim(); // without trimming I got issues in PART, put one here just in case
-                        let message = message_split.get(1).unwrap_or(&"").to_string();
+                        // Extended join adds the account and, as the last parameter, the real name
+                        let _target = msg.param(0);
+                        let message = if msg.params.len() > 1 {
+                            msg.params[msg.params.len() - 1].clone()
+                        } else {
+                            String::new()
+                        };
 
                         let channel: Option<&mut Channel>;
                         channel = channels_lock
@@ -495,6 +771,11 @@ fn main() {
 
                         if channel.is_some() {
                             let channel = channel.unwrap();
+                            if message.is_empty() {
+                                chat_log.write(_target, &format!("* {} joined {}", source, _target));
+                            } else {
+                                chat_log.write(_target, &format!("* {} joined {} ({})", source, _target, message));
+                            }
                             //println!("Message hidden"); // this for testing
                             channel.buffer.push(Message::Joined {
                                 user: source.to_string(),
@@ -504,50 +785,85 @@ fn main() {
                             channel.unread += 1;
                             channel.push_user(source);
                         } else {
-                            println!("\x1B[1m{} joined [{}]\x1B[21m", source, message);
+                            println!(
+                                "{}{} joined [{}]{}",
+                                paint("\x1B[1m"),
+                                source,
+                                message,
+                                paint("\x1B[21m")
+                            );
                         }
                     }
                     "353" => {
-                        // channel users list
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let mut parts: Vec<String> = args.map(|x| x.to_string()).collect();
-                        parts.reverse(); // there is a better way for this surely
-                        parts.pop();
-                        parts.pop();
-                        let chan = parts.pop().unwrap();
-                        parts.reverse();
-                        parts.pop();
-                        if parts[0].starts_with(':') {
-                            //let clone = parts[0].clone().to_string();
-                            //clone.remove(0);
-                            parts[0].remove(0);
+                        // <nick> <symbol> <channel> :<names>, repeated for long lists
+                        if let (Some(chan), Some(names)) = (msg.params.get(2), msg.params.get(3)) {
+                            pending_names
+                                .entry(chan.clone())
+                                .or_default()
+                                .extend(names.split_whitespace().map(|name| name.to_string()));
                         }
+                    }
+                    "366" => {
+                        // <nick> <channel> :End of /NAMES list
+                        if let Some(chan) = msg.params.get(1) {
+                            let users = pending_names.remove(chan).unwrap_or_default();
 
-                        let channel: Option<&mut Channel>;
-                        channel = channels_lock
-                            .0
-                            .iter_mut()
-                            .filter(|channel| channel.get_name() == chan)
-                            .next();
-
-                        let channel = channel.unwrap();
-
-                        let users = parts;
-                        channel.users = vec![];
-                        for user in &users {
-                            channel.push_user(user);
+                            let mut channels_lock = channels.lock().unwrap();
+                            if let Some(channel) = channels_lock.0.iter_mut().find(|channel| channel.get_name() == *chan) {
+                                channel.users = vec![];
+                                for user in &users {
+                                    channel.push_user(user);
+                                }
+                            }
+                            println!(
+                                "{}Users on {}: {}{}",
+                                paint("\x1B[1m"),
+                                chan,
+                                users.join(" "),
+                                paint("\x1B[21m")
+                            );
                         }
                     }
+                    "301" | "305" | "306" | "311" | "312" | "317" | "319" | "331" | "332" | "333" => {
+                        // Shown whichever channel is focused, as they answer a command
+                        match replies::render(cmd, &msg.params) {
+                            Some(text) => println!("{}{}{}", paint("\x1B[1m"), text, paint("\x1B[21m")),
+                            None => println!("{}", line),
+                        }
+                    }
+                    "318" => {
+                        // End of WHOIS, the replies before it said everything
+                    }
                     "MODE" => {
-                        let target = args.next().unwrap_or("");
-                        let mode = args.next().unwrap_or("");
-                        println!("\x1B[1m{} set to mode {}\x1B[21m", target, mode);
+                        let target = msg.param(0);
+                        let mode = msg.params.get(1..).unwrap_or(&[]).join(" ");
+                        println!(
+                            "{}{} set to mode {}{}",
+                            paint("\x1B[1m"),
+                            target,
+                            mode,
+                            paint("\x1B[21m")
+                        );
+                    }
+                    "NOTICE" if ctcp::parse(msg.param(1)).is_some() => {
+                        if let Some((command, args)) = ctcp::parse(msg.param(1)) {
+                            let text = ctcp::render_reply(source, &command, args, unix_now());
+                            println!("{}{}{}", paint("\x1B[1m"), text, paint("\x1B[21m"));
+                        }
+                    }
+                    "PRIVMSG" if ctcp::parse(msg.param(1)).is_some_and(|(command, _)| command != "ACTION") => {
+                        if let Some((command, args)) = ctcp::parse(msg.param(1)) {
+                            println!("{}CTCP {} request from {}{}", paint("\x1B[1m"), command, source, paint("\x1B[21m"));
+                            // Replies go out as NOTICE, which must never be answered automatically
+                            if let Some(reply) = ctcp::reply(&command, args, unix_now()) {
+                                let _ = socket_read.send(format!("NOTICE {} :{}\r\n", source, reply).as_bytes());
+                            }
+                        }
                     }
                     "NOTICE" => {
                         let mut channels_lock = channels.lock().unwrap();
 
-                        let _target = args.next().unwrap_or("");
+                        let _target = msg.param(0);
 
                         let channel: Option<&mut Channel>;
                         channel = channels_lock
@@ -556,14 +872,11 @@ fn main() {
                             .filter(|chan| chan.get_name() == _target)
                             .next();
 
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
+                        let message = strip_irc_formatting(msg.param(1));
 
                         if channel.is_some() {
                             let channel = channel.unwrap();
+                            chat_log.write(_target, &format!("-{}- {}", source, message));
                             //println!("Message hidden"); // this for testing
                             channel.buffer.push(Message::Chat {
                                 user: source.to_string(),
@@ -572,21 +885,21 @@ fn main() {
                             //format!("\x1B[7m{} {}: {}\x1B[27m\n", _target, source, message)
                             channel.unread += 1;
                         } else {
-                            println!("\x1B[7m{} {}: {}\x1B[27m", _target, source, message);
+                            println!(
+                                "{}{} {}: {}{}",
+                                paint("\x1B[7m"),
+                                _target,
+                                source,
+                                message,
+                                paint("\x1B[27m")
+                            );
                         }
                     }
                     "PART" => {
                         let mut channels_lock = channels.lock().unwrap();
 
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        let message_split: Vec<&str> = message.split(":").collect();
-                        let _target = message_split[0].to_string();
-                        let _target = _target.trim();
-                        let message = message_split.get(1).unwrap_or(&"").to_string();
+                        let _target = msg.param(0);
+                        let message = msg.param(1).to_string();
 
                         let channel: Option<&mut Channel>;
                         channel = channels_lock
@@ -597,6 +910,7 @@ fn main() {
 
                         if channel.is_some() {
                             let channel = channel.unwrap();
+                            chat_log.write(_target, &format!("* {} parted {} ({})", source, _target, message));
                             //println!("Message hidden"); // this for testing
                             channel.buffer.push(Message::Parted {
                                 user: source.to_string(),
@@ -606,61 +920,88 @@ fn main() {
                             channel.unread += 1;
                             channel.remove_user(source);
                         } else {
-                            println!("\x1B[1m{} parted {} ({})\x1B[21m", source, _target, message);
+                            println!(
+                                "{}{} parted {} ({}){}",
+                                paint("\x1B[1m"),
+                                source,
+                                _target,
+                                message,
+                                paint("\x1B[21m")
+                            );
                         }
                     }
                     "PING" => {
                         socket_read
-                            .send(format!("PONG {}\r\n", nick).as_bytes())
+                            .send(format!("PONG :{}\r\n", msg.params.last().unwrap_or(&nick)).as_bytes())
                             .unwrap();
                     }
                     "PRIVMSG" => {
                         let mut channels_lock = channels.lock().unwrap();
 
-                        let _target = args.next().unwrap_or("");
+                        let _target = msg.param(0);
+
+                        // Private messages go to a query window named after the sender
+                        let private = _target == nick;
+                        let window = if private { source } else { _target };
+                        if private && !channels_lock.0.iter().any(|chan| chan.get_name() == window) {
+                            channels_lock.0.push(Channel::new_query(window.to_string()));
+                        }
 
                         let channel: Option<&mut Channel>;
                         channel = channels_lock
                             .0
                             .iter_mut()
-                            .filter(|chan| chan.get_name() == _target)
+                            .filter(|chan| chan.get_name() == window)
                             .next();
 
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
+                        // Anything left in CTCP framing here is an ACTION
+                        let action = ctcp::parse(msg.param(1)).map(|(_, args)| strip_irc_formatting(args));
+                        let message = strip_irc_formatting(msg.param(1));
 
                         if channel.is_some() {
-                            let message = message.clone();
+                            let message = action.clone().unwrap_or(message);
                             let channel = channel.unwrap();
-                            //println!("Message hidden"); // this for testing
-                            channel.buffer.push(Message::Chat {
-                                user: source.to_string(),
-                                message: message.clone(),
-                            });
+                            if action.is_some() {
+                                chat_log.write(window, &format!("* {} {}", source, message));
+                                channel.buffer.push(Message::Action {
+                                    user: source.to_string(),
+                                    message: message.clone(),
+                                });
+                            } else {
+                                chat_log.write(window, &format!("<{}> {}", source, message));
+                                //println!("Message hidden"); // this for testing
+                                channel.buffer.push(Message::Chat {
+                                    user: source.to_string(),
+                                    message: message.clone(),
+                                });
+                            }
                             //format!("\x1B[7m{} {}: {}\x1B[27m\n", _target, source, message)
                             channel.unread += 1;
 
-                            if message.contains(&nick) {
+                            if private || mentions(&message, &nick) {
                                 channel.mentioned = true;
                             }
+                        } else if let Some(action) = action {
+                            println!("{}{} * {} {}{}", paint("\x1B[7m"), _target, source, action, paint("\x1B[27m"));
                         } else {
-                            println!("\x1B[7m{} {}: {}\x1B[27m", _target, source, message);
+                            println!(
+                                "{}{} {}: {}{}",
+                                paint("\x1B[7m"),
+                                _target,
+                                source,
+                                message,
+                                paint("\x1B[27m")
+                            );
                         }
                     }
                     "QUIT" => {
                         let mut channels_lock = channels.lock().unwrap();
 
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
+                        let message = msg.param(0).to_string();
 
                         for channel in &mut channels_lock.0 {
                             if channel.has_user(source) {
+                                chat_log.write(&channel.name, &format!("* {} quit ({})", source, message));
                                 channel.buffer.push(Message::Quit {
                                     user: source.to_string(),
                                     message: message.clone(),
@@ -671,13 +1012,8 @@ fn main() {
                         //println!("\x1B[1m{} quit: {}\x1B[21m", source, message);
                     }
                     "372" => {
-                        let _target = args.next().unwrap_or("");
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        println!("\x1B[1m{}\x1B[21m", message);
+                        let message = msg.param(1);
+                        println!("{}{}{}", paint("\x1B[1m"), message, paint("\x1B[21m"));
                     }
                     _ => {
                         println!("{}", line);
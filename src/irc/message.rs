@@ -0,0 +1,210 @@
+/// Longest line a server accepts, `\r\n` included (RFC 2812 2.3)
+pub const MAX_LINE_LEN: usize = 512;
+
+/// A line received from the server, split into its parts (RFC 2812 2.3.1)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IrcMessage {
+    /// Server name or `nick!user@host` of the sender, without the colon
+    pub prefix: Option<String>,
+    /// Command name or three digit numeric reply
+    pub command: String,
+    /// Middle parameters, followed by the trailing one when the line has it
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    /// Nickname of the sender, or the server name, empty without a prefix
+    pub fn source(&self) -> &str {
+        match self.prefix {
+            Some(ref prefix) => prefix.split('!').next().unwrap_or(prefix),
+            None => "",
+        }
+    }
+
+    /// Parameter `i`, empty when the message has fewer
+    pub fn param(&self, i: usize) -> &str {
+        self.params.get(i).map_or("", |param| param.as_str())
+    }
+}
+
+/// Split the parameters of a message, the part after the command
+///
+/// Parameters are separated by spaces, and one starting with `:` takes the
+/// rest of the line, spaces and colons included.
+pub fn params(rest: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut rest = rest.trim_start_matches(' ');
+    while !rest.is_empty() {
+        if let Some(trailing) = rest.strip_prefix(':') {
+            params.push(trailing.to_string());
+            break;
+        }
+        let end = rest.find(' ').unwrap_or(rest.len());
+        params.push(rest[..end].to_string());
+        rest = rest[end..].trim_start_matches(' ');
+    }
+    params
+}
+
+/// Parse `[:prefix] command [params] [:trailing]`, leniently: a line with
+/// nothing in it gives an empty command
+pub fn parse_irc_line(line: &str) -> IrcMessage {
+    let mut rest = line.trim_end_matches(&['\r', '\n'][..]).trim_start_matches(' ');
+
+    let mut prefix = None;
+    if rest.starts_with(':') {
+        let end = rest.find(' ').unwrap_or(rest.len());
+        prefix = Some(rest[1..end].to_string());
+        rest = rest[end..].trim_start_matches(' ');
+    }
+
+    let end = rest.find(' ').unwrap_or(rest.len());
+    IrcMessage {
+        prefix,
+        command: rest[..end].to_string(),
+        params: params(&rest[end..]),
+    }
+}
+
+/// Split `text` into pieces of at most `max` bytes, on character boundaries.
+///
+/// A piece ends at the last space that fits, which is dropped, and is only cut
+/// inside a word that is longer than `max` on its own. Empty text gives one
+/// empty piece.
+pub fn split_text(text: &str, max: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            // Not even one character fits, send it alone rather than loop forever
+            end = rest.chars().next().map_or(1, char::len_utf8);
+        }
+
+        let (piece, next) = if rest.as_bytes().get(end) == Some(&b' ') {
+            (&rest[..end], &rest[end + 1..])
+        } else {
+            match rest[..end].rfind(' ') {
+                Some(space) if space > 0 => (&rest[..space], &rest[space + 1..]),
+                _ => (&rest[..end], &rest[end..]),
+            }
+        };
+        pieces.push(piece);
+        rest = next;
+    }
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// `PRIVMSG` lines sending `text` to `target`, as many as it takes for each
+/// to fit in `MAX_LINE_LEN` with its `\r\n`
+pub fn privmsg_lines(target: &str, text: &str) -> Vec<String> {
+    let prefix = format!("PRIVMSG {} :", target);
+    let max = MAX_LINE_LEN.saturating_sub(prefix.len() + 2);
+    split_text(text, max)
+        .into_iter()
+        .map(|piece| format!("{}{}\r\n", prefix, piece))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{params, parse_irc_line, privmsg_lines, split_text, MAX_LINE_LEN};
+
+    #[test]
+    fn split_params() {
+        assert_eq!(params("me #redox :Welcome to  #redox"), vec!["me", "#redox", "Welcome to  #redox"]);
+        assert_eq!(params("me  bob ::-)"), vec!["me", "bob", ":-)"]);
+        assert_eq!(params(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn embedded_colons() {
+        let msg = parse_irc_line(":bob!~bob@example.org PRIVMSG #redox :see https://redox-os.org: it boots\r\n");
+        assert_eq!(msg.command, "PRIVMSG");
+        assert_eq!(msg.params, vec!["#redox", "see https://redox-os.org: it boots"]);
+
+        let msg = parse_irc_line(":irc.example.org 332 me #redox :Topic: Redox :: 0.9");
+        assert_eq!(msg.params, vec!["me", "#redox", "Topic: Redox :: 0.9"]);
+
+        // An empty trailing parameter is still a parameter
+        let msg = parse_irc_line(":bob!~bob@example.org PART #redox :");
+        assert_eq!(msg.params, vec!["#redox", ""]);
+    }
+
+    #[test]
+    fn no_trailing() {
+        let msg = parse_irc_line(":bob!~bob@example.org JOIN #redox");
+        assert_eq!(msg.command, "JOIN");
+        assert_eq!(msg.params, vec!["#redox"]);
+
+        let msg = parse_irc_line(":me MODE me +i");
+        assert_eq!(msg.params, vec!["me", "+i"]);
+        assert_eq!(msg.param(2), "");
+
+        let msg = parse_irc_line("PING");
+        assert_eq!(msg.prefix, None);
+        assert_eq!(msg.command, "PING");
+        assert!(msg.params.is_empty());
+
+        assert_eq!(parse_irc_line("").command, "");
+    }
+
+    #[test]
+    fn prefixes() {
+        let msg = parse_irc_line(":jackpot51!~jeremy@redox-os.org QUIT :Quit: bye");
+        assert_eq!(msg.prefix, Some("jackpot51!~jeremy@redox-os.org".to_string()));
+        assert_eq!(msg.source(), "jackpot51");
+        assert_eq!(msg.param(0), "Quit: bye");
+
+        let msg = parse_irc_line(":irc.example.org 001 me :Welcome");
+        assert_eq!(msg.source(), "irc.example.org");
+        assert_eq!(parse_irc_line("PING :irc.example.org").source(), "");
+
+        // Nicks and channels are not limited to ASCII
+        let msg = parse_irc_line(":Jürgen!~j@example.org JOIN #café");
+        assert_eq!(msg.source(), "Jürgen");
+        assert_eq!(msg.param(0), "#café");
+    }
+
+    #[test]
+    fn long_messages() {
+        let text = "Grüße aus Köln, ça va? 日本語も大丈夫 🦀 ".repeat(80);
+        let text = text.trim_end();
+        assert!(text.len() > 4000);
+
+        let lines = privmsg_lines("#redox", text);
+        assert!(lines.len() > 8);
+        let mut pieces = Vec::new();
+        for line in &lines {
+            assert!(line.len() <= MAX_LINE_LEN);
+            let piece = line.strip_prefix("PRIVMSG #redox :").unwrap().strip_suffix("\r\n").unwrap();
+            // Split at a space, which is left out
+            assert!(!piece.starts_with(' ') && !piece.ends_with(' '));
+            pieces.push(piece);
+        }
+        assert_eq!(pieces.join(" "), text);
+
+        // Without spaces a word is cut between characters
+        let word = "é🦀".repeat(400);
+        let pieces = split_text(&word, 100);
+        assert!(pieces.iter().all(|piece| piece.len() <= 100));
+        assert!(pieces[..pieces.len() - 1].iter().all(|piece| piece.len() >= 97));
+        assert_eq!(pieces.concat(), word);
+    }
+
+    #[test]
+    fn short_messages() {
+        assert_eq!(privmsg_lines("bob", "hi there"), vec!["PRIVMSG bob :hi there\r\n"]);
+        assert_eq!(privmsg_lines("bob", ""), vec!["PRIVMSG bob :\r\n"]);
+        assert_eq!(split_text("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(split_text("one two three", 8), vec!["one two", "three"]);
+        assert_eq!(split_text("abcdef", 4), vec!["abcd", "ef"]);
+        assert_eq!(split_text("ü", 1), vec!["ü"]);
+    }
+}
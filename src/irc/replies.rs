@@ -0,0 +1,81 @@
+use log::timestamp;
+
+/// Nickname of a `nick!user@host` mask
+fn nick_of(mask: &str) -> &str {
+    mask.split('!').next().unwrap_or(mask)
+}
+
+/// Readable text for a WHOIS, TOPIC or AWAY numeric reply, whose first
+/// parameter is always our own nickname
+///
+/// Returns `None` for the replies that only mark the end of a list, and for
+/// replies missing parameters.
+pub fn render(code: &str, params: &[String]) -> Option<String> {
+    let param = |i: usize| params.get(i).map(|param| param.as_str());
+    match code {
+        // RPL_AWAY <nick> :<away message>
+        "301" => Some(format!("{} is away: {}", param(1)?, param(2).unwrap_or(""))),
+        // RPL_UNAWAY :You are no longer marked as being away
+        "305" => Some("You are no longer marked as away".to_string()),
+        // RPL_NOWAWAY :You have been marked as being away
+        "306" => Some("You have been marked as away".to_string()),
+        // RPL_WHOISUSER <nick> <user> <host> * :<real name>
+        "311" => Some(format!("{} is {}@{} ({})", param(1)?, param(2)?, param(3)?, param(5).unwrap_or(""))),
+        // RPL_WHOISSERVER <nick> <server> :<server info>
+        "312" => Some(format!("{} is connected to {} ({})", param(1)?, param(2)?, param(3).unwrap_or(""))),
+        // RPL_WHOISIDLE <nick> <seconds> <signon> :seconds idle, signon time
+        "317" => Some(format!("{} has been idle {} seconds", param(1)?, param(2)?)),
+        // RPL_WHOISCHANNELS <nick> :<channels>
+        "319" => Some(format!("{} is on {}", param(1)?, param(2)?.trim())),
+        // RPL_NOTOPIC <channel> :No topic is set
+        "331" => Some(format!("No topic is set on {}", param(1)?)),
+        // RPL_TOPIC <channel> :<topic>
+        "332" => Some(format!("Topic of {}: {}", param(1)?, param(2)?)),
+        // RPL_TOPICWHOTIME <channel> <setter> <time>
+        "333" => {
+            let set = match param(3).and_then(|time| time.parse().ok()) {
+                Some(secs) => format!(" on {} UTC", timestamp(secs)),
+                None => String::new(),
+            };
+            Some(format!("Topic of {} set by {}{}", param(1)?, nick_of(param(2)?), set))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use message::params;
+
+    fn render_line(code: &str, rest: &str) -> Option<String> {
+        render(code, &params(rest))
+    }
+
+    #[test]
+    fn numeric_replies() {
+        assert_eq!(
+            render_line("311", "me bob ~bob example.org * :Bob Smith"),
+            Some("bob is ~bob@example.org (Bob Smith)".to_string())
+        );
+        assert_eq!(
+            render_line("312", "me bob irc.example.org :Example server"),
+            Some("bob is connected to irc.example.org (Example server)".to_string())
+        );
+        assert_eq!(render_line("319", "me bob :@#redox #rust "), Some("bob is on @#redox #rust".to_string()));
+        assert_eq!(render_line("332", "me #redox :Redox OS"), Some("Topic of #redox: Redox OS".to_string()));
+        assert_eq!(
+            render_line("333", "me #redox jackpot51!~j@example.org 1700000000"),
+            Some("Topic of #redox set by jackpot51 on 2023-11-14 22:13:20 UTC".to_string())
+        );
+        assert_eq!(render_line("331", "me #empty :No topic is set"), Some("No topic is set on #empty".to_string()));
+
+        assert_eq!(render_line("301", "me bob :Gone fishing"), Some("bob is away: Gone fishing".to_string()));
+        assert_eq!(render_line("306", "me :You have been marked as being away"), Some("You have been marked as away".to_string()));
+        assert_eq!(render_line("305", "me :You are no longer marked as being away"), Some("You are no longer marked as away".to_string()));
+
+        // Truncated or unrelated replies are left to the caller
+        assert_eq!(render_line("311", "me bob"), None);
+        assert_eq!(render_line("318", "me bob :End of /WHOIS list"), None);
+    }
+}
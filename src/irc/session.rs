@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result, Write};
+
+/// Nick and joined channels saved between runs.
+///
+/// The file holds one `nick <name>` line followed by a `join <channel>` line
+/// per channel.
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    pub nick: Option<String>,
+    pub channels: Vec<String>,
+}
+
+impl Session {
+    pub fn parse(text: &str) -> Option<Session> {
+        let mut session = Session::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("nick"), Some(nick), None) => session.nick = Some(nick.to_string()),
+                (Some("join"), Some(channel), None) => session.channels.push(channel.to_string()),
+                _ => return None,
+            }
+        }
+
+        Some(session)
+    }
+
+    /// Load the session file, `Ok(None)` when there is none yet
+    pub fn load(path: &str) -> Result<Option<Session>> {
+        let mut text = String::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                file.read_to_string(&mut text)?;
+            }
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        }
+
+        match Session::parse(&text) {
+            Some(session) => Ok(Some(session)),
+            None => {
+                println!("irc: Ignoring malformed session file {}", path);
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn save(path: &str, nick: &str, channels: &[String]) -> Result<()> {
+        let mut text = format!("nick {}\n", nick);
+        for channel in channels {
+            text.push_str(&format!("join {}\n", channel));
+        }
+
+        let mut file = File::create(path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Session;
+
+    #[test]
+    fn parse_session() {
+        let session = Session::parse("nick redoxer\njoin #redox\n\njoin #rust\n").unwrap();
+        assert_eq!(session.nick, Some("redoxer".to_string()));
+        assert_eq!(session.channels, vec!["#redox".to_string(), "#rust".to_string()]);
+
+        assert_eq!(Session::parse(""), Some(Session::default()));
+        assert_eq!(Session::parse("nick"), None);
+        assert_eq!(Session::parse("join #redox extra"), None);
+        assert_eq!(Session::parse("channel #redox"), None);
+    }
+}
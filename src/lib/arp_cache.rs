@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::Arp;
+use ip::Ipv4Addr;
+use mac::MacAddr;
+
+/// IPv4 to MAC address mappings that expire `ttl` after they were learned
+#[derive(Clone, Debug)]
+pub struct ArpCache {
+    ttl: Duration,
+    entries: BTreeMap<Ipv4Addr, (MacAddr, Instant)>,
+}
+
+impl ArpCache {
+    pub fn new(ttl: Duration) -> Self {
+        ArpCache {
+            ttl: ttl,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Add or refresh a mapping
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        self.insert_at(ip, mac, Instant::now());
+    }
+
+    fn insert_at(&mut self, ip: Ipv4Addr, mac: MacAddr, now: Instant) {
+        self.entries.insert(ip, (mac, now));
+    }
+
+    /// Add a mapping learned `age` ago, e.g. one saved by an earlier run
+    pub fn insert_aged(&mut self, ip: Ipv4Addr, mac: MacAddr, age: Duration) {
+        // Anything older than the clock can go back has certainly expired
+        if let Some(learned) = Instant::now().checked_sub(age) {
+            self.insert_at(ip, mac, learned);
+        }
+    }
+
+    /// MAC address of `ip`, unless it is unknown or has expired
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.lookup_at(ip, Instant::now())
+    }
+
+    fn lookup_at(&self, ip: Ipv4Addr, now: Instant) -> Option<MacAddr> {
+        match self.entries.get(&ip) {
+            Some(&(mac, learned)) if now.duration_since(learned) < self.ttl => Some(mac),
+            _ => None,
+        }
+    }
+
+    /// Drop the expired entries
+    pub fn evict(&mut self) {
+        self.evict_at(Instant::now());
+    }
+
+    fn evict_at(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.entries.retain(|_, &mut (_, learned)| now.duration_since(learned) < ttl);
+    }
+
+    pub fn remove(&mut self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.entries.remove(&ip).map(|(mac, _)| mac)
+    }
+
+    /// Mappings that have not expired, by address, with how long ago each was learned
+    pub fn entries(&self) -> Vec<(Ipv4Addr, MacAddr, Duration)> {
+        self.entries_at(Instant::now())
+    }
+
+    fn entries_at(&self, now: Instant) -> Vec<(Ipv4Addr, MacAddr, Duration)> {
+        self.entries
+            .iter()
+            .map(|(&ip, &(mac, learned))| (ip, mac, now.duration_since(learned)))
+            .filter(|&(_, _, age)| age < self.ttl)
+            .collect()
+    }
+
+    /// Number of entries, including expired ones not evicted yet
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record the sender of a received request or reply, as RFC 826 does for both.
+    /// Probes from 0.0.0.0 announce nothing and are ignored.
+    pub fn learn(&mut self, arp: &Arp) {
+        self.learn_at(arp, Instant::now());
+    }
+
+    fn learn_at(&mut self, arp: &Arp, now: Instant) {
+        let ip = arp.header.src_ip;
+        let mac = arp.header.src_mac;
+        if ip != Ipv4Addr::NULL && mac != MacAddr::default() && mac != MacAddr::BROADCAST {
+            self.insert_at(ip, mac, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArpCache;
+    use super::super::{Arp, ARP_REPLY};
+    use ip::Ipv4Addr;
+    use mac::MacAddr;
+    use std::time::{Duration, Instant};
+
+    fn reply(src_ip: Ipv4Addr, src_mac: MacAddr) -> Arp {
+        Arp::reply(src_mac, src_ip, MacAddr::from_str("02:00:00:00:00:01"), Ipv4Addr::from_str("10.0.0.1"))
+    }
+
+    #[test]
+    fn insert_lookup_expire() {
+        let start = Instant::now();
+        let ip = Ipv4Addr::from_str("10.0.0.2");
+        let mac = MacAddr::from_str("02:00:00:00:00:02");
+        let mut cache = ArpCache::new(Duration::from_secs(60));
+
+        assert_eq!(cache.lookup_at(ip, start), None);
+        cache.insert_at(ip, mac, start);
+        assert_eq!(cache.lookup_at(ip, start + Duration::from_secs(59)), Some(mac));
+        assert_eq!(cache.lookup_at(ip, start + Duration::from_secs(60)), None);
+
+        // Refreshing moves the expiry forward
+        cache.insert_at(ip, mac, start + Duration::from_secs(30));
+        assert_eq!(cache.lookup_at(ip, start + Duration::from_secs(80)), Some(mac));
+
+        cache.insert_at(Ipv4Addr::from_str("10.0.0.3"), mac, start);
+        assert_eq!(cache.entries_at(start + Duration::from_secs(80)), vec![(ip, mac, Duration::from_secs(50))]);
+        cache.evict_at(start + Duration::from_secs(80));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.remove(ip), Some(mac));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn learn_from_reply() {
+        let start = Instant::now();
+        let ip = Ipv4Addr::from_str("10.0.0.2");
+        let mac = MacAddr::from_str("02:00:00:00:00:02");
+        let mut cache = ArpCache::new(Duration::from_secs(60));
+
+        let arp = Arp::from_bytes(&reply(ip, mac).to_bytes()).unwrap();
+        assert_eq!(arp.header.oper.get(), ARP_REPLY);
+        assert_eq!(arp.to_bytes().len(), 28);
+        cache.learn_at(&arp, start);
+        assert_eq!(cache.lookup_at(ip, start), Some(mac));
+        assert_eq!(cache.lookup_at(Ipv4Addr::from_str("10.0.0.1"), start), None);
+
+        // An address probe has no sender address to remember
+        cache.learn_at(&reply(Ipv4Addr::NULL, mac), start);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn aged_entries() {
+        let ip = Ipv4Addr::from_str("10.0.0.2");
+        let mac = MacAddr::from_str("02:00:00:00:00:02");
+        let mut cache = ArpCache::new(Duration::from_secs(60));
+
+        cache.insert_aged(ip, mac, Duration::from_secs(30));
+        cache.insert_aged(Ipv4Addr::from_str("10.0.0.3"), mac, Duration::from_secs(90));
+        let entries = cache.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].0, entries[0].1), (ip, mac));
+        assert!(entries[0].2 >= Duration::from_secs(30));
+    }
+}
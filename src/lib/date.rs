@@ -0,0 +1,103 @@
+//! Calendar dates from Unix time, for tools that print or log times without a
+//! time zone database
+use std::fmt;
+
+/// A point in time broken down into its calendar date and time of day
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    /// 1 to 12
+    pub month: u32,
+    /// 1 to 31
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 for Sunday to 6 for Saturday
+    pub weekday: u32,
+    /// 1 to 366
+    pub yday: u32,
+}
+
+/// Gregorian rule: every fourth year, but only every fourth century
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+impl DateTime {
+    /// Break down `secs` seconds since the Unix epoch, negative ones included
+    pub fn from_unix(secs: i64) -> Self {
+        let days = secs.div_euclid(86400);
+        let time = secs.rem_euclid(86400) as u32;
+
+        // Civil date from days since 1970-01-01, by Howard Hinnant's algorithm,
+        // with years starting in March so the leap day comes last
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4).rem_euclid(7) as u32;
+        let before_month = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334][month as usize - 1];
+        let leap_day = if month > 2 && is_leap_year(year) { 1 } else { 0 };
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+            weekday,
+            yday: before_month + day + leap_day,
+        }
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS`
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DateTime;
+
+    fn date(secs: i64) -> (i64, u32, u32, u32, u32, u32) {
+        let date = DateTime::from_unix(secs);
+        (date.year, date.month, date.day, date.hour, date.minute, date.second)
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(date(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(date(1_700_000_000), (2023, 11, 14, 22, 13, 20));
+        // Leap days, including the one of a century divisible by 400
+        assert_eq!(date(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(date(1_709_164_800), (2024, 2, 29, 0, 0, 0));
+        assert_eq!(date(1_709_251_199), (2024, 2, 29, 23, 59, 59));
+        assert_eq!(date(1_709_251_200), (2024, 3, 1, 0, 0, 0));
+        // Past the 32-bit rollover, and before the epoch
+        assert_eq!(date(2_147_483_648), (2038, 1, 19, 3, 14, 8));
+        assert_eq!(date(-1), (1969, 12, 31, 23, 59, 59));
+
+        let leap = DateTime::from_unix(1_735_603_200);
+        assert_eq!((leap.year, leap.month, leap.day, leap.yday), (2024, 12, 31, 366));
+        assert_eq!(DateTime::from_unix(0).weekday, 4);
+        assert_eq!(DateTime::from_unix(1_709_164_800).weekday, 4);
+
+        assert_eq!(DateTime::from_unix(1_700_000_000).to_string(), "2023-11-14 22:13:20");
+    }
+}
@@ -0,0 +1,604 @@
+//! DNS messages as defined in RFC 1035, shared by the `dns` tool and
+//! anything else that speaks the protocol, such as an mDNS responder
+use super::{n16, NetCfg};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{error, fmt, mem, slice};
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_SERVFAIL: u8 = 2;
+pub const RCODE_NXDOMAIN: u8 = 3;
+
+pub const CLASS_IN: u16 = 1;
+
+/// Flag bits of the second header word
+pub const FLAG_RESPONSE: u16 = 0x8000;
+pub const FLAG_TRUNCATED: u16 = 0x0200;
+pub const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+
+/// Upper bound on compression pointers followed while reading one name
+const MAX_POINTERS: usize = 64;
+/// Longest name on the wire, length bytes included
+const MAX_NAME_LEN: usize = 255;
+
+/// How long `reverse_lookup` waits on the name server
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DnsError {
+    /// The message ends in the middle of a header, name or record
+    Truncated,
+    /// A query arrived where a response was expected
+    NotResponse,
+    /// Name compression pointers that never reach the end of the name
+    CompressionLoop,
+    /// A name with an empty label, a label over 63 bytes or over 255 bytes in all
+    InvalidName,
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DnsError::Truncated => write!(f, "message truncated"),
+            DnsError::NotResponse => write!(f, "not a response"),
+            DnsError::CompressionLoop => write!(f, "name compression loop"),
+            DnsError::InvalidName => write!(f, "invalid name"),
+        }
+    }
+}
+
+impl error::Error for DnsError {}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RecordType {
+    A,
+    NS,
+    CNAME,
+    PTR,
+    MX,
+    TXT,
+    AAAA,
+    Other(u16),
+}
+
+impl RecordType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "A" => Some(RecordType::A),
+            "NS" => Some(RecordType::NS),
+            "CNAME" => Some(RecordType::CNAME),
+            "PTR" => Some(RecordType::PTR),
+            "MX" => Some(RecordType::MX),
+            "TXT" => Some(RecordType::TXT),
+            "AAAA" => Some(RecordType::AAAA),
+            _ => None,
+        }
+    }
+
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            1 => RecordType::A,
+            2 => RecordType::NS,
+            5 => RecordType::CNAME,
+            12 => RecordType::PTR,
+            15 => RecordType::MX,
+            16 => RecordType::TXT,
+            28 => RecordType::AAAA,
+            other => RecordType::Other(other),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            RecordType::A => 1,
+            RecordType::NS => 2,
+            RecordType::CNAME => 5,
+            RecordType::PTR => 12,
+            RecordType::MX => 15,
+            RecordType::TXT => 16,
+            RecordType::AAAA => 28,
+            RecordType::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordType::Other(other) => write!(f, "TYPE{}", other),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// The fixed 12 byte header that starts every message
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[repr(packed)]
+pub struct DnsHeader {
+    pub id: n16,
+    pub flags: n16,
+    /// Number of questions
+    pub qdcount: n16,
+    /// Number of answer records
+    pub ancount: n16,
+    /// Number of authority records
+    pub nscount: n16,
+    /// Number of additional records
+    pub arcount: n16,
+}
+
+impl DnsHeader {
+    pub fn is_response(&self) -> bool {
+        self.flags.get() & FLAG_RESPONSE != 0
+    }
+
+    /// Whether the sender cut the message short to fit, in which case the
+    /// query should be retried over TCP for the full answer
+    pub fn truncated(&self) -> bool {
+        self.flags.get() & FLAG_TRUNCATED != 0
+    }
+
+    pub fn rcode(&self) -> u8 {
+        (self.flags.get() & 0xF) as u8
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: RecordType,
+    pub qclass: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    /// The target of an NS, CNAME or PTR record
+    Name(String),
+    MX { preference: u16, exchange: String },
+    TXT(Vec<String>),
+    Raw(Vec<u8>),
+}
+
+impl fmt::Display for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordData::A(ref addr) => write!(f, "{}", addr),
+            RecordData::AAAA(ref addr) => write!(f, "{}", addr),
+            RecordData::Name(ref name) => write!(f, "{}", name),
+            RecordData::MX { preference, ref exchange } => write!(f, "{} {}", preference, exchange),
+            RecordData::TXT(ref strings) => {
+                for (i, string) in strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{:?}", string)?;
+                }
+                Ok(())
+            }
+            RecordData::Raw(ref data) => {
+                for byte in data {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceRecord {
+    pub name: String,
+    pub rtype: RecordType,
+    /// The class, whose top bit mDNS uses as the cache flush flag
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+impl fmt::Display for ResourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.name, self.ttl, self.rtype, self.data)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DnsMessage {
+    pub header: DnsHeader,
+    pub questions: Vec<Question>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+}
+
+impl DnsMessage {
+    pub fn id(&self) -> u16 {
+        self.header.id.get()
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.header.truncated()
+    }
+
+    pub fn rcode(&self) -> u8 {
+        self.header.rcode()
+    }
+}
+
+fn write_name(name: &str, out: &mut Vec<u8>) -> Result<(), DnsError> {
+    let start = out.len();
+    let name = name.trim_end_matches('.');
+    // The root is just the terminating zero
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(DnsError::InvalidName);
+            }
+            out.push(label.len() as u8);
+            out.extend_from_slice(label.as_bytes());
+        }
+    }
+    out.push(0);
+    if out.len() - start > MAX_NAME_LEN {
+        return Err(DnsError::InvalidName);
+    }
+    Ok(())
+}
+
+/// Build a recursive query for `name` in the Internet class
+pub fn build_query(id: u16, name: &str, qtype: RecordType) -> Result<Vec<u8>, DnsError> {
+    let header = DnsHeader {
+        id: n16::new(id),
+        flags: n16::new(FLAG_RECURSION_DESIRED),
+        qdcount: n16::new(1),
+        ancount: n16::new(0),
+        nscount: n16::new(0),
+        arcount: n16::new(0),
+    };
+
+    let mut data = Vec::with_capacity(mem::size_of::<DnsHeader>() + name.len() + 6);
+    let header_ptr: *const DnsHeader = &header;
+    data.extend_from_slice(unsafe {
+        slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<DnsHeader>())
+    });
+    write_name(name, &mut data)?;
+    data.extend_from_slice(&qtype.to_u16().to_be_bytes());
+    data.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(data)
+}
+
+/// The name to ask a PTR record of to find the host name of `addr`, e.g.
+/// `15.2.0.10.in-addr.arpa` for 10.0.2.15 (RFC 1035 3.5, RFC 3596 2.5)
+pub fn reverse_name(addr: &IpAddr) -> String {
+    match *addr {
+        IpAddr::V4(addr) => {
+            let o = addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        }
+        IpAddr::V6(addr) => {
+            let mut name = String::with_capacity(72);
+            for byte in addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0xF, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, DnsError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+        .ok_or(DnsError::Truncated)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, DnsError> {
+    Ok((read_u16(data, offset)? as u32) << 16 | read_u16(data, offset + 2)? as u32)
+}
+
+/// Read a possibly compressed name, returning it and the offset just past it
+fn read_name(data: &[u8], mut offset: usize) -> Result<(String, usize), DnsError> {
+    let mut name = String::new();
+    let mut end = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = *data.get(offset).ok_or(DnsError::Truncated)? as usize;
+        if len & 0xC0 == 0xC0 {
+            pointers += 1;
+            if pointers > MAX_POINTERS {
+                return Err(DnsError::CompressionLoop);
+            }
+            if end.is_none() {
+                end = Some(offset + 2);
+            }
+            offset = (read_u16(data, offset)? & 0x3FFF) as usize;
+        } else if len == 0 {
+            if name.is_empty() {
+                name.push('.');
+            }
+            return Ok((name, end.unwrap_or(offset + 1)));
+        } else {
+            let label = data.get(offset + 1..offset + 1 + len).ok_or(DnsError::Truncated)?;
+            name.push_str(&String::from_utf8_lossy(label));
+            name.push('.');
+            offset += 1 + len;
+        }
+    }
+}
+
+fn read_data(data: &[u8], offset: usize, len: usize, rtype: RecordType) -> Result<RecordData, DnsError> {
+    let rdata = data.get(offset..offset + len).ok_or(DnsError::Truncated)?;
+    Ok(match rtype {
+        RecordType::A if len == 4 => RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+        RecordType::AAAA if len == 16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(rdata);
+            RecordData::AAAA(Ipv6Addr::from(octets))
+        }
+        RecordType::NS | RecordType::CNAME | RecordType::PTR => RecordData::Name(read_name(data, offset)?.0),
+        RecordType::MX if len >= 3 => RecordData::MX {
+            preference: read_u16(data, offset)?,
+            exchange: read_name(data, offset + 2)?.0,
+        },
+        RecordType::TXT => {
+            let mut strings = Vec::new();
+            let mut i = 0;
+            while i < rdata.len() {
+                let string_len = rdata[i] as usize;
+                let string = rdata.get(i + 1..i + 1 + string_len).ok_or(DnsError::Truncated)?;
+                strings.push(String::from_utf8_lossy(string).into_owned());
+                i += 1 + string_len;
+            }
+            RecordData::TXT(strings)
+        }
+        _ => RecordData::Raw(rdata.to_vec()),
+    })
+}
+
+fn read_record(data: &[u8], offset: usize) -> Result<(ResourceRecord, usize), DnsError> {
+    let (name, next) = read_name(data, offset)?;
+    let rtype = RecordType::from_u16(read_u16(data, next)?);
+    let class = read_u16(data, next + 2)?;
+    let ttl = read_u32(data, next + 4)?;
+    let len = read_u16(data, next + 8)? as usize;
+    let rdata = read_data(data, next + 10, len, rtype)?;
+    let record = ResourceRecord {
+        name,
+        rtype,
+        class,
+        ttl,
+        data: rdata,
+    };
+    Ok((record, next + 10 + len))
+}
+
+/// Parse a query or a response.
+///
+/// When the truncated flag is set the records that arrived whole are kept
+/// and the rest dropped, instead of failing on the cut off one.
+pub fn parse_message(data: &[u8]) -> Result<DnsMessage, DnsError> {
+    if data.len() < mem::size_of::<DnsHeader>() {
+        return Err(DnsError::Truncated);
+    }
+    let header = unsafe { *(data.as_ptr() as *const DnsHeader) };
+
+    let mut offset = mem::size_of::<DnsHeader>();
+    let mut questions = Vec::new();
+    for _ in 0..header.qdcount.get() {
+        let (name, next) = read_name(data, offset)?;
+        questions.push(Question {
+            name,
+            qtype: RecordType::from_u16(read_u16(data, next)?),
+            qclass: read_u16(data, next + 2)?,
+        });
+        offset = next + 4;
+    }
+
+    let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+    let counts = [header.ancount.get(), header.nscount.get(), header.arcount.get()];
+    'sections: for (records, &count) in sections.iter_mut().zip(counts.iter()) {
+        for _ in 0..count {
+            match read_record(data, offset) {
+                Ok((record, next)) => {
+                    records.push(record);
+                    offset = next;
+                }
+                Err(DnsError::Truncated) if header.truncated() => break 'sections,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    let [answers, authorities, additionals] = sections;
+
+    Ok(DnsMessage {
+        header,
+        questions,
+        answers,
+        authorities,
+        additionals,
+    })
+}
+
+/// Parse a message that has to be a response, as a resolver expects
+pub fn parse_response(data: &[u8]) -> Result<DnsMessage, DnsError> {
+    let message = parse_message(data)?;
+    if message.header.is_response() {
+        Ok(message)
+    } else {
+        Err(DnsError::NotResponse)
+    }
+}
+
+/// The first address of `host` from the system resolver. A literal IPv4 or
+/// IPv6 address comes back as it is.
+pub fn resolve_host(host: &str) -> io::Result<IpAddr> {
+    (host, 0)
+        .to_socket_addrs()?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} has no address", host)))
+}
+
+/// Host name that `addr` resolves back to, from a PTR query to the configured
+/// name server. Any failure, a missing record included, gives `None`.
+pub fn reverse_lookup(addr: IpAddr) -> Option<String> {
+    let server = NetCfg::new().get("resolv/nameserver").ok()?;
+    let server: IpAddr = server.lines().next()?.trim().parse().ok()?;
+
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() as u16)
+        .unwrap_or(0);
+    let query = build_query(id, &reverse_name(&addr), RecordType::PTR).ok()?;
+
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.set_read_timeout(Some(LOOKUP_TIMEOUT)).ok()?;
+    socket.connect((server, 53)).ok()?;
+    socket.send(&query).ok()?;
+
+    let mut buf = [0; 1500];
+    loop {
+        let count = socket.recv(&mut buf).ok()?;
+        let response = match parse_response(&buf[..count]) {
+            Ok(response) if response.id() == id => response,
+            _ => continue,
+        };
+        return response.answers.into_iter().find_map(|record| match record.data {
+            RecordData::Name(name) if record.rtype == RecordType::PTR => {
+                Some(name.trim_end_matches('.').to_string())
+            }
+            _ => None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_hosts() {
+        assert_eq!(resolve_host("10.0.2.15").unwrap(), IpAddr::V4(Ipv4Addr::new(10, 0, 2, 15)));
+        assert_eq!(resolve_host("::1").unwrap(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert!(resolve_host("not a host").is_err());
+    }
+
+    #[test]
+    fn query_round_trip() {
+        let query = build_query(0x1234, "redox-os.org", RecordType::MX).unwrap();
+        assert_eq!(&query[..12], &[0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..], b"\x08redox-os\x03org\x00\x00\x0f\x00\x01");
+
+        let message = parse_message(&query).unwrap();
+        assert_eq!(message.id(), 0x1234);
+        assert!(!message.header.is_response());
+        assert_eq!(message.questions, vec![Question {
+            name: "redox-os.org.".to_string(),
+            qtype: RecordType::MX,
+            qclass: CLASS_IN,
+        }]);
+        assert!(message.answers.is_empty());
+        assert_eq!(parse_response(&query).unwrap_err(), DnsError::NotResponse);
+
+        assert_eq!(build_query(0, "bad..name", RecordType::A).unwrap_err(), DnsError::InvalidName);
+        assert_eq!(build_query(0, &"a".repeat(64), RecordType::A).unwrap_err(), DnsError::InvalidName);
+        assert_eq!(build_query(0, &["abcdefgh"; 32].join("."), RecordType::A).unwrap_err(), DnsError::InvalidName);
+    }
+
+    #[test]
+    fn reverse_names() {
+        assert_eq!(reverse_name(&"10.0.2.15".parse().unwrap()), "15.2.0.10.in-addr.arpa");
+        assert_eq!(
+            reverse_name(&"2001:db8::567:89ab".parse().unwrap()),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+        assert!(build_query(0, &reverse_name(&"::1".parse().unwrap()), RecordType::PTR).is_ok());
+    }
+
+    #[test]
+    fn a_record_response() {
+        // dig example.com A, answered by a recursive resolver
+        let data = [
+            0xa9, 0x4e, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01,
+            0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00,
+            0x00, 0x01, 0x00, 0x01,
+            0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0c, 0x8d, 0x00, 0x04,
+            0x5d, 0xb8, 0xd8, 0x22,
+            // EDNS OPT pseudo record in the additional section
+            0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let response = parse_response(&data).unwrap();
+        assert_eq!(response.id(), 0xa94e);
+        assert_eq!(response.rcode(), RCODE_NOERROR);
+        assert!(!response.truncated());
+        assert_eq!(response.questions[0].name, "example.com.");
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].class, CLASS_IN);
+        assert_eq!(response.answers[0].to_string(), "example.com. 3213 A 93.184.216.34");
+        assert!(response.authorities.is_empty());
+        assert_eq!(response.additionals[0].rtype, RecordType::Other(41));
+    }
+
+    #[test]
+    fn record_types() {
+        let mut data = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 5, 0, 1, 0, 0];
+        data.extend_from_slice(b"\x07example\x03com\x00\x00\xff\x00\x01");
+        // example.com. 60 MX 10 mail.example.com.
+        data.extend_from_slice(&[0xC0, 12, 0, 15, 0, 1, 0, 0, 0, 60, 0, 9, 0, 10]);
+        data.extend_from_slice(b"\x04mail\xC0\x0C");
+        // www.example.com. 60 CNAME example.com.
+        data.extend_from_slice(b"\x03www\xC0\x0C\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x02\xC0\x0C");
+        // example.com. 60 AAAA 2001:db8::1
+        data.extend_from_slice(&[0xC0, 12, 0, 28, 0, 1, 0, 0, 0, 60, 0, 16]);
+        data.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        // example.com. 60 TXT "v=spf1" "-all"
+        data.extend_from_slice(b"\xC0\x0C\x00\x10\x00\x01\x00\x00\x00\x3c\x00\x0c\x06v=spf1\x04-all");
+        // 1.0.0.127.in-addr.arpa. 60 PTR localhost.
+        data.extend_from_slice(b"\x011\x010\x010\x03127\x07in-addr\x04arpa\x00");
+        data.extend_from_slice(b"\x00\x0c\x00\x01\x00\x00\x00\x3c\x00\x0b\x09localhost\x00");
+        // Authority: example.com. 60 NS a.iana-servers.net.
+        data.extend_from_slice(b"\xC0\x0C\x00\x02\x00\x01\x00\x00\x00\x3c\x00\x14\x01a\x0ciana-servers\x03net\x00");
+
+        let response = parse_response(&data).unwrap();
+        let records: Vec<String> = response.answers.iter().map(|record| record.to_string()).collect();
+        assert_eq!(records, vec![
+            "example.com. 60 MX 10 mail.example.com.",
+            "www.example.com. 60 CNAME example.com.",
+            "example.com. 60 AAAA 2001:db8::1",
+            "example.com. 60 TXT \"v=spf1\" \"-all\"",
+            "1.0.0.127.in-addr.arpa. 60 PTR localhost.",
+        ]);
+        assert_eq!(response.authorities[0].to_string(), "example.com. 60 NS a.iana-servers.net.");
+    }
+
+    #[test]
+    fn truncated_response() {
+        let mut data = vec![0x12, 0x34, 0x83, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+        data.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        data.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1, 0, 0, 1, 0x2C, 0, 4, 93, 184, 216, 34]);
+        data.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1, 0, 0, 1]);
+
+        // With the TC bit the whole records are kept
+        let response = parse_response(&data).unwrap();
+        assert!(response.truncated());
+        assert_eq!(response.answers.len(), 1);
+
+        // Without it the message is simply broken
+        data[2] = 0x81;
+        assert_eq!(parse_response(&data).unwrap_err(), DnsError::Truncated);
+        assert_eq!(parse_response(&data[..20]).unwrap_err(), DnsError::Truncated);
+        assert_eq!(parse_response(&[0x12, 0x34]).unwrap_err(), DnsError::Truncated);
+    }
+
+    #[test]
+    fn compression_loop() {
+        // Compression pointer pointing at itself
+        let mut data = vec![0x12, 0x34, 0x81, 0x83, 0, 1, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[0xC0, 12]);
+        assert_eq!(parse_response(&data).unwrap_err(), DnsError::CompressionLoop);
+    }
+}
@@ -0,0 +1,303 @@
+use std::{error, fmt, str};
+
+use ip::Ipv4Addr;
+use packet::{Packet, Transport};
+
+/// Which end of a packet an address or port is compared against
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Direction {
+    Either,
+    Src,
+    Dst,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Protocol {
+    Tcp,
+    Udp,
+    Icmp,
+    Arp,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Expr {
+    Protocol(Protocol),
+    Host(Direction, Ipv4Addr),
+    Port(Direction, u16),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FilterParseError {
+    /// The expression stopped where an operand was expected
+    UnexpectedEnd,
+    /// A word that does not fit the grammar at its position
+    UnexpectedToken(String),
+    /// `host` not followed by a dotted quad
+    InvalidAddress(String),
+    /// `port` not followed by a number from 0 to 65535
+    InvalidPort(String),
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterParseError::UnexpectedEnd => write!(f, "filter expression ends too early"),
+            FilterParseError::UnexpectedToken(ref token) => write!(f, "unexpected '{}' in filter", token),
+            FilterParseError::InvalidAddress(ref token) => write!(f, "'{}' is not an IPv4 address", token),
+            FilterParseError::InvalidPort(ref token) => write!(f, "'{}' is not a port number", token),
+        }
+    }
+}
+
+impl error::Error for FilterParseError {}
+
+/// Packet filter in a small subset of the tcpdump syntax
+///
+/// Primitives are `tcp`, `udp`, `icmp`, `arp`, `[src|dst] host <addr>`,
+/// `[src|dst] port <port>`, and a bare `src <addr>` or `dst <addr>`. They
+/// combine with `not`, `and`, `or` and parentheses, with `not` binding
+/// tightest and `or` loosest. Primitives written next to each other are
+/// joined with `and`, so `udp port 53` means `udp and port 53`. The empty
+/// filter matches every packet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filter {
+    expr: Option<Expr>,
+}
+
+impl Filter {
+    pub fn matches(&self, packet: &Packet) -> bool {
+        self.expr.as_ref().is_none_or(|expr| expr.matches(packet))
+    }
+
+    /// Whether this is the empty filter, which also passes frames that don't parse
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+}
+
+impl str::FromStr for Filter {
+    type Err = FilterParseError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(string);
+        if tokens.is_empty() {
+            return Ok(Filter { expr: None });
+        }
+
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            Some(token) => Err(FilterParseError::UnexpectedToken(token.to_string())),
+            None => Ok(Filter { expr: Some(expr) }),
+        }
+    }
+}
+
+/// Split on whitespace, with parentheses as tokens of their own
+fn tokenize(string: &str) -> Vec<String> {
+    string
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Result<&'a str, FilterParseError> {
+        let token = self.peek().ok_or(FilterParseError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterParseError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterParseError> {
+        let mut expr = self.not()?;
+        loop {
+            match self.peek() {
+                Some("and") => self.pos += 1,
+                // Juxtaposed primitives are an implicit and
+                Some(token) if token != "or" && token != ")" => (),
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, FilterParseError> {
+        if self.peek() == Some("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primitive()
+    }
+
+    fn primitive(&mut self) -> Result<Expr, FilterParseError> {
+        let token = self.next()?;
+        let direction = match token {
+            "(" => {
+                let expr = self.or()?;
+                return match self.next()? {
+                    ")" => Ok(expr),
+                    other => Err(FilterParseError::UnexpectedToken(other.to_string())),
+                };
+            }
+            "tcp" => return Ok(Expr::Protocol(Protocol::Tcp)),
+            "udp" => return Ok(Expr::Protocol(Protocol::Udp)),
+            "icmp" => return Ok(Expr::Protocol(Protocol::Icmp)),
+            "arp" => return Ok(Expr::Protocol(Protocol::Arp)),
+            "src" => Direction::Src,
+            "dst" => Direction::Dst,
+            "host" | "port" => {
+                self.pos -= 1;
+                Direction::Either
+            }
+            other => return Err(FilterParseError::UnexpectedToken(other.to_string())),
+        };
+
+        match self.peek() {
+            Some("host") => {
+                self.pos += 1;
+                self.host(direction)
+            }
+            Some("port") => {
+                self.pos += 1;
+                let port = self.next()?;
+                port.parse()
+                    .map(|port| Expr::Port(direction, port))
+                    .map_err(|_| FilterParseError::InvalidPort(port.to_string()))
+            }
+            // `src 10.0.0.1` is short for `src host 10.0.0.1`
+            _ => self.host(direction),
+        }
+    }
+
+    fn host(&mut self, direction: Direction) -> Result<Expr, FilterParseError> {
+        let addr = self.next()?;
+        addr.parse()
+            .map(|addr| Expr::Host(direction, addr))
+            .map_err(|_| FilterParseError::InvalidAddress(addr.to_string()))
+    }
+}
+
+fn direction_matches<T: PartialEq>(direction: Direction, src: T, dst: T, value: T) -> bool {
+    match direction {
+        Direction::Either => src == value || dst == value,
+        Direction::Src => src == value,
+        Direction::Dst => dst == value,
+    }
+}
+
+impl Expr {
+    fn matches(&self, packet: &Packet) -> bool {
+        match *self {
+            Expr::Protocol(protocol) => matches!(
+                (protocol, packet),
+                (Protocol::Tcp, &Packet::Ipv4(_, Transport::Tcp(_)))
+                    | (Protocol::Udp, &Packet::Ipv4(_, Transport::Udp(_)))
+                    | (Protocol::Icmp, &Packet::Ipv4(_, Transport::Icmp))
+                    | (Protocol::Arp, &Packet::Arp(_))
+            ),
+            Expr::Host(direction, addr) => match *packet {
+                Packet::Ipv4(ref ip, _) => direction_matches(direction, ip.header.src, ip.header.dst, addr),
+                Packet::Arp(ref arp) => direction_matches(direction, arp.header.src_ip, arp.header.dst_ip, addr),
+                Packet::Other(_) => false,
+            },
+            Expr::Port(direction, port) => match *packet {
+                Packet::Ipv4(_, Transport::Tcp(ref tcp)) => {
+                    direction_matches(direction, tcp.header.src.get(), tcp.header.dst.get(), port)
+                }
+                Packet::Ipv4(_, Transport::Udp(ref udp)) => {
+                    direction_matches(direction, udp.header.src.get(), udp.header.dst.get(), port)
+                }
+                _ => false,
+            },
+            Expr::Not(ref expr) => !expr.matches(packet),
+            Expr::And(ref a, ref b) => a.matches(packet) && b.matches(packet),
+            Expr::Or(ref a, ref b) => a.matches(packet) || b.matches(packet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Expr, Filter, FilterParseError, Protocol};
+    use super::super::{IpProtocol, Ipv4, Ipv4Addr};
+    use packet::{Packet, Transport};
+    use tcp::TcpConnection;
+    use udp::Udp;
+
+    fn parse(string: &str) -> Option<Expr> {
+        string.parse::<Filter>().unwrap().expr
+    }
+
+    #[test]
+    fn parse_expressions() {
+        let addr = Ipv4Addr::from_str("10.0.0.1");
+        assert_eq!(parse(""), None);
+        assert!("  ".parse::<Filter>().unwrap().is_empty());
+        assert!(!"arp".parse::<Filter>().unwrap().is_empty());
+        assert_eq!(parse("host 10.0.0.1"), Some(Expr::Host(Direction::Either, addr)));
+        assert_eq!(parse("src 10.0.0.1"), Some(Expr::Host(Direction::Src, addr)));
+        assert_eq!(parse("udp port 53"), Some(Expr::And(
+            Box::new(Expr::Protocol(Protocol::Udp)),
+            Box::new(Expr::Port(Direction::Either, 53)),
+        )));
+        assert_eq!(parse("not tcp or dst port 80"), Some(Expr::Or(
+            Box::new(Expr::Not(Box::new(Expr::Protocol(Protocol::Tcp)))),
+            Box::new(Expr::Port(Direction::Dst, 80)),
+        )));
+        assert_eq!(parse("arp or icmp and host 10.0.0.1"), parse("arp or (icmp and host 10.0.0.1)"));
+
+        assert_eq!("tcp and".parse::<Filter>(), Err(FilterParseError::UnexpectedEnd));
+        assert_eq!("(tcp".parse::<Filter>(), Err(FilterParseError::UnexpectedEnd));
+        assert_eq!("tcp)".parse::<Filter>(), Err(FilterParseError::UnexpectedToken(")".to_string())));
+        assert_eq!("port 70000".parse::<Filter>(), Err(FilterParseError::InvalidPort("70000".to_string())));
+        assert_eq!("host example".parse::<Filter>(), Err(FilterParseError::InvalidAddress("example".to_string())));
+        assert_eq!("ip6".parse::<Filter>(), Err(FilterParseError::UnexpectedToken("ip6".to_string())));
+    }
+
+    #[test]
+    fn evaluate() {
+        let a = Ipv4Addr::from_str("10.0.0.1");
+        let b = Ipv4Addr::from_str("10.0.0.2");
+        let dns = Udp::from_bytes(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]).unwrap();
+        let dns = Packet::Ipv4(Ipv4::new(a, b, IpProtocol::Udp, dns.to_bytes()), Transport::Udp(dns));
+        let syn = TcpConnection::new(40000, 80, 1000).syn();
+        let web = Packet::Ipv4(Ipv4::new(b, a, IpProtocol::Tcp, syn.to_bytes()), Transport::Tcp(syn));
+        let ping = Packet::Ipv4(Ipv4::new(a, b, IpProtocol::Icmp, vec![8, 0, 0, 0]), Transport::Icmp);
+
+        let matching = |expr: &str| -> Vec<bool> {
+            let filter: Filter = expr.parse().unwrap();
+            vec![filter.matches(&dns), filter.matches(&web), filter.matches(&ping)]
+        };
+        assert_eq!(matching(""), vec![true, true, true]);
+        assert_eq!(matching("udp port 53"), vec![true, false, false]);
+        assert_eq!(matching("tcp and port 80"), vec![false, true, false]);
+        assert_eq!(matching("src host 10.0.0.1"), vec![true, false, true]);
+        assert_eq!(matching("dst 10.0.0.1"), vec![false, true, false]);
+        assert_eq!(matching("not icmp"), vec![true, true, false]);
+        assert_eq!(matching("icmp or port 40000"), vec![false, true, true]);
+        assert_eq!(matching("host 10.0.0.9 or arp"), vec![false, false, false]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Harness for fuzzing the packet parsers, built with the `arbitrary` feature
+//!
+//! A `cargo fuzz` target only needs to pass its input to `fuzz_roundtrip`.
+//! The packet structs also implement `arbitrary::Arbitrary`, for targets that
+//! start from a structured packet and check `to_bytes` instead.
+
+use std::convert::TryFrom;
+
+use super::{Arp, EthernetII, Ipv4};
+use icmp::Icmp;
+use packet::parse_ethernet;
+use tcp::Tcp;
+use udp::Udp;
+
+/// Parse, serialize and parse again, checking that the second parse gives
+/// back the same bytes
+fn roundtrip<T, F>(bytes: &[u8], to_bytes: F)
+where
+    T: for<'a> TryFrom<&'a [u8]>,
+    F: Fn(&T) -> Vec<u8>,
+{
+    let first = match T::try_from(bytes) {
+        Ok(packet) => to_bytes(&packet),
+        Err(_) => return,
+    };
+    let second = match T::try_from(&first[..]) {
+        Ok(packet) => packet,
+        Err(_) => panic!("serialized packet does not parse again: {:?}", first),
+    };
+    assert_eq!(to_bytes(&second), first, "packet changed on the second round trip");
+}
+
+/// Run every parser over `bytes`, panicking if one of them panics or does not
+/// round trip
+pub fn fuzz_roundtrip(bytes: &[u8]) {
+    roundtrip(bytes, EthernetII::to_bytes);
+    roundtrip(bytes, Arp::to_bytes);
+    roundtrip(bytes, Ipv4::to_bytes);
+    roundtrip(bytes, Tcp::to_bytes);
+    roundtrip(bytes, Udp::to_bytes);
+    roundtrip(bytes, Icmp::to_bytes);
+
+    if let Some(ip) = Ipv4::from_bytes(bytes) {
+        ip.options();
+    }
+    if let Some(icmp) = Icmp::from_bytes(bytes) {
+        icmp.embedded_packet();
+    }
+    parse_ethernet(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzz_roundtrip;
+    use arbitrary::{Arbitrary, Unstructured};
+    use super::super::Ipv4;
+
+    /// Seed corpus: a time exceeded message quoting a UDP probe, and a DNS query
+    const SEEDS: [&[u8]; 2] = [
+        &[
+            0x45, 0x00, 0x00, 0x38, 0x9a, 0x02, 0x00, 0x00, 0x40, 0x01, 0x5d, 0x0d, 0xc0, 0xa8, 0x01, 0x01,
+            0xc0, 0xa8, 0x01, 0x64, 0x0b, 0x00, 0x81, 0xbb, 0x00, 0x00, 0x00, 0x00, 0x45, 0x00, 0x00, 0x3c,
+            0x3c, 0x1d, 0x00, 0x00, 0x01, 0x11, 0xab, 0x78, 0xc0, 0xa8, 0x01, 0x64, 0x08, 0x08, 0x08, 0x08,
+            0xd4, 0x31, 0x82, 0x9b, 0x00, 0x28, 0x1c, 0x4f,
+        ],
+        &[0x30, 0x39, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 0x12, 0x34, 0x01, 0x00],
+    ];
+
+    #[test]
+    fn seeds_and_truncations() {
+        for seed in SEEDS.iter() {
+            for len in 0..=seed.len() {
+                fuzz_roundtrip(&seed[..len]);
+            }
+        }
+        // Header lengths pointing past the end or below the minimum
+        fuzz_roundtrip(&[0x4F; 24]);
+        fuzz_roundtrip(&[0x41; 24]);
+    }
+
+    #[test]
+    fn arbitrary_packets() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut input = Unstructured::new(&data);
+        while !input.is_empty() {
+            let ip = Ipv4::arbitrary(&mut input).unwrap();
+            fuzz_roundtrip(&ip.to_bytes());
+        }
+    }
+}
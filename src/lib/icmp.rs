@@ -0,0 +1,228 @@
+use super::{n32, require, wire, Checksum, Ipv4, Ipv4Header, ParseError};
+use std::convert::TryFrom;
+use std::{cmp, fmt, mem, slice};
+
+pub const ECHO_REPLY: u8 = 0;
+pub const DEST_UNREACHABLE: u8 = 3;
+pub const ECHO_REQUEST: u8 = 8;
+pub const TIME_EXCEEDED: u8 = 11;
+
+/// ICMP header as defined in RFC 792
+///
+/// The meaning of `rest` depends on the type: identifier and sequence number
+/// for echo messages, the next hop MTU for "fragmentation needed", and unused
+/// for time exceeded.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[repr(packed)]
+pub struct IcmpHeader {
+    pub kind: u8,
+    pub code: u8,
+    pub checksum: Checksum,
+    pub rest: n32,
+}
+
+impl IcmpHeader {
+    /// Read the header field by field, without casting the buffer
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        require(bytes, mem::size_of::<IcmpHeader>())?;
+        let mut reader = wire::Reader::new(bytes);
+        Ok(IcmpHeader {
+            kind: reader.u8()?,
+            code: reader.u8()?,
+            checksum: reader.checksum()?,
+            rest: n32::new(reader.u32()?),
+        })
+    }
+
+    /// Append the header to `out` field by field, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.kind);
+        out.push(self.code);
+        wire::write_checksum(out, self.checksum);
+        wire::write_u32_be(out, self.rest.get());
+    }
+}
+
+/// ICMP message consisting of header and data section
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Icmp {
+    pub header: IcmpHeader,
+    pub data: Vec<u8>,
+}
+
+/// The header of an ICMP message decoded by type
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IcmpMessage {
+    EchoReply { id: u16, seq: u16 },
+    EchoRequest { id: u16, seq: u16 },
+    /// `code` 4 is "fragmentation needed", for which routers following
+    /// RFC 1191 fill in `next_hop_mtu`
+    DestinationUnreachable { code: u8, next_hop_mtu: u16 },
+    /// `code` 0 when the TTL ran out in transit, 1 when reassembly timed out
+    TimeExceeded { code: u8 },
+    Other { kind: u8, code: u8 },
+}
+
+impl<'a> TryFrom<&'a [u8]> for Icmp {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        require(bytes, mem::size_of::<IcmpHeader>())?;
+        Ok(Icmp {
+            header: unsafe { *(bytes.as_ptr() as *const IcmpHeader) },
+            data: bytes[mem::size_of::<IcmpHeader>()..].to_vec(),
+        })
+    }
+}
+
+impl Icmp {
+    /// Build a message with the checksum filled in
+    pub fn new(kind: u8, code: u8, rest: u32, data: Vec<u8>) -> Self {
+        let mut icmp = Icmp {
+            header: IcmpHeader {
+                kind: kind,
+                code: code,
+                checksum: Checksum { data: 0 },
+                rest: n32::new(rest),
+            },
+            data: data,
+        };
+        icmp.checksum();
+        icmp
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Icmp::try_from(bytes).ok()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.to_bytes_into(&mut ret);
+        ret
+    }
+
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        let header_ptr: *const IcmpHeader = &self.header;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<IcmpHeader>())
+        });
+        out.extend_from_slice(&self.data);
+    }
+
+    fn sum(&self) -> usize {
+        unsafe {
+            Checksum::sum((&self.header as *const IcmpHeader) as usize, mem::size_of::<IcmpHeader>()) +
+            Checksum::sum(self.data.as_ptr() as usize, self.data.len())
+        }
+    }
+
+    /// Compute the checksum, which covers the header and the data
+    pub fn checksum(&mut self) {
+        self.header.checksum.data = 0;
+        self.header.checksum.data = Checksum::compile(self.sum());
+    }
+
+    pub fn is_valid(&self) -> bool {
+        Checksum::compile(self.sum()) == 0
+    }
+
+    pub fn message(&self) -> IcmpMessage {
+        let rest = self.header.rest.get();
+        match self.header.kind {
+            ECHO_REPLY => IcmpMessage::EchoReply { id: (rest >> 16) as u16, seq: rest as u16 },
+            ECHO_REQUEST => IcmpMessage::EchoRequest { id: (rest >> 16) as u16, seq: rest as u16 },
+            DEST_UNREACHABLE => IcmpMessage::DestinationUnreachable {
+                code: self.header.code,
+                next_hop_mtu: rest as u16,
+            },
+            TIME_EXCEEDED => IcmpMessage::TimeExceeded { code: self.header.code },
+            kind => IcmpMessage::Other { kind: kind, code: self.header.code },
+        }
+    }
+
+    /// The packet that caused a destination unreachable or time exceeded error
+    ///
+    /// Routers quote only the IP header and the first 8 bytes of its data, so
+    /// `data` is usually shorter than the total length in the header says.
+    /// That is enough for the ports of a UDP or TCP probe, or the identifier
+    /// and sequence number of an echo request. Returns `None` for other
+    /// message types and for quotes too short to hold the IP header.
+    pub fn embedded_packet(&self) -> Option<Ipv4> {
+        match self.header.kind {
+            DEST_UNREACHABLE | TIME_EXCEEDED => (),
+            _ => return None,
+        }
+
+        let bytes = &self.data;
+        require(bytes, mem::size_of::<Ipv4Header>()).ok()?;
+        let header = unsafe { *(bytes.as_ptr() as *const Ipv4Header) };
+        let header_len = header.header_len();
+        if header_len < mem::size_of::<Ipv4Header>() || header_len > bytes.len() {
+            return None;
+        }
+        let end = cmp::min(cmp::max(header.len.get() as usize, header_len), bytes.len());
+
+        Some(Ipv4 {
+            header: header,
+            options: bytes[mem::size_of::<Ipv4Header>()..header_len].to_vec(),
+            data: bytes[header_len..end].to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for Icmp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message() {
+            IcmpMessage::EchoReply { id, seq } => write!(f, "ICMP echo reply id={} seq={}", id, seq),
+            IcmpMessage::EchoRequest { id, seq } => write!(f, "ICMP echo request id={} seq={}", id, seq),
+            IcmpMessage::DestinationUnreachable { code, .. } => write!(f, "ICMP destination unreachable code={}", code),
+            IcmpMessage::TimeExceeded { code } => write!(f, "ICMP time exceeded code={}", code),
+            IcmpMessage::Other { kind, code } => write!(f, "ICMP type={} code={}", kind, code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Icmp, IcmpMessage, ECHO_REQUEST};
+    use super::super::{IpProtocol, Ipv4, Ipv4Addr};
+
+    #[test]
+    fn time_exceeded() {
+        // The first hop answering a UDP probe to 8.8.8.8:33435 sent with a TTL of 1
+        let captured = [
+            0x45, 0x00, 0x00, 0x38, 0x9a, 0x02, 0x00, 0x00, 0x40, 0x01, 0x5d, 0x0d, 0xc0, 0xa8, 0x01, 0x01,
+            0xc0, 0xa8, 0x01, 0x64, 0x0b, 0x00, 0x81, 0xbb, 0x00, 0x00, 0x00, 0x00, 0x45, 0x00, 0x00, 0x3c,
+            0x3c, 0x1d, 0x00, 0x00, 0x01, 0x11, 0xab, 0x78, 0xc0, 0xa8, 0x01, 0x64, 0x08, 0x08, 0x08, 0x08,
+            0xd4, 0x31, 0x82, 0x9b, 0x00, 0x28, 0x1c, 0x4f,
+        ];
+        let ip = Ipv4::from_bytes(&captured).unwrap();
+        assert_eq!(ip.protocol(), IpProtocol::Icmp);
+        assert_eq!({ ip.header.src }, Ipv4Addr::from_str("192.168.1.1"));
+
+        let icmp = Icmp::from_bytes(&ip.data).unwrap();
+        assert!(icmp.is_valid());
+        assert_eq!(icmp.message(), IcmpMessage::TimeExceeded { code: 0 });
+
+        // Only the header and eight bytes of the 60 byte probe are quoted
+        let probe = icmp.embedded_packet().unwrap();
+        assert_eq!(probe.total_len(), 60);
+        assert_eq!(probe.ttl(), 1);
+        assert_eq!(probe.id(), 0x3c1d);
+        assert_eq!({ probe.header.dst }, Ipv4Addr::from_str("8.8.8.8"));
+        assert_eq!(probe.data.len(), 8);
+        assert_eq!(u16::from_be_bytes([probe.data[2], probe.data[3]]), 33435);
+    }
+
+    #[test]
+    fn echo() {
+        let request = Icmp::new(ECHO_REQUEST, 0, 0x1234_0007, b"ping".to_vec());
+        let parsed = Icmp::from_bytes(&request.to_bytes()).unwrap();
+        assert!(parsed.is_valid());
+        assert_eq!(parsed.message(), IcmpMessage::EchoRequest { id: 0x1234, seq: 7 });
+        assert_eq!(parsed.data, b"ping");
+        assert!(parsed.embedded_packet().is_none());
+    }
+}
@@ -1,4 +1,8 @@
+use std::convert::TryFrom;
+use std::{net, ops, str};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Ipv4Addr {
     pub bytes: [u8; 4],
 }
@@ -27,6 +31,15 @@ impl Ipv4Addr {
         addr
     }
 
+    /// Address from exactly four raw bytes in network order
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        <[u8; 4]>::try_from(bytes).ok().map(|bytes| Ipv4Addr { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
     pub fn to_string(&self) -> String {
         let mut string = String::new();
 
@@ -39,4 +52,229 @@ impl Ipv4Addr {
 
         string
     }
+
+    /// Host-order integer value of the address
+    pub fn to_u32(&self) -> u32 {
+        (self.bytes[0] as u32) << 24 | (self.bytes[1] as u32) << 16 |
+        (self.bytes[2] as u32) << 8 | self.bytes[3] as u32
+    }
+
+    /// Build an address from its host-order integer value
+    pub fn from_u32(value: u32) -> Self {
+        Ipv4Addr {
+            bytes: [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8],
+        }
+    }
+
+    /// The address after this one, `None` past 255.255.255.255
+    pub fn next(&self) -> Option<Ipv4Addr> {
+        self.offset(1)
+    }
+
+    /// The address before this one, `None` below 0.0.0.0
+    pub fn prev(&self) -> Option<Ipv4Addr> {
+        self.offset(-1)
+    }
+
+    /// The address `delta` steps away, or `None` when that leaves the address space
+    pub fn offset(&self, delta: i64) -> Option<Ipv4Addr> {
+        let value = (self.to_u32() as i64).checked_add(delta)?;
+        if value >= 0 && value <= u32::MAX as i64 {
+            Some(Ipv4Addr::from_u32(value as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Parse a comma, space or newline separated list, returning the addresses
+    /// and the entries that are not valid dotted quads
+    pub fn parse_list(string: &str) -> (Vec<Ipv4Addr>, Vec<String>) {
+        ::parse_list(string)
+    }
+
+    /// Usable host addresses of the `network/prefix` subnet.
+    ///
+    /// The network and broadcast addresses are skipped for prefixes up to 30.
+    /// A /31 yields both addresses (RFC 3021) and a /32 yields the single address.
+    pub fn hosts(network: Ipv4Addr, prefix: u8) -> Ipv4Range {
+        let prefix = if prefix > 32 { 32 } else { prefix };
+        let mask = if prefix == 0 { 0 } else { !0u32 << (32 - prefix) };
+        let first = network.to_u32() & mask;
+        let last = first | !mask;
+
+        if prefix >= 31 {
+            Ipv4Range::new(Ipv4Addr::from_u32(first), Ipv4Addr::from_u32(last))
+        } else {
+            Ipv4Range::new(Ipv4Addr::from_u32(first + 1), Ipv4Addr::from_u32(last - 1))
+        }
+    }
+}
+
+/// Wrapping addition, so `255.255.255.255 + 1` is `0.0.0.0`; see `offset` to detect overflow
+impl ops::Add<u32> for Ipv4Addr {
+    type Output = Ipv4Addr;
+
+    fn add(self, rhs: u32) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.to_u32().wrapping_add(rhs))
+    }
+}
+
+/// Wrapping subtraction, the counterpart of `Add<u32>`
+impl ops::Sub<u32> for Ipv4Addr {
+    type Output = Ipv4Addr;
+
+    fn sub(self, rhs: u32) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.to_u32().wrapping_sub(rhs))
+    }
+}
+
+/// Inclusive range of IPv4 addresses, iterated in ascending order
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Ipv4Range {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+}
+
+impl Ipv4Range {
+    pub fn new(start: Ipv4Addr, end: Ipv4Addr) -> Self {
+        Ipv4Range {
+            start: start,
+            end: end,
+        }
+    }
+
+    pub fn contains(&self, addr: &Ipv4Addr) -> bool {
+        self.start <= *addr && *addr <= self.end
+    }
+}
+
+impl Iterator for Ipv4Range {
+    type Item = Ipv4Addr;
+
+    fn next(&mut self) -> Option<Ipv4Addr> {
+        if self.start > self.end {
+            return None;
+        }
+
+        let addr = self.start;
+        if addr == self.end {
+            // Leave an empty range behind without overflowing at 255.255.255.255
+            self.start = Ipv4Addr::BROADCAST;
+            self.end = Ipv4Addr::NULL;
+        } else {
+            self.start = Ipv4Addr::from_u32(addr.to_u32() + 1);
+        }
+        Some(addr)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.start > self.end {
+            (0, Some(0))
+        } else {
+            // A /0 range has 2^32 addresses, one more than a 32-bit usize holds
+            match ((self.end.to_u32() - self.start.to_u32()) as usize).checked_add(1) {
+                Some(len) => (len, Some(len)),
+                None => (usize::MAX, None),
+            }
+        }
+    }
+}
+
+impl str::FromStr for Ipv4Addr {
+    type Err = net::AddrParseError;
+
+    /// Strict dotted quad parse, unlike the inherent `from_str`
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        string.parse::<net::Ipv4Addr>().map(|addr| Ipv4Addr { bytes: addr.octets() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Ipv4Addr, Ipv4Range};
+
+    #[test]
+    fn u32_round_trip() {
+        let addr = Ipv4Addr::from_str("192.168.1.42");
+        assert_eq!(addr.to_u32(), 0xC0A8012A);
+        assert_eq!(addr, Ipv4Addr::from_u32(0xC0A8012A));
+        assert_eq!(Ipv4Addr::BROADCAST, Ipv4Addr::from_u32(!0));
+    }
+
+    #[test]
+    fn from_bytes_test() {
+        let bytes = [192, 168, 1, 42, 0];
+        let addr = Ipv4Addr::from_bytes(&bytes[..4]).unwrap();
+        assert_eq!(addr, Ipv4Addr::from_str("192.168.1.42"));
+        assert_eq!(addr.as_bytes(), &bytes[..4]);
+        assert_eq!(Ipv4Addr::from_bytes(&bytes[..3]), None);
+        assert_eq!(Ipv4Addr::from_bytes(&bytes), None);
+        assert_eq!(Ipv4Addr::from_bytes(&[]), None);
+    }
+
+    #[test]
+    fn hosts_test() {
+        let hosts: Vec<Ipv4Addr> = Ipv4Addr::hosts(Ipv4Addr::from_str("10.0.0.9"), 29).collect();
+        assert_eq!(hosts.len(), 6);
+        assert_eq!(hosts[0], Ipv4Addr::from_str("10.0.0.9"));
+        assert_eq!(hosts[5], Ipv4Addr::from_str("10.0.0.14"));
+
+        let hosts: Vec<Ipv4Addr> = Ipv4Addr::hosts(Ipv4Addr::from_str("10.0.0.0"), 29).collect();
+        assert_eq!(hosts.first(), Some(&Ipv4Addr::from_str("10.0.0.1")));
+        assert_eq!(hosts.last(), Some(&Ipv4Addr::from_str("10.0.0.6")));
+
+        let hosts: Vec<Ipv4Addr> = Ipv4Addr::hosts(Ipv4Addr::from_str("10.0.0.5"), 31).collect();
+        assert_eq!(hosts, vec![Ipv4Addr::from_str("10.0.0.4"), Ipv4Addr::from_str("10.0.0.5")]);
+
+        let hosts: Vec<Ipv4Addr> = Ipv4Addr::hosts(Ipv4Addr::from_str("10.0.0.5"), 32).collect();
+        assert_eq!(hosts, vec![Ipv4Addr::from_str("10.0.0.5")]);
+    }
+
+    #[test]
+    fn range_end_test() {
+        let range = Ipv4Range::new(Ipv4Addr::from_str("255.255.255.254"), Ipv4Addr::BROADCAST);
+        assert_eq!(range.size_hint(), (2, Some(2)));
+        assert_eq!(range.count(), 2);
+
+        let range = Ipv4Range::new(Ipv4Addr::NULL, Ipv4Addr::BROADCAST);
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(range.size_hint(), (usize::MAX, None));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(range.size_hint(), (1 << 32, Some(1 << 32)));
+
+        let range = Ipv4Range::new(Ipv4Addr::from_str("10.0.0.2"), Ipv4Addr::from_str("10.0.0.1"));
+        assert_eq!(range.count(), 0);
+    }
+
+    #[test]
+    fn parse_list_test() {
+        let (addrs, rejected) = Ipv4Addr::parse_list("10.0.0.1,10.0.0.2\n 192.168.1.1\t,,300.1.1.1 foo\n");
+        assert_eq!(addrs, vec![
+            Ipv4Addr::from_str("10.0.0.1"),
+            Ipv4Addr::from_str("10.0.0.2"),
+            Ipv4Addr::from_str("192.168.1.1"),
+        ]);
+        assert_eq!(rejected, vec!["300.1.1.1".to_string(), "foo".to_string()]);
+
+        assert_eq!(Ipv4Addr::parse_list(""), (vec![], vec![]));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let addr = Ipv4Addr::from_str("10.0.0.255");
+        assert_eq!(addr.next(), Some(Ipv4Addr::from_str("10.0.1.0")));
+        assert_eq!(addr.prev(), Some(Ipv4Addr::from_str("10.0.0.254")));
+        assert_eq!(addr.offset(257), Some(Ipv4Addr::from_str("10.0.2.0")));
+        assert_eq!(addr.offset(-256), Some(Ipv4Addr::from_str("9.255.255.255")));
+        assert_eq!(addr.offset(0), Some(addr));
+        assert_eq!(addr + 2, Ipv4Addr::from_str("10.0.1.1"));
+        assert_eq!(addr - 256, Ipv4Addr::from_str("9.255.255.255"));
+
+        assert_eq!(Ipv4Addr::BROADCAST.next(), None);
+        assert_eq!(Ipv4Addr::NULL.prev(), None);
+        assert_eq!(Ipv4Addr::NULL.offset(i64::MAX), None);
+        assert_eq!(Ipv4Addr::BROADCAST.offset(-(u32::MAX as i64)), Some(Ipv4Addr::NULL));
+        assert_eq!(Ipv4Addr::BROADCAST + 1, Ipv4Addr::NULL);
+        assert_eq!(Ipv4Addr::NULL - 1, Ipv4Addr::BROADCAST);
+    }
 }
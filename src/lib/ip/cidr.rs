@@ -0,0 +1,246 @@
+use std::{error, fmt, str};
+
+use super::addr::Ipv4Addr;
+
+/// An address with its prefix length, as in `10.0.2.15/24`
+///
+/// The address keeps its host bits, so an interface address round trips
+/// through parsing and `Display`. Use `network` for the masked address.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Ipv4Cidr {
+    pub addr: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl Ipv4Cidr {
+    /// Panics if `prefix` is larger than 32
+    pub fn new(addr: Ipv4Addr, prefix: u8) -> Self {
+        assert!(prefix <= 32, "IPv4 prefix length {} is larger than 32", prefix);
+        Ipv4Cidr { addr, prefix }
+    }
+
+    /// Parse a comma, space or newline separated list, returning the
+    /// `addr/prefix` entries and the ones that are not valid
+    pub fn parse_list(string: &str) -> (Vec<Ipv4Cidr>, Vec<String>) {
+        ::parse_list(string)
+    }
+
+    /// Build from an address and a dotted netmask like 255.255.255.0, `None`
+    /// when the mask has a zero bit before a one bit
+    pub fn from_netmask(addr: Ipv4Addr, netmask: Ipv4Addr) -> Option<Self> {
+        let mask = netmask.to_u32();
+        let prefix = (!mask).leading_zeros();
+        if mask.checked_shl(prefix).unwrap_or(0) == 0 {
+            Some(Ipv4Cidr::new(addr, prefix as u8))
+        } else {
+            None
+        }
+    }
+
+    fn mask(&self) -> u32 {
+        if self.prefix == 0 { 0 } else { !0u32 << (32 - self.prefix) }
+    }
+
+    /// The prefix as a dotted mask, e.g. 255.255.255.0 for /24
+    pub fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.mask())
+    }
+
+    /// The address with its host bits cleared
+    pub fn network(&self) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.addr.to_u32() & self.mask())
+    }
+
+    /// The address with its host bits set, which for /31 and /32 is an ordinary host
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.addr.to_u32() | !self.mask())
+    }
+
+    /// Whether `ip` is in the same subnet
+    pub fn contains(&self, ip: &Ipv4Addr) -> bool {
+        ip.to_u32() & self.mask() == self.network().to_u32()
+    }
+}
+
+impl fmt::Display for Ipv4Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr.to_string(), self.prefix)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CidrParseError {
+    /// No `/prefix` after the address
+    MissingPrefix,
+    /// The part before the slash is not a dotted quad
+    InvalidAddr,
+    /// The part after the slash is not a number from 0 to 32
+    InvalidPrefix,
+}
+
+impl fmt::Display for CidrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CidrParseError::MissingPrefix => write!(f, "CIDR block must be address/prefix"),
+            CidrParseError::InvalidAddr => write!(f, "CIDR address is not a valid IPv4 address"),
+            CidrParseError::InvalidPrefix => write!(f, "CIDR prefix is not a number from 0 to 32"),
+        }
+    }
+}
+
+impl error::Error for CidrParseError {}
+
+impl str::FromStr for Ipv4Cidr {
+    type Err = CidrParseError;
+
+    /// Parses `a.b.c.d/prefix`, the prefix in decimal
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let slash = string.find('/').ok_or(CidrParseError::MissingPrefix)?;
+        let (addr, prefix) = (&string[..slash], &string[slash + 1..]);
+        let addr = addr.parse::<Ipv4Addr>().map_err(|_| CidrParseError::InvalidAddr)?;
+        if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(CidrParseError::InvalidPrefix);
+        }
+        match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= 32 => Ok(Ipv4Cidr::new(addr, prefix)),
+            _ => Err(CidrParseError::InvalidPrefix),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OfferError {
+    /// The subnet mask has a zero bit before a one bit
+    NonContiguousMask,
+    /// The offered address is the network or broadcast address of its subnet
+    NotHost,
+    /// The router is not in the offered subnet
+    RouterOutsideSubnet,
+    /// The router is the network or broadcast address, or the offered address itself
+    RouterNotHost,
+}
+
+impl fmt::Display for OfferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OfferError::NonContiguousMask => write!(f, "subnet mask is not contiguous"),
+            OfferError::NotHost => write!(f, "offered address is not a host address in its subnet"),
+            OfferError::RouterOutsideSubnet => write!(f, "router is outside the offered subnet"),
+            OfferError::RouterNotHost => write!(f, "router is not a usable host address"),
+        }
+    }
+}
+
+impl error::Error for OfferError {}
+
+/// Check a lease offer before applying it: `yiaddr` must be a host of the
+/// subnet given by `mask`, and `router` another host of the same subnet
+///
+/// On /31 and /32 subnets every address is a host (RFC 3021).
+pub fn validate_offer(yiaddr: Ipv4Addr, mask: Ipv4Addr, router: Ipv4Addr) -> Result<(), OfferError> {
+    let cidr = Ipv4Cidr::from_netmask(yiaddr, mask).ok_or(OfferError::NonContiguousMask)?;
+    let is_host = |ip: Ipv4Addr| cidr.prefix >= 31 || (ip != cidr.network() && ip != cidr.broadcast());
+    if !is_host(yiaddr) {
+        return Err(OfferError::NotHost);
+    }
+    if !cidr.contains(&router) {
+        return Err(OfferError::RouterOutsideSubnet);
+    }
+    if !is_host(router) || router == yiaddr {
+        return Err(OfferError::RouterNotHost);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_offer, CidrParseError, Ipv4Cidr, OfferError};
+    use ip::Ipv4Addr;
+
+    #[test]
+    fn parse_test() {
+        let cidr: Ipv4Cidr = "10.0.2.15/24".parse().unwrap();
+        assert_eq!(cidr, Ipv4Cidr::new(Ipv4Addr::from_str("10.0.2.15"), 24));
+        assert_eq!(cidr.to_string(), "10.0.2.15/24");
+        assert_eq!(Ok(Ipv4Cidr::new(Ipv4Addr::NULL, 0)), "0.0.0.0/0".parse());
+        assert_eq!(Ok(Ipv4Cidr::new(Ipv4Addr::LOOPBACK, 32)), "127.0.0.1/32".parse());
+
+        assert_eq!(Err(CidrParseError::MissingPrefix), "10.0.2.15".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidPrefix), "10.0.2.15/".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidPrefix), "10.0.2.15/33".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidPrefix), "10.0.2.15/+8".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidPrefix), "10.0.2.15/24/8".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidPrefix), "10.0.2.15/255.255.255.0".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidAddr), "10.0.2/24".parse::<Ipv4Cidr>());
+        assert_eq!(Err(CidrParseError::InvalidAddr), "/24".parse::<Ipv4Cidr>());
+    }
+
+    #[test]
+    fn parse_list_test() {
+        let (cidrs, rejected) = Ipv4Cidr::parse_list("10.0.2.15/24\n192.168.1.2/16, 10.0.0.1\n");
+        assert_eq!(cidrs, vec![
+            Ipv4Cidr::new(Ipv4Addr::from_str("10.0.2.15"), 24),
+            Ipv4Cidr::new(Ipv4Addr::from_str("192.168.1.2"), 16),
+        ]);
+        assert_eq!(rejected, vec!["10.0.0.1".to_string()]);
+
+        assert_eq!(Ipv4Cidr::parse_list(""), (vec![], vec![]));
+    }
+
+    #[test]
+    fn subnet_bounds() {
+        let cases = [
+            ("10.0.2.15/24", "255.255.255.0", "10.0.2.0", "10.0.2.255"),
+            ("172.16.5.4/12", "255.240.0.0", "172.16.0.0", "172.31.255.255"),
+            ("192.168.1.77/30", "255.255.255.252", "192.168.1.76", "192.168.1.79"),
+            ("192.168.1.77/31", "255.255.255.254", "192.168.1.76", "192.168.1.77"),
+            ("192.168.1.77/32", "255.255.255.255", "192.168.1.77", "192.168.1.77"),
+            ("192.168.1.77/0", "0.0.0.0", "0.0.0.0", "255.255.255.255"),
+        ];
+        for &(cidr, netmask, network, broadcast) in cases.iter() {
+            let cidr: Ipv4Cidr = cidr.parse().unwrap();
+            assert_eq!(cidr.netmask(), Ipv4Addr::from_str(netmask));
+            assert_eq!(cidr.network(), Ipv4Addr::from_str(network));
+            assert_eq!(cidr.broadcast(), Ipv4Addr::from_str(broadcast));
+            assert_eq!(Ipv4Cidr::from_netmask(cidr.addr, cidr.netmask()), Some(cidr));
+        }
+        assert_eq!(Ipv4Cidr::from_netmask(Ipv4Addr::NULL, Ipv4Addr::from_str("255.0.255.0")), None);
+        assert_eq!(Ipv4Cidr::from_netmask(Ipv4Addr::NULL, Ipv4Addr::from_str("0.0.0.1")), None);
+    }
+
+    #[test]
+    fn contains_test() {
+        let cidr: Ipv4Cidr = "10.0.2.15/24".parse().unwrap();
+        assert!(cidr.contains(&Ipv4Addr::from_str("10.0.2.0")));
+        assert!(cidr.contains(&Ipv4Addr::from_str("10.0.2.2")));
+        assert!(cidr.contains(&Ipv4Addr::from_str("10.0.2.255")));
+        assert!(!cidr.contains(&Ipv4Addr::from_str("10.0.3.1")));
+        assert!(!cidr.contains(&Ipv4Addr::from_str("11.0.2.15")));
+
+        let all: Ipv4Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&Ipv4Addr::BROADCAST));
+        let host: Ipv4Cidr = "10.0.2.15/32".parse().unwrap();
+        assert!(host.contains(&Ipv4Addr::from_str("10.0.2.15")));
+        assert!(!host.contains(&Ipv4Addr::from_str("10.0.2.14")));
+    }
+
+    #[test]
+    fn offers() {
+        let ip = Ipv4Addr::from_str;
+        let mask = ip("255.255.255.0");
+        assert_eq!(validate_offer(ip("10.0.2.15"), mask, ip("10.0.2.2")), Ok(()));
+
+        assert_eq!(validate_offer(ip("10.0.2.15"), mask, ip("10.0.3.1")), Err(OfferError::RouterOutsideSubnet));
+        assert_eq!(validate_offer(ip("10.0.2.0"), mask, ip("10.0.2.2")), Err(OfferError::NotHost));
+        assert_eq!(validate_offer(ip("10.0.2.255"), mask, ip("10.0.2.2")), Err(OfferError::NotHost));
+        assert_eq!(validate_offer(ip("10.0.2.15"), mask, ip("10.0.2.255")), Err(OfferError::RouterNotHost));
+        assert_eq!(validate_offer(ip("10.0.2.15"), mask, ip("10.0.2.15")), Err(OfferError::RouterNotHost));
+        assert_eq!(
+            validate_offer(ip("10.0.2.15"), ip("255.0.255.0"), ip("10.0.2.2")),
+            Err(OfferError::NonContiguousMask)
+        );
+
+        // Point to point links have no network or broadcast address
+        assert!(validate_offer(ip("192.168.1.76"), ip("255.255.255.254"), ip("192.168.1.77")).is_ok());
+    }
+}
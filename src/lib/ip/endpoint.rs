@@ -0,0 +1,108 @@
+use std::{error, fmt, net, str};
+
+use super::addr::Ipv4Addr;
+
+/// An IPv4 address and port, the source or destination of a TCP or UDP packet
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Endpoint {
+    pub addr: Ipv4Addr,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub fn new(addr: Ipv4Addr, port: u16) -> Self {
+        Endpoint { addr, port }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.addr.to_string(), self.port)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EndpointParseError {
+    /// No `:port` after the address
+    MissingPort,
+    /// The part before the colon is not a dotted quad
+    InvalidAddr,
+    /// The part after the colon is not a number from 0 to 65535
+    InvalidPort,
+}
+
+impl fmt::Display for EndpointParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EndpointParseError::MissingPort => write!(f, "endpoint must be address:port"),
+            EndpointParseError::InvalidAddr => write!(f, "endpoint address is not a valid IPv4 address"),
+            EndpointParseError::InvalidPort => write!(f, "endpoint port is not a number from 0 to 65535"),
+        }
+    }
+}
+
+impl error::Error for EndpointParseError {}
+
+impl str::FromStr for Endpoint {
+    type Err = EndpointParseError;
+
+    /// Parses `a.b.c.d:port`, both parts required
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let colon = string.rfind(':').ok_or(EndpointParseError::MissingPort)?;
+        let (addr, port) = (&string[..colon], &string[colon + 1..]);
+        let addr = addr.parse::<Ipv4Addr>().map_err(|_| EndpointParseError::InvalidAddr)?;
+        if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(EndpointParseError::InvalidPort);
+        }
+        let port = port.parse::<u16>().map_err(|_| EndpointParseError::InvalidPort)?;
+        Ok(Endpoint::new(addr, port))
+    }
+}
+
+impl From<net::SocketAddrV4> for Endpoint {
+    fn from(addr: net::SocketAddrV4) -> Self {
+        Endpoint::new(Ipv4Addr { bytes: addr.ip().octets() }, addr.port())
+    }
+}
+
+impl From<Endpoint> for net::SocketAddrV4 {
+    fn from(endpoint: Endpoint) -> Self {
+        net::SocketAddrV4::new(net::Ipv4Addr::from(endpoint.addr.bytes), endpoint.port)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Endpoint, EndpointParseError};
+    use ip::Ipv4Addr;
+    use std::net::SocketAddrV4;
+
+    #[test]
+    fn parse_test() {
+        let endpoint = Endpoint::new(Ipv4Addr::from_str("1.2.3.4"), 80);
+        assert_eq!(Ok(endpoint), "1.2.3.4:80".parse());
+        assert_eq!(Ok(Endpoint::new(Ipv4Addr::NULL, 0)), "0.0.0.0:0".parse());
+        assert_eq!(Ok(Endpoint::new(Ipv4Addr::BROADCAST, 65535)), "255.255.255.255:65535".parse());
+
+        assert_eq!(Err(EndpointParseError::MissingPort), "1.2.3.4".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidPort), "1.2.3.4:".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidPort), "1.2.3.4:65536".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidPort), "1.2.3.4:+80".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidPort), "1.2.3.4:http".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidAddr), "1.2.3:80".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidAddr), "1.2.3.4:80:80".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidAddr), ":80".parse::<Endpoint>());
+        assert_eq!(Err(EndpointParseError::InvalidAddr), "example.com:80".parse::<Endpoint>());
+    }
+
+    #[test]
+    fn display_and_conversions() {
+        let endpoint: Endpoint = "10.0.2.15:8080".parse().unwrap();
+        assert_eq!(endpoint.to_string(), "10.0.2.15:8080");
+        assert_eq!(Ok(endpoint), endpoint.to_string().parse());
+
+        let addr: SocketAddrV4 = endpoint.into();
+        assert_eq!(addr, "10.0.2.15:8080".parse().unwrap());
+        assert_eq!(Endpoint::from(addr), endpoint);
+    }
+}
@@ -1,3 +1,7 @@
-pub use self::addr::Ipv4Addr;
+pub use self::addr::{Ipv4Addr, Ipv4Range};
+pub use self::cidr::{validate_offer, CidrParseError, Ipv4Cidr, OfferError};
+pub use self::endpoint::{Endpoint, EndpointParseError};
 
 mod addr;
+mod cidr;
+mod endpoint;
@@ -0,0 +1,172 @@
+//! The fixed IPv6 header of RFC 8200 and the pseudo header TCP and UDP
+//! checksums cover when they run over IPv6
+use std::net::Ipv6Addr;
+
+use super::{require, wire, IpProtocol, ParseError};
+
+/// Length of the fixed header, extension headers come after it
+pub const IPV6_HEADER_LEN: usize = 40;
+
+/// The fixed part of an IPv6 header, with the version checked on parse
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Ipv6Header {
+    pub traffic_class: u8,
+    /// Only the low 20 bits are sent
+    pub flow_label: u32,
+    /// Length of everything after this header, extension headers included
+    pub payload_len: u16,
+    /// Protocol number of the header that follows, as in the IPv4 `proto` field
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+}
+
+impl Ipv6Header {
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        require(bytes, IPV6_HEADER_LEN)?;
+        let mut reader = wire::Reader::new(bytes);
+        let first = reader.u32()?;
+        if first >> 28 != 6 {
+            return Err(ParseError::InconsistentHeader);
+        }
+        Ok(Ipv6Header {
+            traffic_class: (first >> 20) as u8,
+            flow_label: first & 0xF_FFFF,
+            payload_len: reader.u16()?,
+            next_header: reader.u8()?,
+            hop_limit: reader.u8()?,
+            src: Ipv6Addr::from(reader.array::<16>()?),
+            dst: Ipv6Addr::from(reader.array::<16>()?),
+        })
+    }
+
+    /// Parse the first 40 bytes, `None` if they are missing or not version 6
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Ipv6Header::parse(bytes).ok()
+    }
+
+    /// Append the header to `out`, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let first = 6 << 28 | (self.traffic_class as u32) << 20 | self.flow_label & 0xF_FFFF;
+        wire::write_u32_be(out, first);
+        wire::write_u16_be(out, self.payload_len);
+        out.push(self.next_header);
+        out.push(self.hop_limit);
+        out.extend_from_slice(&self.src.octets());
+        out.extend_from_slice(&self.dst.octets());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(IPV6_HEADER_LEN);
+        self.encode(&mut bytes);
+        bytes
+    }
+
+    pub fn protocol(&self) -> IpProtocol {
+        IpProtocol::from_u8(self.next_header)
+    }
+}
+
+/// The pseudo header of RFC 8200 section 8.1, summed before an upper-layer
+/// packet of `len` bytes to get its checksum.
+///
+/// Unlike the IPv4 one it has the full addresses and a 32-bit length, with
+/// the protocol number last.
+pub fn pseudo_header(src: &Ipv6Addr, dst: &Ipv6Addr, len: u32, next_header: u8) -> [u8; 40] {
+    let mut header = [0; 40];
+    header[..16].copy_from_slice(&src.octets());
+    header[16..32].copy_from_slice(&dst.octets());
+    header[32..36].copy_from_slice(&len.to_be_bytes());
+    header[39] = next_header;
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pseudo_header, Ipv6Header};
+    use std::net::Ipv6Addr;
+    use tcp::{Tcp, TcpHeader, TCP_SYN};
+    use udp::Udp;
+    use {n16, n32, Checksum, IpProtocol, ParseError};
+
+    // mDNS-style datagram, fe80::5054:ff:fe12:3456 to ff02::fb, port 5353 to
+    // 5353 carrying "hello\n", checksum 0x0fe9
+    const PACKET: [u8; 54] = [
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x0e, 0x11, 0xff,
+        0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x50, 0x54, 0x00, 0xff, 0xfe, 0x12, 0x34, 0x56,
+        0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfb,
+        0x14, 0xe9, 0x14, 0xe9, 0x00, 0x0e, 0x0f, 0xe9,
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x0a,
+    ];
+
+    #[test]
+    fn header_round_trip() {
+        let header = Ipv6Header::from_bytes(&PACKET).unwrap();
+        assert_eq!(header.payload_len, 14);
+        assert_eq!(header.protocol(), IpProtocol::Udp);
+        assert_eq!(header.hop_limit, 255);
+        assert_eq!(header.src, "fe80::5054:ff:fe12:3456".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(header.dst, "ff02::fb".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(header.to_bytes(), &PACKET[..40]);
+
+        let classed = Ipv6Header { traffic_class: 0xb8, flow_label: 0x1_2345, ..header };
+        assert_eq!(&classed.to_bytes()[..4], &[0x6b, 0x81, 0x23, 0x45]);
+        assert_eq!(Ipv6Header::from_bytes(&classed.to_bytes()), Some(classed));
+
+        assert_eq!(Ipv6Header::parse(&PACKET[..39]), Err(ParseError::TooShort { needed: 40, got: 39 }));
+        let mut ipv4 = PACKET;
+        ipv4[0] = 0x45;
+        assert_eq!(Ipv6Header::parse(&ipv4), Err(ParseError::InconsistentHeader));
+    }
+
+    #[test]
+    fn udp_checksum() {
+        let header = Ipv6Header::from_bytes(&PACKET).unwrap();
+        let mut udp = Udp::from_bytes(&PACKET[40..]).unwrap();
+        assert!(udp.is_valid_v6(&header.src, &header.dst));
+        assert!(!udp.is_valid_v6(&header.src, &Ipv6Addr::LOCALHOST));
+
+        udp.header.checksum.data = 0;
+        // A zero checksum is not allowed over IPv6
+        assert!(!udp.is_valid_v6(&header.src, &header.dst));
+        udp.checksum_v6(&header.src, &header.dst);
+        assert_eq!(udp.to_bytes(), &PACKET[40..]);
+
+        udp.data[0] = b'j';
+        assert!(!udp.is_valid_v6(&header.src, &header.dst));
+    }
+
+    #[test]
+    fn tcp_checksum() {
+        let src = "fe80::5054:ff:fe12:3456".parse::<Ipv6Addr>().unwrap();
+        let dst = "ff02::fb".parse::<Ipv6Addr>().unwrap();
+        let mut syn = Tcp {
+            header: TcpHeader {
+                src: n16::new(40000),
+                dst: n16::new(80),
+                sequence: n32::new(1),
+                ack_num: n32::new(0),
+                flags: n16::new(5 << 12 | TCP_SYN),
+                window_size: n16::new(65535),
+                checksum: Checksum { data: 0 },
+                urgent_pointer: n16::new(0),
+            },
+            options: Vec::new(),
+            data: Vec::new(),
+        };
+        syn.checksum_v6(&src, &dst);
+        assert_eq!(&syn.to_bytes()[16..18], &[0x91, 0x16]);
+        assert!(syn.is_valid_v6(&src, &dst));
+        assert!(!syn.is_valid_v6(&src, &Ipv6Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn pseudo_header_layout() {
+        let header = pseudo_header(&Ipv6Addr::LOCALHOST, &Ipv6Addr::UNSPECIFIED, 0x0102_0304, 17);
+        assert_eq!(header[15], 1);
+        assert_eq!(&header[16..32], &[0; 16]);
+        assert_eq!(&header[32..], &[1, 2, 3, 4, 0, 0, 0, 17]);
+    }
+}
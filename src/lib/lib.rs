@@ -1,14 +1,87 @@
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+extern crate libredox;
+
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{Result, Read, Write};
-use std::{mem, slice, u8, u16};
+use std::{cmp, error, fmt, mem, slice, str, u8, u16};
 
-pub use ip::Ipv4Addr;
-pub use mac::MacAddr;
+pub use arp_cache::ArpCache;
+pub use ip::{validate_offer, CidrParseError, Endpoint, EndpointParseError, Ipv4Addr, Ipv4Cidr, Ipv4Range, OfferError};
+pub use ipv6::Ipv6Header;
+pub use mac::{MacAddr, MacAddrParseError};
+pub use netcfg::{NetCfg, NetCfgError};
+pub use port::{PortRange, PortRangeParseError};
+pub use watch::{ConfigChange, ConfigWatcher};
 
+mod arp_cache;
+pub mod date;
+pub mod dns;
+pub mod filter;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod icmp;
 mod ip;
+pub mod ipv6;
+pub mod link;
 mod mac;
+pub mod netcfg;
+pub mod ntp;
+pub mod packet;
+pub mod pcap;
+mod port;
 pub mod tcp;
+pub mod tempdir;
 pub mod udp;
+pub mod watch;
+pub mod wire;
+
+/// Split a list on commas and whitespace and parse every entry, returning the
+/// parsed values and the entries that were rejected
+fn parse_list<T: str::FromStr>(string: &str) -> (Vec<T>, Vec<String>) {
+    let mut parsed = Vec::new();
+    let mut rejected = Vec::new();
+    for token in string.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty()) {
+        match token.parse() {
+            Ok(value) => parsed.push(value),
+            Err(_) => rejected.push(token.to_string()),
+        }
+    }
+    (parsed, rejected)
+}
+
+/// Why a byte slice could not be parsed as a packet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// Fewer bytes than the header, or a length it declares, requires
+    TooShort { needed: usize, got: usize },
+    /// A length field holds a value smaller than its own header
+    BadLength,
+    /// Header fields that contradict each other
+    InconsistentHeader,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::TooShort { needed, got } => write!(f, "packet too short, needed {} bytes but got {}", needed, got),
+            ParseError::BadLength => write!(f, "length field is smaller than the header"),
+            ParseError::InconsistentHeader => write!(f, "header fields are inconsistent"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Fail with `TooShort` unless `bytes` holds at least `needed` bytes
+fn require(bytes: &[u8], needed: usize) -> ::std::result::Result<(), ParseError> {
+    if bytes.len() >= needed {
+        Ok(())
+    } else {
+        Err(ParseError::TooShort { needed: needed, got: bytes.len() })
+    }
+}
 
 pub fn getcfg(key: &str) -> Result<String> {
     let mut value = String::new();
@@ -25,7 +98,8 @@ pub fn setcfg(key: &str, value: &str) -> Result<()> {
     Ok(())
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[allow(non_camel_case_types)]
 #[repr(packed)]
 pub struct n16(u16);
@@ -44,7 +118,21 @@ impl n16 {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+/// Shows the host order value, `n16(20)` rather than the swapped raw field
+impl fmt::Debug for n16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "n16({})", self.get())
+    }
+}
+
+impl fmt::Display for n16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.get(), f)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[allow(non_camel_case_types)]
 #[repr(packed)]
 pub struct n32(u32);
@@ -63,25 +151,62 @@ impl n32 {
     }
 }
 
+/// Shows the host order value, `n32(20)` rather than the swapped raw field
+impl fmt::Debug for n32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "n32({})", self.get())
+    }
+}
+
+impl fmt::Display for n32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.get(), f)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Checksum {
     pub data: u16,
 }
 
 impl Checksum {
-    pub unsafe fn sum(mut ptr: usize, mut len: usize) -> usize {
+    /// Sum the `len` bytes at `ptr`, which need not be aligned
+    pub unsafe fn sum(ptr: usize, len: usize) -> usize {
+        Checksum::sum_chunks(&[slice::from_raw_parts(ptr as *const u8, len)])
+    }
+
+    /// Sum several regions as if they were one buffer, in the same byte order
+    /// as `sum`. A chunk of odd length leaves its last byte to pair with the
+    /// first byte of the next one, where summing each chunk on its own would
+    /// pad it with zero and shift every later word by a byte.
+    pub fn sum_chunks(chunks: &[&[u8]]) -> usize {
         let mut sum = 0;
+        let mut pending = None;
+        for &chunk in chunks {
+            let mut bytes = chunk;
+            if let Some(first) = pending.take() {
+                match bytes.split_first() {
+                    Some((&second, rest)) => {
+                        sum += u16::from_ne_bytes([first, second]) as usize;
+                        bytes = rest;
+                    },
+                    None => {
+                        pending = Some(first);
+                        continue;
+                    },
+                }
+            }
 
-        while len > 1 {
-            sum += *(ptr as *const u16) as usize;
-            len -= 2;
-            ptr += 2;
+            let mut words = bytes.chunks_exact(2);
+            for word in &mut words {
+                sum += u16::from_ne_bytes([word[0], word[1]]) as usize;
+            }
+            pending = words.remainder().first().cloned();
         }
-
-        if len > 0 {
-            sum += *(ptr as *const u8) as usize;
+        if let Some(last) = pending {
+            sum += u16::from_ne_bytes([last, 0]) as usize;
         }
-
         sum
     }
 
@@ -92,9 +217,30 @@ impl Checksum {
 
         0xFFFF - (sum as u16)
     }
+
+    /// Adjust a checksum after one 16-bit word it covers changed from
+    /// `old_word` to `new_word`, using `HC' = ~(~HC + ~m + m')` (RFC 1624).
+    ///
+    /// All three values must be in the same byte order, for instance all
+    /// host order as returned by `n16::get`.
+    pub fn update(old_check: u16, old_word: u16, new_word: u16) -> u16 {
+        Checksum::compile(!old_check as usize + !old_word as usize + new_word as usize)
+    }
+
+    /// Adjust a checksum after a 32-bit field such as an address changed,
+    /// with the check and values in host order
+    pub fn update_u32(old_check: u16, old_value: u32, new_value: u32) -> u16 {
+        let check = Checksum::update(old_check, (old_value >> 16) as u16, (new_value >> 16) as u16);
+        Checksum::update(check, old_value as u16, new_value as u16)
+    }
 }
 
+/// Operation codes for the `oper` field of the ARP header
+pub const ARP_REQUEST: u16 = 1;
+pub const ARP_REPLY: u16 = 2;
+
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(packed)]
 pub struct ArpHeader {
     pub htype: n16,
@@ -108,37 +254,108 @@ pub struct ArpHeader {
     pub dst_ip: Ipv4Addr,
 }
 
+impl ArpHeader {
+    /// Read the header field by field, without casting the buffer
+    pub fn parse(bytes: &[u8]) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<ArpHeader>())?;
+        let mut reader = wire::Reader::new(bytes);
+        Ok(ArpHeader {
+            htype: n16::new(reader.u16()?),
+            ptype: n16::new(reader.u16()?),
+            hlen: reader.u8()?,
+            plen: reader.u8()?,
+            oper: n16::new(reader.u16()?),
+            src_mac: reader.mac()?,
+            src_ip: reader.ipv4()?,
+            dst_mac: reader.mac()?,
+            dst_ip: reader.ipv4()?,
+        })
+    }
+
+    /// Append the header to `out` field by field, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        wire::write_u16_be(out, self.htype.get());
+        wire::write_u16_be(out, self.ptype.get());
+        out.push(self.hlen);
+        out.push(self.plen);
+        wire::write_u16_be(out, self.oper.get());
+        out.extend_from_slice(&{ self.src_mac }.bytes);
+        out.extend_from_slice(&{ self.src_ip }.bytes);
+        out.extend_from_slice(&{ self.dst_mac }.bytes);
+        out.extend_from_slice(&{ self.dst_ip }.bytes);
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Arp {
     pub header: ArpHeader,
     pub data: Vec<u8>,
 }
 
+impl<'a> TryFrom<&'a [u8]> for Arp {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<ArpHeader>())?;
+        Ok(Arp {
+            header: unsafe { *(bytes.as_ptr() as *const ArpHeader) },
+            data: bytes[mem::size_of::<ArpHeader>() ..].to_vec(),
+        })
+    }
+}
+
 impl Arp {
-    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() >= mem::size_of::<ArpHeader>() {
-            unsafe {
-                return Some(Arp {
-                    header: *(bytes.as_ptr() as *const ArpHeader),
-                    data: bytes[mem::size_of::<ArpHeader>() ..].to_vec(),
-                });
-            }
+    /// Ethernet/IPv4 ARP packet, the only kind in use
+    fn ethernet_ipv4(oper: u16, src_mac: MacAddr, src_ip: Ipv4Addr, dst_mac: MacAddr, dst_ip: Ipv4Addr) -> Self {
+        Arp {
+            header: ArpHeader {
+                htype: n16::new(1),
+                ptype: n16::new(packet::ETHERTYPE_IPV4),
+                hlen: 6,
+                plen: 4,
+                oper: n16::new(oper),
+                src_mac: src_mac,
+                src_ip: src_ip,
+                dst_mac: dst_mac,
+                dst_ip: dst_ip,
+            },
+            data: Vec::new(),
         }
-        None
+    }
+
+    /// Ask who has `dst_ip`, to be broadcast
+    pub fn request(src_mac: MacAddr, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> Self {
+        Arp::ethernet_ipv4(ARP_REQUEST, src_mac, src_ip, MacAddr::default(), dst_ip)
+    }
+
+    /// Answer a request from `dst_mac`/`dst_ip` saying `src_ip` is at `src_mac`
+    pub fn reply(src_mac: MacAddr, src_ip: Ipv4Addr, dst_mac: MacAddr, dst_ip: Ipv4Addr) -> Self {
+        Arp::ethernet_ipv4(ARP_REPLY, src_mac, src_ip, dst_mac, dst_ip)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Arp::try_from(bytes).ok()
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        unsafe {
-            let header_ptr: *const ArpHeader = &self.header;
-            let mut ret = Vec::from(slice::from_raw_parts(header_ptr as *const u8,
-                                                          mem::size_of::<ArpHeader>()));
-            ret.extend_from_slice(&self.data);
-            ret
-        }
+        let mut ret = Vec::new();
+        self.to_bytes_into(&mut ret);
+        ret
+    }
+
+    /// Append the wire representation to `out`
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        let header_ptr: *const ArpHeader = &self.header;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<ArpHeader>())
+        });
+        out.extend_from_slice(&self.data);
     }
 }
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(packed)]
 pub struct EthernetIIHeader {
     pub dst: MacAddr,
@@ -146,37 +363,354 @@ pub struct EthernetIIHeader {
     pub ethertype: n16,
 }
 
+impl EthernetIIHeader {
+    /// Read the header field by field, without casting the buffer
+    pub fn parse(bytes: &[u8]) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<EthernetIIHeader>())?;
+        let mut reader = wire::Reader::new(bytes);
+        Ok(EthernetIIHeader {
+            dst: reader.mac()?,
+            src: reader.mac()?,
+            ethertype: n16::new(reader.u16()?),
+        })
+    }
+
+    /// Append the header to `out` field by field, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&{ self.dst }.bytes);
+        out.extend_from_slice(&{ self.src }.bytes);
+        wire::write_u16_be(out, self.ethertype.get());
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct EthernetII {
     pub header: EthernetIIHeader,
     pub data: Vec<u8>,
 }
 
+impl<'a> TryFrom<&'a [u8]> for EthernetII {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<EthernetIIHeader>())?;
+        Ok(EthernetII {
+            header: unsafe { *(bytes.as_ptr() as *const EthernetIIHeader) },
+            data: bytes[mem::size_of::<EthernetIIHeader>() ..].to_vec(),
+        })
+    }
+}
+
 impl EthernetII {
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() >= mem::size_of::<EthernetIIHeader>() {
-            unsafe {
-                return Some(EthernetII {
-                    header: *(bytes.as_ptr() as *const EthernetIIHeader),
-                    data: bytes[mem::size_of::<EthernetIIHeader>() ..].to_vec(),
-                });
-            }
-        }
-        None
+        EthernetII::try_from(bytes).ok()
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        unsafe {
-            let header_ptr: *const EthernetIIHeader = &self.header;
-            let mut ret = Vec::from(slice::from_raw_parts(header_ptr as *const u8,
-                                                          mem::size_of::<EthernetIIHeader>()));
-            ret.extend_from_slice(&self.data);
-            ret
+        let mut ret = Vec::new();
+        self.to_bytes_into(&mut ret);
+        ret
+    }
+
+    /// Append the frame to `out`, so a whole stack of layers can be built
+    /// into one reused buffer instead of allocating per layer
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        let header_ptr: *const EthernetIIHeader = &self.header;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<EthernetIIHeader>())
+        });
+        out.extend_from_slice(&self.data);
+    }
+
+    /// The frame zero padded to `ETHERNET_MIN_FRAME_LEN`, as a short ARP or
+    /// TCP ACK has to be before the NIC sends it
+    pub fn to_bytes_padded(&self) -> Vec<u8> {
+        let mut ret = self.to_bytes();
+        if ret.len() < ETHERNET_MIN_FRAME_LEN {
+            ret.resize(ETHERNET_MIN_FRAME_LEN, 0);
+        }
+        ret
+    }
+
+    /// The padded frame followed by its frame check sequence, for raw
+    /// interfaces that do not add one themselves
+    pub fn with_fcs(&self) -> Vec<u8> {
+        let mut ret = self.to_bytes_padded();
+        let fcs = crc32(&ret);
+        ret.extend_from_slice(&fcs.to_le_bytes());
+        ret
+    }
+
+    /// Whether a received frame ends in a valid frame check sequence
+    pub fn check_fcs(bytes: &[u8]) -> bool {
+        if bytes.len() <= ETHERNET_FCS_LEN {
+            return false;
+        }
+        let (frame, fcs) = bytes.split_at(bytes.len() - ETHERNET_FCS_LEN);
+        crc32(frame).to_le_bytes() == fcs
+    }
+}
+
+/// Shortest Ethernet frame on the wire, not counting the FCS
+pub const ETHERNET_MIN_FRAME_LEN: usize = 60;
+/// Length of the CRC32 frame check sequence that ends every frame
+pub const ETHERNET_FCS_LEN: usize = 4;
+
+/// The IEEE 802.3 CRC32 used for the Ethernet FCS, computed bit by bit since
+/// frames are short and this is rarely on a hot path
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Ethertype of a frame with an 802.1Q tag in front of its real ethertype
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+
+/// The 802.1Q tag of a VLAN-tagged frame, unpacked from its 16-bit TCI
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VlanTag {
+    /// Priority code point, 0 to 7
+    pub pcp: u8,
+    /// Drop eligible indicator
+    pub dei: bool,
+    /// VLAN identifier, only the low 12 bits are sent
+    pub id: u16,
+}
+
+impl VlanTag {
+    pub fn from_tci(tci: u16) -> Self {
+        VlanTag {
+            pcp: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            id: tci & 0xFFF,
+        }
+    }
+
+    pub fn tci(&self) -> u16 {
+        (self.pcp as u16 & 7) << 13 | (self.dei as u16) << 12 | self.id & 0xFFF
+    }
+}
+
+/// Tagged frames parse like any other, with the header ethertype 0x8100 and the
+/// tag and inner ethertype as the first four bytes of `data`. These look past
+/// them, so `to_bytes` gives back the frame unchanged.
+impl EthernetII {
+    fn tag(&self) -> Option<(VlanTag, u16)> {
+        if self.header.ethertype.get() == ETHERTYPE_VLAN && self.data.len() >= 4 {
+            let tci = (self.data[0] as u16) << 8 | self.data[1] as u16;
+            let inner = (self.data[2] as u16) << 8 | self.data[3] as u16;
+            Some((VlanTag::from_tci(tci), inner))
+        } else {
+            None
+        }
+    }
+
+    /// The 802.1Q tag, `None` for an untagged frame
+    pub fn vlan(&self) -> Option<VlanTag> {
+        self.tag().map(|(tag, _)| tag)
+    }
+
+    /// Ethertype of the payload, the one after the tag when there is a tag
+    pub fn ethertype(&self) -> u16 {
+        match self.tag() {
+            Some((_, inner)) => inner,
+            None => self.header.ethertype.get(),
+        }
+    }
+
+    /// What `ethertype` describes, `data` without the tag
+    pub fn payload(&self) -> &[u8] {
+        match self.tag() {
+            Some(_) => &self.data[4..],
+            None => &self.data,
+        }
+    }
+
+    /// Tag the frame with `tag`, replacing the tag it already has
+    pub fn with_vlan(mut self, tag: VlanTag) -> Self {
+        let tci = tag.tci();
+        if self.tag().is_none() {
+            let ethertype = self.header.ethertype.get();
+            self.data.splice(0..0, [0, 0, (ethertype >> 8) as u8, ethertype as u8].iter().cloned());
+            self.header.ethertype = n16::new(ETHERTYPE_VLAN);
+        }
+        self.data[0] = (tci >> 8) as u8;
+        self.data[1] = tci as u8;
+        self
+    }
+}
+
+/// Time to live used by `Ipv4::new`
+pub const IPV4_DEFAULT_TTL: u8 = 64;
+
+/// Protocol numbers for the `proto` field of the IPv4 header
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IpProtocol {
+    Icmp,
+    Tcp,
+    Udp,
+    Other(u8),
+}
+
+impl IpProtocol {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => IpProtocol::Icmp,
+            6 => IpProtocol::Tcp,
+            17 => IpProtocol::Udp,
+            other => IpProtocol::Other(other),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            IpProtocol::Icmp => 1,
+            IpProtocol::Tcp => 6,
+            IpProtocol::Udp => 17,
+            IpProtocol::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for IpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IpProtocol::Icmp => write!(f, "ICMP"),
+            IpProtocol::Tcp => write!(f, "TCP"),
+            IpProtocol::Udp => write!(f, "UDP"),
+            IpProtocol::Other(other) => write!(f, "{}", other),
+        }
+    }
+}
+
+/// Room for options left by the four bit header length, in bytes
+const IPV4_MAX_OPTIONS_LEN: usize = 40;
+
+/// An option from the IPv4 header, see `Ipv4::options`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Ipv4Option {
+    /// End of the option list, anything after it is padding
+    End,
+    /// No operation, used to align the next option
+    Nop,
+    /// Route recorded by the hops so far, with `pointer` being the one based
+    /// offset of the next free slot
+    RecordRoute { pointer: u8, route: Vec<Ipv4Addr> },
+    /// Internet timestamps, each with the address of the hop when `flags` asks for it
+    Timestamp { pointer: u8, overflow: u8, flags: u8, entries: Vec<(Option<Ipv4Addr>, u32)> },
+    /// Any option that is not decoded, as its type and value bytes
+    Other { kind: u8, data: Vec<u8> },
+}
+
+impl Ipv4Option {
+    pub const END: u8 = 0;
+    pub const NOP: u8 = 1;
+    pub const RECORD_ROUTE: u8 = 7;
+    pub const TIMESTAMP: u8 = 68;
+
+    /// An empty record route option with room for `slots` addresses
+    pub fn record_route(slots: usize) -> Self {
+        Ipv4Option::RecordRoute {
+            pointer: 4,
+            route: vec![Ipv4Addr::NULL; slots],
+        }
+    }
+
+    /// Parse the option at the start of `bytes`, returning it and its length,
+    /// or `None` when its length does not fit the buffer
+    fn parse(bytes: &[u8]) -> Option<(Self, usize)> {
+        match *bytes.first()? {
+            Ipv4Option::END => return Some((Ipv4Option::End, 1)),
+            Ipv4Option::NOP => return Some((Ipv4Option::Nop, 1)),
+            _ => (),
+        }
+
+        let kind = bytes[0];
+        let len = *bytes.get(1)? as usize;
+        if len < 2 || len > bytes.len() {
+            return None;
+        }
+        let data = &bytes[2..len];
+
+        let option = match kind {
+            Ipv4Option::RECORD_ROUTE if !data.is_empty() && (data.len() - 1).is_multiple_of(4) => {
+                Ipv4Option::RecordRoute {
+                    pointer: data[0],
+                    route: data[1..].chunks(4).map(|addr| {
+                        Ipv4Addr { bytes: [addr[0], addr[1], addr[2], addr[3]] }
+                    }).collect(),
+                }
+            },
+            Ipv4Option::TIMESTAMP if data.len() >= 2 => {
+                let flags = data[1] & 0xF;
+                let with_addr = flags == 1 || flags == 3;
+                let entry_len = if with_addr { 8 } else { 4 };
+                if !(data.len() - 2).is_multiple_of(entry_len) {
+                    return Some((Ipv4Option::Other { kind: kind, data: data.to_vec() }, len));
+                }
+                Ipv4Option::Timestamp {
+                    pointer: data[0],
+                    overflow: data[1] >> 4,
+                    flags: flags,
+                    entries: data[2..].chunks(entry_len).map(|entry| {
+                        let (addr, time) = entry.split_at(entry_len - 4);
+                        let addr = if with_addr {
+                            Some(Ipv4Addr { bytes: [addr[0], addr[1], addr[2], addr[3]] })
+                        } else {
+                            None
+                        };
+                        (addr, u32::from_be_bytes([time[0], time[1], time[2], time[3]]))
+                    }).collect(),
+                }
+            },
+            _ => Ipv4Option::Other { kind: kind, data: data.to_vec() },
+        };
+        Some((option, len))
+    }
+
+    /// Append the type, length and value encoding of the option to `out`
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        match *self {
+            Ipv4Option::End => out.push(Ipv4Option::END),
+            Ipv4Option::Nop => out.push(Ipv4Option::NOP),
+            Ipv4Option::RecordRoute { pointer, ref route } => {
+                out.extend_from_slice(&[Ipv4Option::RECORD_ROUTE, (3 + route.len() * 4) as u8, pointer]);
+                for addr in route {
+                    out.extend_from_slice(&addr.bytes);
+                }
+            },
+            Ipv4Option::Timestamp { pointer, overflow, flags, ref entries } => {
+                let entry_len = if flags == 1 || flags == 3 { 8 } else { 4 };
+                out.extend_from_slice(&[
+                    Ipv4Option::TIMESTAMP,
+                    (4 + entries.len() * entry_len) as u8,
+                    pointer,
+                    overflow << 4 | flags & 0xF,
+                ]);
+                for &(addr, time) in entries {
+                    if entry_len == 8 {
+                        out.extend_from_slice(&addr.unwrap_or(Ipv4Addr::NULL).bytes);
+                    }
+                    out.extend_from_slice(&time.to_be_bytes());
+                }
+            },
+            Ipv4Option::Other { kind, ref data } => {
+                out.extend_from_slice(&[kind, (2 + data.len()) as u8]);
+                out.extend_from_slice(data);
+            },
         }
     }
 }
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(packed)]
 pub struct Ipv4Header {
     pub ver_hlen: u8,
@@ -191,7 +725,93 @@ pub struct Ipv4Header {
     pub dst: Ipv4Addr,
 }
 
+impl Ipv4Header {
+    /// Read the fixed part of the header field by field, without casting the
+    /// buffer. Options and the lengths are left for the caller to check.
+    pub fn parse(bytes: &[u8]) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<Ipv4Header>())?;
+        let mut reader = wire::Reader::new(bytes);
+        Ok(Ipv4Header {
+            ver_hlen: reader.u8()?,
+            services: reader.u8()?,
+            len: n16::new(reader.u16()?),
+            id: n16::new(reader.u16()?),
+            flags_fragment: n16::new(reader.u16()?),
+            ttl: reader.u8()?,
+            proto: reader.u8()?,
+            checksum: reader.checksum()?,
+            src: reader.ipv4()?,
+            dst: reader.ipv4()?,
+        })
+    }
+
+    /// Append the fixed part of the header to `out`, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.ver_hlen);
+        out.push(self.services);
+        wire::write_u16_be(out, self.len.get());
+        wire::write_u16_be(out, self.id.get());
+        wire::write_u16_be(out, self.flags_fragment.get());
+        out.push(self.ttl);
+        out.push(self.proto);
+        wire::write_checksum(out, self.checksum);
+        out.extend_from_slice(&{ self.src }.bytes);
+        out.extend_from_slice(&{ self.dst }.bytes);
+    }
+
+    /// IP version from the high nibble of `ver_hlen`, 4 for a valid header
+    pub fn version(&self) -> u8 {
+        self.ver_hlen >> 4
+    }
+
+    /// Set the version, keeping the header length, only the low four bits are used
+    pub fn set_version(&mut self, version: u8) {
+        self.ver_hlen = (version & 0xF) << 4 | (self.ver_hlen & 0xF);
+    }
+
+    /// Internet header length, the low nibble of `ver_hlen`, in 32-bit words
+    pub fn ihl(&self) -> u8 {
+        self.ver_hlen & 0xF
+    }
+
+    /// Set the header length in 32-bit words, keeping the version, only the low four bits are used
+    pub fn set_ihl(&mut self, ihl: u8) {
+        self.ver_hlen = (self.ver_hlen & 0xF0) | (ihl & 0xF);
+    }
+
+    /// Header length in bytes, options included
+    pub fn header_len(&self) -> usize {
+        (self.ihl() as usize) << 2
+    }
+
+    /// Set the header length in bytes, rounded down to a multiple of four
+    pub fn set_header_len(&mut self, len: usize) {
+        self.set_ihl((len >> 2) as u8);
+    }
+
+    /// Differentiated services code point, the upper six bits of `services`
+    pub fn dscp(&self) -> u8 {
+        self.services >> 2
+    }
+
+    /// Set the code point, keeping the ECN bits, only the low six bits are used
+    pub fn set_dscp(&mut self, dscp: u8) {
+        self.services = (dscp & 0x3F) << 2 | (self.services & 0x3);
+    }
+
+    /// Explicit congestion notification, the lower two bits of `services`
+    pub fn ecn(&self) -> u8 {
+        self.services & 0x3
+    }
+
+    /// Set the ECN bits, keeping the code point, only the low two bits are used
+    pub fn set_ecn(&mut self, ecn: u8) {
+        self.services = (self.services & 0xFC) | (ecn & 0x3);
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Ipv4 {
     pub header: Ipv4Header,
     pub options: Vec<u8>,
@@ -199,42 +819,841 @@ pub struct Ipv4 {
 }
 
 impl Ipv4 {
+    /// Build a packet with no options, the default TTL, and the length and checksum filled in
+    pub fn new(src: Ipv4Addr, dst: Ipv4Addr, proto: IpProtocol, data: Vec<u8>) -> Self {
+        let mut packet = Ipv4 {
+            header: Ipv4Header {
+                ver_hlen: 0x45,
+                services: 0,
+                len: n16::new(0),
+                id: n16::new(0),
+                flags_fragment: n16::new(0),
+                ttl: IPV4_DEFAULT_TTL,
+                proto: proto.to_u8(),
+                checksum: Checksum { data: 0 },
+                src: src,
+                dst: dst,
+            },
+            options: Vec::new(),
+            data: data,
+        };
+        packet.update();
+        packet
+    }
+
+    /// Set the options, zero padded to a multiple of four bytes
+    ///
+    /// Panics if the options do not fit in the header.
+    pub fn with_options(mut self, mut options: Vec<u8>) -> Self {
+        while !options.len().is_multiple_of(4) {
+            options.push(0);
+        }
+        assert!(options.len() <= IPV4_MAX_OPTIONS_LEN, "IPv4 options longer than {} bytes", IPV4_MAX_OPTIONS_LEN);
+        self.options = options;
+        self.update();
+        self
+    }
+
+    /// Append an option after the existing ones and recompute the header length
+    ///
+    /// Panics if the options would no longer fit in the header.
+    pub fn with_option(self, option: Ipv4Option) -> Self {
+        let mut options = Vec::new();
+        for existing in self.options() {
+            if existing != Ipv4Option::End {
+                existing.to_bytes_into(&mut options);
+            }
+        }
+        option.to_bytes_into(&mut options);
+        self.with_options(options)
+    }
+
+    /// Decode the options, stopping at the end of the list or at the first one
+    /// whose length runs past the options
+    pub fn options(&self) -> Vec<Ipv4Option> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while let Some((option, len)) = Ipv4Option::parse(&self.options[i..]) {
+            i += len;
+            let end = option == Ipv4Option::End;
+            options.push(option);
+            if end {
+                break;
+            }
+        }
+        options
+    }
+
+    pub fn with_ttl(mut self, ttl: u8) -> Self {
+        self.header.ttl = ttl;
+        self.update();
+        self
+    }
+
+    pub fn with_id(mut self, id: u16) -> Self {
+        self.header.id.set(id);
+        self.update();
+        self
+    }
+
+    pub fn protocol(&self) -> IpProtocol {
+        IpProtocol::from_u8(self.header.proto)
+    }
+
+    pub fn ttl(&self) -> u8 {
+        self.header.ttl
+    }
+
+    /// Total length of the packet as given in the header
+    pub fn total_len(&self) -> u16 {
+        self.header.len.get()
+    }
+
+    pub fn id(&self) -> u16 {
+        self.header.id.get()
+    }
+
+    /// Recompute the header length, total length and checksum
+    fn update(&mut self) {
+        let header_len = mem::size_of::<Ipv4Header>() + self.options.len();
+        self.header.set_version(4);
+        self.header.set_header_len(header_len);
+        self.header.len.set((header_len + self.data.len()) as u16);
+        self.checksum();
+    }
+
+    /// Compute the header checksum, which covers the options as well
     pub fn checksum(&mut self) {
         self.header.checksum.data = 0;
 
         self.header.checksum.data = Checksum::compile(unsafe {
-            Checksum::sum((&self.header as *const Ipv4Header) as usize, mem::size_of::<Ipv4Header>())
+            Checksum::sum((&self.header as *const Ipv4Header) as usize, mem::size_of::<Ipv4Header>()) +
+            Checksum::sum(self.options.as_ptr() as usize, self.options.len())
         });
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() >= mem::size_of::<Ipv4Header>() {
-            unsafe {
-                let header = *(bytes.as_ptr() as *const Ipv4Header);
-                let header_len = ((header.ver_hlen & 0xF) << 2) as usize;
-
-                if header_len >= mem::size_of::<Ipv4Header>() && header_len <= bytes.len()
-                    && header.len.get() as usize <= bytes.len() && header_len <= header.len.get() as usize
-                {
-                    return Some(Ipv4 {
-                        header: header,
-                        options: bytes[mem::size_of::<Ipv4Header>() .. header_len].to_vec(),
-                        data: bytes[header_len .. header.len.get() as usize].to_vec(),
-                    });
+        Ipv4::try_from(bytes).ok()
+    }
+
+    /// Like `from_bytes`, but keeps a packet whose total length runs past the
+    /// end of `bytes`, as in a capture cut short by its snap length. The flag
+    /// is true when `data` holds less than the header announces.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Option<(Self, bool)> {
+        Ipv4Ref::parse_lenient(bytes).ok().map(|packet| (packet.to_owned(), packet.is_truncated()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.to_bytes_into(&mut ret);
+        ret
+    }
+
+    /// Like `to_bytes`, but appends to `out` instead of allocating
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        let header_ptr: *const Ipv4Header = &self.header;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<Ipv4Header>())
+        });
+        out.extend_from_slice(&self.options);
+        out.extend_from_slice(&self.data);
+    }
+}
+
+/// Length of the IPv4 header at the start of `bytes` according to its IHL,
+/// when the header is at least the minimum 20 bytes and all there
+fn ipv4_header_len(bytes: &[u8]) -> Option<usize> {
+    let len = (*bytes.first()? & 0xF) as usize * 4;
+    if len < mem::size_of::<Ipv4Header>() || len > bytes.len() {
+        return None;
+    }
+    Some(len)
+}
+
+/// Checksum of the IPv4 header at the start of `bytes`, options included, in
+/// host order. The checksum field itself is skipped, so it may hold anything.
+///
+/// Only the header is covered, its length taken from the IHL. A slice shorter
+/// than that is checksummed as far as it goes.
+pub fn ipv4_header_checksum(bytes: &[u8]) -> u16 {
+    let len = ipv4_header_len(bytes).unwrap_or(bytes.len());
+    let header = &bytes[..len];
+    let (before, rest) = header.split_at(cmp::min(10, len));
+    let after = rest.get(2..).unwrap_or(&[]);
+    let check = Checksum::compile(Checksum::sum_chunks(&[before, &[0, 0], after]));
+    u16::from_be_bytes(check.to_ne_bytes())
+}
+
+/// Whether the IPv4 header at the start of `bytes` carries a correct checksum.
+/// A header shorter than 20 bytes or than its IHL never does.
+pub fn ipv4_checksum_valid(bytes: &[u8]) -> bool {
+    match ipv4_header_len(bytes) {
+        // A correct header, checksum included, sums to zero
+        Some(len) => Checksum::compile(Checksum::sum_chunks(&[&bytes[..len]])) == 0,
+        None => false,
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Ipv4 {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> ::std::result::Result<Self, ParseError> {
+        Ipv4Ref::try_from(bytes).map(|packet| packet.to_owned())
+    }
+}
+
+/// An IPv4 packet borrowed from the buffer it was received in, for loops that
+/// only look at headers and would waste time copying every payload into an `Ipv4`
+#[derive(Copy, Clone, Debug)]
+pub struct Ipv4Ref<'a> {
+    /// The packet up to its total length, already validated
+    bytes: &'a [u8],
+    /// Set by `parse_lenient` when `bytes` ends before the total length
+    truncated: bool,
+}
+
+impl<'a> TryFrom<&'a [u8]> for Ipv4Ref<'a> {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> ::std::result::Result<Self, ParseError> {
+        Ipv4Ref::parse(bytes, false)
+    }
+}
+
+impl<'a> Ipv4Ref<'a> {
+    /// Trailing bytes past the total length, such as Ethernet padding, are left
+    /// out. Missing ones fail the parse unless `lenient` is set.
+    fn parse(bytes: &'a [u8], lenient: bool) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<Ipv4Header>())?;
+        let header = unsafe { &*(bytes.as_ptr() as *const Ipv4Header) };
+        let header_len = header.header_len();
+        let total_len = header.len.get() as usize;
+
+        if header_len < mem::size_of::<Ipv4Header>() {
+            return Err(ParseError::BadLength);
+        }
+        require(bytes, header_len)?;
+        if !lenient {
+            require(bytes, total_len)?;
+        }
+        if header_len > total_len {
+            return Err(ParseError::InconsistentHeader);
+        }
+
+        let truncated = total_len > bytes.len();
+        Ok(Ipv4Ref {
+            bytes: &bytes[..cmp::min(total_len, bytes.len())],
+            truncated,
+        })
+    }
+
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        Ipv4Ref::try_from(bytes).ok()
+    }
+
+    /// Parse a packet that may have been cut short, keeping the payload that
+    /// is there. The header itself, options included, must be complete.
+    pub fn parse_lenient(bytes: &'a [u8]) -> ::std::result::Result<Self, ParseError> {
+        Ipv4Ref::parse(bytes, true)
+    }
+
+    /// Whether the payload is shorter than the total length in the header
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn header(&self) -> &'a Ipv4Header {
+        // Packed, so the header can be read in place at any alignment
+        unsafe { &*(self.bytes.as_ptr() as *const Ipv4Header) }
+    }
+
+    pub fn options(&self) -> &'a [u8] {
+        &self.bytes[mem::size_of::<Ipv4Header>() .. self.header().header_len()]
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[self.header().header_len() ..]
+    }
+
+    pub fn protocol(&self) -> IpProtocol {
+        IpProtocol::from_u8(self.header().proto)
+    }
+
+    /// Copy into an owned `Ipv4`, as `Ipv4::from_bytes` would have returned
+    pub fn to_owned(&self) -> Ipv4 {
+        Ipv4 {
+            header: *self.header(),
+            options: self.options().to_vec(),
+            data: self.payload().to_vec(),
+        }
+    }
+}
+
+/// One line summary, e.g. `IPv4 10.0.0.1 -> 10.0.0.2 proto=UDP ttl=64 len=48 id=0x1234`
+impl fmt::Display for Ipv4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IPv4 {} -> {} proto={} ttl={} len={} id={:#06x}",
+               self.header.src.to_string(),
+               self.header.dst.to_string(),
+               self.protocol(),
+               self.ttl(),
+               self.total_len(),
+               self.id())
+    }
+}
+
+/// One line tcpdump style summary of an Ethernet frame, for printing captures
+///
+/// ```text
+/// 52:54:00:12:34:56 > 52:55:0a:00:02:02 IPv4 10.0.2.15.40000 > 10.0.2.2.80: TCP SYN len 0
+/// 52:54:00:12:34:56 > ff:ff:ff:ff:ff:ff ARP who-has 10.0.2.2 tell 10.0.2.15
+/// ```
+///
+/// Layers that can't be decoded are summarized by their length instead, down
+/// to `unparseable N bytes` for a frame that is not even valid Ethernet.
+pub fn describe(frame: &[u8]) -> String {
+    let (ethernet, packet) = match (EthernetII::from_bytes(frame), packet::parse_ethernet(frame)) {
+        (Some(ethernet), Some(packet)) => (ethernet, packet),
+        _ => return format!("unparseable {} bytes", frame.len()),
+    };
+    let (src, dst) = (ethernet.header.src, ethernet.header.dst);
+
+    let summary = match packet {
+        packet::Packet::Arp(arp) => {
+            let header = arp.header;
+            match header.oper.get() {
+                ARP_REQUEST => format!("ARP who-has {} tell {}", header.dst_ip.to_string(), header.src_ip.to_string()),
+                ARP_REPLY => format!("ARP reply {} is-at {}", header.src_ip.to_string(), { header.src_mac }),
+                oper => format!("ARP oper {}", oper),
+            }
+        }
+        packet::Packet::Ipv4(ip, transport) => {
+            let (ip_src, ip_dst) = (ip.header.src.to_string(), ip.header.dst.to_string());
+            match transport {
+                packet::Transport::Tcp(tcp) => {
+                    let flags = tcp.flag_names();
+                    format!("IPv4 {}.{} > {}.{}: TCP {}{}len {}",
+                            ip_src, tcp.header.src.get(), ip_dst, tcp.header.dst.get(),
+                            flags.join(","), if flags.is_empty() { "" } else { " " }, tcp.data.len())
+                }
+                packet::Transport::Udp(udp) => {
+                    format!("IPv4 {}.{} > {}.{}: UDP len {}",
+                            ip_src, udp.header.src.get(), ip_dst, udp.header.dst.get(), udp.data.len())
+                }
+                packet::Transport::Icmp => match icmp::Icmp::from_bytes(&ip.data) {
+                    Some(icmp) => format!("IPv4 {} > {}: {}", ip_src, ip_dst, icmp),
+                    None => format!("IPv4 {} > {}: ICMP len {}", ip_src, ip_dst, ip.data.len()),
+                },
+                packet::Transport::Other => {
+                    let proto = match ip.protocol() {
+                        IpProtocol::Other(number) => format!("proto {}", number),
+                        // A later fragment of a TCP or UDP packet
+                        proto => proto.to_string(),
+                    };
+                    format!("IPv4 {} > {}: {} len {}", ip_src, ip_dst, proto, ip.data.len())
                 }
             }
         }
-        None
+        packet::Packet::Other(ethertype) => format!("ethertype {:#06x} len {}", ethertype, ethernet.payload().len()),
+    };
+    match ethernet.vlan() {
+        Some(tag) => format!("{} > {} vlan {} {}", src, dst, tag.id, summary),
+        None => format!("{} > {} {}", src, dst, summary),
     }
+}
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        unsafe {
-            let header_ptr: *const Ipv4Header = &self.header;
-            let mut ret = Vec::<u8>::from(slice::from_raw_parts(header_ptr as *const u8,
-                                                                mem::size_of::<Ipv4Header>()));
-            ret.extend_from_slice(&self.options);
-            ret.extend_from_slice(&self.data);
-            ret
-        }
+#[cfg(test)]
+mod tests {
+    use super::{crc32, describe, ipv4_checksum_valid, ipv4_header_checksum, n16, n32, Arp, Checksum, EthernetII, IpProtocol, Ipv4, Ipv4Addr, Ipv4Option, Ipv4Ref, ParseError, VlanTag};
+    use super::{ETHERNET_FCS_LEN, ETHERNET_MIN_FRAME_LEN, ETHERTYPE_VLAN, IPV4_DEFAULT_TTL};
+    use std::convert::TryFrom;
+    use tcp::Tcp;
+    use tcp::{TcpConnection, TcpRef, TCP_ACK};
+    use udp::{Udp, UdpRef};
+
+    #[test]
+    fn ipv4_builder_round_trip() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let packet = Ipv4::new(src, dst, IpProtocol::Udp, b"payload".to_vec())
+            .with_options(vec![1, 1, 1])
+            .with_ttl(32)
+            .with_id(0x1234);
+
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes.len(), 20 + 4 + 7);
+        assert_eq!(bytes[0], 0x46);
+
+        // A correct header checksums to zero
+        let sum = unsafe { Checksum::sum(bytes.as_ptr() as usize, 24) };
+        assert_eq!(Checksum::compile(sum), 0);
+
+        let parsed = Ipv4::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.header.len.get(), 31);
+        assert_eq!(parsed.header.id.get(), 0x1234);
+        assert_eq!(parsed.header.ttl, 32);
+        assert_eq!(IpProtocol::from_u8(parsed.header.proto), IpProtocol::Udp);
+        assert_eq!({ parsed.header.src }, src);
+        assert_eq!({ parsed.header.dst }, dst);
+        assert_eq!(parsed.options, vec![1, 1, 1, 0]);
+        assert_eq!(parsed.data, b"payload".to_vec());
+
+        let plain = Ipv4::new(src, dst, IpProtocol::Icmp, Vec::new());
+        assert_eq!(plain.header.ver_hlen, 0x45);
+        assert_eq!(plain.header.ttl, IPV4_DEFAULT_TTL);
+    }
+
+    #[test]
+    fn ipv4_packed_fields() {
+        let mut header = Ipv4::new(Ipv4Addr::NULL, Ipv4Addr::NULL, IpProtocol::Udp, Vec::new()).header;
+        assert_eq!((header.version(), header.ihl(), header.header_len()), (4, 5, 20));
+        assert_eq!((header.dscp(), header.ecn()), (0, 0));
+
+        header.set_ihl(0xF);
+        assert_eq!((header.ver_hlen, header.header_len()), (0x4F, 60));
+        header.set_version(6);
+        assert_eq!((header.ver_hlen, header.version(), header.ihl()), (0x6F, 6, 0xF));
+        header.set_header_len(24);
+        assert_eq!((header.ver_hlen, header.ihl()), (0x66, 6));
+        // Values wider than the field are truncated instead of spilling into the neighbour
+        header.set_version(0x14);
+        header.set_ihl(0x15);
+        assert_eq!(header.ver_hlen, 0x45);
+
+        // Expedited forwarding (46) with ECN capable transport (2)
+        header.set_dscp(46);
+        header.set_ecn(2);
+        assert_eq!((header.services, header.dscp(), header.ecn()), (0xBA, 46, 2));
+        header.set_ecn(0x7);
+        assert_eq!((header.services, header.dscp()), (0xBB, 46));
+        header.set_dscp(0xFF);
+        assert_eq!((header.services, header.dscp(), header.ecn()), (0xFF, 0x3F, 3));
+        header.set_dscp(0);
+        assert_eq!((header.services, header.ecn()), (0x03, 3));
+    }
+
+    #[test]
+    fn ipv4_options() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let hop = Ipv4Addr::from_str("192.168.0.1");
+
+        let packet = Ipv4::new(src, dst, IpProtocol::Icmp, b"ping".to_vec())
+            .with_option(Ipv4Option::Nop)
+            .with_option(Ipv4Option::record_route(3));
+        assert_eq!(packet.options.len(), 16);
+        assert_eq!(packet.header.ver_hlen, 0x49);
+        assert_eq!(packet.total_len(), 20 + 16 + 4);
+
+        // A router fills in the first slot
+        let mut bytes = packet.to_bytes();
+        bytes[20 + 3] = 8;
+        bytes[24..28].copy_from_slice(&hop.bytes);
+        let parsed = Ipv4::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.options(), vec![
+            Ipv4Option::Nop,
+            Ipv4Option::RecordRoute { pointer: 8, route: vec![hop, Ipv4Addr::NULL, Ipv4Addr::NULL] },
+        ]);
+
+        let timestamp = Ipv4Option::Timestamp { pointer: 5, overflow: 0, flags: 1, entries: vec![(Some(hop), 1000)] };
+        let packet = parsed.with_option(timestamp.clone());
+        assert_eq!(packet.options.len(), 28);
+        assert_eq!(packet.options()[2], timestamp);
+
+        // Padding shows up as the end of the list
+        let padded = Ipv4::new(src, dst, IpProtocol::Icmp, Vec::new()).with_option(Ipv4Option::Nop);
+        assert_eq!(padded.options, vec![1, 0, 0, 0]);
+        assert_eq!(padded.options(), vec![Ipv4Option::Nop, Ipv4Option::End]);
+
+        // A length running past the buffer ends the parse
+        let truncated = Ipv4::new(src, dst, IpProtocol::Icmp, Vec::new())
+            .with_options(vec![Ipv4Option::NOP, Ipv4Option::RECORD_ROUTE, 11, 4]);
+        assert_eq!(truncated.options(), vec![Ipv4Option::Nop]);
+        let unknown = Ipv4::new(src, dst, IpProtocol::Icmp, Vec::new()).with_options(vec![130, 4, 0xAB, 0xCD]);
+        assert_eq!(unknown.options(), vec![Ipv4Option::Other { kind: 130, data: vec![0xAB, 0xCD] }]);
+
+        // The header length field has room for 40 bytes of options and no more
+        let full = Ipv4::new(src, dst, IpProtocol::Icmp, Vec::new()).with_options(vec![Ipv4Option::NOP; 40]);
+        assert_eq!(full.header.header_len(), 60);
+    }
+
+    #[test]
+    #[should_panic(expected = "IPv4 options longer than 40 bytes")]
+    fn options_too_long() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        Ipv4::new(src, dst, IpProtocol::Icmp, Vec::new()).with_options(vec![Ipv4Option::NOP; 41]);
+    }
+
+    #[test]
+    fn padded_and_truncated() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let bytes = Ipv4::new(src, dst, IpProtocol::Udp, b"0123456789".to_vec()).to_bytes();
+
+        // Padding up to the Ethernet minimum is left out of the data
+        let mut padded = bytes.clone();
+        padded.resize(46, 0);
+        assert_eq!(Ipv4::from_bytes(&padded).unwrap().data, b"0123456789".to_vec());
+        let (packet, truncated) = Ipv4::from_bytes_lenient(&padded).unwrap();
+        assert_eq!(packet.data, b"0123456789".to_vec());
+        assert!(!truncated);
+
+        // A capture cut short keeps what it has, but only when asked to
+        let short = &bytes[..24];
+        assert!(Ipv4::from_bytes(short).is_none());
+        let (packet, truncated) = Ipv4::from_bytes_lenient(short).unwrap();
+        assert_eq!(packet.data, b"0123".to_vec());
+        assert_eq!(packet.total_len(), 30);
+        assert!(truncated);
+        let packet = Ipv4Ref::parse_lenient(short).unwrap();
+        assert!(packet.is_truncated());
+        assert_eq!(packet.payload(), b"0123");
+        assert!(!Ipv4Ref::from_bytes(&bytes).unwrap().is_truncated());
+
+        // The header has to be there in full either way
+        assert_eq!(Ipv4Ref::parse_lenient(&bytes[..19]).unwrap_err(), ParseError::TooShort { needed: 20, got: 19 });
+    }
+
+    #[test]
+    fn parse_errors() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let bytes = Ipv4::new(src, dst, IpProtocol::Udp, vec![0; 8]).to_bytes();
+
+        assert_eq!(Ipv4::try_from(&bytes[..]).unwrap().data.len(), 8);
+        assert_eq!(Ipv4::try_from(&bytes[..12]).unwrap_err(), ParseError::TooShort { needed: 20, got: 12 });
+        assert_eq!(Ipv4::try_from(&bytes[..24]).unwrap_err(), ParseError::TooShort { needed: 28, got: 24 });
+        let mut bad = bytes.clone();
+        bad[0] = 0x44;
+        assert_eq!(Ipv4::try_from(&bad[..]).unwrap_err(), ParseError::BadLength);
+        bad[0] = 0x46;
+        bad[2..4].copy_from_slice(&[0, 22]);
+        assert_eq!(Ipv4::try_from(&bad[..]).unwrap_err(), ParseError::InconsistentHeader);
+        assert!(Ipv4::from_bytes(&bad).is_none());
+        assert!(Ipv4::from_bytes_lenient(&bad).is_none());
+
+        assert_eq!(Arp::try_from(&[0; 27][..]).unwrap_err(), ParseError::TooShort { needed: 28, got: 27 });
+        assert_eq!(EthernetII::try_from(&[0; 13][..]).unwrap_err(), ParseError::TooShort { needed: 14, got: 13 });
+
+        assert_eq!(Udp::try_from(&[0; 7][..]).unwrap_err(), ParseError::TooShort { needed: 8, got: 7 });
+        assert_eq!(Udp::try_from(&[0, 1, 0, 2, 0, 4, 0, 0][..]).unwrap_err(), ParseError::BadLength);
+        assert_eq!(Udp::try_from(&[0, 1, 0, 2, 0, 12, 0, 0][..]).unwrap_err(), ParseError::TooShort { needed: 12, got: 8 });
+
+        let mut segment = TcpConnection::new(40000, 80, 1000).syn().to_bytes();
+        assert_eq!(Tcp::try_from(&segment[..19]).unwrap_err(), ParseError::TooShort { needed: 20, got: 19 });
+        segment[12] = 0x40;
+        assert_eq!(Tcp::try_from(&segment[..]).unwrap_err(), ParseError::BadLength);
+        segment[12] = 0x60;
+        assert_eq!(Tcp::try_from(&segment[..]).unwrap_err(), ParseError::TooShort { needed: 24, got: 20 });
+    }
+
+    #[test]
+    fn to_bytes_into_appends() {
+        let arp = Arp::from_bytes(&[
+            0, 1, 8, 0, 6, 4, 0, 1, 1, 2, 3, 4, 5, 6, 10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 10, 0, 0, 2,
+        ]).unwrap();
+        let udp = Udp::from_bytes(&[0x30, 0x39, 0, 53, 0, 11, 0, 0, b'a', b'b', b'c']).unwrap();
+        let tcp = TcpConnection::new(12345, 80, 1000).syn();
+
+        let mut out = vec![0xAA, 0xBB];
+        arp.to_bytes_into(&mut out);
+        assert_eq!(&out[..2], &[0xAA, 0xBB]);
+        assert_eq!(&out[2..], &arp.to_bytes()[..]);
+
+        out.truncate(1);
+        udp.to_bytes_into(&mut out);
+        assert_eq!(&out[1..], &udp.to_bytes()[..]);
+
+        out.clear();
+        tcp.to_bytes_into(&mut out);
+        assert_eq!(out, tcp.to_bytes());
+
+        // Build a stacked frame layer by layer into one buffer
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let ip = Ipv4::new(src, dst, IpProtocol::Udp, udp.to_bytes());
+        let frame = EthernetII::from_bytes(&[0; 14]).unwrap();
+
+        let mut stacked = Vec::new();
+        frame.to_bytes_into(&mut stacked);
+        let ip_header = Ipv4 { data: Vec::new(), ..ip.clone() };
+        ip_header.to_bytes_into(&mut stacked);
+        udp.to_bytes_into(&mut stacked);
+
+        let expected = EthernetII { data: ip.to_bytes(), ..frame };
+        assert_eq!(stacked, expected.to_bytes());
+    }
+
+    #[test]
+    fn ref_views_match_owned() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let udp = Udp::from_bytes(&[0x30, 0x39, 0, 53, 0, 11, 0, 0, b'a', b'b', b'c']).unwrap();
+        let ip = Ipv4::new(src, dst, IpProtocol::Udp, udp.to_bytes())
+            .with_option(Ipv4Option::Other { kind: 148, data: vec![0, 0] });
+        // Trailing link layer padding is not part of the packet
+        let mut bytes = ip.to_bytes();
+        bytes.extend_from_slice(&[0; 6]);
+
+        let view = Ipv4Ref::from_bytes(&bytes).unwrap();
+        let owned = Ipv4::from_bytes(&bytes).unwrap();
+        assert_eq!(view.header().src, owned.header.src);
+        assert_eq!(view.header().len.get(), owned.total_len());
+        assert_eq!(view.protocol(), owned.protocol());
+        assert_eq!(view.options(), &owned.options[..]);
+        assert_eq!(view.payload(), &owned.data[..]);
+        assert_eq!(view.payload().as_ptr(), bytes[24..].as_ptr());
+        assert_eq!(view.to_owned().to_bytes(), owned.to_bytes());
+
+        let udp_view = UdpRef::from_bytes(view.payload()).unwrap();
+        assert_eq!(udp_view.header().dst.get(), 53);
+        assert_eq!(udp_view.payload(), b"abc");
+        assert_eq!(udp_view.to_owned().to_bytes(), udp.to_bytes());
+
+        let mut tcp = TcpConnection::new(40000, 80, 1000).send(b"GET /");
+        tcp.options = vec![2, 4, 0x05, 0xb4];
+        tcp.header.flags.set(tcp.header.flags.get() & 0x0FFF | 6 << 12);
+        let bytes = tcp.to_bytes();
+        let tcp_view = TcpRef::from_bytes(&bytes).unwrap();
+        let owned = Tcp::from_bytes(&bytes).unwrap();
+        assert_eq!(tcp_view.header().sequence.get(), owned.header.sequence.get());
+        assert_eq!(tcp_view.options(), &owned.options[..]);
+        assert_eq!(tcp_view.payload(), b"GET /");
+        assert_eq!(tcp_view.to_owned().to_bytes(), bytes);
+
+        // The views reject what the owned parsers reject
+        assert_eq!(Ipv4Ref::try_from(&bytes[..10]).unwrap_err(), Ipv4::try_from(&bytes[..10]).unwrap_err());
+        assert!(UdpRef::from_bytes(&[0, 1, 0, 2, 0, 4, 0, 0]).is_none());
+        assert!(TcpRef::from_bytes(&bytes[..19]).is_none());
+    }
+
+    #[test]
+    fn checksum_incremental_update() {
+        let src = Ipv4Addr::from_str("192.168.1.20");
+        let dst = Ipv4Addr::from_str("93.184.216.34");
+        let public = Ipv4Addr::from_str("203.0.113.7");
+
+        // Rewrite the IPv4 source like a NAT would
+        let mut ip = Ipv4::new(src, dst, IpProtocol::Tcp, Vec::new()).with_id(0xBEEF);
+        let check = Checksum::update_u32(u16::from_be(ip.header.checksum.data), src.to_u32(), public.to_u32());
+        ip.header.src = public;
+        ip.checksum();
+        assert_eq!(u16::from_be(ip.header.checksum.data), check);
+
+        // The TCP checksum covers the port and, through the pseudo header, the address
+        let mut tcp = TcpConnection::new(40000, 80, 0x12345678).syn();
+        tcp.checksum(&src, &dst);
+        let check = u16::from_be(tcp.header.checksum.data);
+        let check = Checksum::update_u32(check, src.to_u32(), public.to_u32());
+        let check = Checksum::update(check, 40000, 61000);
+        tcp.header.src.set(61000);
+        tcp.checksum(&public, &dst);
+        assert_eq!(u16::from_be(tcp.header.checksum.data), check);
+
+        // Changing a word to itself leaves the checksum alone
+        assert_eq!(Checksum::update(0x1234, 0xABCD, 0xABCD), 0x1234);
+    }
+
+    #[test]
+    fn ipv4_header_checksums() {
+        // 192.168.0.1 -> 192.168.0.199, UDP, from the usual worked example
+        let mut header = vec![
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61,
+            0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        assert_eq!(ipv4_header_checksum(&header), 0xb861);
+        assert!(ipv4_checksum_valid(&header));
+        // The payload after the header is not covered
+        header.extend_from_slice(b"payload");
+        assert!(ipv4_checksum_valid(&header));
+
+        header[8] = 0x3f;
+        assert!(!ipv4_checksum_valid(&header));
+        assert_eq!(ipv4_header_checksum(&header), 0xb961);
+        assert!(!ipv4_checksum_valid(&header[..19]));
+
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let bytes = Ipv4::new(src, dst, IpProtocol::Icmp, b"ping".to_vec())
+            .with_option(Ipv4Option::record_route(3))
+            .to_bytes();
+        let check = u16::from_be_bytes([bytes[10], bytes[11]]);
+        assert_eq!(ipv4_header_checksum(&bytes), check);
+        assert!(ipv4_checksum_valid(&bytes));
+        // The IHL says the options are part of the header
+        assert!(!ipv4_checksum_valid(&bytes[..20]));
+        let mut corrupted = bytes.clone();
+        corrupted[22] ^= 0xff;
+        assert!(!ipv4_checksum_valid(&corrupted));
+        assert_ne!(ipv4_header_checksum(&corrupted), check);
+    }
+
+    #[test]
+    fn checksum_chunks() {
+        let bytes = [0x45, 0x00, 0x12, 0x34, 0xAB, 0xCD, 0x01];
+        let whole = unsafe { Checksum::sum(bytes.as_ptr() as usize, bytes.len()) };
+        assert_eq!(Checksum::sum_chunks(&[&bytes]), whole);
+
+        // An odd chunk in the middle pairs its last byte with the next chunk
+        let split = [&bytes[..3], &bytes[3..4], &[][..], &bytes[4..]];
+        assert_eq!(Checksum::compile(Checksum::sum_chunks(&split)), Checksum::compile(whole));
+        // Summing each chunk on its own pads the odd ones and gets it wrong
+        let separate: usize = split.iter().map(|chunk| Checksum::sum_chunks(&[chunk])).sum();
+        assert_ne!(Checksum::compile(separate), Checksum::compile(whole));
+
+        // A segment with odd data checks out against one contiguous buffer
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let mut tcp = TcpConnection::new(40000, 80, 1000).syn();
+        tcp.data = b"odd".to_vec();
+        tcp.checksum(&src, &dst);
+        let segment = tcp.to_bytes();
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&src.bytes);
+        buffer.extend_from_slice(&dst.bytes);
+        buffer.extend_from_slice(&[0, 6, 0, segment.len() as u8]);
+        buffer.extend_from_slice(&segment);
+        assert_eq!(Checksum::compile(unsafe { Checksum::sum(buffer.as_ptr() as usize, buffer.len()) }), 0);
+    }
+
+    #[test]
+    fn ethernet_padding_and_fcs() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let mut bytes = vec![0xFF; 6];
+        bytes.extend_from_slice(&[0x52, 0x54, 0x00, 0x12, 0x34, 0x56, 0x08, 0x06]);
+        bytes.extend_from_slice(b"hello");
+        let frame = EthernetII::from_bytes(&bytes).unwrap();
+
+        let padded = frame.to_bytes_padded();
+        assert_eq!(padded.len(), ETHERNET_MIN_FRAME_LEN);
+        assert_eq!(&padded[..bytes.len()], &bytes[..]);
+        assert!(padded[bytes.len()..].iter().all(|&b| b == 0));
+
+        let large = EthernetII { data: vec![0xAB; 100], ..frame.clone() };
+        assert_eq!(large.to_bytes_padded(), large.to_bytes());
+
+        let sent = frame.with_fcs();
+        assert_eq!(sent.len(), ETHERNET_MIN_FRAME_LEN + ETHERNET_FCS_LEN);
+        assert_eq!(&sent[ETHERNET_MIN_FRAME_LEN..], &[0x01, 0x2B, 0x1A, 0xF9]);
+        assert!(EthernetII::check_fcs(&sent));
+
+        let mut corrupt = sent.clone();
+        corrupt[20] ^= 0x01;
+        assert!(!EthernetII::check_fcs(&corrupt));
+        assert!(!EthernetII::check_fcs(&sent[..ETHERNET_FCS_LEN]));
+    }
+
+    #[test]
+    fn network_order_formatting() {
+        let len = n16::new(20);
+        assert_eq!(format!("{:?}", len), "n16(20)");
+        assert_eq!(len.to_string(), "20");
+        assert_eq!(format!("{:>4}", len), "  20");
+        assert_eq!(format!("{:?}", n32::new(0x0102_0304)), "n32(16909060)");
+
+        // Headers print their fields the same way, packed or not
+        let header = Udp::from_bytes(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]).unwrap().header;
+        assert!(format!("{:?}", header).contains("len: n16(8)"));
+    }
+
+    #[test]
+    fn display_summaries() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let udp = Udp::from_bytes(&[0x30, 0x39, 0, 53, 0, 11, 0, 0, b'a', b'b', b'c']).unwrap();
+        let ip = Ipv4::new(src, dst, IpProtocol::Udp, udp.to_bytes()).with_id(0x1234);
+        assert_eq!(ip.to_string(), "IPv4 10.0.0.1 -> 10.0.0.2 proto=UDP ttl=64 len=31 id=0x1234");
+        assert_eq!(udp.to_string(), "UDP 12345 -> 53 len=11");
+
+        let other = Ipv4::new(src, dst, IpProtocol::Other(47), Vec::new()).with_ttl(1);
+        assert_eq!(other.to_string(), "IPv4 10.0.0.1 -> 10.0.0.2 proto=47 ttl=1 len=20 id=0x0000");
+
+        let mut client = TcpConnection::new(40000, 80, 1000);
+        let mut server = TcpConnection::listen(80, 40000, 5000);
+        let syn = client.syn();
+        assert_eq!(syn.to_string(), "TCP 40000 -> 80 [SYN] seq=1000 ack=0 win=65535 len=0");
+        let syn_ack = server.on_segment(&syn).remove(0);
+        assert!(syn_ack.header.flags.get() & TCP_ACK == TCP_ACK);
+        assert_eq!(syn_ack.to_string(), "TCP 80 -> 40000 [SYN,ACK] seq=5000 ack=1001 win=65535 len=0");
+    }
+
+    #[test]
+    fn describe_frames() {
+        // A guest connecting to the host web server, and asking for the gateway's MAC first
+        let syn = [
+            0x52, 0x55, 0x0a, 0x00, 0x02, 0x02, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56, 0x08, 0x00, 0x45, 0x00,
+            0x00, 0x28, 0x1c, 0x46, 0x40, 0x00, 0x40, 0x06, 0x06, 0x7a, 0x0a, 0x00, 0x02, 0x0f, 0x0a, 0x00,
+            0x02, 0x02, 0x9c, 0x40, 0x00, 0x50, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x50, 0x02,
+            0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut arp = vec![
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56, 0x08, 0x06, 0x00, 0x01,
+            0x08, 0x00, 0x06, 0x04, 0x00, 0x01, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56, 0x0a, 0x00, 0x02, 0x0f,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x02, 0x02,
+        ];
+        // Short frames are padded to the Ethernet minimum on the wire
+        arp.resize(60, 0);
+
+        assert_eq!(describe(&syn),
+                   "52:54:00:12:34:56 > 52:55:0a:00:02:02 IPv4 10.0.2.15.40000 > 10.0.2.2.80: TCP SYN len 0");
+        assert_eq!(describe(&arp), "52:54:00:12:34:56 > ff:ff:ff:ff:ff:ff ARP who-has 10.0.2.2 tell 10.0.2.15");
+        arp[21] = 2;
+        assert_eq!(describe(&arp),
+                   "52:54:00:12:34:56 > ff:ff:ff:ff:ff:ff ARP reply 10.0.2.15 is-at 52:54:00:12:34:56");
+
+        let mut frame = syn[..14].to_vec();
+        let udp = Udp::from_bytes(&[0x30, 0x39, 0, 53, 0, 11, 0, 0, b'a', b'b', b'c']).unwrap();
+        Ipv4::new(Ipv4Addr::from_str("10.0.2.15"), Ipv4Addr::from_str("10.0.2.3"), IpProtocol::Udp, udp.to_bytes())
+            .to_bytes_into(&mut frame);
+        assert!(describe(&frame).ends_with(" IPv4 10.0.2.15.12345 > 10.0.2.3.53: UDP len 3"));
+
+        let mut ipv6 = syn.to_vec();
+        ipv6[12..14].copy_from_slice(&[0x86, 0xdd]);
+        assert!(describe(&ipv6).ends_with(" ethertype 0x86dd len 40"));
+        assert_eq!(describe(&syn[..30]), "unparseable 30 bytes");
+        assert_eq!(describe(&[]), "unparseable 0 bytes");
+    }
+
+    // ARP request for 192.168.100.1 from 52:54:00:12:34:56, on VLAN 100 with
+    // priority 3
+    const TAGGED_ARP: [u8; 46] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56,
+        0x81, 0x00, 0x60, 0x64, 0x08, 0x06,
+        0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01,
+        0x52, 0x54, 0x00, 0x12, 0x34, 0x56, 0xc0, 0xa8, 0x64, 0x0a,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0xa8, 0x64, 0x01,
+    ];
+
+    #[test]
+    fn vlan_tags() {
+        let frame = EthernetII::from_bytes(&TAGGED_ARP).unwrap();
+        assert_eq!(frame.header.ethertype.get(), ETHERTYPE_VLAN);
+        assert_eq!(frame.vlan(), Some(VlanTag { pcp: 3, dei: false, id: 100 }));
+        assert_eq!(frame.ethertype(), 0x0806);
+        assert_eq!(frame.payload(), &TAGGED_ARP[18..]);
+        assert_eq!(frame.to_bytes(), &TAGGED_ARP[..]);
+
+        let mut untagged = TAGGED_ARP[..12].to_vec();
+        untagged.extend_from_slice(&TAGGED_ARP[16..]);
+        let plain = EthernetII::from_bytes(&untagged).unwrap();
+        assert_eq!(plain.vlan(), None);
+        assert_eq!(plain.ethertype(), 0x0806);
+        assert_eq!(plain.payload(), &TAGGED_ARP[18..]);
+
+        let tag = VlanTag { pcp: 3, dei: false, id: 100 };
+        assert_eq!(plain.clone().with_vlan(tag).to_bytes(), &TAGGED_ARP[..]);
+        // Tagging again replaces the tag rather than nesting another one
+        let retagged = frame.with_vlan(VlanTag { pcp: 0, dei: true, id: 4095 });
+        assert_eq!(&retagged.to_bytes()[12..18], &[0x81, 0x00, 0x1f, 0xff, 0x08, 0x06]);
+        assert_eq!(VlanTag::from_tci(0x1fff), VlanTag { pcp: 0, dei: true, id: 4095 });
+
+        // A tagged ethertype with the tag cut off is left as it is
+        let cut = EthernetII::from_bytes(&TAGGED_ARP[..16]).unwrap();
+        assert_eq!((cut.vlan(), cut.ethertype()), (None, ETHERTYPE_VLAN));
+
+        assert_eq!(describe(&TAGGED_ARP),
+                   "52:54:00:12:34:56 > ff:ff:ff:ff:ff:ff vlan 100 ARP who-has 192.168.100.1 tell 192.168.100.10");
     }
 }
@@ -0,0 +1,73 @@
+//! Raw Ethernet frames of the network adapter, for tools working below IP
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libredox::{flag, Fd};
+
+/// Where the network scheme hands out raw frames
+///
+/// NOTE: the network stack reads the same device, so a frame may be taken by
+/// it instead. Tools waiting for a reply repeat their request until the wait
+/// is over to make up for that.
+pub const NETWORK_DEVICE: &str = "/scheme/network";
+
+/// How long to sleep when no frame is waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct Link {
+    fd: Fd,
+}
+
+impl Link {
+    /// Open the adapter to send frames and poll for them without blocking
+    pub fn open() -> io::Result<Link> {
+        Ok(Link {
+            fd: Fd::open(NETWORK_DEVICE, flag::O_RDWR | flag::O_NONBLOCK, 0)?,
+        })
+    }
+
+    /// Open the adapter read only, for capturing every frame as it arrives
+    pub fn open_capture() -> io::Result<Link> {
+        Ok(Link {
+            fd: Fd::open(NETWORK_DEVICE, flag::O_RDONLY, 0)?,
+        })
+    }
+
+    pub fn send(&self, frame: &[u8]) -> io::Result<()> {
+        self.fd.write(frame)?;
+        Ok(())
+    }
+
+    /// Next frame if one is waiting, `None` otherwise
+    pub fn try_recv(&self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        match self.fd.read(buf) {
+            Ok(count) => Ok(Some(count)),
+            Err(err) if err.is_wouldblock() || err.is_interrupt() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Wait for the next frame, `None` once `deadline` has passed without one
+    pub fn recv_until(&self, buf: &mut [u8], deadline: Instant) -> io::Result<Option<usize>> {
+        loop {
+            if let Some(count) = self.try_recv(buf)? {
+                return Ok(Some(count));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Wait as long as it takes for the next frame
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.try_recv(buf)? {
+                Some(count) => return Ok(count),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+}
@@ -1,4 +1,8 @@
+use std::convert::TryFrom;
+use std::{error, fmt, str};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct MacAddr {
     pub bytes: [u8; 6],
 }
@@ -6,48 +10,106 @@ pub struct MacAddr {
 impl MacAddr {
     pub const BROADCAST: MacAddr = MacAddr { bytes: [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF] };
 
+    /// Lenient parse returning the zero address on invalid input, use `str::parse` to get the error
     pub fn from_str(string: &str) -> Self {
-        MacAddr::try_parse_with_delimeter(string, ':')
-            .or_else(|| MacAddr::try_parse_with_delimeter(string, '-'))
-            .unwrap_or_default()
+        string.parse().unwrap_or_default()
+    }
+
+    /// Address from exactly six raw bytes, as found in a frame or a chaddr field
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        <[u8; 6]>::try_from(bytes).ok().map(|bytes| MacAddr { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
     }
 
-    fn try_parse_with_delimeter(string: &str, delimeter: char) -> Option<MacAddr> {
+    /// Dashed uppercase form, `AA-BB-CC-DD-EE-FF`, as read and written by the
+    /// netcfg scheme
+    pub fn to_string_dashed(&self) -> String {
+        format!("{:>02X}-{:>02X}-{:>02X}-{:>02X}-{:>02X}-{:>02X}",
+                self.bytes[0],
+                self.bytes[1],
+                self.bytes[2],
+                self.bytes[3],
+                self.bytes[4],
+                self.bytes[5])
+    }
+
+    /// Canonical colon lowercase form, `aa:bb:cc:dd:ee:ff`, same as `Display`
+    pub fn to_string_colon(&self) -> String {
+        format!("{}", self)
+    }
+
+    /// Parse a comma, space or newline separated list, returning the addresses
+    /// and the entries that are not valid addresses
+    pub fn parse_list(string: &str) -> (Vec<MacAddr>, Vec<String>) {
+        ::parse_list(string)
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+               self.bytes[0],
+               self.bytes[1],
+               self.bytes[2],
+               self.bytes[3],
+               self.bytes[4],
+               self.bytes[5])
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MacAddrParseError {
+    /// Not six segments separated by a single kind of delimiter
+    InvalidLength,
+    /// A segment is not one or two hex digits
+    InvalidHex,
+}
+
+impl fmt::Display for MacAddrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MacAddrParseError::InvalidLength => write!(f, "MAC address must have six segments"),
+            MacAddrParseError::InvalidHex => write!(f, "MAC address segment is not a hex byte"),
+        }
+    }
+}
+
+impl error::Error for MacAddrParseError {}
+
+impl str::FromStr for MacAddr {
+    type Err = MacAddrParseError;
+
+    /// Accepts `:` or `-` delimited hex bytes, in either case
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let delimiter = if string.contains(':') { ':' } else { '-' };
         let mut addr = MacAddr::default();
         let mut segments = 0;
 
-        for part in string.split(delimeter) {
+        for part in string.split(delimiter) {
             if segments >= addr.bytes.len() {
-                return None;
+                return Err(MacAddrParseError::InvalidLength);
+            }
+            if part.is_empty() || part.len() > 2 || !part.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(MacAddrParseError::InvalidHex);
             }
-            addr.bytes[segments] = match u8::from_str_radix(part, 16) {
-                Ok(b) => b,
-                _ => return None,
-            };
+            addr.bytes[segments] = u8::from_str_radix(part, 16).map_err(|_| MacAddrParseError::InvalidHex)?;
             segments += 1;
         }
 
         if segments == addr.bytes.len() {
-            Some(addr)
+            Ok(addr)
         } else {
-            None
+            Err(MacAddrParseError::InvalidLength)
         }
     }
-
-    pub fn to_string(&self) -> String {
-        format!("{:>02X}-{:>02X}-{:>02X}-{:>02X}-{:>02X}-{:>02X}",
-                self.bytes[0],
-                self.bytes[1],
-                self.bytes[2],
-                self.bytes[3],
-                self.bytes[4],
-                self.bytes[5])
-    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::MacAddr;
+    use super::{MacAddr, MacAddrParseError};
 
     #[test]
     fn from_str_test() {
@@ -70,7 +132,58 @@ mod test {
         assert_eq!(empty_mac, MacAddr::from_str("12"));
         assert_eq!(empty_mac, MacAddr::from_str("0:0:0:0:0:0"));
 
+        assert_eq!(mac, MacAddr::from_str(&mac.to_string_dashed()));
+        assert_eq!(empty_mac, MacAddr::from_str(&empty_mac.to_string_dashed()));
         assert_eq!(mac, MacAddr::from_str(&mac.to_string()));
         assert_eq!(empty_mac, MacAddr::from_str(&empty_mac.to_string()));
     }
+
+    #[test]
+    fn display_test() {
+        let mac = MacAddr { bytes: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab] };
+
+        assert_eq!(format!("{}", mac), "01:23:45:67:89:ab");
+        assert_eq!(mac.to_string_colon(), "01:23:45:67:89:ab");
+        assert_eq!(mac.to_string_dashed(), "01-23-45-67-89-AB");
+        assert_eq!(mac.to_string(), "01:23:45:67:89:ab");
+    }
+
+    #[test]
+    fn parse_test() {
+        let mac = MacAddr { bytes: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab] };
+
+        assert_eq!(Ok(mac), "01:23:45:67:89:ab".parse());
+        assert_eq!(Ok(mac), "01-23-45-67-89-AB".parse());
+        assert_eq!(Ok(mac), format!("{}", mac).parse());
+
+        assert_eq!(Err(MacAddrParseError::InvalidLength), "01:23:45:67:89".parse::<MacAddr>());
+        assert_eq!(Err(MacAddrParseError::InvalidLength), "01:23:45:67:89:ab:cd".parse::<MacAddr>());
+        assert_eq!(Err(MacAddrParseError::InvalidHex), "01:23-45-67-89-ab".parse::<MacAddr>());
+        assert_eq!(Err(MacAddrParseError::InvalidHex), "".parse::<MacAddr>());
+        assert_eq!(Err(MacAddrParseError::InvalidHex), "01:23:45:67:89:ag".parse::<MacAddr>());
+        assert_eq!(Err(MacAddrParseError::InvalidHex), "01:23:45:67:89:+a".parse::<MacAddr>());
+        assert_eq!(Err(MacAddrParseError::InvalidHex), "012:23:45:67:89:ab".parse::<MacAddr>());
+    }
+
+    #[test]
+    fn from_bytes_test() {
+        let bytes = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd];
+        let mac = MacAddr::from_bytes(&bytes[..6]).unwrap();
+        assert_eq!(mac, MacAddr { bytes: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab] });
+        assert_eq!(mac.as_bytes(), &bytes[..6]);
+        assert_eq!(MacAddr::from_bytes(&bytes[..5]), None);
+        assert_eq!(MacAddr::from_bytes(&bytes), None);
+        assert_eq!(MacAddr::from_bytes(&[]), None);
+    }
+
+    #[test]
+    fn parse_list_test() {
+        let (macs, rejected) = MacAddr::parse_list("01:23:45:67:89:ab, 01-23-45-67-89-AC\nff:ff:ff:ff:ff:ff,01:23");
+        assert_eq!(macs, vec![
+            MacAddr { bytes: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab] },
+            MacAddr { bytes: [0x01, 0x23, 0x45, 0x67, 0x89, 0xac] },
+            MacAddr::BROADCAST,
+        ]);
+        assert_eq!(rejected, vec!["01:23".to_string()]);
+    }
 }
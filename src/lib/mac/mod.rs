@@ -1,3 +1,3 @@
-pub use self::addr::MacAddr;
+pub use self::addr::{MacAddr, MacAddrParseError};
 
 mod addr;
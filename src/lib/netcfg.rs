@@ -0,0 +1,180 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+/// Where the network stack exposes its configuration
+pub const NETCFG_ROOT: &str = "/scheme/netcfg";
+
+#[derive(Debug)]
+pub enum NetCfgError {
+    /// The entry does not exist, e.g. because the interface is unknown
+    NotFound(PathBuf),
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for NetCfgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetCfgError::NotFound(ref path) => write!(f, "{} does not exist", path.display()),
+            NetCfgError::Read(ref path, ref err) => write!(f, "can't read {}: {}", path.display(), err),
+            NetCfgError::Write(ref path, ref err) => write!(f, "can't write {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl error::Error for NetCfgError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            NetCfgError::NotFound(_) => None,
+            NetCfgError::Read(_, ref err) | NetCfgError::Write(_, ref err) => Some(err),
+        }
+    }
+}
+
+fn read_error(path: PathBuf, err: io::Error) -> NetCfgError {
+    if err.kind() == io::ErrorKind::NotFound {
+        NetCfgError::NotFound(path)
+    } else {
+        NetCfgError::Read(path, err)
+    }
+}
+
+/// Access to the netcfg scheme, with entries named by their path below it,
+/// such as `route/list` or `ifaces/eth0/mac`
+#[derive(Clone, Debug)]
+pub struct NetCfg {
+    root: PathBuf,
+}
+
+impl Default for NetCfg {
+    fn default() -> Self {
+        NetCfg::new()
+    }
+}
+
+impl NetCfg {
+    pub fn new() -> Self {
+        NetCfg::with_root(NETCFG_ROOT)
+    }
+
+    /// Use another directory in place of the scheme, e.g. for tests
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Self {
+        NetCfg { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Value of an entry, without the surrounding whitespace
+    pub fn get(&self, path: &str) -> Result<String, NetCfgError> {
+        let path = self.root.join(path);
+        match fs::read_to_string(&path) {
+            Ok(value) => Ok(value.trim().to_string()),
+            Err(err) => Err(read_error(path, err)),
+        }
+    }
+
+    /// Write a value to an existing entry in a single write, as the scheme
+    /// takes each write as one complete value
+    pub fn set(&self, path: &str, value: &str) -> Result<(), NetCfgError> {
+        let path = self.root.join(path);
+        let mut file = match OpenOptions::new().write(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(NetCfgError::NotFound(path)),
+            Err(err) => return Err(NetCfgError::Write(path, err)),
+        };
+        file.write_all(value.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|err| NetCfgError::Write(path, err))
+    }
+
+    pub fn get_iface(&self, iface: &str, key: &str) -> Result<String, NetCfgError> {
+        self.get(&format!("ifaces/{}/{}", iface, key))
+    }
+
+    pub fn set_iface(&self, iface: &str, key: &str, value: &str) -> Result<(), NetCfgError> {
+        self.set(&format!("ifaces/{}/{}", iface, key), value)
+    }
+
+    /// Names of the network interfaces, sorted
+    pub fn list_ifaces(&self) -> Result<Vec<String>, NetCfgError> {
+        let path = self.root.join("ifaces");
+        let entries = fs::read_dir(&path).map_err(|err| read_error(path.clone(), err))?;
+        let mut ifaces = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| NetCfgError::Read(path.clone(), err))?;
+            ifaces.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        ifaces.sort();
+        Ok(ifaces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NetCfg, NetCfgError};
+    use std::fs;
+    use tempdir::TempDir;
+
+    /// A directory laid out like the scheme, with eth0 configured and lo bare
+    fn scheme() -> TempDir {
+        let root = TempDir::new("netcfg-test").unwrap();
+        fs::create_dir_all(root.path().join("ifaces/eth0/addr")).unwrap();
+        fs::create_dir_all(root.path().join("ifaces/lo")).unwrap();
+        fs::create_dir_all(root.path().join("route")).unwrap();
+        fs::write(root.path().join("ifaces/eth0/mac"), "52-54-00-12-34-56\n").unwrap();
+        fs::write(root.path().join("ifaces/eth0/addr/list"), "10.0.2.15/24\n").unwrap();
+        fs::write(root.path().join("route/add"), "").unwrap();
+        root
+    }
+
+    #[test]
+    fn list_ifaces_sorted() {
+        let root = scheme();
+        let cfg = NetCfg::with_root(root.path());
+        assert_eq!(cfg.list_ifaces().unwrap(), vec!["eth0".to_string(), "lo".to_string()]);
+    }
+
+    #[test]
+    fn get_trims_value() {
+        let root = scheme();
+        let cfg = NetCfg::with_root(root.path());
+        assert_eq!(cfg.get_iface("eth0", "mac").unwrap(), "52-54-00-12-34-56");
+        assert_eq!(cfg.get("ifaces/eth0/addr/list").unwrap(), "10.0.2.15/24");
+    }
+
+    #[test]
+    fn set_replaces_value() {
+        let root = scheme();
+        let cfg = NetCfg::with_root(root.path());
+        cfg.set("route/add", "default via 10.0.2.2").unwrap();
+        assert_eq!(cfg.get("route/add").unwrap(), "default via 10.0.2.2");
+        cfg.set_iface("eth0", "mac", "52-54-00-ab-cd-ef").unwrap();
+        assert_eq!(cfg.get_iface("eth0", "mac").unwrap(), "52-54-00-ab-cd-ef");
+    }
+
+    #[test]
+    fn set_never_creates_entries() {
+        let root = scheme();
+        let cfg = NetCfg::with_root(root.path());
+        match cfg.set_iface("eth1", "mac", "00-00-00-00-00-00") {
+            Err(NetCfgError::NotFound(path)) => assert_eq!(path, root.path().join("ifaces/eth1/mac")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(!root.path().join("ifaces/eth1").exists());
+    }
+
+    #[test]
+    fn missing_entries_not_found() {
+        let root = scheme();
+        let cfg = NetCfg::with_root(root.path());
+        assert!(matches!(cfg.get_iface("eth1", "mac"), Err(NetCfgError::NotFound(_))));
+        assert!(matches!(
+            NetCfg::with_root(root.path().join("missing")).list_ifaces(),
+            Err(NetCfgError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+//! NTP packets as defined in RFC 5905 and the SNTP client exchange of RFC 4330
+use super::{n32, require, ParseError};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{mem, slice};
+
+/// Seconds from the NTP epoch, 1900-01-01, to the Unix epoch, 1970-01-01
+pub const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+pub const NTP_PORT: u16 = 123;
+pub const NTP_VERSION: u8 = 4;
+
+pub const MODE_CLIENT: u8 = 3;
+pub const MODE_SERVER: u8 = 4;
+
+/// How long `sntp_query` waits for the server
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Seconds and a binary fraction of a second since the NTP epoch
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[repr(packed)]
+pub struct NtpTimestamp {
+    pub seconds: n32,
+    pub fraction: n32,
+}
+
+impl NtpTimestamp {
+    pub const ZERO: NtpTimestamp = NtpTimestamp { seconds: n32(0), fraction: n32(0) };
+
+    /// Timestamp of a time given as the duration since the Unix epoch
+    pub fn from_unix(time: Duration) -> Self {
+        // Wraps in 2036 into the next era, which `to_unix` expects
+        let seconds = (time.as_secs() + NTP_UNIX_OFFSET) as u32;
+        let fraction = ((time.subsec_nanos() as u64) << 32) / 1_000_000_000;
+        NtpTimestamp {
+            seconds: n32::new(seconds),
+            fraction: n32::new(fraction as u32),
+        }
+    }
+
+    /// Time since the Unix epoch. Seconds with the top bit clear are taken to
+    /// be in the era starting in 2036, as RFC 4330 suggests.
+    pub fn to_unix(&self) -> Duration {
+        let mut seconds = self.seconds.get() as u64;
+        if seconds & 0x8000_0000 == 0 {
+            seconds += 1 << 32;
+        }
+        let nanos = (self.fraction.get() as u64 * 1_000_000_000) >> 32;
+        Duration::new(seconds - NTP_UNIX_OFFSET, nanos as u32)
+    }
+
+    pub fn now() -> Self {
+        NtpTimestamp::from_unix(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == NtpTimestamp::ZERO
+    }
+
+    fn as_secs_f64(&self) -> f64 {
+        self.to_unix().as_secs_f64()
+    }
+}
+
+/// The 48 byte NTP header, without extension fields or authenticator
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[repr(packed)]
+pub struct NtpPacket {
+    /// Leap indicator in the top two bits, version in the next three, mode in the last three
+    pub li_vn_mode: u8,
+    /// 1 for a primary server, up to 15 for secondary ones, 0 for a kiss-o'-death
+    pub stratum: u8,
+    /// Log2 of the maximum interval between messages, in seconds
+    pub poll: i8,
+    /// Log2 of the precision of the clock, in seconds
+    pub precision: i8,
+    /// Round trip delay to the reference clock, in seconds as 16.16 fixed point
+    pub root_delay: n32,
+    /// Dispersion to the reference clock, in seconds as 16.16 fixed point
+    pub root_dispersion: n32,
+    /// Reference clock code for stratum 1, the server address above, the kiss code for 0
+    pub reference_id: [u8; 4],
+    /// When the clock was last set
+    pub reference: NtpTimestamp,
+    /// Transmit time of the request this answers
+    pub origin: NtpTimestamp,
+    /// When the request arrived at the server
+    pub receive: NtpTimestamp,
+    /// When the packet left its sender
+    pub transmit: NtpTimestamp,
+}
+
+impl<'a> TryFrom<&'a [u8]> for NtpPacket {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> ::std::result::Result<Self, ParseError> {
+        require(bytes, mem::size_of::<NtpPacket>())?;
+        Ok(unsafe { *(bytes.as_ptr() as *const NtpPacket) })
+    }
+}
+
+impl NtpPacket {
+    /// A client request stamped with `transmit`, which the answer echoes as its origin
+    pub fn request(transmit: NtpTimestamp) -> Self {
+        NtpPacket {
+            li_vn_mode: NTP_VERSION << 3 | MODE_CLIENT,
+            stratum: 0,
+            poll: 0,
+            precision: 0,
+            root_delay: n32::new(0),
+            root_dispersion: n32::new(0),
+            reference_id: [0; 4],
+            reference: NtpTimestamp::ZERO,
+            origin: NtpTimestamp::ZERO,
+            receive: NtpTimestamp::ZERO,
+            transmit,
+        }
+    }
+
+    pub fn leap(&self) -> u8 {
+        self.li_vn_mode >> 6
+    }
+
+    pub fn version(&self) -> u8 {
+        (self.li_vn_mode >> 3) & 0x7
+    }
+
+    pub fn mode(&self) -> u8 {
+        self.li_vn_mode & 0x7
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        NtpPacket::try_from(bytes).ok()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let packet_ptr: *const NtpPacket = self;
+        unsafe { slice::from_raw_parts(packet_ptr as *const u8, mem::size_of::<NtpPacket>()) }.to_vec()
+    }
+}
+
+/// What one SNTP exchange found out about the local clock
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NtpResult {
+    /// Server time when the answer left, since the Unix epoch
+    pub time: Duration,
+    /// Seconds to add to the local clock to match the server
+    pub offset: f64,
+    /// Round trip time in seconds, not counting the time spent in the server
+    pub delay: f64,
+    pub stratum: u8,
+}
+
+impl NtpResult {
+    /// Clock offset and delay from the four timestamps of an exchange: request
+    /// sent (t1), received by the server (t2), answer sent (t3) and received (t4)
+    pub fn from_timestamps(t1: NtpTimestamp, t2: NtpTimestamp, t3: NtpTimestamp, t4: NtpTimestamp, stratum: u8) -> Self {
+        let (t1, t2, t3_secs, t4) = (t1.as_secs_f64(), t2.as_secs_f64(), t3.as_secs_f64(), t4.as_secs_f64());
+        NtpResult {
+            time: t3.to_unix(),
+            offset: ((t2 - t1) + (t3_secs - t4)) / 2.0,
+            delay: (t4 - t1) - (t3_secs - t2),
+            stratum,
+        }
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Ask `server`, a host with an optional `:port`, for the time
+pub fn sntp_query(server: &str) -> Result<NtpResult> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_read_timeout(Some(READ_TIMEOUT))?;
+    if server.contains(':') {
+        socket.connect(server)?;
+    } else {
+        socket.connect((server, NTP_PORT))?;
+    }
+
+    let sent = NtpTimestamp::now();
+    socket.send(&NtpPacket::request(sent).to_bytes())?;
+
+    let mut buf = [0; 512];
+    loop {
+        let count = socket.recv(&mut buf)?;
+        let received = NtpTimestamp::now();
+        let reply = match NtpPacket::from_bytes(&buf[..count]) {
+            // Anything else is a late answer to someone else's request
+            Some(reply) if { reply.origin } == sent => reply,
+            _ => continue,
+        };
+
+        if reply.mode() != MODE_SERVER {
+            return Err(invalid("reply is not from a server"));
+        }
+        if reply.stratum == 0 {
+            let code = String::from_utf8_lossy(&reply.reference_id).into_owned();
+            return Err(Error::other(format!("server refused the request ({})", code)));
+        }
+        if reply.leap() == 3 || reply.transmit.is_zero() {
+            return Err(invalid("server clock is not synchronized"));
+        }
+        return Ok(NtpResult::from_timestamps(sent, reply.receive, reply.transmit, received, reply.stratum));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_conversion() {
+        // 2023-11-14 22:13:20 UTC
+        let time = Duration::new(1_700_000_000, 500_000_000);
+        let ts = NtpTimestamp::from_unix(time);
+        assert_eq!(ts.seconds.get(), 3_908_988_800);
+        assert_eq!(ts.fraction.get(), 0x8000_0000);
+        assert_eq!(ts.to_unix(), time);
+
+        assert_eq!(NtpTimestamp::from_unix(Duration::from_secs(0)).seconds.get() as u64, NTP_UNIX_OFFSET);
+        // Sub-second precision survives the round trip to within a nanosecond
+        let time = Duration::new(1_000_000_000, 123_456_789);
+        let back = NtpTimestamp::from_unix(time).to_unix();
+        assert!(time - back <= Duration::from_nanos(1));
+
+        // 2040 is past the 2036 rollover of the seconds field
+        let time = Duration::from_secs(2_208_988_800);
+        let ts = NtpTimestamp::from_unix(time);
+        assert_eq!(ts.seconds.get(), 2_208_988_800u64.wrapping_add(NTP_UNIX_OFFSET) as u32);
+        assert_eq!(ts.to_unix(), time);
+    }
+
+    #[test]
+    fn offset_and_delay() {
+        let at = |secs: f64| NtpTimestamp::from_unix(Duration::from_secs_f64(1_700_000_000.0 + secs));
+        // The server is 2 seconds ahead, 50 ms away each way, and holds the request for 10 ms
+        let result = NtpResult::from_timestamps(at(0.0), at(2.05), at(2.06), at(0.11), 2);
+        assert!((result.offset - 2.0).abs() < 1e-6);
+        assert!((result.delay - 0.1).abs() < 1e-6);
+        assert_eq!(result.time, at(2.06).to_unix());
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let sent = NtpTimestamp::from_unix(Duration::from_secs(1_700_000_000));
+        let bytes = NtpPacket::request(sent).to_bytes();
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(bytes[0], 0x23);
+        assert_eq!(&bytes[40..44], &3_908_988_800u32.to_be_bytes());
+
+        let packet = NtpPacket::from_bytes(&bytes).unwrap();
+        assert_eq!(packet.version(), 4);
+        assert_eq!(packet.mode(), MODE_CLIENT);
+        assert_eq!({ packet.transmit }, sent);
+        assert!(NtpPacket::from_bytes(&bytes[..47]).is_none());
+    }
+}
@@ -0,0 +1,332 @@
+use super::{n16, n32, Arp, Checksum, EthernetII, EthernetIIHeader, IpProtocol, Ipv4, Ipv4Addr, MacAddr};
+use std::mem;
+use tcp::{Tcp, TcpHeader, TCP_ACK};
+use udp::{Udp, UdpHeader};
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// Transport layer of an IPv4 packet
+#[derive(Debug)]
+pub enum Transport {
+    Tcp(Tcp),
+    Udp(Udp),
+    /// ICMP message, left in the IPv4 data
+    Icmp,
+    /// Another protocol, a later fragment, or a segment too short to decode
+    Other,
+}
+
+/// An Ethernet frame decoded as deep as the dispatcher understands it
+#[derive(Debug)]
+pub enum Packet {
+    Arp(Arp),
+    Ipv4(Ipv4, Transport),
+    /// A frame carrying another ethertype
+    Other(u16),
+}
+
+/// Decode an Ethernet II frame and the ARP or IPv4 packet it carries, looking
+/// past an 802.1Q tag
+///
+/// Returns `None` for frames too short for their headers.
+pub fn parse_ethernet(bytes: &[u8]) -> Option<Packet> {
+    let frame = EthernetII::from_bytes(bytes)?;
+    match frame.ethertype() {
+        ETHERTYPE_ARP => Arp::from_bytes(frame.payload()).map(Packet::Arp),
+        ETHERTYPE_IPV4 => {
+            let ip = Ipv4::from_bytes(frame.payload())?;
+            // Only the first fragment starts with the transport header
+            let first_fragment = ip.header.flags_fragment.get() & 0x1FFF == 0;
+            let transport = match ip.protocol() {
+                IpProtocol::Tcp if first_fragment => Tcp::from_bytes(&ip.data).map_or(Transport::Other, Transport::Tcp),
+                IpProtocol::Udp if first_fragment => Udp::from_bytes(&ip.data).map_or(Transport::Other, Transport::Udp),
+                IpProtocol::Icmp => Transport::Icmp,
+                _ => Transport::Other,
+            };
+            Some(Packet::Ipv4(ip, transport))
+        }
+        ethertype => Some(Packet::Other(ethertype)),
+    }
+}
+
+/// Builds an Ethernet frame carrying a UDP or TCP segment over IPv4, filling
+/// in every length and checksum:
+///
+/// ```ignore
+/// let frame = PacketBuilder::ethernet(dst_mac, src_mac)
+///     .ipv4(src_ip, dst_ip)
+///     .udp(68, 67)
+///     .payload(&data)
+///     .build();
+/// ```
+pub struct PacketBuilder {
+    dst: MacAddr,
+    src: MacAddr,
+}
+
+impl PacketBuilder {
+    pub fn ethernet(dst: MacAddr, src: MacAddr) -> Self {
+        PacketBuilder { dst, src }
+    }
+
+    pub fn ipv4(self, src: Ipv4Addr, dst: Ipv4Addr) -> Ipv4Builder {
+        Ipv4Builder {
+            ethernet: self,
+            src,
+            dst,
+            ttl: None,
+        }
+    }
+}
+
+/// The IPv4 layer of a `PacketBuilder`
+pub struct Ipv4Builder {
+    ethernet: PacketBuilder,
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    ttl: Option<u8>,
+}
+
+impl Ipv4Builder {
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn udp(self, src_port: u16, dst_port: u16) -> UdpBuilder {
+        UdpBuilder {
+            ip: self,
+            src_port,
+            dst_port,
+            payload: Vec::new(),
+        }
+    }
+
+    /// A TCP segment with no flags and the largest unscaled window, set the
+    /// rest with the methods of `TcpBuilder`
+    pub fn tcp(self, src_port: u16, dst_port: u16) -> TcpBuilder {
+        TcpBuilder {
+            ip: self,
+            header: TcpHeader {
+                src: n16::new(src_port),
+                dst: n16::new(dst_port),
+                sequence: n32::new(0),
+                ack_num: n32::new(0),
+                flags: n16::new((mem::size_of::<TcpHeader>() << 10) as u16),
+                window_size: n16::new(0xFFFF),
+                checksum: Checksum { data: 0 },
+                urgent_pointer: n16::new(0),
+            },
+            payload: Vec::new(),
+        }
+    }
+
+    /// Wrap the transport segment in the IPv4 packet and the Ethernet frame
+    fn finish(self, proto: IpProtocol, segment: Vec<u8>) -> Vec<u8> {
+        let mut ip = Ipv4::new(self.src, self.dst, proto, segment);
+        if let Some(ttl) = self.ttl {
+            ip = ip.with_ttl(ttl);
+        }
+        let mut frame = Vec::new();
+        EthernetII {
+            header: EthernetIIHeader {
+                dst: self.ethernet.dst,
+                src: self.ethernet.src,
+                ethertype: n16::new(ETHERTYPE_IPV4),
+            },
+            data: Vec::new(),
+        }.to_bytes_into(&mut frame);
+        ip.to_bytes_into(&mut frame);
+        frame
+    }
+}
+
+/// The UDP layer of a `PacketBuilder`
+pub struct UdpBuilder {
+    ip: Ipv4Builder,
+    src_port: u16,
+    dst_port: u16,
+    payload: Vec<u8>,
+}
+
+impl UdpBuilder {
+    pub fn payload(mut self, data: &[u8]) -> Self {
+        self.payload = data.to_vec();
+        self
+    }
+
+    /// The frame with the UDP checksum computed over the IPv4 pseudo header
+    pub fn build(self) -> Vec<u8> {
+        let mut udp = Udp {
+            header: UdpHeader {
+                src: n16::new(self.src_port),
+                dst: n16::new(self.dst_port),
+                len: n16::new((mem::size_of::<UdpHeader>() + self.payload.len()) as u16),
+                checksum: Checksum { data: 0 },
+            },
+            data: self.payload,
+        };
+        udp.checksum(&self.ip.src, &self.ip.dst);
+        self.ip.finish(IpProtocol::Udp, udp.to_bytes())
+    }
+}
+
+/// The TCP layer of a `PacketBuilder`
+pub struct TcpBuilder {
+    ip: Ipv4Builder,
+    header: TcpHeader,
+    payload: Vec<u8>,
+}
+
+impl TcpBuilder {
+    pub fn sequence(mut self, sequence: u32) -> Self {
+        self.header.sequence.set(sequence);
+        self
+    }
+
+    /// Acknowledge everything before `ack_num`, which also sets ACK
+    pub fn ack(mut self, ack_num: u32) -> Self {
+        self.header.ack_num.set(ack_num);
+        self.flags(TCP_ACK)
+    }
+
+    /// Set the given `TCP_*` flags in addition to those already set
+    pub fn flags(mut self, flags: u16) -> Self {
+        let flags = self.header.flags.get() | (flags & 0x01FF);
+        self.header.flags.set(flags);
+        self
+    }
+
+    pub fn window(mut self, window_size: u16) -> Self {
+        self.header.window_size.set(window_size);
+        self
+    }
+
+    pub fn payload(mut self, data: &[u8]) -> Self {
+        self.payload = data.to_vec();
+        self
+    }
+
+    /// The frame with the TCP checksum computed over the IPv4 pseudo header
+    pub fn build(self) -> Vec<u8> {
+        let mut tcp = Tcp {
+            header: self.header,
+            options: Vec::new(),
+            data: self.payload,
+        };
+        tcp.checksum(&self.ip.src, &self.ip.dst);
+        self.ip.finish(IpProtocol::Tcp, tcp.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ethernet, Packet, PacketBuilder, Transport, ETHERTYPE_IPV4};
+    use super::super::{n16, Checksum, EthernetII, IpProtocol, Ipv4, Ipv4Addr, MacAddr, VlanTag};
+    use super::super::EthernetIIHeader;
+    use tcp::{TcpConnection, TCP_ACK, TCP_PSH};
+
+    /// Whether the IPv4 header, options included, sums to zero
+    fn header_checksum_ok(frame: &[u8]) -> bool {
+        let header = &frame[14..14 + (frame[14] & 0x0F) as usize * 4];
+        Checksum::compile(unsafe { Checksum::sum(header.as_ptr() as usize, header.len()) }) == 0
+    }
+
+    #[test]
+    fn dispatch() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let syn = TcpConnection::new(40000, 80, 1000).syn();
+        let frame = EthernetII {
+            header: EthernetIIHeader {
+                dst: MacAddr::BROADCAST,
+                src: MacAddr::default(),
+                ethertype: n16::new(ETHERTYPE_IPV4),
+            },
+            data: Ipv4::new(src, dst, IpProtocol::Tcp, syn.to_bytes()).to_bytes(),
+        };
+
+        match parse_ethernet(&frame.to_bytes()) {
+            Some(Packet::Ipv4(ip, Transport::Tcp(tcp))) => {
+                assert_eq!({ ip.header.dst }, dst);
+                assert_eq!(tcp.header.dst.get(), 80);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut bytes = frame.to_bytes();
+        bytes[12..14].copy_from_slice(&[0x86, 0xDD]);
+        assert!(matches!(parse_ethernet(&bytes), Some(Packet::Other(0x86DD))));
+        assert!(parse_ethernet(&bytes[..10]).is_none());
+
+        // A VLAN tag is skipped, the inner ethertype picks the decoder
+        match parse_ethernet(&frame.with_vlan(VlanTag { pcp: 0, dei: false, id: 100 }).to_bytes()) {
+            Some(Packet::Ipv4(ip, Transport::Tcp(tcp))) => {
+                assert_eq!({ ip.header.src }, src);
+                assert_eq!(tcp.header.src.get(), 40000);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn build_udp() {
+        let src = Ipv4Addr::from_str("10.0.2.15");
+        let dst = Ipv4Addr::from_str("10.0.2.3");
+        let frame = PacketBuilder::ethernet(MacAddr::BROADCAST, MacAddr::default())
+            .ipv4(src, dst)
+            .ttl(16)
+            .udp(5353, 53)
+            .payload(b"query")
+            .build();
+        assert_eq!(frame.len(), 14 + 20 + 8 + 5);
+        assert!(header_checksum_ok(&frame));
+
+        match parse_ethernet(&frame) {
+            Some(Packet::Ipv4(ip, Transport::Udp(udp))) => {
+                assert_eq!(ip.total_len(), 33);
+                assert_eq!(ip.ttl(), 16);
+                assert_eq!(udp.header.src.get(), 5353);
+                assert_eq!(udp.header.len.get(), 13);
+                assert_eq!(udp.data, b"query");
+                assert_ne!({ udp.header.checksum.data }, 0);
+                assert!(udp.is_valid(&src, &dst));
+                // The pseudo header ties the checksum to the addresses
+                assert!(!udp.is_valid(&src, &src));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn build_tcp() {
+        let src = Ipv4Addr::from_str("10.0.2.15");
+        let dst = Ipv4Addr::from_str("93.184.216.34");
+        let frame = PacketBuilder::ethernet(MacAddr::BROADCAST, MacAddr::default())
+            .ipv4(src, dst)
+            .tcp(40000, 80)
+            .sequence(1000)
+            .ack(2000)
+            .flags(TCP_PSH)
+            .payload(b"GET / HTTP/1.0\r\n\r\n")
+            .build();
+        assert!(header_checksum_ok(&frame));
+
+        match parse_ethernet(&frame) {
+            Some(Packet::Ipv4(ip, Transport::Tcp(tcp))) => {
+                assert_eq!(ip.total_len() as usize, 20 + 20 + 18);
+                assert_eq!(tcp.header.sequence.get(), 1000);
+                assert_eq!(tcp.header.ack_num.get(), 2000);
+                assert_eq!(tcp.flag_names(), vec!["PSH", "ACK"]);
+                assert_eq!(tcp.header.flags.get() & TCP_ACK, TCP_ACK);
+                assert_eq!(tcp.data, b"GET / HTTP/1.0\r\n\r\n");
+
+                let mut check = tcp.clone();
+                check.checksum(&src, &dst);
+                assert_eq!({ check.header.checksum.data }, { tcp.header.checksum.data });
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::ops::RangeInclusive;
+use std::{error, fmt, str};
+
+/// Ports given as `80`, `20-25`, or a comma separated list of either, such
+/// as `22,80,8000-8080`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PortRange {
+    spans: Vec<RangeInclusive<u16>>,
+}
+
+impl PortRange {
+    pub fn single(port: u16) -> Self {
+        PortRange { spans: vec![port..=port] }
+    }
+
+    /// Every port, in the order the list names them
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.spans.iter().flat_map(|span| span.clone())
+    }
+
+    /// Number of ports the iterator yields, counting repeats
+    pub fn count(&self) -> usize {
+        self.spans.iter().map(|span| (span.end() - span.start()) as usize + 1).sum()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PortRangeParseError {
+    /// Nothing between two commas, or nothing at all
+    Empty,
+    /// Not a number from 1 to 65535
+    InvalidPort(String),
+    /// A range ending before it starts, like `80-20`
+    Reversed(u16, u16),
+}
+
+impl fmt::Display for PortRangeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PortRangeParseError::Empty => write!(f, "empty port list"),
+            PortRangeParseError::InvalidPort(ref port) => write!(f, "invalid port {}", port),
+            PortRangeParseError::Reversed(start, end) => write!(f, "invalid port range {}-{}", start, end),
+        }
+    }
+}
+
+impl error::Error for PortRangeParseError {}
+
+fn parse_port(port: &str) -> Result<u16, PortRangeParseError> {
+    if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(PortRangeParseError::InvalidPort(port.to_string()));
+    }
+    match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(PortRangeParseError::InvalidPort(port.to_string())),
+    }
+}
+
+impl str::FromStr for PortRange {
+    type Err = PortRangeParseError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut spans = Vec::new();
+        for item in string.split(',') {
+            if item.is_empty() {
+                return Err(PortRangeParseError::Empty);
+            }
+            let span = match item.find('-') {
+                Some(i) => {
+                    let (start, end) = (parse_port(&item[..i])?, parse_port(&item[i + 1..])?);
+                    if start > end {
+                        return Err(PortRangeParseError::Reversed(start, end));
+                    }
+                    start..=end
+                }
+                None => {
+                    let port = parse_port(item)?;
+                    port..=port
+                }
+            };
+            spans.push(span);
+        }
+        Ok(PortRange { spans })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, span) in self.spans.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if span.start() == span.end() {
+                write!(f, "{}", span.start())?;
+            } else {
+                write!(f, "{}-{}", span.start(), span.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PortRange, PortRangeParseError};
+
+    fn ports(string: &str) -> Vec<u16> {
+        string.parse::<PortRange>().unwrap().iter().collect()
+    }
+
+    #[test]
+    fn syntax_forms() {
+        assert_eq!(ports("80"), [80]);
+        assert_eq!(ports("20-23"), [20, 21, 22, 23]);
+        assert_eq!(ports("443,22,80"), [443, 22, 80]);
+        assert_eq!(ports("22,8000-8002,1"), [22, 8000, 8001, 8002, 1]);
+        assert_eq!(ports("65535-65535"), [65535]);
+
+        let range = "1-1024,8080".parse::<PortRange>().unwrap();
+        assert_eq!(range.count(), 1025);
+        assert_eq!(range.to_string(), "1-1024,8080");
+        assert_eq!(PortRange::single(53).to_string(), "53");
+    }
+
+    #[test]
+    fn malformed() {
+        let parse = |string: &str| string.parse::<PortRange>().unwrap_err();
+        assert_eq!(parse("80-20"), PortRangeParseError::Reversed(80, 20));
+        assert_eq!(parse("70000"), PortRangeParseError::InvalidPort("70000".to_string()));
+        assert_eq!(parse("0"), PortRangeParseError::InvalidPort("0".to_string()));
+        assert_eq!(parse("http"), PortRangeParseError::InvalidPort("http".to_string()));
+        assert_eq!(parse("+80"), PortRangeParseError::InvalidPort("+80".to_string()));
+        assert_eq!(parse("20-"), PortRangeParseError::InvalidPort("".to_string()));
+        assert_eq!(parse("1-2-3"), PortRangeParseError::InvalidPort("2-3".to_string()));
+        assert_eq!(parse(""), PortRangeParseError::Empty);
+        assert_eq!(parse("22,,80"), PortRangeParseError::Empty);
+        assert_eq!(parse("80-20").to_string(), "invalid port range 80-20");
+    }
+}
@@ -1,7 +1,10 @@
-use super::{n16, n32, Checksum};
-use std::{mem, slice, u8};
+use super::{n16, n32, require, wire, Checksum, ParseError};
+use std::convert::TryFrom;
+use std::net::Ipv6Addr;
+use std::{fmt, mem, slice, u8};
 
 use ip::Ipv4Addr;
+use ipv6;
 
 pub const TCP_FIN: u16 = 1;
 pub const TCP_SYN: u16 = 1 << 1;
@@ -10,6 +13,7 @@ pub const TCP_PSH: u16 = 1 << 3;
 pub const TCP_ACK: u16 = 1 << 4;
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(packed)]
 pub struct TcpHeader {
     pub src: n16,
@@ -22,56 +26,486 @@ pub struct TcpHeader {
     pub urgent_pointer: n16,
 }
 
+impl TcpHeader {
+    /// Read the fixed part of the header field by field, without casting the buffer
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        require(bytes, mem::size_of::<TcpHeader>())?;
+        let mut reader = wire::Reader::new(bytes);
+        Ok(TcpHeader {
+            src: n16::new(reader.u16()?),
+            dst: n16::new(reader.u16()?),
+            sequence: n32::new(reader.u32()?),
+            ack_num: n32::new(reader.u32()?),
+            flags: n16::new(reader.u16()?),
+            window_size: n16::new(reader.u16()?),
+            checksum: reader.checksum()?,
+            urgent_pointer: n16::new(reader.u16()?),
+        })
+    }
+
+    /// Append the fixed part of the header to `out`, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        wire::write_u16_be(out, self.src.get());
+        wire::write_u16_be(out, self.dst.get());
+        wire::write_u32_be(out, self.sequence.get());
+        wire::write_u32_be(out, self.ack_num.get());
+        wire::write_u16_be(out, self.flags.get());
+        wire::write_u16_be(out, self.window_size.get());
+        wire::write_checksum(out, self.checksum);
+        wire::write_u16_be(out, self.urgent_pointer.get());
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Tcp {
     pub header: TcpHeader,
     pub options: Vec<u8>,
     pub data: Vec<u8>,
 }
 
+impl<'a> TryFrom<&'a [u8]> for Tcp {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        TcpRef::try_from(bytes).map(|segment| segment.to_owned())
+    }
+}
+
+/// Data offset from the top four bits of `flags`, converted to bytes
+fn header_len(header: &TcpHeader) -> usize {
+    ((header.flags.get() & 0xF000) >> 10) as usize
+}
+
+/// A TCP segment borrowed from a receive buffer, with the options and payload
+/// left where they are
+#[derive(Copy, Clone, Debug)]
+pub struct TcpRef<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for TcpRef<'a> {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        require(bytes, mem::size_of::<TcpHeader>())?;
+        let header_len = header_len(unsafe { &*(bytes.as_ptr() as *const TcpHeader) });
+
+        if header_len < mem::size_of::<TcpHeader>() {
+            return Err(ParseError::BadLength);
+        }
+        require(bytes, header_len)?;
+
+        Ok(TcpRef { bytes })
+    }
+}
+
+impl<'a> TcpRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        TcpRef::try_from(bytes).ok()
+    }
+
+    pub fn header(&self) -> &'a TcpHeader {
+        unsafe { &*(self.bytes.as_ptr() as *const TcpHeader) }
+    }
+
+    pub fn options(&self) -> &'a [u8] {
+        &self.bytes[mem::size_of::<TcpHeader>()..header_len(self.header())]
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[header_len(self.header())..]
+    }
+
+    /// Copy the options and payload into an owned `Tcp`
+    pub fn to_owned(&self) -> Tcp {
+        Tcp {
+            header: *self.header(),
+            options: self.options().to_vec(),
+            data: self.payload().to_vec(),
+        }
+    }
+}
+
 impl Tcp {
     pub fn checksum(&mut self, src_addr: &Ipv4Addr, dst_addr: &Ipv4Addr) {
-        self.header.checksum.data = 0;
-
-        let proto = n16::new(0x06);
-        let segment_len = n16::new((mem::size_of::<TcpHeader>() + self.options.len() + self.data.len()) as u16);
-        self.header.checksum.data = Checksum::compile(unsafe {
-            Checksum::sum(src_addr.bytes.as_ptr() as usize, src_addr.bytes.len()) +
-            Checksum::sum(dst_addr.bytes.as_ptr() as usize, dst_addr.bytes.len()) +
-            Checksum::sum((&segment_len as *const n16) as usize, mem::size_of::<n16>()) +
-            Checksum::sum((&proto as *const n16) as usize, mem::size_of::<n16>()) +
-            Checksum::sum((&self.header as *const TcpHeader) as usize, mem::size_of::<TcpHeader>()) +
-            Checksum::sum(self.options.as_ptr() as usize, self.options.len()) +
-            Checksum::sum(self.data.as_ptr() as usize, self.data.len())
-        });
+        let [len_hi, len_lo] = (self.segment_len() as u16).to_be_bytes();
+        let pseudo_header = [0, 0x06, len_hi, len_lo];
+        self.header.checksum.data = self.sum_with(&[&src_addr.bytes, &dst_addr.bytes, &pseudo_header]);
+    }
+
+    /// Fill in the checksum for a segment sent over IPv6 from `src_addr` to `dst_addr`
+    pub fn checksum_v6(&mut self, src_addr: &Ipv6Addr, dst_addr: &Ipv6Addr) {
+        self.header.checksum.data = self.compute_checksum_v6(src_addr, dst_addr);
+    }
+
+    /// Compare the checksum received with the one computed over the IPv6 pseudo header
+    pub fn is_valid_v6(&self, src_addr: &Ipv6Addr, dst_addr: &Ipv6Addr) -> bool {
+        self.compute_checksum_v6(src_addr, dst_addr) == self.header.checksum.data
+    }
+
+    fn compute_checksum_v6(&self, src_addr: &Ipv6Addr, dst_addr: &Ipv6Addr) -> u16 {
+        let pseudo_header = ipv6::pseudo_header(src_addr, dst_addr, self.segment_len() as u32, 0x06);
+        self.sum_with(&[&pseudo_header])
+    }
+
+    /// Header, options and data, the length the pseudo header gives
+    fn segment_len(&self) -> usize {
+        mem::size_of::<TcpHeader>() + self.options.len() + self.data.len()
+    }
+
+    /// Checksum over `pseudo_header` and the segment with its checksum field zeroed
+    fn sum_with(&self, pseudo_header: &[&[u8]]) -> u16 {
+        let mut header = self.header;
+        header.checksum.data = 0;
+        let header_ptr: *const TcpHeader = &header;
+        let header = unsafe { slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<TcpHeader>()) };
+        let mut chunks = pseudo_header.to_vec();
+        chunks.extend_from_slice(&[header, &self.options, &self.data]);
+        Checksum::compile(Checksum::sum_chunks(&chunks))
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() >= mem::size_of::<TcpHeader>() {
-            unsafe {
-                let header = *(bytes.as_ptr() as *const TcpHeader);
-                let header_len = ((header.flags.get() & 0xF000) >> 10) as usize;
-
-                if header_len >= mem::size_of::<TcpHeader>() && header_len <= bytes.len() {
-                    return Some(Tcp {
-                        header: header,
-                        options: bytes[mem::size_of::<TcpHeader>()..header_len].to_vec(),
-                        data: bytes[header_len..bytes.len()].to_vec(),
-                    });
+        Tcp::try_from(bytes).ok()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.to_bytes_into(&mut ret);
+        ret
+    }
+
+    /// Append the header, options and data to `out`
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        let header_ptr: *const TcpHeader = &self.header;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<TcpHeader>())
+        });
+        out.extend_from_slice(&self.options);
+        out.extend_from_slice(&self.data);
+    }
+
+    /// Names of the flags that are set, e.g. `["SYN", "ACK"]`
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        let flags = self.header.flags.get();
+        [
+            (TCP_FIN, "FIN"),
+            (TCP_SYN, "SYN"),
+            (TCP_RST, "RST"),
+            (TCP_PSH, "PSH"),
+            (TCP_ACK, "ACK"),
+        ].iter()
+            .filter(|&&(flag, _)| flags & flag == flag)
+            .map(|&(_, name)| name)
+            .collect()
+    }
+}
+
+/// One line summary with the ports, flags, sequence numbers and payload size
+impl fmt::Display for Tcp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TCP {} -> {} [{}] seq={} ack={} win={} len={}",
+               self.header.src.get(),
+               self.header.dst.get(),
+               self.flag_names().join(","),
+               self.header.sequence.get(),
+               self.header.ack_num.get(),
+               self.header.window_size.get(),
+               self.data.len())
+    }
+}
+
+/// Connection states from RFC 793
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Minimal TCP connection tracking for tools speaking raw TCP.
+///
+/// Handles the three-way handshake, in-order data and the orderly close. There is
+/// no retransmission, reordering or congestion control. Segments are returned
+/// without a checksum, call `Tcp::checksum` with the IP addresses before sending.
+#[derive(Clone, Debug)]
+pub struct TcpConnection {
+    pub state: TcpState,
+    pub src_port: u16,
+    pub dst_port: u16,
+    /// Oldest sequence number not yet acknowledged by the peer (SND.UNA)
+    pub send_unacked: u32,
+    /// Sequence number of the next byte to send (SND.NXT)
+    pub send_next: u32,
+    /// Sequence number expected next from the peer (RCV.NXT)
+    pub recv_next: u32,
+    pub window_size: u16,
+    /// Data accepted in order, for the caller to drain
+    pub received: Vec<u8>,
+}
+
+impl TcpConnection {
+    pub fn new(src_port: u16, dst_port: u16, initial_sequence: u32) -> Self {
+        TcpConnection {
+            state: TcpState::Closed,
+            src_port: src_port,
+            dst_port: dst_port,
+            send_unacked: initial_sequence,
+            send_next: initial_sequence,
+            recv_next: 0,
+            window_size: 0xFFFF,
+            received: Vec::new(),
+        }
+    }
+
+    /// Passive open, waiting for a SYN from any peer on `dst_port`
+    pub fn listen(src_port: u16, dst_port: u16, initial_sequence: u32) -> Self {
+        let mut connection = TcpConnection::new(src_port, dst_port, initial_sequence);
+        connection.state = TcpState::Listen;
+        connection
+    }
+
+    fn segment(&self, sequence: u32, flags: u16, data: &[u8]) -> Tcp {
+        let ack_num = if flags & TCP_ACK == TCP_ACK { self.recv_next } else { 0 };
+        Tcp {
+            header: TcpHeader {
+                src: n16::new(self.src_port),
+                dst: n16::new(self.dst_port),
+                sequence: n32::new(sequence),
+                ack_num: n32::new(ack_num),
+                flags: n16::new(((mem::size_of::<TcpHeader>() << 10) as u16) | flags),
+                window_size: n16::new(self.window_size),
+                checksum: Checksum { data: 0 },
+                urgent_pointer: n16::new(0),
+            },
+            options: Vec::new(),
+            data: data.to_vec(),
+        }
+    }
+
+    fn ack(&self) -> Tcp {
+        self.segment(self.send_next, TCP_ACK, &[])
+    }
+
+    /// Active open, returning the SYN to send
+    pub fn syn(&mut self) -> Tcp {
+        let syn = self.segment(self.send_next, TCP_SYN, &[]);
+        self.send_unacked = self.send_next;
+        self.send_next = self.send_next.wrapping_add(1);
+        self.state = TcpState::SynSent;
+        syn
+    }
+
+    /// Segment carrying `data`, to be used once the connection is established
+    pub fn send(&mut self, data: &[u8]) -> Tcp {
+        let segment = self.segment(self.send_next, TCP_ACK | TCP_PSH, data);
+        self.send_next = self.send_next.wrapping_add(data.len() as u32);
+        segment
+    }
+
+    /// Start an orderly close, returning the FIN to send if the state allows one
+    pub fn close(&mut self) -> Option<Tcp> {
+        let next_state = match self.state {
+            TcpState::SynReceived | TcpState::Established => TcpState::FinWait1,
+            TcpState::CloseWait => TcpState::LastAck,
+            TcpState::Listen | TcpState::SynSent => {
+                self.state = TcpState::Closed;
+                return None;
+            }
+            _ => return None,
+        };
+
+        let fin = self.segment(self.send_next, TCP_FIN | TCP_ACK, &[]);
+        self.send_next = self.send_next.wrapping_add(1);
+        self.state = next_state;
+        Some(fin)
+    }
+
+    /// Process a segment from the peer, returning the segments to send in response
+    pub fn on_segment(&mut self, seg: &Tcp) -> Vec<Tcp> {
+        let flags = seg.header.flags.get();
+        let sequence = seg.header.sequence.get();
+        let ack_num = seg.header.ack_num.get();
+
+        if flags & TCP_RST == TCP_RST {
+            if self.state != TcpState::Listen {
+                self.state = TcpState::Closed;
+            }
+            return Vec::new();
+        }
+
+        match self.state {
+            TcpState::Closed => return Vec::new(),
+            TcpState::Listen => {
+                if flags & TCP_SYN == TCP_SYN {
+                    self.recv_next = sequence.wrapping_add(1);
+                    let syn_ack = self.segment(self.send_next, TCP_SYN | TCP_ACK, &[]);
+                    self.send_unacked = self.send_next;
+                    self.send_next = self.send_next.wrapping_add(1);
+                    self.state = TcpState::SynReceived;
+                    return vec![syn_ack];
+                }
+                return Vec::new();
+            }
+            TcpState::SynSent => {
+                if flags & TCP_SYN != TCP_SYN {
+                    return Vec::new();
+                }
+                self.recv_next = sequence.wrapping_add(1);
+                if flags & TCP_ACK == TCP_ACK {
+                    if ack_num != self.send_next {
+                        return Vec::new();
+                    }
+                    self.send_unacked = ack_num;
+                    self.state = TcpState::Established;
+                    return vec![self.ack()];
+                }
+                // Simultaneous open, answer with our SYN again plus an ACK
+                self.state = TcpState::SynReceived;
+                return vec![self.segment(self.send_unacked, TCP_SYN | TCP_ACK, &[])];
+            }
+            _ => (),
+        }
+
+        let has_fin = flags & TCP_FIN == TCP_FIN;
+        if sequence != self.recv_next {
+            // Out of order or duplicate, re-acknowledge what we have
+            if seg.data.is_empty() && !has_fin {
+                return Vec::new();
+            }
+            return vec![self.ack()];
+        }
+
+        if flags & TCP_ACK == TCP_ACK {
+            let acked = ack_num.wrapping_sub(self.send_unacked) as i32;
+            let outstanding = self.send_next.wrapping_sub(self.send_unacked) as i32;
+            if acked > 0 && acked <= outstanding {
+                self.send_unacked = ack_num;
+            }
+
+            if self.send_unacked == self.send_next {
+                self.state = match self.state {
+                    TcpState::SynReceived => TcpState::Established,
+                    TcpState::FinWait1 => TcpState::FinWait2,
+                    TcpState::Closing => TcpState::TimeWait,
+                    TcpState::LastAck => TcpState::Closed,
+                    state => state,
+                };
+            }
+        }
+
+        let mut need_ack = false;
+        if !seg.data.is_empty() {
+            match self.state {
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => {
+                    self.received.extend_from_slice(&seg.data);
+                    self.recv_next = self.recv_next.wrapping_add(seg.data.len() as u32);
                 }
+                _ => (),
             }
+            need_ack = true;
+        }
+
+        if has_fin {
+            self.recv_next = self.recv_next.wrapping_add(1);
+            self.state = match self.state {
+                TcpState::SynReceived | TcpState::Established => TcpState::CloseWait,
+                TcpState::FinWait1 => TcpState::Closing,
+                TcpState::FinWait2 => TcpState::TimeWait,
+                state => state,
+            };
+            need_ack = true;
+        }
+
+        if need_ack {
+            vec![self.ack()]
+        } else {
+            Vec::new()
         }
-        None
     }
+}
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        unsafe {
-            let header_ptr: *const TcpHeader = &self.header;
-            let mut ret = Vec::from(slice::from_raw_parts(header_ptr as *const u8,
-                                                          mem::size_of::<TcpHeader>()));
-            ret.extend_from_slice(&self.options);
-            ret.extend_from_slice(&self.data);
-            ret
+#[cfg(test)]
+mod tests {
+    use super::{TcpConnection, TcpState, TCP_ACK, TCP_FIN, TCP_SYN};
+
+    /// Deliver every segment to the peer and return everything it sent back
+    fn deliver(to: &mut TcpConnection, segments: Vec<super::Tcp>) -> Vec<super::Tcp> {
+        let mut replies = Vec::new();
+        for segment in segments {
+            replies.extend(to.on_segment(&segment));
         }
+        replies
+    }
+
+    #[test]
+    fn handshake_data_and_close() {
+        let mut client = TcpConnection::new(40000, 80, 1000);
+        let mut server = TcpConnection::listen(80, 40000, 5000);
+
+        let syn = client.syn();
+        assert_eq!(syn.header.flags.get() & 0x1FF, TCP_SYN);
+        assert_eq!(client.state, TcpState::SynSent);
+
+        let syn_ack = deliver(&mut server, vec![syn]);
+        assert_eq!(syn_ack.len(), 1);
+        assert_eq!(syn_ack[0].header.flags.get() & 0x1FF, TCP_SYN | TCP_ACK);
+        assert_eq!(syn_ack[0].header.ack_num.get(), 1001);
+        assert_eq!(server.state, TcpState::SynReceived);
+
+        let ack = deliver(&mut client, syn_ack);
+        assert_eq!(client.state, TcpState::Established);
+        assert_eq!(ack[0].header.ack_num.get(), 5001);
+
+        assert!(deliver(&mut server, ack).is_empty());
+        assert_eq!(server.state, TcpState::Established);
+
+        let data = client.send(b"GET /");
+        let ack = deliver(&mut server, vec![data]);
+        assert_eq!(server.received, b"GET /".to_vec());
+        assert_eq!(ack[0].header.ack_num.get(), 1006);
+        assert!(deliver(&mut client, ack).is_empty());
+        assert_eq!(client.send_unacked, client.send_next);
+
+        let fin = client.close().unwrap();
+        assert_eq!(fin.header.flags.get() & TCP_FIN, TCP_FIN);
+        assert_eq!(client.state, TcpState::FinWait1);
+
+        let ack = deliver(&mut server, vec![fin]);
+        assert_eq!(server.state, TcpState::CloseWait);
+        deliver(&mut client, ack);
+        assert_eq!(client.state, TcpState::FinWait2);
+
+        let fin = server.close().unwrap();
+        assert_eq!(server.state, TcpState::LastAck);
+        let ack = deliver(&mut client, vec![fin]);
+        assert_eq!(client.state, TcpState::TimeWait);
+        assert_eq!(ack[0].header.flags.get() & 0x1FF, TCP_ACK);
+
+        deliver(&mut server, ack);
+        assert_eq!(server.state, TcpState::Closed);
+    }
+
+    #[test]
+    fn out_of_order_is_reacknowledged() {
+        let mut client = TcpConnection::new(40000, 80, 1000);
+        let mut server = TcpConnection::listen(80, 40000, 5000);
+        let syn_ack = deliver(&mut server, vec![client.syn()]);
+        let ack = deliver(&mut client, syn_ack);
+        deliver(&mut server, ack);
+
+        client.send(b"lost");
+        let late = client.send(b"late");
+        let ack = deliver(&mut server, vec![late]);
+        assert!(server.received.is_empty());
+        assert_eq!(ack[0].header.ack_num.get(), 1001);
     }
 }
@@ -0,0 +1,64 @@
+//! Scratch directories for tests that work on real files.
+//!
+//! The directory is removed when the `TempDir` is dropped, which also
+//! happens while a failed assertion unwinds.
+use std::env;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tells apart the directories of tests running at the same time
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create an empty directory in the system temporary directory, named
+    /// after `prefix`, the process and a counter
+    pub fn new(prefix: &str) -> Result<TempDir> {
+        let count = COUNT.fetch_add(1, Ordering::SeqCst);
+        let dir = TempDir {
+            path: env::temp_dir().join(format!("{}-{}-{}", prefix, process::id(), count)),
+        };
+        // Left over by an earlier process with the same id
+        let _ = fs::remove_dir_all(&dir.path);
+        fs::create_dir_all(&dir.path)?;
+        Ok(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TempDir;
+    use std::fs;
+
+    #[test]
+    fn removed_on_drop() {
+        let dir = TempDir::new("tempdir-test").unwrap();
+        let path = dir.path().to_path_buf();
+        fs::write(path.join("file"), "data").unwrap();
+        assert!(path.is_dir());
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn distinct_paths() {
+        let (first, second) = (TempDir::new("tempdir-test").unwrap(), TempDir::new("tempdir-test").unwrap());
+        assert_ne!(first.path(), second.path());
+    }
+}
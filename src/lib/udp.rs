@@ -1,10 +1,14 @@
-use super::{n16, Checksum};
-use std::{mem, slice, u8};
+use super::{n16, require, wire, Checksum, ParseError};
+use std::convert::TryFrom;
+use std::net::Ipv6Addr;
+use std::{fmt, mem, slice, u8};
 
 use ip::Ipv4Addr;
+use ipv6;
 
 /// UDP header as defined in RFC 768
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(packed)]
 pub struct UdpHeader {
     /// Source port
@@ -17,44 +21,113 @@ pub struct UdpHeader {
     pub checksum: Checksum,
 }
 
+impl UdpHeader {
+    /// Read the header field by field, without casting the buffer
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        require(bytes, mem::size_of::<UdpHeader>())?;
+        let mut reader = wire::Reader::new(bytes);
+        Ok(UdpHeader {
+            src: n16::new(reader.u16()?),
+            dst: n16::new(reader.u16()?),
+            len: n16::new(reader.u16()?),
+            checksum: reader.checksum()?,
+        })
+    }
+
+    /// Append the header to `out` field by field, the counterpart of `parse`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        wire::write_u16_be(out, self.src.get());
+        wire::write_u16_be(out, self.dst.get());
+        wire::write_u16_be(out, self.len.get());
+        wire::write_checksum(out, self.checksum);
+    }
+}
+
 /// UDP datagram for IPv4 stack consisting of header and data section
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Udp {
     pub header: UdpHeader,
     pub data: Vec<u8>,
 }
 
+impl<'a> TryFrom<&'a [u8]> for Udp {
+    type Error = ParseError;
+
+    /// Parse the wire representation, reporting which check failed
+    fn try_from(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        UdpRef::try_from(bytes).map(|datagram| datagram.to_owned())
+    }
+}
+
+/// A UDP datagram read in place, without copying the payload
+#[derive(Copy, Clone, Debug)]
+pub struct UdpRef<'a> {
+    /// The datagram up to the length in its header
+    bytes: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for UdpRef<'a> {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        require(bytes, mem::size_of::<UdpHeader>())?;
+        let header = unsafe { &*(bytes.as_ptr() as *const UdpHeader) };
+        let len = header.len.get() as usize;
+
+        if len < mem::size_of::<UdpHeader>() {
+            return Err(ParseError::BadLength);
+        }
+        require(bytes, len)?;
+
+        Ok(UdpRef { bytes: &bytes[..len] })
+    }
+}
+
+impl<'a> UdpRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        UdpRef::try_from(bytes).ok()
+    }
+
+    pub fn header(&self) -> &'a UdpHeader {
+        unsafe { &*(self.bytes.as_ptr() as *const UdpHeader) }
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[mem::size_of::<UdpHeader>()..]
+    }
+
+    /// Copy the payload into an owned `Udp`
+    pub fn to_owned(&self) -> Udp {
+        Udp {
+            header: *self.header(),
+            data: self.payload().to_vec(),
+        }
+    }
+}
+
 impl Udp {
     /// Read wire representation and parse it into its
     /// structural represantation.
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() >= mem::size_of::<UdpHeader>() {
-            unsafe {
-                let header = *(bytes.as_ptr() as *const UdpHeader);
-
-                if header.len.get() as usize <= bytes.len() &&
-                   mem::size_of::<UdpHeader>() <= header.len.get() as usize {
-                    return Some(Udp {
-                        header: header,
-                        data: bytes[mem::size_of::<UdpHeader>()..header.len.get() as usize]
-                            .to_vec(),
-                    });
-                }
-            }
-        }
-        None
+        Udp::try_from(bytes).ok()
     }
 
     /// Compile the `self` structure into its wire
     /// representation.
     pub fn to_bytes(&self) -> Vec<u8> {
-        unsafe {
-            let header_ptr: *const UdpHeader = &self.header;
-            let mut ret = Vec::from(slice::from_raw_parts(header_ptr as *const u8,
-                                                          mem::size_of::<UdpHeader>()));
-            ret.extend_from_slice(&self.data);
-            ret
-        }
+        let mut ret = Vec::new();
+        self.to_bytes_into(&mut ret);
+        ret
+    }
+
+    /// Compile the datagram into `out`, after whatever it already holds.
+    pub fn to_bytes_into(&self, out: &mut Vec<u8>) {
+        let header_ptr: *const UdpHeader = &self.header;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<UdpHeader>())
+        });
+        out.extend_from_slice(&self.data);
     }
 
     /// Compute a checksum of the `self` datagram
@@ -87,29 +160,60 @@ impl Udp {
         if self.header.checksum.data == 0 {
             true
         } else {
-            let mut header = self.header;
-            header.checksum.data = 0;
-            let mut computed_checksum: u16 = Checksum::compile(unsafe {
-                // Pseudo header
-                Checksum::sum(src_addr.bytes.as_ptr() as usize, src_addr.bytes.len()) +
-                Checksum::sum(dst_addr.bytes.as_ptr() as usize, dst_addr.bytes.len()) +
-                Checksum::sum((&0x1100u16 as *const u16) as usize, mem::size_of::<u16>()) +
-                Checksum::sum((&header.len as *const n16) as usize, mem::size_of::<n16>()) +
-                // Real header
-                Checksum::sum((&header as *const UdpHeader) as usize, mem::size_of::<UdpHeader>()) +
-                // Data
-                Checksum::sum(self.data.as_ptr() as usize, self.data.len())
-            });
-            if computed_checksum == 0 {
-                computed_checksum = 0xFFFF;
-            }
-            if computed_checksum == self.header.checksum.data {
-                true
-            } else {
-                false
-            }
+            self.compute_checksum(src_addr, dst_addr) == self.header.checksum.data
         }
     }
+
+    /// Fill in the checksum for a datagram sent from `src_addr` to `dst_addr`
+    pub fn checksum(&mut self, src_addr: &Ipv4Addr, dst_addr: &Ipv4Addr) {
+        self.header.checksum.data = self.compute_checksum(src_addr, dst_addr);
+    }
+
+    /// Checksum over the pseudo header, the header without its checksum and
+    /// the data, never zero as that would mean no checksum
+    fn compute_checksum(&self, src_addr: &Ipv4Addr, dst_addr: &Ipv4Addr) -> u16 {
+        let [len_hi, len_lo] = self.header.len.get().to_be_bytes();
+        self.sum_with(&[&src_addr.bytes, &dst_addr.bytes, &[0, 0x11, len_hi, len_lo]])
+    }
+
+    fn sum_with(&self, pseudo_header: &[&[u8]]) -> u16 {
+        let mut header = self.header;
+        header.checksum.data = 0;
+        let header_ptr: *const UdpHeader = &header;
+        let header = unsafe { slice::from_raw_parts(header_ptr as *const u8, mem::size_of::<UdpHeader>()) };
+        let mut chunks = pseudo_header.to_vec();
+        chunks.push(header);
+        chunks.push(&self.data);
+        let computed_checksum: u16 = Checksum::compile(Checksum::sum_chunks(&chunks));
+        if computed_checksum == 0 {
+            0xFFFF
+        } else {
+            computed_checksum
+        }
+    }
+
+    /// Fill in the checksum for a datagram sent over IPv6 from `src_addr` to `dst_addr`
+    pub fn checksum_v6(&mut self, src_addr: &Ipv6Addr, dst_addr: &Ipv6Addr) {
+        self.header.checksum.data = self.compute_checksum_v6(src_addr, dst_addr);
+    }
+
+    /// Check the datagram against the IPv6 pseudo header. IPv6 has no header
+    /// checksum of its own, so RFC 8200 makes the UDP one mandatory and a
+    /// zero checksum is invalid rather than absent.
+    pub fn is_valid_v6(&self, src_addr: &Ipv6Addr, dst_addr: &Ipv6Addr) -> bool {
+        self.header.checksum.data != 0 && self.compute_checksum_v6(src_addr, dst_addr) == self.header.checksum.data
+    }
+
+    fn compute_checksum_v6(&self, src_addr: &Ipv6Addr, dst_addr: &Ipv6Addr) -> u16 {
+        let pseudo_header = ipv6::pseudo_header(src_addr, dst_addr, self.header.len.get() as u32, 0x11);
+        self.sum_with(&[&pseudo_header])
+    }
+}
+
+impl fmt::Display for Udp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UDP {} -> {} len={}", self.header.src.get(), self.header.dst.get(), self.header.len.get())
+    }
 }
 
 #[test]
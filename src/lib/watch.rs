@@ -0,0 +1,202 @@
+//! Notice when netcfg entries change, by reading them again at an interval.
+//!
+//! Polling needs nothing from the scheme beyond plain reads, so it works the
+//! same on a directory standing in for it.
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+use netcfg::{NetCfg, NetCfgError};
+
+/// How often `ConfigWatcher::watch` reads the entries unless told otherwise
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A watched entry that changed, with `None` for an entry that does not exist
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigChange {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Keeps the last value of some netcfg entries, such as `ifaces/eth0/addr/list`
+/// or `route/list`, and reports the ones that changed since
+#[derive(Clone, Debug)]
+pub struct ConfigWatcher {
+    cfg: NetCfg,
+    values: BTreeMap<String, Option<String>>,
+    interval: Duration,
+}
+
+/// Value of an entry, `Ok(None)` when it does not exist
+fn read(cfg: &NetCfg, path: &str) -> Result<Option<String>, NetCfgError> {
+    match cfg.get(path) {
+        Ok(value) => Ok(Some(value)),
+        Err(NetCfgError::NotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+impl ConfigWatcher {
+    /// Watch `paths` below the root of `cfg`, starting from their current values.
+    /// Entries that can't be read yet count as missing.
+    pub fn new<I, S>(cfg: NetCfg, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let values = paths
+            .into_iter()
+            .map(|path| {
+                let path = path.into();
+                let value = read(&cfg, &path).unwrap_or(None);
+                (path, value)
+            })
+            .collect();
+        ConfigWatcher {
+            cfg: cfg,
+            values: values,
+            interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The value last seen for `path`, `None` if it is missing or not watched
+    pub fn value(&self, path: &str) -> Option<&str> {
+        self.values.get(path).and_then(|value| value.as_deref())
+    }
+
+    /// Read every entry again and return the ones that changed, in path order.
+    ///
+    /// An entry that fails to read for another reason than not existing keeps
+    /// its value until a later poll reads it, so a passing error is not
+    /// reported as a change.
+    pub fn poll(&mut self) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+        for (path, value) in self.values.iter_mut() {
+            let new = match read(&self.cfg, path) {
+                Ok(new) => new,
+                Err(_) => continue,
+            };
+            if new != *value {
+                changes.push(ConfigChange {
+                    path: path.clone(),
+                    old: value.clone(),
+                    new: new.clone(),
+                });
+                *value = new;
+            }
+        }
+        changes
+    }
+
+    /// Poll at the interval and pass every change to `callback`, until it
+    /// returns `false`
+    pub fn watch<F: FnMut(&ConfigChange) -> bool>(&mut self, mut callback: F) {
+        loop {
+            thread::sleep(self.interval);
+            for change in self.poll() {
+                if !callback(&change) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigChange, ConfigWatcher};
+    use netcfg::NetCfg;
+    use std::time::Duration;
+    use std::{fs, thread};
+    use tempdir::TempDir;
+
+    const ADDR_LIST: &str = "ifaces/eth0/addr/list";
+
+    fn change(path: &str, old: Option<&str>, new: Option<&str>) -> ConfigChange {
+        ConfigChange {
+            path: path.to_string(),
+            old: old.map(str::to_string),
+            new: new.map(str::to_string),
+        }
+    }
+
+    /// A scheme directory with an address and a route, but no nameserver yet,
+    /// and a watcher on all three
+    fn scheme() -> (TempDir, ConfigWatcher) {
+        let root = TempDir::new("netcfg-watch-test").unwrap();
+        fs::create_dir_all(root.path().join("ifaces/eth0/addr")).unwrap();
+        fs::create_dir_all(root.path().join("route")).unwrap();
+        fs::write(root.path().join(ADDR_LIST), "10.0.2.15/24\n").unwrap();
+        fs::write(root.path().join("route/list"), "default via 10.0.2.2\n").unwrap();
+
+        let paths = vec![ADDR_LIST, "route/list", "resolv/nameserver"];
+        let watcher = ConfigWatcher::new(NetCfg::with_root(root.path()), paths);
+        (root, watcher)
+    }
+
+    #[test]
+    fn starts_from_current_values() {
+        let (_root, mut watcher) = scheme();
+        assert_eq!(watcher.value(ADDR_LIST), Some("10.0.2.15/24"));
+        assert_eq!(watcher.value("resolv/nameserver"), None);
+        assert_eq!(watcher.value("route/add"), None);
+        assert!(watcher.poll().is_empty());
+    }
+
+    #[test]
+    fn ignores_surrounding_whitespace() {
+        let (root, mut watcher) = scheme();
+        fs::write(root.path().join("route/list"), "default via 10.0.2.2").unwrap();
+        assert!(watcher.poll().is_empty());
+    }
+
+    #[test]
+    fn reports_changed_and_created_entries() {
+        let (root, mut watcher) = scheme();
+        fs::write(root.path().join(ADDR_LIST), "10.0.2.16/24\n").unwrap();
+        fs::create_dir_all(root.path().join("resolv")).unwrap();
+        fs::write(root.path().join("resolv/nameserver"), "10.0.2.3\n").unwrap();
+        assert_eq!(watcher.poll(), vec![
+            change(ADDR_LIST, Some("10.0.2.15/24"), Some("10.0.2.16/24")),
+            change("resolv/nameserver", None, Some("10.0.2.3")),
+        ]);
+        assert_eq!(watcher.value(ADDR_LIST), Some("10.0.2.16/24"));
+        assert!(watcher.poll().is_empty());
+    }
+
+    #[test]
+    fn reports_removed_entries() {
+        let (root, mut watcher) = scheme();
+        fs::remove_file(root.path().join("route/list")).unwrap();
+        assert_eq!(watcher.poll(), vec![change("route/list", Some("default via 10.0.2.2"), None)]);
+        assert_eq!(watcher.value("route/list"), None);
+    }
+
+    #[test]
+    fn watch_stops_when_callback_returns_false() {
+        let (root, watcher) = scheme();
+        let mut watcher = watcher.with_interval(Duration::from_millis(10));
+        let writer = {
+            let (path, temp) = (root.path().join(ADDR_LIST), root.path().join("list.tmp"));
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                // Renamed into place, as a poll could catch a plain write halfway
+                fs::write(&temp, "10.0.2.17/24").unwrap();
+                fs::rename(&temp, path).unwrap();
+            })
+        };
+        let mut seen = Vec::new();
+        watcher.watch(|change| {
+            seen.push(change.clone());
+            false
+        });
+        writer.join().unwrap();
+        assert_eq!(seen, vec![change(ADDR_LIST, Some("10.0.2.15/24"), Some("10.0.2.17/24"))]);
+    }
+}
@@ -0,0 +1,187 @@
+//! Explicit big-endian reads and writes for parsing headers field by field.
+//!
+//! The `TryFrom<&[u8]>` implementations read headers by casting the buffer to
+//! a packed struct, which is fast but leans on `unsafe`. The `parse` and
+//! `encode` methods on the header types go through this module instead and
+//! never look at the buffer as anything but bytes.
+use super::{require, Checksum, ParseError};
+use ip::Ipv4Addr;
+use mac::MacAddr;
+
+/// The big-endian `u16` at `offset`, or `None` past the end of `bytes`
+pub fn read_u16_be(bytes: &[u8], offset: usize) -> Option<u16> {
+    let field = bytes.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_be_bytes([field[0], field[1]]))
+}
+
+/// The big-endian `u32` at `offset`, or `None` past the end of `bytes`
+pub fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    let field = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+/// Append `value` to `out` in network order
+pub fn write_u16_be(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append `value` to `out` in network order
+pub fn write_u32_be(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Reads consecutive fields from the front of a buffer
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, offset: 0 }
+    }
+
+    /// Bytes read so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The next `len` bytes, failing with the length the field needs when the buffer ends first
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.offset + len;
+        require(self.bytes, end)?;
+        let field = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(field)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, ParseError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    /// A checksum kept as it appears on the wire, which is how `Checksum` stores it
+    pub fn checksum(&mut self) -> Result<Checksum, ParseError> {
+        Ok(Checksum { data: u16::from_ne_bytes(self.array()?) })
+    }
+
+    pub fn mac(&mut self) -> Result<MacAddr, ParseError> {
+        Ok(MacAddr { bytes: self.array()? })
+    }
+
+    pub fn ipv4(&mut self) -> Result<Ipv4Addr, ParseError> {
+        Ok(Ipv4Addr { bytes: self.array()? })
+    }
+}
+
+/// Append a checksum as `Reader::checksum` reads it
+pub fn write_checksum(out: &mut Vec<u8>, checksum: Checksum) {
+    out.extend_from_slice(&checksum.data.to_ne_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icmp::{Icmp, IcmpHeader, ECHO_REQUEST};
+    use tcp::{TcpConnection, TcpHeader};
+    use udp::UdpHeader;
+    use {Arp, ArpHeader, EthernetIIHeader, IpProtocol, Ipv4, Ipv4Header};
+
+    #[test]
+    fn integers() {
+        let bytes = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        assert_eq!(read_u16_be(&bytes, 0), Some(0x1234));
+        assert_eq!(read_u16_be(&bytes, 3), Some(0x789a));
+        assert_eq!(read_u16_be(&bytes, 4), None);
+        assert_eq!(read_u32_be(&bytes, 1), Some(0x3456_789a));
+        assert_eq!(read_u32_be(&bytes, 2), None);
+        assert_eq!(read_u32_be(&bytes, usize::MAX), None);
+
+        let mut out = Vec::new();
+        write_u16_be(&mut out, 0x1234);
+        write_u32_be(&mut out, 0x5678_9abc);
+        assert_eq!(out, [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]);
+
+        let mut reader = Reader::new(&out);
+        assert_eq!(reader.u8(), Ok(0x12));
+        assert_eq!(reader.u32(), Ok(0x3456_789a));
+        assert_eq!(reader.offset(), 5);
+        assert_eq!(reader.u16(), Err(ParseError::TooShort { needed: 7, got: 6 }));
+        assert_eq!(reader.u8(), Ok(0xbc));
+    }
+
+    /// Parse `bytes` at an odd address too, where the cast would read unaligned
+    fn misaligned(bytes: &[u8]) -> Vec<u8> {
+        let mut shifted = vec![0];
+        shifted.extend_from_slice(bytes);
+        shifted
+    }
+
+    #[test]
+    fn headers_match_fast_path() {
+        let src = Ipv4Addr::from_str("10.0.0.1");
+        let dst = Ipv4Addr::from_str("10.0.0.2");
+        let mac = MacAddr::from_str("52:54:00:12:34:56");
+
+        let ip = Ipv4::new(src, dst, IpProtocol::Udp, vec![0; 12]).with_ttl(7).with_id(0xbeef).to_bytes();
+        let shifted = misaligned(&ip);
+        let header = Ipv4Header::parse(&shifted[1..]).unwrap();
+        assert_eq!(header.ttl, 7);
+        assert_eq!(header.id.get(), 0xbeef);
+        assert_eq!({ header.src }, src);
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, &ip[..20]);
+        assert_eq!(Ipv4Header::parse(&ip[..19]).unwrap_err(), ParseError::TooShort { needed: 20, got: 19 });
+
+        let arp = Arp::request(mac, src, dst).to_bytes();
+        let header = ArpHeader::parse(&misaligned(&arp)[1..]).unwrap();
+        assert_eq!({ header.src_mac }, mac);
+        assert_eq!({ header.dst_ip }, dst);
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, arp);
+
+        let ethernet = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x52, 0x54, 0, 0x12, 0x34, 0x56, 0x08, 0x06];
+        let header = EthernetIIHeader::parse(&ethernet).unwrap();
+        assert_eq!({ header.src }, mac);
+        assert_eq!(header.ethertype.get(), 0x0806);
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, ethernet);
+
+        let udp = [0x9c, 0x40, 0, 53, 0, 8, 0xab, 0xcd];
+        let header = UdpHeader::parse(&misaligned(&udp)[1..]).unwrap();
+        assert_eq!((header.src.get(), header.dst.get(), header.len.get()), (40000, 53, 8));
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, udp);
+
+        let tcp = TcpConnection::new(40000, 80, 1000).syn().to_bytes();
+        let header = TcpHeader::parse(&misaligned(&tcp)[1..]).unwrap();
+        assert_eq!(header.sequence.get(), 1000);
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, &tcp[..20]);
+
+        let icmp = Icmp::new(ECHO_REQUEST, 0, 0x1234_0001, b"ping".to_vec()).to_bytes();
+        let header = IcmpHeader::parse(&misaligned(&icmp)[1..]).unwrap();
+        assert_eq!(header.rest.get(), 0x1234_0001);
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, &icmp[..8]);
+    }
+}
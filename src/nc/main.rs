@@ -1,17 +1,31 @@
 use std::env;
 use std::io::{self, Write};
+use std::time::Duration;
+
+extern crate net2;
+extern crate netutils;
+extern crate rustls;
+extern crate webpki_roots;
 
 mod modes;
+mod scan;
+mod tls;
 use modes::*;
 
 static MAN_PAGE: &'static str = /* @MANSTART{nc} */ r#"
 NAME
     nc - Concatenate and redirect sockets
 SYNOPSIS
-    nc [[-h | --help] | [-u | --udp] | [-l | --listen]] [hostname:port]
+    nc [[-h | --help] | [-u | --udp] | [-l | --listen] | [--ssl [--ssl-no-verify]]]
+       [-4 | -6] [-v | --verbose] [--crlf] [-d | --recv-only | --send-only] [-f file]
+       [-s source_address] [-p source_port] [--nodelay] [--keepalive seconds]
+       [hostname:port]
+    nc -U path [-v] [--crlf] [-d | --recv-only | --send-only] [-f file]
+    nc -z [-u] [-4 | -6] [-w timeout] hostname:ports
 DESCRIPTION
-    Netcat (nc) is command line utility which can read and write data across network. Currently
-    it only works with IPv4 and does not support any encryption.
+    Netcat (nc) is command line utility which can read and write data across network, over
+    IPv4 or IPv6. Write IPv6 addresses in brackets, as in [::1]:8080. Encryption is only
+    available for outgoing TCP connections.
 OPTIONS
     -h
     --help
@@ -23,6 +37,78 @@ OPTIONS
     -l
     --listen
         Listen for incoming connections.
+
+    -4
+        Only use IPv4 addresses, for the peer or the address to listen on.
+
+    -6
+        Only use IPv6 addresses. Without -4 or -6 a host name may resolve to
+        either, and connections try each address in turn.
+
+    -v
+    --verbose
+        Print the local and remote addresses once connected, and the number of
+        bytes sent and received on exit. Both go to standard error.
+
+    --ssl
+    --tls
+        Wrap the TCP connection in a TLS session.
+
+    --ssl-no-verify
+        Do not verify the server certificate, useful for self-signed servers.
+
+    --crlf
+        Send lines from standard input with \r\n endings, as line based protocols
+        like SMTP and HTTP expect.
+
+    -d
+    --recv-only
+        Only print what the peer sends, without reading standard input. The
+        session ends when the peer closes the connection, or on interrupt for UDP.
+
+    --send-only
+        Only send standard input, without reading from the peer. The session ends
+        with standard input. Not available when listening for UDP, as there is no
+        peer to send to.
+
+    -f file
+    --file file
+        Send the contents of file instead of standard input. With --send-only the
+        connection is closed once the whole file is sent, as a one-shot upload.
+
+    -s source_address
+        Bind outgoing TCP connections to this local address.
+
+    -p source_port
+        Bind outgoing TCP connections to this local port.
+
+    --nodelay
+        Set TCP_NODELAY, so small writes such as single keystrokes are sent at
+        once instead of waiting to be coalesced by Nagle's algorithm.
+
+    --keepalive seconds
+        Enable TCP keepalive, probing the peer once the connection has been idle
+        for this many seconds, so a vanished peer is noticed on long sessions.
+
+        Both only apply to TCP connections. If the system refuses one, a warning
+        is printed and the connection is used without it.
+
+    -U path
+    --scheme path
+        Open path, usually a Redox scheme such as chan:name, and exchange data
+        with it instead of a network peer. Standard input is written to it and
+        what it returns is printed, exactly as for a TCP connection.
+
+    -z
+        Scan the given ports instead of sending data, and print whether each one is
+        open, closed or filtered. With -u an empty datagram is sent to each
+        port: a reply means open, an ICMP port unreachable means closed, and silence is
+        reported as open|filtered since it cannot tell a quiet service from a firewall.
+        Ports are a single port, a range like 20-25, or a comma separated list of
+        both, like 22,80,8000-8080.
+
+    -w timeout
+        Seconds to wait for each -z probe, 1 by default.
 AUTHOR
     Written by Sehny.
 "#; /* @MANEND */
@@ -43,6 +129,17 @@ fn main() {
     let mut hostname = "".to_string();
     let mut proto = TransportProtocol::Tcp;
     let mut mode = NcMode::Connect;
+    let mut tls = false;
+    let mut tls_verify = true;
+    let mut source = Source::default();
+    let mut family = Family::Any;
+    let mut crlf = false;
+    let mut verbose = false;
+    let mut direction = Direction::Both;
+    let mut input_path = None;
+    let mut zero_io = false;
+    let mut timeout = None;
+    let mut scheme_path = None;
     let mut stdout = io::stdout();
 
     while let Some(arg) = args.next() {
@@ -56,6 +153,73 @@ fn main() {
                 "-l" | "--listen" => {
                     mode = NcMode::Listen;
                 }
+                "--ssl" | "--tls" => tls = true,
+                "--ssl-no-verify" => {
+                    tls = true;
+                    tls_verify = false;
+                }
+                "-4" | "-6" => {
+                    let requested = if arg == "-4" { Family::V4 } else { Family::V6 };
+                    if family != Family::Any && family != requested {
+                        println!("nc error: -4 and -6 are mutually exclusive");
+                        return;
+                    }
+                    family = requested;
+                }
+                "--crlf" => crlf = true,
+                "-v" | "--verbose" => verbose = true,
+                "-d" | "--recv-only" | "--send-only" => {
+                    let requested = if arg == "--send-only" { Direction::SendOnly } else { Direction::RecvOnly };
+                    if direction != Direction::Both && direction != requested {
+                        println!("nc error: --send-only and --recv-only are mutually exclusive");
+                        return;
+                    }
+                    direction = requested;
+                }
+                "-f" | "--file" => match args.next() {
+                    Some(path) => input_path = Some(path),
+                    None => {
+                        println!("nc error: {} requires a file name", arg);
+                        return;
+                    }
+                },
+                "-s" => match args.next().map(|addr| addr.parse()) {
+                    Some(Ok(addr)) => source.addr = Some(addr),
+                    _ => {
+                        println!("nc error: -s requires a valid IP address");
+                        return;
+                    }
+                },
+                "-U" | "--scheme" => match args.next() {
+                    Some(path) => scheme_path = Some(path),
+                    None => {
+                        println!("nc error: {} requires a path", arg);
+                        return;
+                    }
+                },
+                "--nodelay" => source.options.nodelay = true,
+                "--keepalive" => match args.next().and_then(|secs| secs.parse::<u64>().ok()) {
+                    Some(secs) if secs > 0 => source.options.keepalive = Some(Duration::from_secs(secs)),
+                    _ => {
+                        println!("nc error: --keepalive requires a positive number of seconds");
+                        return;
+                    }
+                },
+                "-z" => zero_io = true,
+                "-w" => match args.next().and_then(|secs| secs.parse::<u64>().ok()) {
+                    Some(secs) if secs > 0 => timeout = Some(Duration::from_secs(secs)),
+                    _ => {
+                        println!("nc error: -w requires a positive number of seconds");
+                        return;
+                    }
+                },
+                "-p" => match args.next().map(|port| port.parse()) {
+                    Some(Ok(port)) => source.port = Some(port),
+                    _ => {
+                        println!("nc error: -p requires a valid port number");
+                        return;
+                    }
+                },
                 _ => {
                     println!("Invalid argument!");
                     return;
@@ -66,24 +230,119 @@ fn main() {
         }
     }
 
+    source.family = family;
+
+    if timeout.is_some() && !zero_io {
+        println!("nc error: -w is only supported with -z");
+        return;
+    }
+    let plain = !tls && source.addr.is_none() && source.port.is_none();
+    if source.options.nodelay || source.options.keepalive.is_some() {
+        let unsupported = match proto {
+            _ if scheme_path.is_some() => Some("-U"),
+            TransportProtocol::Udp => Some("--udp"),
+            _ if zero_io => Some("-z"),
+            _ => None,
+        };
+        if let Some(unsupported) = unsupported {
+            println!("nc error: --nodelay and --keepalive cannot be combined with {}", unsupported);
+            return;
+        }
+    }
+    let scheme_conflict = match (&mode, &proto) {
+        _ if !hostname.is_empty() => Some("a host name"),
+        (NcMode::Listen, _) => Some("--listen"),
+        (_, TransportProtocol::Udp) => Some("--udp"),
+        _ if zero_io => Some("-z"),
+        _ if !plain => Some("--ssl, -s or -p"),
+        _ => None,
+    };
+    if let (Some(_), Some(conflict)) = (&scheme_path, scheme_conflict) {
+        println!("nc error: -U cannot be combined with {}", conflict);
+        return;
+    }
+
+    if input_path.is_some() {
+        let unused = match (&mode, &proto) {
+            _ if zero_io => Some("-z"),
+            _ if direction == Direction::RecvOnly => Some("--recv-only"),
+            (NcMode::Listen, TransportProtocol::Udp) => Some("UDP listen mode"),
+            _ => None,
+        };
+        if let Some(unused) = unused {
+            println!("nc error: -f cannot be combined with {}, which sends nothing", unused);
+            return;
+        }
+    }
+    // Opened before connecting, so a missing file does not leave the peer an empty session
+    let input = match open_input(input_path.as_deref()) {
+        Ok(input) => input,
+        Err(e) => {
+            println!("nc error: {}", e);
+            return;
+        }
+    };
+
+    if let Some(path) = scheme_path {
+        connect_scheme(&path, input, crlf, direction, verbose).unwrap_or_else(|e| {
+            println!("nc error: {}", e);
+        });
+        return;
+    }
+
     match (mode, proto) {
+        (NcMode::Connect, TransportProtocol::Tcp) if zero_io && plain => {
+            scan::parse_target(&hostname)
+                .and_then(|(host, ports)| {
+                    scan::scan_tcp(&host, family, &ports, timeout.unwrap_or(scan::DEFAULT_TIMEOUT))
+                })
+                .unwrap_or_else(|e| {
+                    println!("nc error: {}", e);
+                });
+        }
+        (NcMode::Connect, TransportProtocol::Udp) if zero_io && plain => {
+            scan::parse_target(&hostname)
+                .and_then(|(host, ports)| {
+                    scan::scan_udp(&host, family, &ports, timeout.unwrap_or(scan::DEFAULT_TIMEOUT))
+                })
+                .unwrap_or_else(|e| {
+                    println!("nc error: {}", e);
+                });
+        }
+        (_, _) if zero_io => {
+            println!("nc error: -z cannot be combined with --listen, --ssl, -s or -p");
+        }
+        (NcMode::Connect, TransportProtocol::Tcp) if tls => {
+            connect_tls(&hostname, &source, tls_verify, input, crlf, direction, verbose).unwrap_or_else(|e| {
+                println!("nc error: {}", e);
+            });
+        }
+        (_, _) if tls => {
+            println!("nc error: --ssl is only supported for outgoing TCP connections");
+        }
         (NcMode::Connect, TransportProtocol::Tcp) => {
-            connect_tcp(&hostname).unwrap_or_else(|e| {
+            connect_tcp(&hostname, &source, input, crlf, direction, verbose).unwrap_or_else(|e| {
                 println!("nc error: {}", e);
             });
         }
+        (_, _) if source.addr.is_some() || source.port.is_some() => {
+            println!("nc error: -s and -p are only supported for outgoing TCP connections");
+        }
         (NcMode::Listen, TransportProtocol::Tcp) => {
-            listen_tcp(&hostname).unwrap_or_else(|e| {
+            listen_tcp(&hostname, family, &source.options, input, crlf, direction, verbose).unwrap_or_else(|e| {
                 println!("nc error: {}", e);
             });
         }
         (NcMode::Connect, TransportProtocol::Udp) => {
-            connect_udp(&hostname).unwrap_or_else(|e| {
+            connect_udp(&hostname, family, input, crlf, direction, verbose).unwrap_or_else(|e| {
                 println!("nc error: {}", e);
             });
         }
+        (NcMode::Listen, TransportProtocol::Udp) if direction == Direction::SendOnly => {
+            println!("nc error: --send-only needs a peer, which UDP listen mode does not have");
+        }
         (NcMode::Listen, TransportProtocol::Udp) => {
-            listen_udp(&hostname).unwrap_or_else(|e| {
+            listen_udp(&hostname, family, verbose).unwrap_or_else(|e| {
                 println!("nc error: {}", e);
             });
         }
@@ -1,8 +1,15 @@
-use std::io::{stdin, Read, Write};
-use std::net::{TcpListener, TcpStream, UdpSocket};
-use std::process::exit;
-use std::str;
+use std::fs::{File, OpenOptions};
+use std::io::{self, stdin, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::{fmt, str};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+
+use net2::{TcpBuilder, TcpStreamExt};
+
+use tls;
 
 macro_rules! print_err {
     ($($arg:tt)*) => (
@@ -20,76 +27,505 @@ macro_rules! print_err {
 // TODO: variable buffer size?
 const BUFFER_SIZE: usize = 65636;
 
-/// Read from the input file into a buffer in an infinite loop.
-/// Handle the buffer content with handler function.
-fn rw_loop<R, F>(input: &mut R, mut handler: F) -> !
+/// Read from the input file into a buffer until it ends.
+/// Handle the buffer content with handler function, stopping at its first error.
+fn rw_loop<R, F>(input: &mut R, mut handler: F) -> Result<(), String>
 where
     R: Read,
-    F: FnMut(&[u8], usize) -> (),
+    F: FnMut(&[u8]) -> Result<(), String>,
 {
+    let mut buffer = [0u8; BUFFER_SIZE];
     loop {
-        let mut buffer = [0u8; BUFFER_SIZE];
-        // TODO: improve error messages
         let count = match input.read(&mut buffer) {
             Ok(0) => {
                 print_err!("End of input file/socket.");
-                exit(0);
+                return Ok(());
             }
             Ok(c) => c,
-            Err(_) => {
-                print_err!("Error occurred while reading from file/socket.");
-                exit(1);
-            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("cannot read from file/socket ({})", e)),
         };
-        handler(&buffer, count);
+        handler(&buffer[..count])?;
     }
 }
 
-/// Use the rw_loop in both direction (TCP connection)
-fn both_dir_rw_loop(mut stream_read: TcpStream, mut stream_write: TcpStream) -> Result<(), String> {
-    // Read loop
-    thread::spawn(move || {
-        rw_loop(&mut stream_read, |buffer, count| {
-            print!("{}", unsafe { str::from_utf8_unchecked(&buffer[..count]) });
-        });
-    });
+/// Bytes moved in each direction, printed on exit with -v
+#[derive(Default)]
+pub struct Transfer {
+    sent: AtomicUsize,
+    received: AtomicUsize,
+}
+
+impl Transfer {
+    fn sent(&self, count: usize) {
+        self.sent.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn received(&self, count: usize) {
+        self.received.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Print the totals to stderr, leaving stdout to the data
+    fn report(&self) {
+        eprintln!(
+            "sent {} bytes, received {} bytes",
+            self.sent.load(Ordering::Relaxed),
+            self.received.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// Translate lone `\n` into `\r\n` for `--crlf`. `after_cr` tells whether the
+/// previous chunk ended with `\r`, so a `\r\n` split across reads is kept as is.
+fn to_crlf(input: &[u8], after_cr: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() + input.len() / 8);
+    let mut prev_cr = after_cr;
+    for &byte in input {
+        if byte == b'\n' && !prev_cr {
+            output.push(b'\r');
+        }
+        output.push(byte);
+        prev_cr = byte == b'\r';
+    }
+    output
+}
+
+/// Connections whose directions can be shut down independently
+pub trait HalfClose {
+    fn close(&mut self, how: Shutdown) -> io::Result<()>;
+}
+
+impl HalfClose for TcpStream {
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.shutdown(how)
+    }
+}
+
+/// Scheme and device files cannot be half closed, the other end only sees the
+/// file closed when the session ends
+impl HalfClose for File {
+    fn close(&mut self, _how: Shutdown) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether a socket error only means the peer went away
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Which ways data flows, set with --send-only and --recv-only (-d)
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Both,
+    /// Send the input without reading anything from the peer
+    SendOnly,
+    /// Print what the peer sends without touching the input
+    RecvOnly,
+}
+
+/// The data to send, stdin or the file given with -f
+pub type Input = Box<dyn Read + Send>;
+
+/// Open the file given with -f, or stdin without one
+pub fn open_input(path: Option<&str>) -> Result<Input, String> {
+    match path {
+        Some(path) => File::open(path)
+            .map(|file| Box::new(file) as Input)
+            .map_err(|e| format!("cannot open {} ({})", path, e)),
+        None => Ok(Box::new(stdin())),
+    }
+}
+
+/// Copy the input to the stream until it ends, then shut down the sending side.
+///
+/// An error other than the peer going away is returned with the stream left
+/// open, for the caller to record before it wakes a concurrent reader.
+fn send_input<W>(
+    input: &mut Input,
+    stream_write: &mut W,
+    crlf: bool,
+    transfer: &Transfer,
+    peer_closed: &AtomicBool,
+) -> Result<(), String>
+where
+    W: Write + HalfClose,
+{
+    let mut buffer = [0u8; BUFFER_SIZE];
+    let mut after_cr = false;
+    loop {
+        let count = match input.read(&mut buffer) {
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("cannot read input ({})", e)),
+        };
+        if peer_closed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if count == 0 {
+            // Tell the peer we are done, but keep receiving its data
+            let _ = stream_write.close(Shutdown::Write);
+            return Ok(());
+        }
+        let written = if crlf {
+            let data = to_crlf(&buffer[..count], after_cr);
+            after_cr = buffer[count - 1] == b'\r';
+            stream_write.write_all(&data).map(|_| data.len())
+        } else {
+            stream_write.write_all(&buffer[..count]).map(|_| count)
+        };
+        match written {
+            Ok(count) => transfer.sent(count),
+            Err(ref e) if is_disconnect(e) => return Ok(()),
+            Err(e) => return Err(format!("cannot write into socket ({})", e)),
+        }
+    }
+}
+
+/// Copy the stream to stdout until the peer closes it
+fn recv_stdout<R: Read>(stream_read: &mut R, transfer: &Transfer) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut buffer = [0u8; BUFFER_SIZE];
+    let result = loop {
+        let count = match stream_read.read(&mut buffer) {
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(ref e) if is_disconnect(e) => 0,
+            Err(e) => break Err(format!("cannot read from socket ({})", e)),
+        };
+        if count == 0 {
+            break Ok(());
+        }
+        transfer.received(count);
+        match stdout.write_all(&buffer[..count]).and_then(|_| stdout.flush()) {
+            Ok(()) => (),
+            // Whoever reads our output has gone away, which ends the session
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => break Ok(()),
+            Err(e) => break Err(format!("cannot write to stdout ({})", e)),
+        }
+    };
+    let _ = stdout.flush();
+    result
+}
+
+/// Copy the input to the stream and the stream to stdout (TCP or TLS connection).
+///
+/// When the input ends the sending side is shut down and the stream is drained
+/// until the peer closes. When the peer closes first, stdout is flushed and
+/// this returns without waiting for the input.
+///
+/// Send-only sessions end with the input and never read the stream, receive-only
+/// sessions end when the peer closes and never read the input.
+fn both_dir_rw_loop<R, W>(
+    mut input: Input,
+    mut stream_read: R,
+    mut stream_write: W,
+    crlf: bool,
+    direction: Direction,
+    transfer: Arc<Transfer>,
+) -> Result<(), String>
+where
+    R: Read,
+    W: Write + HalfClose + Send + 'static,
+{
+    match direction {
+        Direction::SendOnly => {
+            return send_input(&mut input, &mut stream_write, crlf, &transfer, &AtomicBool::new(false));
+        }
+        Direction::RecvOnly => return recv_stdout(&mut stream_read, &transfer),
+        Direction::Both => (),
+    }
+
+    let peer_closed = Arc::new(AtomicBool::new(false));
+    let write_error = Arc::new(Mutex::new(None));
 
     // Write loop
-    let mut stdin = stdin();
-    rw_loop(&mut stdin, |buffer, count| {
-        let _ = stream_write.write(&buffer[..count]).unwrap_or_else(|e| {
-            print_err!("Error occurred while writing into socket: {} ", e);
-            exit(1);
+    {
+        let peer_closed = peer_closed.clone();
+        let write_error = write_error.clone();
+        let transfer = transfer.clone();
+        thread::spawn(move || {
+            if let Err(err) = send_input(&mut input, &mut stream_write, crlf, &transfer, &peer_closed) {
+                // Stored before the shutdown wakes the reader, which looks for it
+                *write_error.lock().unwrap() = Some(err);
+                let _ = stream_write.close(Shutdown::Both);
+            }
         });
-    });
+    }
+
+    // Read loop
+    let result = recv_stdout(&mut stream_read, &transfer);
+    peer_closed.store(true, Ordering::SeqCst);
+
+    let write_error = write_error.lock().unwrap().take();
+    match write_error {
+        Some(err) => Err(err),
+        None => result,
+    }
+}
+
+/// Address family of the peer, restricted with -4 or -6
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Family {
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            Family::Any => true,
+            Family::V4 => addr.is_ipv4(),
+            Family::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Family::Any => write!(f, "IP"),
+            Family::V4 => write!(f, "IPv4"),
+            Family::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// Addresses of `host`, a name or address followed by `:port`, in the given
+/// family. IPv6 addresses go in brackets, as in `[::1]:8080`.
+pub fn resolve(host: &str, family: Family) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = host
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {} ({})", host, e))?
+        .filter(|addr| family.matches(addr))
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} has no {} address", host, family));
+    }
+    Ok(addrs)
+}
+
+/// The any address of the same family as `remote`, to bind a socket that can reach it
+fn unspecified(remote: &SocketAddr) -> IpAddr {
+    match *remote {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+    }
+}
+
+/// Local endpoint for outgoing TCP connections, set with -s and -p, the
+/// family -4 or -6 limit them to, and the options set once connected
+#[derive(Default)]
+pub struct Source {
+    pub addr: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub family: Family,
+    pub options: TcpOptions,
+}
+
+/// Open a TCP connection, binding to the requested source first if there is one
+fn open_tcp(host: &str, source: &Source) -> Result<TcpStream, String> {
+    let stream = connect_from(host, source)?;
+    source.options.apply(&stream);
+    Ok(stream)
+}
+
+fn connect_from(host: &str, source: &Source) -> Result<TcpStream, String> {
+    let remotes = resolve(host, source.family)?;
+    if source.addr.is_none() && source.port.is_none() {
+        // Each address in turn, as a host may have both families but listen on one
+        return TcpStream::connect(&remotes[..])
+            .map_err(|e| format!("cannot create socket ({})", e));
+    }
+
+    // Prefer an address the source address can reach
+    let remote = *remotes
+        .iter()
+        .find(|remote| source.addr.is_none_or(|addr| addr.is_ipv4() == remote.is_ipv4()))
+        .unwrap_or(&remotes[0]);
+
+    let local_ip = source.addr.unwrap_or_else(|| unspecified(&remote));
+    let local = SocketAddr::new(local_ip, source.port.unwrap_or(0));
+    if local.is_ipv4() != remote.is_ipv4() {
+        return Err(format!("source address {} cannot reach {}", local_ip, remote));
+    }
+
+    let builder = match local {
+        SocketAddr::V4(_) => TcpBuilder::new_v4(),
+        SocketAddr::V6(_) => TcpBuilder::new_v6(),
+    }.map_err(|e| format!("cannot create socket ({})", e))?;
+
+    // Binding fails for addresses that are not assigned to a local interface
+    builder.bind(local).map_err(|e| match e.kind() {
+        io::ErrorKind::AddrNotAvailable => {
+            format!("source address {} is not a local address", local_ip)
+        }
+        io::ErrorKind::AddrInUse => format!("source port {} is already in use", local.port()),
+        _ => format!("cannot bind to {} ({})", local, e),
+    })?;
+
+    builder
+        .connect(remote)
+        .map_err(|e| format!("cannot connect to {} ({})", remote, e))
+}
+
+/// Options for TCP connections, set with --nodelay and --keepalive
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpOptions {
+    /// Send small writes at once instead of coalescing them (TCP_NODELAY)
+    pub nodelay: bool,
+    /// Enable SO_KEEPALIVE, probing the peer after this long without traffic
+    pub keepalive: Option<Duration>,
+}
+
+impl TcpOptions {
+    /// Set the options on a connected stream. One the system refuses is
+    /// reported and the connection goes on without it.
+    fn apply(&self, stream: &TcpStream) {
+        if self.nodelay {
+            if let Err(e) = stream.set_nodelay(true) {
+                eprintln!("nc warning: cannot set TCP_NODELAY ({})", e);
+            }
+        }
+        if let Some(idle) = self.keepalive {
+            if let Err(e) = TcpStreamExt::set_keepalive(stream, Some(idle)) {
+                eprintln!("nc warning: cannot enable keepalive ({})", e);
+            }
+        }
+    }
+}
+
+/// Run the session loop, printing the totals afterwards when verbose
+fn session<R, W>(
+    input: Input,
+    stream_read: R,
+    stream_write: W,
+    crlf: bool,
+    direction: Direction,
+    verbose: bool,
+) -> Result<(), String>
+where
+    R: Read,
+    W: Write + HalfClose + Send + 'static,
+{
+    let transfer = Arc::new(Transfer::default());
+    let result = both_dir_rw_loop(input, stream_read, stream_write, crlf, direction, transfer.clone());
+    if verbose {
+        transfer.report();
+    }
+    result
+}
+
+/// Print both ends of a connection to stderr
+fn report_endpoints(stream: &TcpStream) {
+    if let (Ok(local), Ok(peer)) = (stream.local_addr(), stream.peer_addr()) {
+        eprintln!("Connected from {} to {}", local, peer);
+    }
 }
 
 /// Connect to listening TCP socket
-pub fn connect_tcp(host: &str) -> Result<(), String> {
+pub fn connect_tcp(
+    host: &str,
+    source: &Source,
+    input: Input,
+    crlf: bool,
+    direction: Direction,
+    verbose: bool,
+) -> Result<(), String> {
     // Open socket and create its clone
-    let stream_read = TcpStream::connect(host)
-        .map_err(|e| format!("connect_tcp error: cannot create socket ({})", e))?;
+    let stream_read = open_tcp(host, source)
+        .map_err(|e| format!("connect_tcp error: {}", e))?;
 
     let stream_write = stream_read
         .try_clone()
         .map_err(|e| format!("connect_tcp error: cannot create socket clone ({})", e))?;
 
     println!("Remote host: {}", host);
+    if verbose {
+        report_endpoints(&stream_read);
+    }
+
+    session(input, stream_read, stream_write, crlf, direction, verbose)
+}
+
+/// Connect to a TLS server, optionally skipping certificate verification
+pub fn connect_tls(
+    host: &str,
+    source: &Source,
+    verify: bool,
+    input: Input,
+    crlf: bool,
+    direction: Direction,
+    verbose: bool,
+) -> Result<(), String> {
+    let socket = open_tcp(host, source)
+        .map_err(|e| format!("connect_tls error: {}", e))?;
+    if verbose {
+        report_endpoints(&socket);
+    }
+    let stream_read = tls::connect(host, socket, verify)?;
+    let stream_write = stream_read.clone();
+
+    println!("Remote host: {}", host);
+
+    session(input, stream_read, stream_write, crlf, direction, verbose)
+}
+
+/// Open a scheme path such as `tcp:` or `chan:name` and talk to it like a
+/// connection, or any other file that can be read and written on other systems
+pub fn connect_scheme(path: &str, input: Input, crlf: bool, direction: Direction, verbose: bool) -> Result<(), String> {
+    let stream_read = OpenOptions::new()
+        .read(direction != Direction::SendOnly)
+        .write(direction != Direction::RecvOnly)
+        .open(path)
+        .map_err(|e| format!("connect_scheme error: cannot open {} ({})", path, e))?;
+
+    let stream_write = stream_read
+        .try_clone()
+        .map_err(|e| format!("connect_scheme error: cannot duplicate {} ({})", path, e))?;
+
+    if verbose {
+        eprintln!("Opened {}", path);
+    }
 
-    both_dir_rw_loop(stream_read, stream_write)
+    session(input, stream_read, stream_write, crlf, direction, verbose)
 }
 
 /// Listen on specified port and accept the first incoming connection
 /// NOTE: "-k Accept multiple connections in listen mode" is not implemented
-pub fn listen_tcp(host: &str) -> Result<(), String> {
+pub fn listen_tcp(
+    host: &str,
+    family: Family,
+    options: &TcpOptions,
+    input: Input,
+    crlf: bool,
+    direction: Direction,
+    verbose: bool,
+) -> Result<(), String> {
     // Bind the listener to the specified host
-    let listener = TcpListener::bind(host)
+    let addrs = resolve(host, family).map_err(|e| format!("listen_tcp error: {}", e))?;
+    let listener = TcpListener::bind(&addrs[..])
         .map_err(|e| format!("listen_tcp error: cannot bind to specified port ({})", e))?;
+    if verbose {
+        if let Ok(addr) = listener.local_addr() {
+            eprintln!("Listening on {}", addr);
+        }
+    }
 
     // Accept an incoming connection
     let (stream_read, socketaddr) = listener
         .accept()
         .map_err(|e| format!("listen_tcp error: cannot establish connection ({})", e))?;
+    options.apply(&stream_read);
 
     // Clone the stream for bidirectional communication
     let stream_write = stream_read
@@ -100,56 +536,215 @@ pub fn listen_tcp(host: &str) -> Result<(), String> {
     eprintln!("Incoming connection from: {}", socketaddr);
 
     // Handle the bidirectional read/write loop
-    both_dir_rw_loop(stream_read, stream_write)
+    session(input, stream_read, stream_write, crlf, direction, verbose)
 }
 
-pub fn connect_udp(host: &str) -> Result<(), String> {
-    // Bind the UDP socket to a local port
-    // TODO: Implement some port selection process (while loop?)
-    let socket = UdpSocket::bind("localhost:30000")
-        .map_err(|e| format!("connect_udp error: could not bind to local socket ({})", e))?;
-    
-    // Connect the UDP socket to the remote host
-    socket.connect(host)
-        .map_err(|e| format!("connect_udp error: could not set up remote socket ({})", e))?;
-
-    // Read from stdin and send data via UDP
-    let mut stdin = stdin();
-    rw_loop(&mut stdin, |buffer, count| {
-        socket.send(&buffer[..count]).unwrap_or_else(|e| {
-            eprintln!("Error occurred while writing into socket: {}", e);
-            exit(1); // Exit on send error
-        });
-    });
+/// Print the datagrams received on a connected socket until it fails
+fn recv_udp(socket: &UdpSocket, transfer: &Transfer) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut buffer = [0u8; BUFFER_SIZE];
+    loop {
+        let count = match socket.recv(&mut buffer) {
+            Ok(count) => count,
+            // An ICMP error for an earlier datagram, the next one may get through
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("cannot read from socket ({})", e)),
+        };
+        transfer.received(count);
+        let mut stdout = stdout.lock();
+        stdout.write_all(&buffer[..count])
+            .and_then(|_| stdout.flush())
+            .map_err(|e| format!("cannot write to stdout ({})", e))?;
+    }
+}
+
+/// A UDP socket on an ephemeral port, connected to the first address of `host`
+fn open_udp(host: &str, family: Family) -> Result<UdpSocket, String> {
+    let remote = resolve(host, family)?[0];
+    let socket = UdpSocket::bind(SocketAddr::new(unspecified(&remote), 0))
+        .map_err(|e| format!("could not bind to local socket ({})", e))?;
+    socket.connect(remote)
+        .map_err(|e| format!("could not set up remote socket ({})", e))?;
+    Ok(socket)
+}
+
+pub fn connect_udp(host: &str, family: Family, mut input: Input, crlf: bool, direction: Direction, verbose: bool) -> Result<(), String> {
+    let socket = open_udp(host, family).map_err(|e| format!("connect_udp error: {}", e))?;
+
+    if verbose {
+        if let (Ok(local), Ok(peer)) = (socket.local_addr(), socket.peer_addr()) {
+            eprintln!("Sending from {} to {}", local, peer);
+        }
+    }
+
+    let transfer = Arc::new(Transfer::default());
+    let result = match direction {
+        // Nothing ever closes a UDP socket, so this runs until interrupted
+        Direction::RecvOnly => recv_udp(&socket, &transfer).map_err(|e| format!("connect_udp error: {}", e)),
+        _ => {
+            if direction == Direction::Both {
+                // Print replies in the background, ending with the process once stdin is done
+                let socket = socket
+                    .try_clone()
+                    .map_err(|e| format!("connect_udp error: cannot create socket clone ({})", e))?;
+                let transfer = transfer.clone();
+                thread::spawn(move || {
+                    if let Err(e) = recv_udp(&socket, &transfer) {
+                        print_err!("connect_udp error: {}", e);
+                    }
+                });
+            }
+
+            // Read the input and send data via UDP
+            let mut after_cr = false;
+            rw_loop(&mut input, |buffer| {
+                let sent = if crlf {
+                    let data = to_crlf(buffer, after_cr);
+                    after_cr = buffer[buffer.len() - 1] == b'\r';
+                    socket.send(&data)
+                } else {
+                    socket.send(buffer)
+                };
+                let count = sent.map_err(|e| format!("connect_udp error: cannot write into socket ({})", e))?;
+                transfer.sent(count);
+                Ok(())
+            })
+        }
+    };
 
-    Ok(())
+    if verbose {
+        transfer.report();
+    }
+    result
 }
 
 /// Listen for UDP datagrams on the specified socket
-pub fn listen_udp(host: &str) -> Result<(), String> {
-    let socket = try!(UdpSocket::bind(host)
-        .map_err(|e| { format!("connect_udp error: could not bind to local socket ({})", e) }));
-    loop {
-        let mut buffer = [0u8; BUFFER_SIZE];
+pub fn listen_udp(host: &str, family: Family, verbose: bool) -> Result<(), String> {
+    let addrs = resolve(host, family).map_err(|e| format!("listen_udp error: {}", e))?;
+    let socket = UdpSocket::bind(&addrs[..])
+        .map_err(|e| format!("connect_udp error: could not bind to local socket ({})", e))?;
+    if verbose {
+        if let Ok(addr) = socket.local_addr() {
+            eprintln!("Listening on {}", addr);
+        }
+    }
+
+    let transfer = Transfer::default();
+    let mut buffer = [0u8; BUFFER_SIZE];
+    let result = loop {
         let count = match socket.recv_from(&mut buffer) {
             Ok((0, _)) => {
                 print_err!("End of input file/socket.");
-                exit(0);
+                break Ok(());
             }
             Ok((c, _)) => c,
-            Err(_) => {
-                print_err!("Error occurred while reading from file/socket.");
-                exit(1);
-            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => break Err(format!("listen_udp error: cannot read from socket ({})", e)),
         };
+        transfer.received(count);
         print!("{}", unsafe { str::from_utf8_unchecked(&buffer[..count]) });
+    };
+
+    if verbose {
+        transfer.report();
     }
+    result
 }
 
 //TODO: write some unit tests
 #[cfg(test)]
 mod tests {
+    use super::{both_dir_rw_loop, open_tcp, open_udp, resolve, to_crlf, Direction, Family, Source, TcpOptions, Transfer};
+    use net2::TcpStreamExt;
+    use std::io::{self, Read};
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct BrokenInput;
+
+    impl Read for BrokenInput {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+    }
 
     #[test]
     fn pass() {}
+
+    #[test]
+    fn crlf_translation() {
+        assert_eq!(to_crlf(b"HELO a\nMAIL\r\n\n", false), b"HELO a\r\nMAIL\r\n\r\n".to_vec());
+        assert_eq!(to_crlf(b"\r\r\n", false), b"\r\r\n".to_vec());
+        assert_eq!(to_crlf(b"no newline", false), b"no newline".to_vec());
+
+        // A \r\n split between two reads is not converted twice
+        assert_eq!(to_crlf(b"\nrest\n", true), b"\nrest\r\n".to_vec());
+        assert_eq!(to_crlf(b"\n", false), b"\r\n".to_vec());
+    }
+
+    #[test]
+    fn families() {
+        assert!(resolve("127.0.0.1:80", Family::V4).is_ok());
+        assert_eq!(resolve("127.0.0.1:80", Family::V6), Err("127.0.0.1:80 has no IPv6 address".to_string()));
+        assert_eq!(resolve("[::1]:80", Family::Any).unwrap()[0].to_string(), "[::1]:80");
+        assert!(resolve("[::1]:80", Family::V4).is_err());
+    }
+
+    #[test]
+    fn ipv6_loopback() {
+        // Hosts without IPv6 have nothing to test
+        let listener = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        let host = format!("[::1]:{}", listener.local_addr().unwrap().port());
+        let stream = open_tcp(&host, &Source { family: Family::V6, ..Source::default() }).unwrap();
+        assert!(stream.local_addr().unwrap().is_ipv6());
+        assert!(open_tcp(&host, &Source { family: Family::V4, ..Source::default() }).is_err());
+
+        let server = UdpSocket::bind("[::1]:0").unwrap();
+        let socket = open_udp(&server.local_addr().unwrap().to_string(), Family::Any).unwrap();
+        socket.send(b"ping").unwrap();
+        let mut buf = [0; 4];
+        let (count, from) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..count], b"ping");
+        assert_eq!(from, socket.local_addr().unwrap());
+    }
+
+    #[test]
+    fn socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = open_tcp(&listener.local_addr().unwrap().to_string(), &Source::default()).unwrap();
+        assert!(!stream.nodelay().unwrap());
+
+        TcpOptions::default().apply(&stream);
+        assert!(!stream.nodelay().unwrap());
+        assert_eq!(TcpStreamExt::keepalive(&stream).unwrap(), None);
+
+        let options = TcpOptions { nodelay: true, keepalive: Some(Duration::from_secs(30)) };
+        options.apply(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert_eq!(TcpStreamExt::keepalive(&stream).unwrap(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn input_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        for _ in 0..20 {
+            let stream = open_tcp(&listener.local_addr().unwrap().to_string(), &Source::default()).unwrap();
+            // The peer stays open, so only the failed input ends the session
+            let (_peer, _) = listener.accept().unwrap();
+            let result = both_dir_rw_loop(
+                Box::new(BrokenInput),
+                stream.try_clone().unwrap(),
+                stream.try_clone().unwrap(),
+                false,
+                Direction::Both,
+                Arc::new(Transfer::default()),
+            );
+            assert_eq!(result, Err("cannot read input (broken)".to_string()));
+        }
+    }
 }
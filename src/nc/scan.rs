@@ -0,0 +1,134 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use modes::Family;
+use netutils::PortRange;
+
+/// Timeout for each probe when -w is not given
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Split `host:ports` into the host and the ports to probe, given as a
+/// port, a `low-high` range or a comma separated list of both
+pub fn parse_target(target: &str) -> Result<(String, PortRange), String> {
+    let (host, ports) = match target.rfind(':') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => return Err(format!("missing port in {}", target)),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("missing host in {}", target));
+    }
+
+    let ports = ports.parse::<PortRange>().map_err(|e| e.to_string())?;
+    Ok((host.to_string(), ports))
+}
+
+fn resolve(host: &str, family: Family) -> Result<IpAddr, String> {
+    (host, 0)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {} ({})", host, e))?
+        .find(|addr| family.matches(addr))
+        .map(|addr| addr.ip())
+        .ok_or_else(|| format!("{} has no {} address", host, family))
+}
+
+/// Try a TCP connection to every port, reporting the ones that accept it
+pub fn scan_tcp(host: &str, family: Family, ports: &PortRange, timeout: Duration) -> Result<(), String> {
+    let ip = resolve(host, family)?;
+    for port in ports.iter() {
+        let state = match TcpStream::connect_timeout(&SocketAddr::new(ip, port), timeout) {
+            Ok(_) => "open",
+            Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => "closed",
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => "filtered",
+            Err(e) => return Err(format!("cannot connect to {}:{} ({})", ip, port, e)),
+        };
+        println!("{} {}/tcp {}", ip, port, state);
+    }
+    Ok(())
+}
+
+/// Send an empty datagram to one port and wait for an answer
+///
+/// A reply means something is listening. A closed port answers with an ICMP
+/// port unreachable, which only shows up as a refused read when the network
+/// stack reports it to connected sockets. Silence is ambiguous: the port may
+/// be open to a service that ignores empty datagrams, or a firewall may have
+/// dropped the probe or the unreachable.
+fn probe_udp(remote: SocketAddr, timeout: Duration) -> Result<&'static str, String> {
+    let local = match remote {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
+    };
+    // A fresh socket per port keeps a late unreachable from being blamed on the next one
+    let socket = UdpSocket::bind(local)
+        .map_err(|e| format!("could not bind to local socket ({})", e))?;
+    socket.connect(remote)
+        .map_err(|e| format!("could not set up remote socket ({})", e))?;
+    socket.set_read_timeout(Some(timeout))
+        .map_err(|e| format!("could not set timeout ({})", e))?;
+
+    if let Err(e) = socket.send(&[]) {
+        return match e.kind() {
+            // Linux can report the unreachable of an earlier send here
+            ErrorKind::ConnectionRefused => Ok("closed"),
+            _ => Err(format!("cannot send to {} ({})", remote, e)),
+        };
+    }
+
+    let mut buffer = [0; 1];
+    match socket.recv(&mut buffer) {
+        Ok(_) => Ok("open"),
+        Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => Ok("closed"),
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            Ok("open|filtered")
+        }
+        Err(e) => Err(format!("cannot read from {} ({})", remote, e)),
+    }
+}
+
+/// Probe every port over UDP, reporting open, closed or open|filtered
+pub fn scan_udp(host: &str, family: Family, ports: &PortRange, timeout: Duration) -> Result<(), String> {
+    let ip = resolve(host, family)?;
+    for port in ports.iter() {
+        let state = probe_udp(SocketAddr::new(ip, port), timeout)?;
+        println!("{} {}/udp {}", ip, port, state);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_target, probe_udp, DEFAULT_TIMEOUT};
+    use netutils::PortRange;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    #[test]
+    fn targets() {
+        let ports = |ports: &str| ports.parse::<PortRange>().unwrap();
+        assert_eq!(parse_target("localhost:80"), Ok(("localhost".to_string(), PortRange::single(80))));
+        assert_eq!(parse_target("10.0.0.1:20-25"), Ok(("10.0.0.1".to_string(), ports("20-25"))));
+        assert_eq!(parse_target("[::1]:53"), Ok(("::1".to_string(), ports("53"))));
+        assert_eq!(parse_target("[::1]:22,80,8000-8080"), Ok(("::1".to_string(), ports("22,80,8000-8080"))));
+        assert!(parse_target("localhost").is_err());
+        assert!(parse_target(":80").is_err());
+        assert!(parse_target("localhost:0").is_err());
+        assert!(parse_target("localhost:25-20").is_err());
+        assert!(parse_target("localhost:http").is_err());
+    }
+
+    #[test]
+    fn udp_reply_is_open() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let echo = thread::spawn(move || {
+            let mut buffer = [0; 16];
+            let (_, peer) = server.recv_from(&mut buffer).unwrap();
+            server.send_to(b"hi", peer).unwrap();
+        });
+
+        assert_eq!(probe_udp(addr, DEFAULT_TIMEOUT), Ok("open"));
+        echo.join().unwrap();
+    }
+}
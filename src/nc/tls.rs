@@ -0,0 +1,171 @@
+use std::convert::TryFrom;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use modes::HalfClose;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme, StreamOwned};
+
+/// TLS client stream that can be shared between the read and the write loop.
+///
+/// A rustls session can't be split like a `TcpStream`, so both halves lock the
+/// same session. A reader only takes the lock once the socket has something
+/// for it, and waits on its own handle of the socket in between, so a write
+/// never queues behind a read that has nothing to do.
+#[derive(Clone)]
+pub struct TlsStream {
+    inner: Arc<Mutex<StreamOwned<ClientConnection, TcpStream>>>,
+    socket: Arc<TcpStream>,
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Data already decrypted, or the end of the stream
+            match self.inner.lock().unwrap().conn.reader().read(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                res => return res,
+            }
+
+            // Block until a record or the end of the stream arrives, without the lock
+            match self.socket.peek(&mut [0]) {
+                Ok(_) => (),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            let mut stream = self.inner.lock().unwrap();
+            let StreamOwned { ref mut conn, ref mut sock } = *stream;
+            conn.read_tls(sock)?;
+            conn.process_new_packets()
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            // Answer what the server asked for, such as a key update
+            while conn.wants_write() {
+                conn.write_tls(sock)?;
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut stream = self.inner.lock().unwrap();
+        let count = stream.write(buf)?;
+        stream.flush()?;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl HalfClose for TlsStream {
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        let mut stream = self.inner.lock().unwrap();
+        if how != Shutdown::Read {
+            // Let the server know the end of the data is genuine, not a truncation
+            stream.conn.send_close_notify();
+            stream.flush()?;
+        }
+        stream.sock.shutdown(how)
+    }
+}
+
+/// Accepts any server certificate, used by `--ssl-no-verify`
+#[derive(Debug)]
+struct NoVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer,
+        _intermediates: &[CertificateDer],
+        _server_name: &ServerName,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn client_config(verify: bool) -> Result<ClientConfig, String> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("connect_tls error: cannot configure TLS ({})", e))?;
+
+    let config = if verify {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        builder.with_root_certificates(roots).with_no_client_auth()
+    } else {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerifier(provider)))
+            .with_no_client_auth()
+    };
+
+    Ok(config)
+}
+
+/// Connect to `host` (in `hostname:port` form) and complete the TLS handshake
+pub fn connect(host: &str, mut socket: TcpStream, verify: bool) -> Result<TlsStream, String> {
+    let hostname = match host.rfind(':') {
+        Some(i) => &host[..i],
+        None => host,
+    };
+    let server_name = ServerName::try_from(hostname.trim_matches(|c| c == '[' || c == ']').to_string())
+        .map_err(|e| format!("connect_tls error: invalid server name '{}' ({})", hostname, e))?;
+
+    let config = client_config(verify)?;
+    let mut session = ClientConnection::new(Arc::new(config), server_name)
+        .map_err(|e| format!("connect_tls error: cannot create TLS session ({})", e))?;
+
+    // Handshake eagerly so certificate problems are reported before any data is sent
+    while session.is_handshaking() {
+        session.complete_io(&mut socket).map_err(|e| {
+            match e.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()) {
+                Some(rustls::Error::InvalidCertificate(cert_err)) => format!(
+                    "connect_tls error: certificate validation failed ({:?}), use --ssl-no-verify to skip",
+                    cert_err
+                ),
+                _ => format!("connect_tls error: TLS handshake failed ({})", e),
+            }
+        })?;
+    }
+
+    let waiter = socket
+        .try_clone()
+        .map_err(|e| format!("connect_tls error: cannot clone the socket ({})", e))?;
+
+    Ok(TlsStream {
+        inner: Arc::new(Mutex::new(StreamOwned::new(session, socket))),
+        socket: Arc::new(waiter),
+    })
+}
@@ -1,34 +1,83 @@
 #![deny(warnings)]
 
-extern crate ntpclient;
-
-use ntpclient::retrieve_ntp_timestamp;
-use std::env;
-
-fn format_time(mut ts: i64) -> String {
-    let s = ts%86400;
-    ts /= 86400;
-    let h = s/3600;
-    let m = s/60%60;
-    let s = s%60;
-    let x = (ts*4+102032)/146097+15;
-    let b = ts+2442113+x-(x/4);
-    let mut c = (b*20-2442)/7305;
-    let d = b-365*c-c/4;
-    let mut e = d*1000/30601;
-    let f = d-e*30-e*601/1000;
-    if e < 14 {
-        c -= 4716;
-        e -= 1;
-    } else {
-        c -= 4715;
-        e -= 13;
+extern crate netutils;
+
+use netutils::ntp::sntp_query;
+use std::{env, process};
+
+use time::{format_time, Zone, DEFAULT_FORMAT};
+
+mod time;
+
+const USAGE: &str = "usage: ntp [--utc | --local] [--format FORMAT | --epoch] [server]
+
+Query the time from an SNTP server, pool.ntp.org by default.
+
+    --utc            print the time in UTC, the default
+    --local          print the time in the zone TZ names, such as CET-1
+    --format FORMAT  print only the time, formatted with strftime
+                     conversions like %Y-%m-%dT%H:%M:%S%z
+    --epoch          print only the time, as seconds since the Unix epoch";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("ntp: {}\n{}", message, USAGE);
+    process::exit(1);
+}
+
+/// The zone for --local. Only fixed offsets are known, so an unset or
+/// unrecognised TZ means UTC.
+fn local_zone() -> Zone {
+    match env::var("TZ") {
+        Ok(tz) => Zone::from_tz(&tz).unwrap_or_else(|| {
+            eprintln!("ntp: unsupported TZ '{}', using UTC", tz);
+            Zone::utc()
+        }),
+        Err(_) => Zone::utc(),
     }
-    format!("{:>04}-{:>02}-{:>02} {:>02}:{:>02}:{:>02}", c, e, f, h, m, s)
 }
 
 fn main() {
-    let server = env::args().nth(1).unwrap_or("pool.ntp.org".to_string());
-    let ntp_time = retrieve_ntp_timestamp(&server).unwrap();
-    println!("{}: {}", server, format_time(ntp_time.sec));
+    let mut server = None;
+    let mut format = None;
+    let mut epoch = false;
+    let mut local = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "--utc" => local = false,
+            "--local" => local = true,
+            "--epoch" => epoch = true,
+            "--format" => match args.next() {
+                Some(value) => format = Some(value),
+                None => usage_error("--format needs a value"),
+            },
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option {}", arg)),
+            _ if server.is_none() => server = Some(arg),
+            _ => usage_error("more than one server given"),
+        }
+    }
+    if epoch && format.is_some() {
+        usage_error("--epoch and --format can't be used together");
+    }
+
+    let server = server.unwrap_or("pool.ntp.org".to_string());
+    let result = sntp_query(&server).unwrap_or_else(|err| {
+        eprintln!("ntp: {}: {}", server, err);
+        process::exit(1);
+    });
+
+    let unix = result.time.as_secs() as i64;
+    let zone = if local { local_zone() } else { Zone::utc() };
+    if epoch {
+        println!("{}", unix);
+    } else if let Some(format) = format {
+        println!("{}", format_time(unix, &format, &zone));
+    } else {
+        println!("{}: {}", server, format_time(unix, DEFAULT_FORMAT, &zone));
+    }
 }
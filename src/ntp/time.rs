@@ -0,0 +1,149 @@
+use std::fmt::Write;
+
+use netutils::date::DateTime;
+
+const WEEKDAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// The default output, `2023-11-14 22:13:20`
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A fixed offset from UTC and the name to print for it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    /// Seconds east of UTC
+    pub offset: i64,
+}
+
+impl Zone {
+    pub fn utc() -> Self {
+        Zone { name: "UTC".to_string(), offset: 0 }
+    }
+
+    /// Parse the standard time part of a POSIX `TZ` value such as `CET-1`,
+    /// `EST5` or `<+0530>-5:30`. The offset is hours west of UTC, as POSIX
+    /// has it. Daylight saving rules after it are ignored.
+    pub fn from_tz(tz: &str) -> Option<Self> {
+        let (name, rest) = if let Some(quoted) = tz.strip_prefix('<') {
+            let end = quoted.find('>')?;
+            (&quoted[..end], &quoted[end + 1..])
+        } else {
+            let end = tz.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(tz.len());
+            (&tz[..end], &tz[end..])
+        };
+        if name.len() < 3 {
+            return None;
+        }
+        if rest.is_empty() {
+            // A bare name is only meaningful for UTC itself
+            return if name == "UTC" || name == "GMT" { Some(Zone { name: name.to_string(), offset: 0 }) } else { None };
+        }
+
+        let (sign, rest) = match rest.as_bytes()[0] {
+            b'-' => (1, &rest[1..]),
+            b'+' => (-1, &rest[1..]),
+            _ => (-1, rest),
+        };
+        let end = rest.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(rest.len());
+        let mut parts = rest[..end].split(':');
+        let mut offset = 0;
+        for unit in &[3600, 60, 1] {
+            match parts.next() {
+                Some(part) => offset += part.parse::<i64>().ok()? * unit,
+                None => break,
+            }
+        }
+        if parts.next().is_some() || offset > 24 * 3600 {
+            return None;
+        }
+        Some(Zone { name: name.to_string(), offset: sign * offset })
+    }
+}
+
+/// Format `date` like strftime, with the conversions `%Y %y %m %d %e %j %H %M
+/// %S %a %A %b %B %F %T %s %z %Z %n %t %%`. Anything else is copied as is.
+/// `%s` prints `unix`, the time the date was made from.
+fn strftime(date: &DateTime, format: &str, zone: &Zone, unix: i64) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let _ = match chars.next() {
+            Some('Y') => write!(out, "{:04}", date.year),
+            Some('y') => write!(out, "{:02}", date.year.rem_euclid(100)),
+            Some('m') => write!(out, "{:02}", date.month),
+            Some('d') => write!(out, "{:02}", date.day),
+            Some('e') => write!(out, "{:2}", date.day),
+            Some('j') => write!(out, "{:03}", date.yday),
+            Some('H') => write!(out, "{:02}", date.hour),
+            Some('M') => write!(out, "{:02}", date.minute),
+            Some('S') => write!(out, "{:02}", date.second),
+            Some('a') => write!(out, "{}", &WEEKDAYS[date.weekday as usize][..3]),
+            Some('A') => write!(out, "{}", WEEKDAYS[date.weekday as usize]),
+            Some('b') => write!(out, "{}", &MONTHS[date.month as usize - 1][..3]),
+            Some('B') => write!(out, "{}", MONTHS[date.month as usize - 1]),
+            Some('F') => write!(out, "{:04}-{:02}-{:02}", date.year, date.month, date.day),
+            Some('T') => write!(out, "{:02}:{:02}:{:02}", date.hour, date.minute, date.second),
+            Some('s') => write!(out, "{}", unix),
+            Some('z') => {
+                let sign = if zone.offset < 0 { '-' } else { '+' };
+                let offset = zone.offset.abs();
+                write!(out, "{}{:02}{:02}", sign, offset / 3600, offset / 60 % 60)
+            }
+            Some('Z') => write!(out, "{}", zone.name),
+            Some('n') => out.write_char('\n'),
+            Some('t') => out.write_char('\t'),
+            Some('%') => out.write_char('%'),
+            Some(other) => write!(out, "%{}", other),
+            None => out.write_char('%'),
+        };
+    }
+    out
+}
+
+/// Format `unix`, seconds since the epoch, as a time in `zone`
+pub fn format_time(unix: i64, format: &str, zone: &Zone) -> String {
+    strftime(&DateTime::from_unix(unix + zone.offset), format, zone, unix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_time, Zone, DEFAULT_FORMAT};
+
+    #[test]
+    fn formats() {
+        let utc = Zone::utc();
+        assert_eq!(format_time(1_700_000_000, DEFAULT_FORMAT, &utc), "2023-11-14 22:13:20");
+        assert_eq!(
+            format_time(1_709_164_800, "%a %e %b %Y, %A %d %B, day %j %%", &utc),
+            "Thu 29 Feb 2024, Thursday 29 February, day 060 %"
+        );
+        assert_eq!(format_time(1_700_000_000, "%s %F %T %Z %z %q", &utc), "1700000000 2023-11-14 22:13:20 UTC +0000 %q");
+
+        let cet = Zone::from_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(cet, Zone { name: "CET".to_string(), offset: 3600 });
+        assert_eq!(format_time(1_700_000_000, "%F %T %z", &cet), "2023-11-14 23:13:20 +0100");
+        let india = Zone::from_tz("<+0530>-5:30").unwrap();
+        assert_eq!(format_time(1_700_000_000, "%T %Z %z", &india), "03:43:20 +0530 +0530");
+        let est = Zone::from_tz("EST5").unwrap();
+        assert_eq!(format_time(1_700_000_000, "%d %H %z", &est), "14 17 -0500");
+    }
+
+    #[test]
+    fn tz_values() {
+        assert_eq!(Zone::from_tz("UTC"), Some(Zone::utc()));
+        assert_eq!(Zone::from_tz("UTC0"), Some(Zone::utc()));
+        assert_eq!(Zone::from_tz("Europe/Paris"), None);
+        assert_eq!(Zone::from_tz("EST"), None);
+        assert_eq!(Zone::from_tz("XX5"), None);
+        assert_eq!(Zone::from_tz("BAD99"), None);
+        assert_eq!(Zone::from_tz(""), None);
+    }
+}
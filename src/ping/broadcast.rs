@@ -0,0 +1,26 @@
+use netutils::{Ipv4Addr, Ipv4Cidr, NetCfg};
+use std::net::IpAddr;
+
+/// Whether `addr` reaches several hosts: a multicast group, the limited
+/// broadcast address, or the broadcast address of a configured subnet
+pub fn is_broadcast(addr: IpAddr) -> bool {
+    let addr = match addr {
+        IpAddr::V4(addr) => addr,
+        IpAddr::V6(addr) => return addr.is_multicast(),
+    };
+    if addr.is_multicast() || addr.is_broadcast() {
+        return true;
+    }
+
+    let addr = Ipv4Addr { bytes: addr.octets() };
+    let cfg = NetCfg::new();
+    let ifaces = cfg.list_ifaces().unwrap_or_default();
+    ifaces.iter().any(|iface| {
+        cfg.get_iface(iface, "addr/list")
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse::<Ipv4Cidr>().ok())
+            // A /31 or /32 has no broadcast address
+            .any(|cidr| cidr.prefix < 31 && cidr.broadcast() == addr)
+    })
+}
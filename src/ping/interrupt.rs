@@ -0,0 +1,31 @@
+//! SIGINT handling, so that Ctrl-C always ends with the statistics
+//!
+//! The handler only sets a flag. It is installed without `SA_RESTART`, so a
+//! blocked read of the event queue or write of the echo file returns `EINTR`
+//! and the event loop gets to check the flag right away.
+
+use std::mem;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use libredox::data::SigAction;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_signal: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+pub fn install() -> Result<()> {
+    let mut action: SigAction = unsafe { mem::zeroed() };
+    action.sa_sigaction = on_sigint as extern "C" fn(c_int) as usize;
+    libredox::call::sigaction(libredox::flag::SIGINT, Some(&action), None)
+        .context("Failed to install the SIGINT handler")?;
+    Ok(())
+}
+
+/// Whether SIGINT was received since `install`
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -1,18 +1,25 @@
+mod broadcast;
+mod interrupt;
 mod ping;
+mod samples;
 mod stats;
+use broadcast::is_broadcast;
 use ping::Ping;
+use netutils::dns::{resolve_host, reverse_lookup};
+use samples::SampleWriter;
 
 extern crate anyhow;
 extern crate clap;
 extern crate event;
 extern crate libredox;
+extern crate netutils;
 
 use anyhow::{anyhow, bail, Context, Result};
+use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, Command};
 use event::{user_data, EventFlags, EventQueue};
 use std::mem;
 use std::net::IpAddr;
-use std::net::ToSocketAddrs;
 
 use libredox::data::TimeSpec;
 use libredox::errno::EINTR;
@@ -25,7 +32,7 @@ NAME
     ping - send ICMP ECHO_REQUEST to network hosts
 
 SYNOPSIS
-    ping [-h | --help] [-c count] [-i interval] [-t ttl] destination
+    ping [-h | --help] [-c count] [-i interval] [-t ttl] [-w deadline] [-n] [-O] [-b] destination
 
 DESCRIPTION
     ping sends ICMP ECHO_REQUEST packets to the specified destination host
@@ -42,6 +49,27 @@ OPTIONS
     -i interval
         Wait interval seconds before sending next packet.
 
+    -w deadline
+        Stop after deadline seconds, counting unanswered packets as timeouts.
+        Without -c, packets are sent until the deadline. 0 means no deadline.
+
+    -n
+        Numeric output only, without looking up the host name of the
+        destination address for the banner and replies.
+
+    -O
+        Report packets still unanswered when the next one is sent, instead of
+        staying silent until they time out.
+
+    -b
+        Allow pinging a broadcast or multicast address. Each request then waits
+        its full timeout for further replies, which are printed as (DUP!). The
+        icmp scheme does not report who sent a reply, so replies from different
+        hosts can't be told apart and are all shown from the destination address.
+
+    --out file
+        Append one CSV line per reply or timeout to file.
+
 "#; /* @MANEND */ */
 
 const PING_TIMEOUT_S: i64 = 5;
@@ -58,16 +86,6 @@ const MICROSECONDS_PER_MILLISECOND: i64 = 1_000;
 //const PING_PACKETS_TO_SEND: usize = 4;
 //const PING_INTERVAL_S: i64 = 1;
 
-fn resolve_host(host: &str) -> Result<IpAddr> {
-    match (host, 0).to_socket_addrs()?.next() {
-        Some(addr) => Ok(addr.ip()),
-        None => {
-            println!("Failed to resolve host: {}", host);
-            Err(anyhow!("Failed to resolve remote host's IP address"))
-        }
-    }
-}
-
 /// Computes the difference between `from` and `to` in milliseconds,
 /// taking into account both the seconds (`tv_sec`) and nanoseconds (`tv_nsec`) fields
 /// of the `TimeSpec` structure.
@@ -88,7 +106,18 @@ fn time_diff_ms(from: &TimeSpec, to: &TimeSpec) -> f32 {
     (seconds_diff + nanoseconds_diff) as f32 / 1_000.0
 }
 
-fn parse_args() -> Result<(String, usize, i64)> {
+struct Args {
+    remote_host: String,
+    count: usize,
+    interval: i64,
+    deadline: i64,
+    report_outstanding: bool,
+    numeric: bool,
+    broadcast: bool,
+    out: Option<String>,
+}
+
+fn parse_args() -> Result<Args> {
     let matches = Command::new("ping")
         .about("send ICMP ECHO_REQUEST to network hosts")
         //.after_help(PING_MAN)
@@ -118,6 +147,42 @@ fn parse_args() -> Result<(String, usize, i64)> {
                 .num_args(1)
                 .action(ArgAction::Set),
         )
+        .arg(
+            Arg::new("deadline")
+                .short('w')
+                .long("deadline")
+                .value_name("DEADLINE")
+                .help("Stop after DEADLINE seconds, whatever was sent or received (0 means no deadline).")
+                .default_value("0")
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("numeric")
+                .short('n')
+                .help("Print addresses only, without reverse DNS lookups.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("outstanding")
+                .short('O')
+                .help("Print a line for every packet not answered by the time the next one is sent.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("broadcast")
+                .short('b')
+                .help("Allow pinging a broadcast or multicast address. Replies from different hosts can't be told apart.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("out")
+                .long("out")
+                .value_name("FILE")
+                .help("Append sequence, send time, RTT and result of every packet to FILE as CSV.")
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
         // TODO : TTL
         // The TTL feature has been removed because icmp/ttl is not ready.
         // If needed in the future, uncomment the following code and add the u8 in the function
@@ -145,7 +210,7 @@ fn parse_args() -> Result<(String, usize, i64)> {
     let count_str = matches
         .get_one::<String>("count")
         .expect("count should have a default and thus always be present");
-    let count: usize = count_str
+    let mut count: usize = count_str
         .parse()
         .map_err(|e| anyhow!("Invalid packet count for -c: {} ({})", count_str, e))?;
 
@@ -169,12 +234,49 @@ fn parse_args() -> Result<(String, usize, i64)> {
     // if !(1..=MAX_TTL).contains(&ttl) {
     //    bail!("TTL must be between 1 and {}", MAX_TTL);
 
-    Ok((remote_host, count, interval))
+    let deadline_str = matches
+        .get_one::<String>("deadline")
+        .expect("deadline should have a default");
+    let deadline: i64 = deadline_str
+        .parse()
+        .map_err(|e| anyhow!("Invalid deadline for -w: {} ({})", deadline_str, e))?;
+    if deadline < 0 {
+        bail!("Deadline must not be negative");
+    }
+    // A deadline without an explicit count pings until the deadline
+    if deadline > 0 && matches.value_source("count") == Some(ValueSource::DefaultValue) {
+        count = 0;
+    }
+
+    let report_outstanding = matches.get_flag("outstanding");
+    let numeric = matches.get_flag("numeric");
+    let broadcast = matches.get_flag("broadcast");
+    let out = matches.get_one::<String>("out").cloned();
+
+    Ok(Args {
+        remote_host,
+        count,
+        interval,
+        deadline,
+        report_outstanding,
+        numeric,
+        broadcast,
+        out,
+    })
 }
 
 fn main() -> Result<()> {
     // Parsing the command line
-    let (remote_host, count, interval) = parse_args()?;
+    let Args {
+        remote_host,
+        count,
+        interval,
+        deadline,
+        report_outstanding,
+        numeric,
+        broadcast,
+        out,
+    } = parse_args()?;
 
     user_data! {
         enum EventSource {
@@ -183,14 +285,26 @@ fn main() -> Result<()> {
         }
     }
 
-    let remote_host = resolve_host(&remote_host)?;
+    let destination = remote_host;
+    let remote_host = resolve_host(&destination)
+        .with_context(|| format!("Failed to resolve host: {}", destination))?;
+    if is_broadcast(remote_host) && !broadcast {
+        bail!("{} is a broadcast or multicast address, use -b to ping it", remote_host);
+    }
+    // Looked up once, replies reuse it
+    let remote_name = if numeric || broadcast { None } else { reverse_lookup(remote_host) };
+    // The banner names the host as given, or as found for a bare address
+    let banner_name = match remote_name {
+        Some(ref name) if destination.parse::<IpAddr>().is_ok() => name.clone(),
+        _ => destination,
+    };
 
     let data_size = ECHO_PAYLOAD_SIZE;
     let total_size = data_size + IP_HEADER_SIZE + ICMP_HEADER_SIZE;
     // Print the line similar to standard ping output
     println!(
         "PING {} ({}) {}({}) bytes of data.",
-        remote_host, remote_host, data_size, total_size
+        banner_name, remote_host, data_size, total_size
     );
 
     // Create the path to the ICMP echo file for the remote host
@@ -218,17 +332,35 @@ fn main() -> Result<()> {
 
     // Create a new Ping instance with the specified parameters
     let mut ping = Ping::new(remote_host, count, interval, echo_fd, time_fd);
+    ping.remote_name = remote_name;
+    ping.report_outstanding = report_outstanding;
+    ping.broadcast = broadcast;
+    if let Some(path) = out {
+        ping.samples = Some(SampleWriter::open(&path)?);
+    }
+
+    interrupt::install()?;
 
     // Send the first ping immediately
     let current_time = libredox::call::clock_gettime(libredox::flag::CLOCK_MONOTONIC)
         .context("Failed to get the current time")?;
-    ping.send_ping(&current_time)?;
+    if let Err(err) = ping.send_ping(&current_time) {
+        if !interrupt::interrupted() {
+            return Err(err);
+        }
+    }
+    if deadline > 0 {
+        ping.deadline = Some(TimeSpec {
+            tv_sec: current_time.tv_sec + deadline,
+            tv_nsec: current_time.tv_nsec,
+        });
+    }
 
     // Schedule the next time event
     let mut buf = [0_u8; mem::size_of::<TimeSpec>()];
     let time = libredox::data::timespec_from_mut_bytes(&mut buf);
 
-    time.tv_sec = current_time.tv_sec + interval;
+    time.tv_sec = current_time.tv_sec + if deadline > 0 { interval.min(deadline) } else { interval };
     time.tv_nsec = current_time.tv_nsec;
     ping.time_file
         .write(&buf)
@@ -236,6 +368,9 @@ fn main() -> Result<()> {
 
     // Start the event loop
     for event_res in event_queue {
+        if interrupt::interrupted() {
+            break;
+        }
         match event_res {
             Ok(event) => {
                 let done = match event.user_data {
@@ -243,15 +378,21 @@ fn main() -> Result<()> {
                     EventSource::Time => ping.on_time_event(),
                 };
 
-                if let Some(_) = done? {
-                    break;
+                match done {
+                    Ok(Some(())) => break,
+                    Ok(None) => (),
+                    // A read or write cut short by SIGINT is not an error
+                    Err(_) if interrupt::interrupted() => break,
+                    Err(err) => return Err(err),
                 }
             }
             Err(e) => {
                 // Handle Interrupted system call error
                 if e.errno() == EINTR {
-                    println!("Interrupted! Exiting gracefully.");
-                    break;
+                    if interrupt::interrupted() {
+                        break;
+                    }
+                    continue;
                 }
                 eprintln!("Event queue error: {:?}", e);
                 break;
@@ -259,7 +400,7 @@ fn main() -> Result<()> {
         }
     }
 
-    ping.print_final_statistics();
+    ping.print_final_statistics(interrupt::interrupted());
 
     Ok(())
 }
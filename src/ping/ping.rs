@@ -2,12 +2,13 @@
 use libredox::data::TimeSpec;
 use libredox::Fd;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::mem;
 use std::net::IpAddr;
 use std::ops::{Deref, DerefMut};
 use std::slice;
 
+use crate::samples::SampleWriter;
 use crate::stats::PingStatistics;
 
 use anyhow::{bail, Context, Result};
@@ -129,8 +130,18 @@ impl DerefMut for EchoPayload {
     }
 }
 
+/// Whether request `seq` is waited for and has no reply yet. With -b an
+/// answered request stays in `waiting_for` to collect other replies, so being
+/// there is not enough.
+fn is_unanswered(waiting_for: &BTreeMap<OrderedTimeSpec, u16>, answered: &BTreeSet<u16>, seq: u16) -> bool {
+    waiting_for.values().any(|&waiting| waiting == seq) && !answered.contains(&seq)
+}
+
 pub struct Ping {
     pub remote_host: IpAddr,
+    /// Host name of `remote_host` printed with replies, `None` with -n or
+    /// when the reverse lookup found nothing
+    pub remote_name: Option<String>,
     pub time_file: Fd,
     pub echo_file: Fd,
     pub seq: u16, // Changed from usize to u16 (max 65 535, ICMP spec)
@@ -140,6 +151,17 @@ pub struct Ping {
     pub packets_to_send: usize,
     pub interval: i64,
     pub stats: PingStatistics,
+    pub samples: Option<SampleWriter>,
+    /// Monotonic time at which the session ends, set with -w
+    pub deadline: Option<TimeSpec>,
+    /// Print a line for packets still unanswered one interval later, set with -O
+    pub report_outstanding: bool,
+    /// Sequence number sent at the previous tick, checked by -O at the next one
+    last_sent: Option<u16>,
+    /// Wait for every host of a broadcast or multicast destination, set with -b
+    pub broadcast: bool,
+    /// With -b, requests that got a first reply and still collect others until they time out
+    answered: BTreeSet<u16>,
     //pub ttl: u8,
 }
 
@@ -154,6 +176,7 @@ impl Ping {
     ) -> Ping {
         Ping {
             remote_host,
+            remote_name: None,
             echo_file,
             time_file,
             seq: 0,
@@ -163,6 +186,12 @@ impl Ping {
             packets_to_send,
             interval,
             stats: PingStatistics::new(),
+            samples: None,
+            deadline: None,
+            report_outstanding: false,
+            last_sent: None,
+            broadcast: false,
+            answered: BTreeSet::new(),
             //ttl: ttl.unwrap_or(DEFAULT_TTL),
         }
     }
@@ -184,15 +213,6 @@ impl Ping {
             Err(e) => return Err(e).context("Failed to read from echo file"),
         };
 
-        if self.received > 0 {
-            let time = libredox::call::clock_gettime(libredox::flag::CLOCK_MONOTONIC)
-                .context("Failed to get the current time")?;
-            let rtt = time_diff_ms(&payload.timestamp, &time);
-            self.stats.record_received(rtt);
-        } else {
-            self.stats.record_error();
-        }
-
         if readed == 0 {
             return Ok(None);
         }
@@ -204,24 +224,56 @@ impl Ping {
         let time = libredox::call::clock_gettime(libredox::flag::CLOCK_MONOTONIC)
             .context("Failed to get the current time")?;
 
-        let remote_host = self.remote_host;
+        let from = match self.remote_name {
+            Some(ref name) => format!("{} ({})", name, self.remote_host),
+            None => self.remote_host.to_string(),
+        };
 
+        let rtt = time_diff_ms(&payload.timestamp, &time);
         let mut received = 0;
-        self.waiting_for.retain(|_ts, &mut seq| {
-            if seq as u16 == payload.seq {
-                received += 1;
-                println!(
-                    "From {} icmp_seq={} time={}ms",
-                    remote_host,
-                    seq,
-                    time_diff_ms(&payload.timestamp, &time)
-                );
-                false
-            } else {
-                true
+        let mut duplicate = false;
+        if self.broadcast {
+            // Kept waiting until the timeout, so the other hosts can still answer
+            if self.waiting_for.values().any(|&seq| seq == payload.seq) {
+                if self.answered.insert(payload.seq) {
+                    received = 1;
+                } else {
+                    duplicate = true;
+                }
             }
-        });
+        } else {
+            self.waiting_for.retain(|_ts, &mut seq| {
+                if seq == payload.seq {
+                    received += 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        if received > 0 || duplicate {
+            println!(
+                "From {} icmp_seq={} time={}ms{}",
+                from,
+                payload.seq,
+                rtt,
+                if duplicate { " (DUP!)" } else { "" }
+            );
+        }
         self.received += received;
+        if received > 0 {
+            self.stats.record_received(rtt);
+        }
+        if duplicate {
+            self.stats.record_duplicate();
+        }
+
+        if let Some(ref mut samples) = self.samples {
+            // A reply for a sequence no longer waited for was already answered
+            let result = if received > 0 { "ok" } else { "dup" };
+            samples.record(payload.seq, &payload.timestamp, Some(rtt), result)?;
+        }
+
         self.is_finished()
     }
 
@@ -231,9 +283,35 @@ impl Ping {
             bail!("Failed to read from time file");
         }
         let time = libredox::data::timespec_from_mut_bytes(&mut buf);
+
+        if let Some(deadline) = self.deadline {
+            if OrderedTimeSpec(*time) >= OrderedTimeSpec(deadline) {
+                // Whatever is still outstanding will not be waited for
+                while let Some((ts, seq)) = self.waiting_for.pop_first() {
+                    self.report_timeout(ts, seq)?;
+                }
+                return Ok(Some(()));
+            }
+        }
+
+        let previous = self.last_sent.take();
         self.send_ping(&time)?;
         self.check_timeouts(&time)?;
+        if self.report_outstanding {
+            // Checked after the timeouts, so a packet that just timed out is only reported once
+            if let Some(seq) = previous {
+                if is_unanswered(&self.waiting_for, &self.answered, seq) {
+                    println!("no answer yet for icmp_seq={}", seq);
+                }
+            }
+        }
         time.tv_sec += self.interval;
+        if let Some(deadline) = self.deadline {
+            // Wake up at the deadline if it comes before the next ping
+            if OrderedTimeSpec(deadline) < OrderedTimeSpec(*time) {
+                *time = deadline;
+            }
+        }
         self.time_file
             .write(&buf)
             .context("Failed to write to time file")?;
@@ -268,6 +346,7 @@ impl Ping {
         self.waiting_for
             .insert(OrderedTimeSpec(timeout_time), self.seq);
 
+        self.last_sent = Some(self.seq);
         self.seq += 1;
 
         self.stats.record_sent();
@@ -275,13 +354,11 @@ impl Ping {
         Ok(None)
     }
 
-    pub fn print_final_statistics(&self) {
-        self.stats.print_summary(self.remote_host);
+    pub fn print_final_statistics(&self, interrupted: bool) {
+        self.stats.print_summary(self.remote_host, interrupted);
     }
 
     fn check_timeouts(&mut self, time: &TimeSpec) -> Result<Option<()>> {
-        let remote_host = self.remote_host;
-
         // Loop until we find a timeout that is still in the past
         while let Some((&ts, &seq)) = self.waiting_for.first_key_value() {
             // ts is &OrderedTimeSpec, so ts.0 is the inner TimeSpec
@@ -290,13 +367,31 @@ impl Ping {
                 break;
             }
             // This one timed out
-            println!("From {} icmp_seq={} timeout", remote_host, seq);
             self.waiting_for.pop_first();
+            self.report_timeout(ts, seq)?;
         }
 
         Ok(None)
     }
 
+    /// Report a request that got no reply, `ts` being when it was due to time out.
+    /// With -b this is also where an answered request stops collecting replies.
+    fn report_timeout(&mut self, ts: OrderedTimeSpec, seq: u16) -> Result<()> {
+        if self.answered.remove(&seq) {
+            return Ok(());
+        }
+        println!("From {} icmp_seq={} timeout", self.remote_host, seq);
+        self.stats.record_error();
+
+        if let Some(ref mut samples) = self.samples {
+            let mut sent = ts.0;
+            sent.tv_sec -= PING_TIMEOUT_S;
+            samples.record(seq, &sent, None, "timeout")?;
+        }
+
+        Ok(())
+    }
+
     fn is_finished(&self) -> Result<Option<()>> {
         if self.packets_to_send > 0
             && usize::from(self.seq) == self.packets_to_send
@@ -308,3 +403,25 @@ impl Ping {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{is_unanswered, OrderedTimeSpec};
+    use libredox::data::TimeSpec;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn outstanding_requests() {
+        let mut waiting_for = BTreeMap::new();
+        waiting_for.insert(OrderedTimeSpec(TimeSpec { tv_sec: 10, tv_nsec: 0 }), 3);
+        waiting_for.insert(OrderedTimeSpec(TimeSpec { tv_sec: 11, tv_nsec: 0 }), 4);
+        let mut answered = BTreeSet::new();
+        assert!(is_unanswered(&waiting_for, &answered, 3));
+        assert!(!is_unanswered(&waiting_for, &answered, 5));
+
+        // With -b, seq 3 got a reply but is still waited for until it times out
+        answered.insert(3);
+        assert!(!is_unanswered(&waiting_for, &answered, 3));
+        assert!(is_unanswered(&waiting_for, &answered, 4));
+    }
+}
@@ -0,0 +1,40 @@
+/// samples.rs
+use libredox::data::TimeSpec;
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+/// Appends one CSV line per reply or timeout so runs can be analysed later
+pub struct SampleWriter {
+    file: File,
+}
+
+impl SampleWriter {
+    pub fn open(path: &str) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open sample file {}", path))?;
+
+        // Only a fresh file gets the header, appended runs continue the table
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "seq,sent,rtt_ms,result").context("Failed to write sample file")?;
+        }
+
+        Ok(SampleWriter { file })
+    }
+
+    /// `result` is one of `ok`, `timeout` or `dup`
+    pub fn record(&mut self, seq: u16, sent: &TimeSpec, rtt: Option<f32>, result: &str) -> Result<()> {
+        let rtt = rtt.map_or(String::new(), |rtt| format!("{:.3}", rtt));
+        writeln!(
+            self.file,
+            "{},{}.{:09},{},{}",
+            seq, sent.tv_sec, sent.tv_nsec, rtt, result
+        )
+        .context("Failed to write sample file")?;
+        self.file.flush().context("Failed to flush sample file")
+    }
+}
@@ -5,6 +5,8 @@ pub struct PingStatistics {
     pub total_sent: u32,
     pub total_received: u32,
     pub total_errors: u32,
+    /// Further replies to an already answered request, from other hosts with -b
+    pub total_duplicates: u32,
     pub min_rtt: Option<f32>,
     pub max_rtt: Option<f32>,
     pub avg_rtt: f32,
@@ -17,6 +19,7 @@ impl PingStatistics {
             total_sent: 0,
             total_received: 0,
             total_errors: 0,
+            total_duplicates: 0,
             min_rtt: None,
             max_rtt: None,
             avg_rtt: 0.0,
@@ -42,10 +45,15 @@ impl PingStatistics {
         self.avg_rtt = self.rtts.iter().sum::<f32>() / self.rtts.len() as f32;
     }
 
+    /// Count a request that timed out without a reply
     pub fn record_error(&mut self) {
         self.total_errors += 1;
     }
 
+    pub fn record_duplicate(&mut self) {
+        self.total_duplicates += 1;
+    }
+
     fn packet_loss_percentage(&self) -> f32 {
         if self.total_sent == 0 {
             0.0
@@ -54,12 +62,22 @@ impl PingStatistics {
         }
     }
 
-    pub fn print_summary(&self, remote_host: IpAddr) {
-        println!("--- {} ping statistics ---", remote_host);
+    pub fn print_summary(&self, remote_host: IpAddr, interrupted: bool) {
+        if interrupted {
+            println!("--- {} ping statistics (interrupted) ---", remote_host);
+        } else {
+            println!("--- {} ping statistics ---", remote_host);
+        }
+        let duplicates = if self.total_duplicates > 0 {
+            format!(", +{} duplicates", self.total_duplicates)
+        } else {
+            String::new()
+        };
         println!(
-            "{} packets transmitted, {} packets received, {:.2}% packet loss",
+            "{} packets transmitted, {} packets received{}, {:.2}% packet loss",
             self.total_sent,
             self.total_received,
+            duplicates,
             self.packet_loss_percentage()
         );
 
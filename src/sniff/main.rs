@@ -0,0 +1,226 @@
+extern crate anyhow;
+extern crate clap;
+extern crate netutils;
+
+use anyhow::{bail, Context, Result};
+use clap::{Arg, ArgAction, Command};
+use netutils::dns::reverse_lookup;
+use netutils::filter::Filter;
+use netutils::link::{Link, NETWORK_DEVICE};
+use netutils::packet::parse_ethernet;
+use netutils::pcap::{PcapWriter, DEFAULT_SNAPLEN};
+use netutils::describe;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/*
+static SNIFF_MAN: &'static str = /* @MANSTART{sniff} */
+    r#"
+NAME
+    sniff - print or save the frames seen by the network adapter
+
+SYNOPSIS
+    sniff [-h | --help] [-n] [-c count] [-w file] [expression]
+
+DESCRIPTION
+    sniff reads raw Ethernet frames from the network scheme and prints a one
+    line summary of each, like tcpdump. The expression selects which frames
+    are shown, in a subset of the tcpdump syntax: tcp, udp, icmp, arp,
+    [src|dst] host address and [src|dst] port number, combined with not, and,
+    or and parentheses.
+
+    The network stack reads the same device, so frames it takes first are not
+    seen by sniff.
+
+OPTIONS
+    -h
+    --help
+        Print this manual page.
+
+    -n
+        Print addresses only, without reverse DNS lookups.
+
+    -c count
+        Exit after count matching frames.
+
+    -w file
+        Also write the matching frames to file in the pcap format, for
+        tcpdump -r or Wireshark.
+"#; /* @MANEND */ */
+
+struct Args {
+    filter: Filter,
+    count: Option<u64>,
+    numeric: bool,
+    output: Option<String>,
+}
+
+fn parse_args() -> Result<Args> {
+    let matches = Command::new("sniff")
+        .about("print or save the frames seen by the network adapter")
+        //.after_help(SNIFF_MAN)
+        .arg(
+            Arg::new("expression")
+                .help("Only show frames matching this filter, e.g. 'udp port 53'")
+                .num_args(0..)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("count")
+                .short('c')
+                .value_name("COUNT")
+                .help("Exit after COUNT matching frames.")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("numeric")
+                .short('n')
+                .help("Print addresses only, without reverse DNS lookups.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("write")
+                .short('w')
+                .value_name("FILE")
+                .help("Write the matching frames to FILE in the pcap format.")
+                .action(ArgAction::Set),
+        )
+        .get_matches();
+
+    // Like tcpdump, the expression may be one argument or spread over several
+    let expression = matches
+        .get_many::<String>("expression")
+        .map(|words| words.cloned().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    let filter = match expression.parse::<Filter>() {
+        Ok(filter) => filter,
+        Err(err) => bail!("Invalid filter '{}': {}", expression, err),
+    };
+
+    Ok(Args {
+        filter,
+        count: matches.get_one::<u64>("count").copied(),
+        numeric: matches.get_flag("numeric"),
+        output: matches.get_one::<String>("write").cloned(),
+    })
+}
+
+/// Time of day in UTC, `HH:MM:SS.micros` as tcpdump prints it
+fn time_of_day(time: Duration) -> String {
+    let secs = time.as_secs() % 86400;
+    format!("{:02}:{:02}:{:02}.{:06}", secs / 3600, secs / 60 % 60, secs % 60, time.subsec_micros())
+}
+
+/// Replace the addresses in a summary from `describe` with host names.
+///
+/// Addresses appear alone or followed by a port, as in `10.0.2.15.40000`, and
+/// the last one before the payload ends in a colon.
+fn with_names<F: FnMut(IpAddr) -> Option<String>>(summary: &str, mut lookup: F) -> String {
+    let words: Vec<String> = summary
+        .split(' ')
+        .map(|word| {
+            let (word, colon) = match word.strip_suffix(':') {
+                Some(word) => (word, ":"),
+                None => (word, ""),
+            };
+            // Split off a port after the fourth octet
+            let (addr, port) = match word.match_indices('.').nth(3) {
+                Some((i, _)) => (&word[..i], &word[i..]),
+                None => (word, ""),
+            };
+            match addr.parse::<IpAddr>().ok().and_then(&mut lookup) {
+                Some(name) => format!("{}{}{}", name, port, colon),
+                None => format!("{}{}", word, colon),
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+
+    let link = Link::open_capture()
+        .with_context(|| format!("Can't open {} to read frames", NETWORK_DEVICE))?;
+
+    let mut pcap = match args.output {
+        Some(ref path) => {
+            let file = File::create(path).with_context(|| format!("Can't create {}", path))?;
+            let pcap = PcapWriter::new(BufWriter::new(file), DEFAULT_SNAPLEN)
+                .with_context(|| format!("Can't write to {}", path))?;
+            Some(pcap)
+        }
+        None => None,
+    };
+
+    // Names found so far, failed lookups included so they are not repeated
+    let mut names: BTreeMap<IpAddr, Option<String>> = BTreeMap::new();
+    let mut buf = [0; 65536];
+    let mut matched = 0;
+    while args.count.is_none_or(|count| matched < count) {
+        let count = link.recv(&mut buf).context("Failed to read a frame")?;
+        let frame = &buf[..count];
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        // Frames the library can't parse only pass the empty filter
+        let show = match parse_ethernet(frame) {
+            Some(packet) => args.filter.matches(&packet),
+            None => args.filter.is_empty(),
+        };
+        if !show {
+            continue;
+        }
+        matched += 1;
+
+        let summary = describe(frame);
+        let summary = if args.numeric {
+            summary
+        } else {
+            with_names(&summary, |addr| names.entry(addr).or_insert_with(|| reverse_lookup(addr)).clone())
+        };
+        println!("{} {}", time_of_day(time), summary);
+
+        if let Some(ref mut pcap) = pcap {
+            // Flushed every frame, so the file is complete whenever sniff is stopped
+            pcap.write_frame(frame, time)
+                .and_then(|_| pcap.flush())
+                .context("Failed to write the capture file")?;
+        }
+    }
+
+    println!("{} packets captured", matched);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{time_of_day, with_names};
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn names() {
+        let lookup = |addr: IpAddr| match addr.to_string().as_str() {
+            "10.0.2.2" => Some("gateway".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            with_names("52:54:00:12:34:56 > 52:55:0a:00:02:02 IPv4 10.0.2.15.40000 > 10.0.2.2.80: TCP SYN len 0", lookup),
+            "52:54:00:12:34:56 > 52:55:0a:00:02:02 IPv4 10.0.2.15.40000 > gateway.80: TCP SYN len 0"
+        );
+        assert_eq!(
+            with_names("ARP who-has 10.0.2.2 tell 10.0.2.15", lookup),
+            "ARP who-has gateway tell 10.0.2.15"
+        );
+        assert_eq!(with_names("IPv4 10.0.2.15 > 10.0.2.2: ICMP len 8", lookup), "IPv4 10.0.2.15 > gateway: ICMP len 8");
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(time_of_day(Duration::new(1_700_000_000, 123_456_789)), "22:13:20.123456");
+    }
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a cached response is served before asking again
+pub const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Least time between two queries to the same server, also across runs
+pub const MIN_QUERY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// `$HOME/.cache/whois`, or a directory under /tmp without a home
+pub fn default_dir() -> PathBuf {
+    match env::var_os("HOME") {
+        Some(home) => Path::new(&home).join(".cache/whois"),
+        None => PathBuf::from("/tmp/whois"),
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+/// File name for a query sent to `host`, which keeps letters, digits, dots
+/// and dashes and escapes everything else so different queries never collide
+pub fn cache_key(host: &str, port: u16, query: &str) -> String {
+    let mut key = format!("{}_{}_", host.to_ascii_lowercase(), port);
+    for byte in query.trim().to_lowercase().bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' => key.push(byte as char),
+            _ => key.push_str(&format!("%{:02x}", byte)),
+        }
+    }
+    key
+}
+
+/// The response stored in a cache file, when it is younger than `ttl`.
+///
+/// Files start with the Unix time they were written at on a line of its own.
+/// Entries from the future are treated as expired, in case the clock was wrong.
+pub fn parse_entry(text: &str, now: SystemTime, ttl: Duration) -> Option<&str> {
+    let newline = text.find('\n')?;
+    let stored = text[..newline].parse::<u64>().ok()?;
+    let age = unix_secs(now).checked_sub(stored)?;
+    if age < ttl.as_secs() {
+        Some(&text[newline + 1..])
+    } else {
+        None
+    }
+}
+
+pub fn format_entry(response: &str, now: SystemTime) -> String {
+    format!("{}\n{}", unix_secs(now), response)
+}
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Cache { dir }
+    }
+
+    /// A fresh response for `key`, if one was stored
+    pub fn get(&self, key: &str) -> Option<String> {
+        let text = fs::read_to_string(self.dir.join(key)).ok()?;
+        parse_entry(&text, SystemTime::now(), CACHE_TTL).map(|response| response.to_string())
+    }
+
+    /// Store the response, failing quietly since the cache is only an optimization
+    pub fn put(&self, key: &str, response: &str) {
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(self.dir.join(key), format_entry(response, SystemTime::now()));
+        }
+    }
+
+    /// Sleep until `MIN_QUERY_INTERVAL` has passed since the last query to
+    /// `host`, then record this one
+    pub fn wait_turn(&self, host: &str) {
+        let dir = self.dir.join("last-query");
+        let path = dir.join(host.to_ascii_lowercase());
+        let last = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        if let Some(elapsed) = last.and_then(|last| last.elapsed().ok()) {
+            if elapsed < MIN_QUERY_INTERVAL {
+                thread::sleep(MIN_QUERY_INTERVAL - elapsed);
+            }
+        }
+        if fs::create_dir_all(&dir).is_ok() {
+            let _ = fs::write(&path, b"");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, format_entry, parse_entry};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn keys() {
+        assert_eq!(cache_key("whois.iana.org", 43, "example.com"), "whois.iana.org_43_example.com");
+        assert_eq!(cache_key("WHOIS.iana.org", 43, " Example.COM\n"), cache_key("whois.iana.org", 43, "example.com"));
+        assert_eq!(cache_key("whois.arin.net", 43, "n + 8.8.8.8"), "whois.arin.net_43_n%20%2b%208.8.8.8");
+        assert_eq!(cache_key("whois.iana.org", 43, "../etc/passwd"), "whois.iana.org_43_..%2fetc%2fpasswd");
+        assert_ne!(cache_key("whois.iana.org", 43, "a/b"), cache_key("whois.iana.org", 43, "a%2fb"));
+        assert_ne!(cache_key("whois.iana.org", 43, "a"), cache_key("whois.iana.org", 4343, "a"));
+    }
+
+    #[test]
+    fn ttl() {
+        let written = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let ttl = Duration::from_secs(3600);
+        let entry = format_entry("domain: example.com\n", written);
+        assert_eq!(entry, "1700000000\ndomain: example.com\n");
+
+        assert_eq!(parse_entry(&entry, written, ttl), Some("domain: example.com\n"));
+        assert_eq!(parse_entry(&entry, written + Duration::from_secs(3599), ttl), Some("domain: example.com\n"));
+        assert_eq!(parse_entry(&entry, written + Duration::from_secs(3600), ttl), None);
+        assert_eq!(parse_entry(&entry, written - Duration::from_secs(1), ttl), None);
+
+        assert_eq!(parse_entry("garbage\ndomain: example.com\n", written, ttl), None);
+        assert_eq!(parse_entry("1700000000", written, ttl), None);
+    }
+}
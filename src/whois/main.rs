@@ -3,7 +3,12 @@ extern crate arg_parser;
 use std::process::exit;
 use std::error::Error;
 use std::net::TcpStream;
-use std::io::{Write, BufRead, BufReader};
+use std::io::{Write, BufRead, BufReader, Read};
+use std::path::PathBuf;
+
+use cache::Cache;
+
+mod cache;
 
 fn main() {
     // Setup stderr stream in case of failure. Required by fail()
@@ -13,21 +18,30 @@ fn main() {
     let mut host = "whois.iana.org".to_string();
     let mut port: u16 = 43;
     let query: String;
+    let use_cache: bool;
+    let mut cache_dir = cache::default_dir();
 
     // Parse the arguments.
     {
         let mut parser = arg_parser::ArgParser::new(3)
             .add_flag(&["", "help"])
+            .add_flag(&["", "no-cache"])
             .add_opt("h", "host")
-            .add_opt("p", "port");
+            .add_opt("p", "port")
+            .add_opt("", "cache-dir");
 
         parser.parse(std::env::args());
 
         if parser.found("help") {
-            println!("Usage: whois [(-h | --host) hostname] [(-p | --port) port] query");
+            println!("Usage: whois [(-h | --host) hostname] [(-p | --port) port] [--no-cache] [--cache-dir dir] query");
             exit(0);
         }
 
+        use_cache = !parser.found("no-cache");
+        if let Some(dir) = parser.get_opt("cache-dir") {
+            cache_dir = PathBuf::from(dir);
+        }
+
         if let Some(hostname) = parser.get_opt("host") {
             // For easier case insensitive comparisons, lowercase the host.
             host = hostname.to_ascii_lowercase();
@@ -52,10 +66,25 @@ fn main() {
         fail("Query is empty", &mut stderr);
     }
 
+    // Responses are cached under the first server asked, which is what a repeated run asks too
+    let cache = Cache::new(cache_dir);
+    let key = cache::cache_key(&host, port, &query);
+    if use_cache {
+        if let Some(response) = cache.get(&key) {
+            print!("{}", response);
+            return;
+        }
+    }
+
     // Remember previous hosts to prevent an infinite loop
     let mut previous_hosts = Vec::with_capacity(1);
+    // The response of the last server in the referral chain
+    let mut response = String::new();
     while host != "" {
         let mut nhost = "".to_string();
+        response.clear();
+        // Registries block clients that query too often
+        cache.wait_turn(&host);
         // Connect to the whois host
         let connect_result = TcpStream::connect((host.as_str(), port));
         match connect_result {
@@ -78,6 +107,7 @@ fn main() {
                         Ok(0) => break,
                         Ok(_) => {
                             print!("{}", line);
+                            response.push_str(&line);
                             let trimmed_line = line.trim_start();
                             if let Some(trimmed_line) =
                                 [
@@ -98,16 +128,18 @@ fn main() {
                                     .to_ascii_lowercase();
 
                                 //Print the rest of the whois data
-                                if let Err(e) = std::io::copy(&mut reader, &mut std::io::stdout()) {
+                                let start = response.len();
+                                if let Err(e) = reader.read_to_string(&mut response) {
                                     fail(
                                         format!(
-                                            "Can't print whois data from {}, {}",
+                                            "Can't read whois data from {}, {}",
                                             host,
                                             e.description()
                                         ).as_str(),
                                         &mut stderr,
                                     );
                                 }
+                                print!("{}", &response[start..]);
                                 break;
                             }
                         }
@@ -152,6 +184,10 @@ fn main() {
         previous_hosts.push(host.clone());
         host = nhost;
     }
+
+    if use_cache {
+        cache.put(&key, &response);
+    }
 }
 
 /// Print error message to standard error, and exit with code, _1_.